@@ -1,14 +1,41 @@
 use common::{error::AppError, msmc::Subscription};
-use binance_future_connector::trade::{enums::{MarginAssetMode, PositionMode}, new_order::NewOrderRequest};
-use trade::{sim_trade_server::{SimTradeConfig, SimTradeServer, SimNewOrderRequest}, trade_server::{Position, TradeEvent, TradeServer, Wallet}};
+use market::market_server::MarketData;
+use binance_future_connector::trade::enums::{MarginAssetMode, PositionMode};
+use binance_future_connector::{market as bn_market, ureq::BinanceHttpClient};
+use trade::{order::{ModifyOrderRequest, OrderRequest}, sim_trade_server::{FundingRate, FundingRateLoader, SimMarginBracket, SimNewOrderRequest, SimOrderStatus, SimSymbolMargin, SimTradeConfig, SimTradeServer}, trade_server::{Order, OrderAck, Position, TradeEvent, TradeServer, Wallet}};
+use crate::bn_trade_server::validate_bn_order_request;
 use crate::model::*;
+
+pub struct BnFundingRateLoader {
+
+}
+
+impl FundingRateLoader for BnFundingRateLoader {
+    fn load_funding_rate(&self, symbol: &str, start_time: u64, limit: u32) -> Result<Vec<FundingRate>, AppError> {
+        let client = BinanceHttpClient::default();
+        let request = bn_market::funding_rate(symbol).start_time(start_time).limit(limit);
+        let data = get_resp_result(client.send(request), vec![])?;
+        let rates: Vec<FundingRateResp> = serde_json::from_str(&data).map_err(|e| AppError::new(-200, format!("{:?}", e).as_str()))?;
+        Ok(rates.into_iter().map(|r| FundingRate {
+            symbol: r.symbol,
+            funding_time: r.funding_time,
+            funding_rate: r.funding_rate,
+        }).collect())
+    }
+}
 pub struct BnSimTradeServer {
     pub inner: SimTradeServer,
 }
 
 impl BnSimTradeServer {
     pub fn new(config: SimTradeConfig) -> Self {
-        let inner = SimTradeServer::new(config);
+        let order_status = SimOrderStatus {
+            new: "NEW".to_string(),
+            partially_filled: "PARTIALLY_FILLED".to_string(),
+            filled: config.order_completed_status.clone(),
+            canceled: "CANCELED".to_string(),
+        };
+        let inner = SimTradeServer::new(config, order_status, Some(Box::new(BnFundingRateLoader {})));
         BnSimTradeServer {
             inner,
         }
@@ -16,7 +43,7 @@ impl BnSimTradeServer {
 }
 
 impl TradeServer for BnSimTradeServer {
-    type OrderRequest = NewOrderRequest;
+    type OrderRequest = OrderRequest;
     type CancelOrderRequest = String;
     type SymbolConfig = SymbolConfig;
     type SymbolInfo = SymbolInfo;
@@ -30,20 +57,20 @@ impl TradeServer for BnSimTradeServer {
         self.inner.start()
     }
 
-    fn new_order(&mut self, symbol: String, request : NewOrderRequest) -> Result<(), AppError> {
-        let sim_order = SimNewOrderRequest{
-            symbol: request.symbol.clone(),
-            side: request.side.to_string(),
-            position_side: request.position_side.unwrap().to_string(),
-            order_type: request.type_.to_string(),
-            reduce_only: if request.reduce_only.is_some() {"true" == request.reduce_only.unwrap()} else { false},
-            quantity: request.quantity,
-            price: request.price,
-            new_client_order_id: request.new_client_order_id,
-        };
+    fn bind_market(&mut self, subscription: Subscription<MarketData>) -> Result<(), AppError> {
+        self.inner.bind_market(subscription)
+    }
+
+    fn new_order(&mut self, symbol: String, request : OrderRequest) -> Result<OrderAck, AppError> {
+        validate_bn_order_request(&symbol, &request, PositionMode::OneWayMode)?;
+        let sim_order = SimNewOrderRequest::from_order(&symbol, &request)?;
         self.inner.new_order(symbol, sim_order)
     }
 
+    fn modify_order(&mut self, symbol: String, request: ModifyOrderRequest) -> Result<OrderAck, AppError> {
+        self.inner.modify_order(symbol, request)
+    }
+ 
     fn cancel_order(&mut self, symbol: String, request: String) -> Result<(), AppError> {
         self.inner.cancel_order(symbol, request)
     }
@@ -52,6 +79,14 @@ impl TradeServer for BnSimTradeServer {
         self.inner.cancel_orders(symbol)
     }
 
+    fn get_open_orders(&self, symbol: String) -> Result<Vec<Order>, AppError> {
+        self.inner.get_open_orders(symbol)
+    }
+
+    fn get_order(&self, symbol: String, client_order_id: &str) -> Result<Option<Order>, AppError> {
+        self.inner.get_order(symbol, client_order_id)
+    }
+
     fn get_positions(&self, symbol: String) -> Result<Vec<Position>, AppError> {
         self.inner.get_positions(symbol)
     }
@@ -61,25 +96,40 @@ impl TradeServer for BnSimTradeServer {
     }
     
     fn init_symbol(&self, symbol: String, config: Self::SymbolConfig) -> Result<SymbolInfo, AppError> {
+        if config.leverage < 1 {
+            return Err(AppError::new(-200, &format!("The leverage {} of {} must be at least 1", config.leverage, symbol)));
+        }
+        let brackets = config.brackets.clone().unwrap_or_default();
+        let mut maint_margin_ratio = leverage_margin_ratio(config.leverage);
+        for bracket in brackets.iter() {
+            if bracket.initial_leverge <= config.leverage as usize {
+                maint_margin_ratio = bracket.maint_margin_ratio;
+                break;
+            }
+        }
         let symbol_info = SymbolInfo {
             symbol: symbol.to_string(),
             leverage: config.leverage,
             margin_type: config.margin_type,
             dual_position_side: PositionMode::OneWayMode,
             multi_assets_margin: MarginAssetMode::SingleAsset,
-            maint_margin_ratio: leverage_margin_ratio(config.leverage),
+            maint_margin_ratio: maint_margin_ratio,
             quantity_precision: 8,
             price_precision: 8,
             quote_precision: 8,
         };
+        self.inner.set_symbol_margin(&symbol, SimSymbolMargin {
+            margin_ratio: 1.0 / config.leverage as f64,
+            maint_margin_ratio: symbol_info.maint_margin_ratio,
+            volume_multiple: 1.0,
+            brackets: brackets.iter().map(|b| SimMarginBracket {
+                notional_cap: b.notional_cap as f64,
+                maint_margin_ratio: b.maint_margin_ratio,
+            }).collect(),
+        });
         Ok(symbol_info)
     }
 
     fn close(&self) {
     }
 }
-
-fn leverage_margin_ratio(leverage: i32) -> f64 {
-    1.0 as f64 / leverage as f64 / 4.0 as f64
-}
-
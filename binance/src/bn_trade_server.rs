@@ -2,9 +2,11 @@ use std::{sync::{atomic::{AtomicUsize, Ordering}, Arc, Mutex, RwLock}, thread::J
 use common::{error::AppError, msmc::Subscription, thread::{Handler, InteractiveThread, Rx}};
 use serde_json::Value;
 use binance_future_connector::{
-    account, http::Credentials, market as bn_market, trade::{self as bn_trade, enums::Side, new_order::NewOrderRequest}, ureq::BinanceHttpClient, user_data_stream, wss_listen_key_keepalive::WssListeneKeyKeepalive
+    account, http::Credentials, market as bn_market, trade::{self as bn_trade, enums::{OrderType, PositionMode, PositionSide, Side, TimeInForceType}, new_order::NewOrderRequest}, ureq::BinanceHttpClient, user_data_stream, wss_listen_key_keepalive::WssListeneKeyKeepalive
 };
-use trade::trade_server::{Order, Position, TradeEvent, TradeServer, Wallet};
+use market::market_server::MarketData;
+use trade::order::{invalid_field, to_decimal, ModifyOrderRequest, OrderKind, OrderPositionSide, OrderRequest, OrderSide, TimeInForce};
+use trade::trade_server::{BatchOrderResult, Execution, Order, OrderAck, Position, TradeEvent, TradeServer, Wallet};
 use tungstenite::Message;
 use crate::model::*;
 use log::*;
@@ -112,6 +114,23 @@ impl WssStream {
                                             offset: if order_trade_update_event.order.is_reduce_only { "CLOSE".to_string() } else { "OPEN".to_string() },
                                             ..Default::default()
                                         };
+                                        let o = &order_trade_update_event.order;
+                                        if o.execution_type == "TRADE" {
+                                            subscription.send(&TradeEvent::Fill(Execution {
+                                                symbol: o.symbol.clone(),
+                                                order_id: o.order_id.to_string(),
+                                                client_order_id: o.client_order_id.clone(),
+                                                trade_id: o.trade_id.to_string(),
+                                                side: o.side.clone(),
+                                                offset: order.offset.clone(),
+                                                price: o.last_filled_price,
+                                                quantity: o.order_last_filled_quantity,
+                                                fee: o.commission.parse().unwrap_or(0.0),
+                                                fee_asset: o.commission_asset.clone(),
+                                                is_maker: o.is_maker_side,
+                                                timestamp: o.order_trade_time,
+                                            }));
+                                        }
                                         subscription.send(&TradeEvent::OrderUpdate(order));
                                     },
                                     _ => {
@@ -262,8 +281,98 @@ impl BnTradeServer {
 
 }
 
+fn to_order(o: &OrderQueryResp) -> Order {
+    Order {
+        order_id: o.order_id.to_string(),
+        client_order_id: o.client_order_id.clone(),
+        order_type: o.order_type.clone(),
+        symbol: o.symbol.clone(),
+        status: o.status.clone(),
+        price: o.price,
+        traded: o.executed_qty,
+        total: o.orig_qty,
+        side: o.side.clone(),
+        timestamp: o.update_time,
+        offset: if o.reduce_only { "CLOSE".to_string() } else { "OPEN".to_string() },
+        ..Default::default()
+    }
+}
+
+const BATCH_ORDER_LIMIT: usize = 5;
+const BATCH_CANCEL_LIMIT: usize = 10;
+
+fn to_batch_item<T: serde::de::DeserializeOwned>(item: Value) -> Result<T, AppError> {
+    if let Some(code) = item.get("code").and_then(|c| c.as_i64()) {
+        let msg = item.get("msg").and_then(|m| m.as_str()).unwrap_or_default();
+        return Err(AppError::new(-200, &format!("{} {}", code, msg)));
+    }
+    serde_json::from_value::<T>(item).map_err(|e| AppError::new(-200, format!("{:?}", e).as_str()))
+}
+
+fn send_batch<R: Into<binance_future_connector::http::request::Request>>(client: &BinanceHttpClient, request: R) -> Result<Vec<Value>, AppError> {
+    let data = get_resp_result(client.send(request), vec![])?;
+    serde_json::from_str(&data).map_err(|e| AppError::new(-200, format!("{:?}", e).as_str()))
+}
+
+/// Rejects the orders Binance would reject, so the simulator accepts the same orders as the live server.
+pub fn validate_bn_order_request(symbol: &str, request: &OrderRequest, position_mode: PositionMode) -> Result<(), AppError> {
+    to_bn_order_request(symbol, request, position_mode).map(|_| ())
+}
+
+/// Hedge mode takes the position side and rejects `reduceOnly`, one-way mode takes `reduceOnly` for a closing order.
+pub fn to_bn_order_request(symbol: &str, request: &OrderRequest, position_mode: PositionMode) -> Result<NewOrderRequest, AppError> {
+    let side = match request.side {
+        OrderSide::Buy => Side::Buy,
+        OrderSide::Sell => Side::Sell,
+    };
+    let order_type = match request.order_type {
+        OrderKind::Limit => OrderType::Limit,
+        OrderKind::Market => OrderType::Market,
+        OrderKind::Stop => OrderType::Stop,
+        OrderKind::StopMarket => OrderType::StopMarket,
+        OrderKind::TakeProfit => OrderType::TakeProfit,
+        OrderKind::TakeProfitMarket => OrderType::TakeProfitMarket,
+    };
+    let mut ret = NewOrderRequest::new(symbol, side, order_type).quantity(to_decimal("quantity", request.quantity)?);
+    match position_mode {
+        PositionMode::HedgeMode => {
+            ret = ret.position_side(match request.resolved_position_side() {
+                Some(OrderPositionSide::Long) => PositionSide::Long,
+                Some(OrderPositionSide::Short) => PositionSide::Short,
+                _ => return Err(invalid_field("positionSide", "LONG or SHORT is required in hedge mode")),
+            });
+        },
+        PositionMode::OneWayMode => {
+            if request.is_reduce_only() {
+                ret = ret.reduce_only("true");
+            }
+        },
+    }
+    if let Some(price) = request.price {
+        ret = ret.price(to_decimal("price", price)?);
+    }
+    if let Some(stop_price) = request.stop_price {
+        ret = ret.stop_price(to_decimal("stopPrice", stop_price)?);
+    }
+    let time_in_force = match request.time_in_force {
+        Some(TimeInForce::Gtc) => Some(TimeInForceType::Gtc),
+        Some(TimeInForce::Ioc) => Some(TimeInForceType::Ioc),
+        Some(TimeInForce::Fok) => Some(TimeInForceType::Fok),
+        Some(TimeInForce::Gtx) => return Err(invalid_field("timeInForce", "GTX is not supported by Binance")),
+        None if request.order_type.requires_price() => Some(TimeInForceType::Gtc),
+        None => None,
+    };
+    if let Some(time_in_force) = time_in_force {
+        ret = ret.time_in_force(time_in_force);
+    }
+    if let Some(client_order_id) = &request.client_order_id {
+        ret = ret.new_client_order_id(client_order_id);
+    }
+    Ok(ret)
+}
+
 impl TradeServer for BnTradeServer {
-    type OrderRequest = NewOrderRequest;
+    type OrderRequest = OrderRequest;
     type CancelOrderRequest = String;
     type SymbolConfig = SymbolConfig;
     type SymbolInfo = SymbolInfo;
@@ -289,12 +398,42 @@ impl TradeServer for BnTradeServer {
         Ok(ext_sub)
     }
 
-    fn new_order(&mut self, _symbol: String, request : NewOrderRequest) -> Result<(), AppError> {
-        let client = BinanceHttpClient::default().credentials(self.credentials.clone());
-        let _ = get_resp_result(client.send(request), vec![])?;
+    fn bind_market(&mut self, _subscription: Subscription<MarketData>) -> Result<(), AppError> {
         Ok(())
     }
 
+    fn new_order(&mut self, symbol: String, request : OrderRequest) -> Result<OrderAck, AppError> {
+        let request = to_bn_order_request(&symbol, &request, self.config.dual_position_side)?;
+        let client = BinanceHttpClient::default().credentials(self.credentials.clone());
+        let data = get_resp_result(client.send(request), vec![])?;
+        let resp: NewOrderResp = serde_json::from_str(&data).map_err(|e| AppError::new(-200, format!("{:?}", e).as_str()))?;
+        Ok(OrderAck {
+            order_id: resp.order_id.to_string(),
+            client_order_id: resp.client_order_id,
+            status: resp.status,
+            timestamp: resp.update_time,
+            ..Default::default()
+        })
+    }
+
+    fn modify_order(&mut self, symbol: String, request: ModifyOrderRequest) -> Result<OrderAck, AppError> {
+        request.validate()?;
+        let order = self.get_order(symbol.clone(), &request.client_order_id)?.ok_or(AppError::new(-200, &format!("The order {} is not found", request.client_order_id)))?;
+        let side = if order.side == "BUY" { Side::Buy } else { Side::Sell };
+        let quantity = to_decimal("quantity", request.quantity.unwrap_or(order.total))?;
+        let price = to_decimal("price", request.price.unwrap_or(order.price))?;
+        let client = BinanceHttpClient::default().credentials(self.credentials.clone());
+        let data = get_resp_result(client.send(bn_trade::modify_order(&symbol, side, quantity, price).orig_client_order_id(&request.client_order_id)), vec![])?;
+        let resp: OrderQueryResp = serde_json::from_str(&data).map_err(|e| AppError::new(-200, format!("{:?}", e).as_str()))?;
+        Ok(OrderAck {
+            order_id: resp.order_id.to_string(),
+            client_order_id: resp.client_order_id,
+            status: resp.status,
+            timestamp: resp.update_time,
+            ..Default::default()
+        })
+    }
+
     fn cancel_order(&mut self, symbol: String, request: String) -> Result<(), AppError> {
         let client = BinanceHttpClient::default().credentials(self.credentials.clone());
         let request = bn_trade::cancel_order(&symbol).orig_client_order_id(&request);
@@ -310,6 +449,90 @@ impl TradeServer for BnTradeServer {
         Ok(())
     }
 
+    fn set_countdown_cancel(&mut self, symbol: String, timeout: u64) -> Result<bool, AppError> {
+        let client = BinanceHttpClient::default().credentials(self.credentials.clone());
+        let request = bn_trade::countdown_cancel_all(&symbol, timeout as i64);
+        let _ = get_resp_result(client.send(request), vec![])?;
+        Ok(true)
+    }
+
+    fn new_orders(&mut self, symbol: String, requests: Vec<OrderRequest>) -> Result<Vec<BatchOrderResult>, AppError> {
+        let mut results = vec![];
+        let mut batch = vec![];
+        for (index, request) in requests.iter().enumerate() {
+            match to_bn_order_request(&symbol, request, self.config.dual_position_side) {
+                Ok(r) => batch.push((index, r)),
+                Err(e) => results.push(BatchOrderResult::from_result(index, Err(e))),
+            }
+        }
+        let client = BinanceHttpClient::default().credentials(self.credentials.clone());
+        for chunk in batch.chunks(BATCH_ORDER_LIMIT) {
+            let mut request = bn_trade::new_multi_order();
+            for (_, r) in chunk.iter() {
+                request = request.add(r.clone());
+            }
+            let items = match send_batch(&client, request) {
+                Ok(items) => items,
+                Err(e) => {
+                    error!("The batch of orders {:?} of {} failed, continue with the next batch: {:?}", chunk.iter().map(|(index, _)| index).collect::<Vec<_>>(), symbol, e);
+                    results.extend(chunk.iter().map(|(index, _)| BatchOrderResult { index: *index, ack: None, error: Some(e.message.clone()) }));
+                    continue;
+                },
+            };
+            for ((index, _), item) in chunk.iter().zip(items) {
+                let ret = to_batch_item::<NewOrderResp>(item).map(|resp| Some(OrderAck {
+                    order_id: resp.order_id.to_string(),
+                    client_order_id: resp.client_order_id,
+                    status: resp.status,
+                    timestamp: resp.update_time,
+                    ..Default::default()
+                }));
+                results.push(BatchOrderResult::from_result(*index, ret));
+            }
+        }
+        results.sort_by_key(|r| r.index);
+        Ok(results)
+    }
+
+    fn cancel_orders_by_id(&mut self, symbol: String, requests: Vec<String>) -> Result<Vec<BatchOrderResult>, AppError> {
+        let mut results = vec![];
+        let client = BinanceHttpClient::default().credentials(self.credentials.clone());
+        for (n, chunk) in requests.chunks(BATCH_CANCEL_LIMIT).enumerate() {
+            let request = bn_trade::cancel_multi_order(&symbol).orig_client_order_id_list(chunk.to_vec());
+            info!("Cancel Orders {:?}", chunk);
+            let items = match send_batch(&client, request) {
+                Ok(items) => items,
+                Err(e) => {
+                    error!("Canceling the orders {:?} of {} failed, continue with the next batch: {:?}", chunk, symbol, e);
+                    results.extend((0..chunk.len()).map(|i| BatchOrderResult { index: n * BATCH_CANCEL_LIMIT + i, ack: None, error: Some(e.message.clone()) }));
+                    continue;
+                },
+            };
+            for (i, item) in items.into_iter().enumerate() {
+                let ret = to_batch_item::<NewOrderResp>(item).map(|_| None);
+                results.push(BatchOrderResult::from_result(n * BATCH_CANCEL_LIMIT + i, ret));
+            }
+        }
+        Ok(results)
+    }
+
+    fn get_open_orders(&self, symbol: String) -> Result<Vec<Order>, AppError> {
+        let client = BinanceHttpClient::default().credentials(self.credentials.clone());
+        let data = get_resp_result(client.send(bn_trade::open_orders().symbol(&symbol)), vec![])?;
+        let orders: Vec<OrderQueryResp> = serde_json::from_str(&data).map_err(|e| AppError::new(-200, format!("{:?}", e).as_str()))?;
+        Ok(orders.iter().map(to_order).collect())
+    }
+
+    fn get_order(&self, symbol: String, client_order_id: &str) -> Result<Option<Order>, AppError> {
+        let client = BinanceHttpClient::default().credentials(self.credentials.clone());
+        let data = get_resp_result(client.send(bn_trade::get_order(&symbol).orig_client_order_id(client_order_id)), vec![-2013])?;
+        if data.is_empty() {
+            return Ok(None);
+        }
+        let order: OrderQueryResp = serde_json::from_str(&data).map_err(|e| AppError::new(-200, format!("{:?}", e).as_str()))?;
+        Ok(Some(to_order(&order)))
+    }
+
     fn get_positions(&self, symbol: String) -> Result<Vec<Position>, AppError> {
         let positions = self.positions.read().unwrap();
         let mut ret = vec![];
@@ -2,6 +2,10 @@ use binance_future_connector::{http::error::ClientError, market_stream::enums::{
 use common::error::AppError;
 use serde::{Deserialize, Deserializer, Serialize};
 
+pub fn leverage_margin_ratio(leverage: i32) -> f64 {
+    1.0 as f64 / leverage as f64 / 4.0 as f64
+}
+
 pub fn get_resp_result(ret: Result<Response, Box<Error>>, skipped_code: Vec<i16>) -> Result<String, AppError> {
     let err;
     match ret {
@@ -38,6 +42,7 @@ pub fn get_resp_result(ret: Result<Response, Box<Error>>, skipped_code: Vec<i16>
 pub struct SymbolConfig {
     pub margin_type: MarginType, 
     pub leverage: i32,
+    pub brackets: Option<Vec<BracketResp>>,
 }
 
 impl SymbolConfig {
@@ -45,6 +50,7 @@ impl SymbolConfig {
         SymbolConfig {
             margin_type: MarginType::Isolated,
             leverage: 5,
+            brackets: None,
         }
     }
 }
@@ -498,6 +504,16 @@ pub struct BracketResp {
     pub cum: f64,
 }
 
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct FundingRateResp {
+    #[serde(rename = "symbol")]
+    pub symbol: String,
+    #[serde(rename = "fundingTime")]
+    pub funding_time: u64,
+    #[serde(rename = "fundingRate", deserialize_with = "string_to_f64")]
+    pub funding_rate: f64,
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone, Default)]
 pub struct LeverageBracketQueryResp {
     #[serde(rename = "symbol")]
@@ -661,4 +677,42 @@ where
         parsed_f64s.push(parsed_f64);
     }
     Ok(parsed_f64s)
-}
\ No newline at end of file
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct NewOrderResp {
+    #[serde(rename = "orderId")]
+    pub order_id: i64,
+    #[serde(rename = "clientOrderId")]
+    pub client_order_id: String,
+    #[serde(rename = "status")]
+    pub status: String,
+    #[serde(rename = "updateTime")]
+    pub update_time: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct OrderQueryResp {
+    #[serde(rename = "orderId")]
+    pub order_id: i64,
+    #[serde(rename = "clientOrderId")]
+    pub client_order_id: String,
+    #[serde(rename = "symbol")]
+    pub symbol: String,
+    #[serde(rename = "status")]
+    pub status: String,
+    #[serde(rename = "type")]
+    pub order_type: String,
+    #[serde(rename = "side")]
+    pub side: String,
+    #[serde(rename = "price", deserialize_with = "string_to_f64")]
+    pub price: f64,
+    #[serde(rename = "origQty", deserialize_with = "string_to_f64")]
+    pub orig_qty: f64,
+    #[serde(rename = "executedQty", deserialize_with = "string_to_f64")]
+    pub executed_qty: f64,
+    #[serde(rename = "reduceOnly")]
+    pub reduce_only: bool,
+    #[serde(rename = "updateTime")]
+    pub update_time: u64,
+}
@@ -47,8 +47,7 @@ impl CancelMultiOrderRequest {
         }
 
         if let Some(orig_client_order_id_list) = &self.orig_client_order_id_list {
-            let orig_client_order_id_str = orig_client_order_id_list.iter().map(|id| id.clone()).collect::<Vec<String>>().join(",");
-            params.push(("origClientOrderIdList".to_owned(), format!("[{}]", orig_client_order_id_str)));
+            params.push(("origClientOrderIdList".to_owned(), serde_json::json!(orig_client_order_id_list).to_string()));
         }
 
         if let Some(recv_window) = self.recv_window {
@@ -1,17 +1,46 @@
-use std::str::FromStr;
-
+use bybit_connector::{enums::Category, market as bb_market, ureq::BybitHttpClient};
 use common::{error::AppError, msmc::Subscription};
-use bybit_connector::trade::new_order::NewOrderRequest;
-use rust_decimal::Decimal;
-use trade::{sim_trade_server::{SimTradeConfig, SimTradeServer, SimNewOrderRequest}, trade_server::{Position, TradeEvent, TradeServer, Wallet}};
+use market::market_server::MarketData;
+use trade::{order::{ModifyOrderRequest, OrderRequest}, sim_trade_server::{FundingRate, FundingRateLoader, SimNewOrderRequest, SimOrderStatus, SimSymbolMargin, SimTradeConfig, SimTradeServer}, trade_server::{Order, OrderAck, Position, TradeEvent, TradeServer, Wallet}};
+use crate::bb_trade_server::validate_bb_order_request;
 use crate::model::*;
+
+/// The shortest funding interval of Bybit perpetuals, a window of `limit` intervals never holds more than `limit` rates.
+const MIN_FUNDING_INTERVAL: u64 = 60 * 60 * 1000;
+
+pub struct BbFundingRateLoader {
+
+}
+
+impl FundingRateLoader for BbFundingRateLoader {
+    fn load_funding_rate(&self, symbol: &str, start_time: u64, limit: u32) -> Result<Vec<FundingRate>, AppError> {
+        let client = BybitHttpClient::default();
+        let end_time = start_time + limit as u64 * MIN_FUNDING_INTERVAL;
+        let request = bb_market::funding_history(Category::Linear, symbol).start_time(start_time).end_time(end_time).limit(limit as u64);
+        let data = get_resp_result::<FundingHistoryQueryResp>(client.send(request), vec![], false)?;
+        let mut rates: Vec<FundingRate> = data.map(|v| v.list).unwrap_or_default().into_iter().map(|r| FundingRate {
+            symbol: r.symbol,
+            funding_time: r.funding_rate_timestamp as u64,
+            funding_rate: r.funding_rate,
+        }).collect();
+        rates.sort_by_key(|r| r.funding_time);
+        Ok(rates)
+    }
+}
+
 pub struct BbSimTradeServer {
     pub inner: SimTradeServer,
 }
 
 impl BbSimTradeServer {
     pub fn new(config: SimTradeConfig) -> Self {
-        let inner = SimTradeServer::new(config);
+        let order_status = SimOrderStatus {
+            new: "New".to_string(),
+            partially_filled: "PartiallyFilled".to_string(),
+            filled: config.order_completed_status.clone(),
+            canceled: "Cancelled".to_string(),
+        };
+        let inner = SimTradeServer::new(config, order_status, Some(Box::new(BbFundingRateLoader {})));
         BbSimTradeServer {
             inner,
         }
@@ -19,7 +48,7 @@ impl BbSimTradeServer {
 }
 
 impl TradeServer for BbSimTradeServer {
-    type OrderRequest = NewOrderRequest;
+    type OrderRequest = OrderRequest;
     type CancelOrderRequest = String;
     type SymbolConfig = SymbolConfig;
     type SymbolInfo = SymbolInfo;
@@ -33,20 +62,20 @@ impl TradeServer for BbSimTradeServer {
         self.inner.start()
     }
 
-    fn new_order(&mut self, symbol: String, request : NewOrderRequest) -> Result<(), AppError> {
-        let sim_order = SimNewOrderRequest{
-            symbol: request.symbol.clone(),
-            side: request.side.to_string(),
-            position_side: request.position_idx.unwrap().to_string(),
-            order_type: request.order_type.to_string(),
-            reduce_only: if request.reduce_only.is_some() {request.reduce_only.unwrap()} else { false},
-            quantity: Some(Decimal::from_str(&request.qty).map_err(|e| AppError::new(-200, &e.to_string()))?),
-            price: request.price,
-            new_client_order_id: request.order_link_id.clone(),
-        };
+    fn bind_market(&mut self, subscription: Subscription<MarketData>) -> Result<(), AppError> {
+        self.inner.bind_market(subscription)
+    }
+
+    fn new_order(&mut self, symbol: String, request : OrderRequest) -> Result<OrderAck, AppError> {
+        validate_bb_order_request(&symbol, &request, 0)?;
+        let sim_order = SimNewOrderRequest::from_order(&symbol, &request)?;
         self.inner.new_order(symbol, sim_order)
     }
 
+    fn modify_order(&mut self, symbol: String, request: ModifyOrderRequest) -> Result<OrderAck, AppError> {
+        self.inner.modify_order(symbol, request)
+    }
+ 
     fn cancel_order(&mut self, symbol: String, request: String) -> Result<(), AppError> {
         self.inner.cancel_order(symbol, request)
     }
@@ -55,6 +84,14 @@ impl TradeServer for BbSimTradeServer {
         self.inner.cancel_orders(symbol)
     }
 
+    fn get_open_orders(&self, symbol: String) -> Result<Vec<Order>, AppError> {
+        self.inner.get_open_orders(symbol)
+    }
+
+    fn get_order(&self, symbol: String, client_order_id: &str) -> Result<Option<Order>, AppError> {
+        self.inner.get_order(symbol, client_order_id)
+    }
+
     fn get_positions(&self, symbol: String) -> Result<Vec<Position>, AppError> {
         self.inner.get_positions(symbol)
     }
@@ -64,12 +101,16 @@ impl TradeServer for BbSimTradeServer {
     }
     
     fn init_symbol(&self, symbol: String, config: Self::SymbolConfig) -> Result<SymbolInfo, AppError> {
+        if config.leverage < 1 {
+            return Err(AppError::new(-200, &format!("The leverage {} of {} must be at least 1", config.leverage, symbol)));
+        }
         let symbol_info = SymbolInfo {
             symbol: symbol.to_string(),
             leverage: config.leverage,
             margin_type: config.margin_type.to_string(),
             dual_position_side: "1".to_string(),
         };
+        self.inner.set_symbol_margin(&symbol, SimSymbolMargin::from_leverage(config.leverage as u64));
         Ok(symbol_info)
     }
 
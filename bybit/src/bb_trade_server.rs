@@ -2,9 +2,11 @@ use std::{sync::{atomic::{AtomicUsize, Ordering}, Arc, Mutex, RwLock}, thread::J
 use common::{error::AppError, msmc::Subscription, thread::{Handler, InteractiveThread, Rx}};
 use serde_json::Value;
 use bybit_connector::{
-    account, enums::Category, http::Credentials, trade::{self as bb_trade, new_order::NewOrderRequest}, ureq::BybitHttpClient, websocket::Stream, wss_keepalive::WssKeepalive
+    account, enums::{Category, OrderType, Side, TimeInForceType}, http::Credentials, trade::{self as bb_trade, new_order::NewOrderRequest}, ureq::BybitHttpClient, websocket::Stream, wss_keepalive::WssKeepalive
 };
-use trade::trade_server::{Order, Position, TradeEvent, TradeServer, Wallet};
+use market::market_server::MarketData;
+use trade::order::{invalid_field, to_decimal, ModifyOrderRequest, OrderKind, OrderPositionSide, OrderRequest, OrderSide, TimeInForce};
+use trade::trade_server::{BatchOrderResult, Execution, Order, OrderAck, Position, TradeEvent, TradeServer, Wallet};
 use tungstenite::Message;
 use crate::model::*;
 use log::*;
@@ -15,16 +17,18 @@ pub struct WssStream {
     connect_ticket: Arc<AtomicUsize>,
     server_ping: Arc<AtomicUsize>,
     pub credentials: Credentials,
+    settle_coin: String,
 }
 
 impl WssStream {
-    pub fn new(credentials: Credentials) -> Self {
+    pub fn new(credentials: Credentials, settle_coin: &str) -> Self {
         WssStream {
             subscription: Arc::new(Mutex::new(Subscription::top())),
             handler : None,
             connect_ticket: Arc::new(AtomicUsize::new(0)),
             server_ping: Arc::new(AtomicUsize::new(0)),
             credentials,
+            settle_coin: settle_coin.to_string(),
         }
     }
 
@@ -44,11 +48,13 @@ impl WssStream {
         let server_ping_ref = self.server_ping.clone();
         let subscription_ref = self.subscription.clone();
         let credentials = self.credentials.clone();
+        let settle_coin = self.settle_coin.clone();
         let closure = move |_rx: Rx<String>| {
             let subscription = subscription_ref.lock().unwrap();
             let mut keepalive = WssKeepalive::new(&format!("{}/v5/private", bybit_connector::config::wss_api())).credentials(credentials).prepare(|conn| {
                 conn.subscribe(vec![
                     &Stream::new("order"), 
+                    &Stream::new("execution"), 
                     &Stream::new("position"), 
                     &Stream::new("wallet")
                     ]);
@@ -113,6 +119,26 @@ impl WssStream {
                                         subscription.send(&TradeEvent::OrderUpdate(order));
                                     }
                                 },
+                                "execution" => {
+                                    let execution_update: ExecutionData = serde_json::from_str(&string_data).map_err(Box::new)?;
+                                    for e in execution_update.data.into_iter().filter(|e| e.exec_type == "Trade") {
+                                        let closed = e.closed_size.parse::<f64>().unwrap_or(0.0) > 0.0;
+                                        subscription.send(&TradeEvent::Fill(Execution {
+                                            symbol: e.symbol,
+                                            order_id: e.order_id,
+                                            client_order_id: e.order_link_id,
+                                            trade_id: e.exec_id,
+                                            side: e.side,
+                                            offset: if closed { "CLOSE".to_string() } else { "OPEN".to_string() },
+                                            price: e.exec_price,
+                                            quantity: e.exec_qty,
+                                            fee: e.exec_fee,
+                                            fee_asset: if e.fee_currency.is_empty() { settle_coin.clone() } else { e.fee_currency },
+                                            is_maker: e.is_maker,
+                                            timestamp: e.exec_time as u64,
+                                        }));
+                                    }
+                                },
                                 _ => {
                                     debug!("Received other event: {}", string_data);
                                 },
@@ -143,7 +169,7 @@ impl WssStream {
 }
 
 pub trait BbTradeServerTrait : TradeServer<
-        OrderRequest = NewOrderRequest,
+        OrderRequest = OrderRequest,
         CancelOrderRequest = String,
         SymbolConfig = SymbolConfig,
         SymbolInfo = SymbolInfo,
@@ -167,8 +193,8 @@ impl BbTradeServer {
         let credentials = Credentials::from_hmac(config.api_key.clone(), config.api_secret.clone());
         BbTradeServer {
             credentials: credentials.clone(),
+            wss_stream: WssStream::new(credentials.clone(), &config.settle_coin),
             config,
-            wss_stream: WssStream::new(credentials.clone()),
             positions: Arc::new(RwLock::new(Vec::new())),
             wallets: Arc::new(RwLock::new(Vec::new())),
             handler: None,
@@ -282,8 +308,76 @@ pub type BbTradeServerType = dyn TradeServer<
             Symbol = String,
             >;
 
+fn to_order(o: &OrderInfo) -> Order {
+    Order {
+        order_id: o.order_id.clone(),
+        client_order_id: o.order_link_id.clone(),
+        order_type: o.order_type.clone(),
+        symbol: o.symbol.clone(),
+        status: o.order_status.clone(),
+        price: o.price,
+        traded: o.cum_exec_qty,
+        total: o.qty,
+        side: o.side.clone(),
+        message: o.reject_reason.clone(),
+        timestamp: o.updated_time as u64,
+        offset: if o.reduce_only { "CLOSE".to_string() } else { "OPEN".to_string() },
+        ..Default::default()
+    }
+}
+
+const BATCH_ORDER_LIMIT: usize = 10;
+const DCP_MIN_WINDOW: u64 = 3;
+const DCP_MAX_WINDOW: u64 = 300;
+
+/// The position mode of `switch-mode` where both sides are held.
+pub const HEDGE_MODE: u32 = 3;
+
+/// Validation-only form of `to_bb_order_request`, used by the simulator.
+pub fn validate_bb_order_request(symbol: &str, request: &OrderRequest, position_mode: u32) -> Result<(), AppError> {
+    to_bb_order_request(symbol, request, position_mode).map(|_| ())
+}
+
+/// Hedge mode takes the position index of the side, one-way mode always takes index 0.
+pub fn to_bb_order_request(symbol: &str, request: &OrderRequest, position_mode: u32) -> Result<NewOrderRequest, AppError> {
+    let side = match request.side {
+        OrderSide::Buy => Side::Buy,
+        OrderSide::Sell => Side::Sell,
+    };
+    let order_type = if request.order_type.requires_price() { OrderType::Limit } else { OrderType::Market };
+    let mut ret = NewOrderRequest::new(Category::Linear, symbol, side, order_type, &to_decimal("quantity", request.quantity)?.to_string());
+    if let Some(price) = request.price {
+        ret = ret.price(to_decimal("price", price)?);
+    }
+    if let Some(stop_price) = request.stop_price {
+        let rising = matches!((request.order_type, request.side),
+            (OrderKind::Stop | OrderKind::StopMarket, OrderSide::Buy) | (OrderKind::TakeProfit | OrderKind::TakeProfitMarket, OrderSide::Sell));
+        ret = ret.trigger_price(to_decimal("stopPrice", stop_price)?).trigger_direction(if rising { 1 } else { 2 });
+    }
+    match request.time_in_force {
+        Some(TimeInForce::Gtc) => ret = ret.time_in_force(TimeInForceType::GTC),
+        Some(TimeInForce::Ioc) => ret = ret.time_in_force(TimeInForceType::IOC),
+        Some(TimeInForce::Fok) => return Err(invalid_field("timeInForce", "FOK is not supported by Bybit")),
+        Some(TimeInForce::Gtx) => return Err(invalid_field("timeInForce", "GTX is not supported by Bybit")),
+        None => {},
+    }
+    ret = ret.position_idx(match (position_mode, request.resolved_position_side()) {
+        (HEDGE_MODE, Some(OrderPositionSide::Long)) => 1,
+        (HEDGE_MODE, Some(OrderPositionSide::Short)) => 2,
+        (HEDGE_MODE, _) => return Err(invalid_field("positionIdx", "LONG or SHORT is required in hedge mode")),
+        _ => 0,
+    });
+    if request.is_reduce_only() {
+        ret = ret.reduce_only(true);
+    }
+    if let Some(client_order_id) = &request.client_order_id {
+        ret = ret.order_link_id(client_order_id);
+    }
+    Ok(ret)
+}
+
 impl TradeServer for BbTradeServer {
-    type OrderRequest = NewOrderRequest;
+    type OrderRequest = OrderRequest;
     type CancelOrderRequest = String;
     type SymbolConfig = SymbolConfig;
     type SymbolInfo = SymbolInfo;
@@ -308,12 +402,43 @@ impl TradeServer for BbTradeServer {
         Ok(ext_sub)
     }
 
-    fn new_order(&mut self, _symbol: String, request : NewOrderRequest) -> Result<(), AppError> {
-        let client = BybitHttpClient::default().credentials(self.credentials.clone());
-        let _ = get_resp_result::<Ignore>(client.send(request), vec![], true)?;
+    fn bind_market(&mut self, _subscription: Subscription<MarketData>) -> Result<(), AppError> {
         Ok(())
     }
 
+    fn new_order(&mut self, symbol: String, request : OrderRequest) -> Result<OrderAck, AppError> {
+        let request = to_bb_order_request(&symbol, &request, self.config.position_side)?;
+        let client = BybitHttpClient::default().credentials(self.credentials.clone());
+        let resp = get_resp_result::<NewOrderResp>(client.send(request), vec![], false)?.ok_or(AppError::new(-200, "Empty response of new order"))?;
+        Ok(OrderAck {
+            order_id: resp.order_id,
+            client_order_id: resp.order_link_id,
+            status: "New".to_string(),
+            timestamp: chrono::Utc::now().timestamp_millis() as u64,
+            ..Default::default()
+        })
+    }
+
+    fn modify_order(&mut self, symbol: String, request: ModifyOrderRequest) -> Result<OrderAck, AppError> {
+        request.validate()?;
+        let mut amend = bb_trade::amend_order(Category::Linear, &symbol).order_link_id(&request.client_order_id);
+        if let Some(quantity) = request.quantity {
+            amend = amend.qty(&to_decimal("quantity", quantity)?.to_string());
+        }
+        if let Some(price) = request.price {
+            amend = amend.price(to_decimal("price", price)?);
+        }
+        let client = BybitHttpClient::default().credentials(self.credentials.clone());
+        let resp = get_resp_result::<NewOrderResp>(client.send(amend), vec![], false)?.ok_or(AppError::new(-200, "Empty response of modify order"))?;
+        Ok(OrderAck {
+            order_id: resp.order_id,
+            client_order_id: resp.order_link_id,
+            status: "New".to_string(),
+            timestamp: chrono::Utc::now().timestamp_millis() as u64,
+            ..Default::default()
+        })
+    }
+
     fn cancel_order(&mut self, symbol: String, request: String) -> Result<(), AppError> {
         let client = BybitHttpClient::default().credentials(self.credentials.clone());
         let request = bb_trade::cancel_order(Category::Linear, &symbol).order_link_id(&request);
@@ -329,6 +454,111 @@ impl TradeServer for BbTradeServer {
         Ok(())
     }
 
+    fn set_countdown_cancel(&mut self, _symbol: String, timeout: u64) -> Result<bool, AppError> {
+        if timeout == 0 {
+            return Ok(false);
+        }
+        let client = BybitHttpClient::default().credentials(self.credentials.clone());
+        let time_window = (timeout / 1000).clamp(DCP_MIN_WINDOW, DCP_MAX_WINDOW) as u32;
+        let request = bb_trade::disconnected_cancel_all(time_window).product("DERIVATIVES");
+        let _ = get_resp_result::<Ignore>(client.send(request), vec![], true)?;
+        Ok(true)
+    }
+
+    fn new_orders(&mut self, symbol: String, requests: Vec<OrderRequest>) -> Result<Vec<BatchOrderResult>, AppError> {
+        let mut results = vec![];
+        let mut batch = vec![];
+        for (index, request) in requests.iter().enumerate() {
+            match to_bb_order_request(&symbol, request, self.config.position_side) {
+                Ok(r) => batch.push((index, r)),
+                Err(e) => results.push(BatchOrderResult::from_result(index, Err(e))),
+            }
+        }
+        let client = BybitHttpClient::default().credentials(self.credentials.clone());
+        for chunk in batch.chunks(BATCH_ORDER_LIMIT) {
+            let mut request = bb_trade::new_batch_order(Category::Linear);
+            for (_, r) in chunk.iter() {
+                request = request.add(r.clone());
+            }
+            let items = match get_batch_resp_result::<NewOrderResp>(client.send(request)) {
+                Ok(items) => items,
+                Err(e) => {
+                    error!("The batch of orders {:?} of {} failed, continue with the next batch: {:?}", chunk.iter().map(|(index, _)| index).collect::<Vec<_>>(), symbol, e);
+                    results.extend(chunk.iter().map(|(index, _)| BatchOrderResult { index: *index, ack: None, error: Some(e.message.clone()) }));
+                    continue;
+                },
+            };
+            let timestamp = chrono::Utc::now().timestamp_millis() as u64;
+            for ((index, _), item) in chunk.iter().zip(items) {
+                let ret = item.map(|resp| Some(OrderAck {
+                    order_id: resp.order_id,
+                    client_order_id: resp.order_link_id,
+                    status: "New".to_string(),
+                    timestamp,
+                    ..Default::default()
+                }));
+                results.push(BatchOrderResult::from_result(*index, ret));
+            }
+        }
+        results.sort_by_key(|r| r.index);
+        Ok(results)
+    }
+
+    fn cancel_orders_by_id(&mut self, symbol: String, requests: Vec<String>) -> Result<Vec<BatchOrderResult>, AppError> {
+        let mut results = vec![];
+        let client = BybitHttpClient::default().credentials(self.credentials.clone());
+        for (n, chunk) in requests.chunks(BATCH_ORDER_LIMIT).enumerate() {
+            let mut request = bb_trade::cancel_batch_order(Category::Linear);
+            for id in chunk.iter() {
+                request = request.add(bb_trade::cancel_order(Category::Linear, &symbol).order_link_id(id));
+            }
+            info!("Cancel Orders {:?}", chunk);
+            let items = match get_batch_resp_result::<NewOrderResp>(client.send(request)) {
+                Ok(items) => items,
+                Err(e) => {
+                    error!("Canceling the orders {:?} of {} failed, continue with the next batch: {:?}", chunk, symbol, e);
+                    results.extend((0..chunk.len()).map(|i| BatchOrderResult { index: n * BATCH_ORDER_LIMIT + i, ack: None, error: Some(e.message.clone()) }));
+                    continue;
+                },
+            };
+            for (i, item) in items.into_iter().enumerate() {
+                results.push(BatchOrderResult::from_result(n * BATCH_ORDER_LIMIT + i, item.map(|_| None)));
+            }
+        }
+        Ok(results)
+    }
+
+    fn get_open_orders(&self, symbol: String) -> Result<Vec<Order>, AppError> {
+        let client = BybitHttpClient::default().credentials(self.credentials.clone());
+        let mut ret = vec![];
+        let mut cursor = "".to_string();
+        loop {
+            let mut request = bb_trade::open_orders(Category::Linear).symbol(&symbol).open_only(0).limit(50);
+            if !cursor.is_empty() {
+                request = request.cursor(&cursor);
+            }
+            let data = get_resp_result::<OrderQueryResp>(client.send(request), vec![], false)?;
+            match data {
+                Some(data) => {
+                    ret.extend(data.list.iter().map(to_order));
+                    if data.next_page_cursor.is_empty() || data.list.is_empty() {
+                        break;
+                    }
+                    cursor = data.next_page_cursor;
+                },
+                None => break,
+            }
+        }
+        Ok(ret)
+    }
+
+    fn get_order(&self, symbol: String, client_order_id: &str) -> Result<Option<Order>, AppError> {
+        let client = BybitHttpClient::default().credentials(self.credentials.clone());
+        let request = bb_trade::open_orders(Category::Linear).symbol(&symbol).order_link_id(client_order_id);
+        let data = get_resp_result::<OrderQueryResp>(client.send(request), vec![], false)?;
+        Ok(data.and_then(|v| v.list.first().map(to_order)))
+    }
+
     fn get_positions(&self, symbol: String) -> Result<Vec<Position>, AppError> {
         let positions = self.positions.read().unwrap();
         let mut ret = vec![];
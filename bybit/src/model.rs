@@ -54,6 +54,31 @@ pub fn get_resp_result<T: DeserializeOwned>(ret: Result<Response, Box<Error>>, s
     Err(AppError::new(-200, format!("{:?}", err).as_str()))
 }
 
+pub fn get_batch_resp_result<T: DeserializeOwned>(ret: Result<Response, Box<Error>>) -> Result<Vec<Result<T, AppError>>, AppError> {
+    let data = ret.map_err(|e| AppError::new(-200, format!("{:?}", e).as_str()))?
+        .into_body_str().map_err(|e| AppError::new(-200, format!("{:?}", e).as_str()))?;
+    let json_value: Value = serde_json::from_str(&data).map_err(|e| AppError::new(-200, &e.to_string()))?;
+    let code = json_value.get("retCode").and_then(|v| v.as_i64()).ok_or(AppError::new(-200, "Incorrect response structure"))?;
+    if code != 0 {
+        return Err(AppError::new(-200, json_value.get("retMsg").and_then(|v| v.as_str()).unwrap_or_default()));
+    }
+    let empty = vec![];
+    let items = json_value.pointer("/result/list").and_then(|v| v.as_array()).unwrap_or(&empty);
+    let infos = json_value.pointer("/retExtInfo/list").and_then(|v| v.as_array()).unwrap_or(&empty);
+    let mut ret = vec![];
+    for (i, item) in items.iter().enumerate() {
+        let info = infos.get(i);
+        let item_code = info.and_then(|v| v.get("code")).and_then(|v| v.as_i64()).unwrap_or(0);
+        if item_code != 0 {
+            let msg = info.and_then(|v| v.get("msg")).and_then(|v| v.as_str()).unwrap_or_default();
+            ret.push(Err(AppError::new(-200, &format!("{} {}", item_code, msg))));
+        } else {
+            ret.push(serde_json::from_value::<T>(item.clone()).map_err(|e| AppError::new(-200, &e.to_string())));
+        }
+    }
+    Ok(ret)
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct BybitOrderbook {
@@ -315,6 +340,39 @@ pub struct OrderDetail {
     pub fee_currency: String,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExecutionData {
+    pub id: String,
+    pub topic: String,
+    pub creation_time: i64,
+    pub data: Vec<ExecutionDetail>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExecutionDetail {
+    pub symbol: String,
+    pub order_id: String,
+    pub order_link_id: String,
+    pub side: String,
+    pub exec_id: String,
+    #[serde(deserialize_with = "string_to_f64")]
+    pub exec_price: f64,
+    #[serde(deserialize_with = "string_to_f64")]
+    pub exec_qty: f64,
+    #[serde(deserialize_with = "string_to_f64")]
+    pub exec_fee: f64,
+    pub exec_type: String,
+    #[serde(deserialize_with = "string_to_f64")]
+    pub exec_time: f64,
+    pub is_maker: bool,
+    #[serde(default)]
+    pub closed_size: String,
+    #[serde(default)]
+    pub fee_currency: String,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct WalletData {
@@ -441,6 +499,23 @@ fn string_to_f64<'de, D>(deserializer: D) -> Result<f64, D::Error>
 
 
 
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FundingHistoryQueryResp {
+    pub category: String,
+    pub list: Vec<FundingRateResp>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct FundingRateResp {
+    pub symbol: String,
+    #[serde(deserialize_with = "string_to_f64")]
+    pub funding_rate: f64,
+    #[serde(deserialize_with = "string_to_f64")]
+    pub funding_rate_timestamp: f64,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct KlineQueryResp {
@@ -558,4 +633,40 @@ pub struct PositionInfo {
     pub is_reduce_only: bool,
     pub mmr_sys_updated_time: String,
     pub leverage_sys_updated_time: String,
-}
\ No newline at end of file
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct NewOrderResp {
+    pub order_id: String,
+    pub order_link_id: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OrderQueryResp {
+    pub category: String,
+    pub list: Vec<OrderInfo>,
+    pub next_page_cursor: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OrderInfo {
+    pub symbol: String,
+    pub order_id: String,
+    pub order_link_id: String,
+    pub side: String,
+    pub order_type: String,
+    pub order_status: String,
+    #[serde(deserialize_with = "string_to_f64")]
+    pub price: f64,
+    #[serde(deserialize_with = "string_to_f64")]
+    pub qty: f64,
+    #[serde(deserialize_with = "string_to_f64")]
+    pub cum_exec_qty: f64,
+    pub reduce_only: bool,
+    pub reject_reason: String,
+    #[serde(deserialize_with = "string_to_f64")]
+    pub updated_time: f64,
+}
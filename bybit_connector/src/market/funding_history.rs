@@ -0,0 +1,73 @@
+use crate::{enums::Category, http::{request::Request, Method}};
+use serde::{Serialize, Deserialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GetFundingHistoryRequest {
+    pub category: Category,
+    pub symbol: String,
+    pub start_time: Option<u64>,
+    pub end_time: Option<u64>,
+    pub limit: Option<u64>,
+}
+
+impl GetFundingHistoryRequest {
+    pub fn new(category: Category, symbol: &str) -> Self {
+        Self {
+            category: category.to_owned(),
+            symbol: symbol.to_owned(),
+            start_time: None,
+            end_time: None,
+            limit: None,
+        }
+    }
+
+    pub fn start_time(mut self, start_time: u64) -> Self {
+        self.start_time = Some(start_time);
+        self
+    }
+
+    pub fn end_time(mut self, end_time: u64) -> Self {
+        self.end_time = Some(end_time);
+        self
+    }
+
+    pub fn limit(mut self, limit: u64) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    pub fn get_params(&self) -> Vec<(String, String)> {
+        let mut params = Vec::new();
+        params.push(("category".to_owned(), self.category.to_string()));
+        params.push(("symbol".to_owned(), self.symbol.clone()));
+
+        if let Some(start_time) = &self.start_time {
+            params.push(("startTime".to_owned(), start_time.to_string()));
+        }
+
+        if let Some(end_time) = &self.end_time {
+            params.push(("endTime".to_owned(), end_time.to_string()));
+        }
+
+        if let Some(limit) = self.limit {
+            params.push(("limit".to_owned(), limit.to_string()));
+        }
+        params
+    }
+}
+
+impl From<GetFundingHistoryRequest> for Request {
+    fn from(request: GetFundingHistoryRequest) -> Request {
+        let params = request.get_params();
+        Request {
+            path: "/v5/market/funding/history".to_owned(),
+            method: Method::Get,
+            params,
+            credentials: None,
+            sign: true,
+            body: "".to_string(),
+            recv_window: 5000
+        }
+    }
+}
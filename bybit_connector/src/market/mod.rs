@@ -1,8 +1,10 @@
+use funding_history::GetFundingHistoryRequest;
 use klines::GetKlinesRequest;
 use time::GetServerTimeRequest;
 
 use crate::enums::{Category, KlineInterval};
 
+pub mod funding_history;
 pub mod klines;
 pub mod time;
 
@@ -10,6 +12,10 @@ pub fn klines(category: Category, symbol: &str, interval: KlineInterval) -> GetK
     GetKlinesRequest::new(category, symbol, interval)
 }
 
+pub fn funding_history(category: Category, symbol: &str) -> GetFundingHistoryRequest {
+    GetFundingHistoryRequest::new(category, symbol)
+}
+
 pub fn time() -> GetServerTimeRequest {
     GetServerTimeRequest::new()
 }
\ No newline at end of file
@@ -0,0 +1,73 @@
+use crate::{enums::Category, http::{request::Request, Method}};
+use rust_decimal::Decimal;
+use serde::{Serialize, Deserialize};
+use serde_json::Result;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AmendOrderRequest {
+    pub category: Category,
+    pub symbol: String,
+    pub order_id: Option<String>,
+    pub order_link_id: Option<String>,
+    pub qty: Option<String>,
+    pub price: Option<Decimal>,
+    pub trigger_price: Option<Decimal>,
+}
+
+impl AmendOrderRequest {
+    pub fn new(category: Category, symbol: &str) -> Self {
+        Self {
+            category: category.to_owned(),
+            symbol: symbol.to_owned(),
+            order_id: None,
+            order_link_id: None,
+            qty: None,
+            price: None,
+            trigger_price: None,
+        }
+    }
+
+    pub fn order_id(mut self, order_id: &str) -> Self {
+        self.order_id = Some(order_id.to_owned());
+        self
+    }
+
+    pub fn order_link_id(mut self, order_link_id: &str) -> Self {
+        self.order_link_id = Some(order_link_id.to_owned());
+        self
+    }
+
+    pub fn qty(mut self, qty: &str) -> Self {
+        self.qty = Some(qty.to_owned());
+        self
+    }
+
+    pub fn price(mut self, price: Decimal) -> Self {
+        self.price = Some(price);
+        self
+    }
+
+    pub fn trigger_price(mut self, trigger_price: Decimal) -> Self {
+        self.trigger_price = Some(trigger_price);
+        self
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        serde_json::to_string(&self)
+    }
+}
+
+impl From<AmendOrderRequest> for Request {
+    fn from(request: AmendOrderRequest) -> Request {
+        Request {
+            path: "/v5/order/amend".to_owned(),
+            method: Method::Post,
+            params: vec![],
+            credentials: None,
+            sign: true,
+            body: request.to_json().unwrap(),
+            recv_window: 5000
+        }
+    }
+}
@@ -0,0 +1,51 @@
+use crate::{enums::Category, http::{request::Request, Method}};
+use super::cancel_order::CancelOrderRequest;
+use serde::{Serialize, Deserialize};
+use serde_json::Result;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CancelBatchOrderRequest {
+    pub category: Category,
+    pub request: Vec<CancelOrderRequest>,
+}
+
+impl CancelBatchOrderRequest {
+    pub fn new(category: Category) -> Self {
+        Self {
+            category,
+            request: vec![],
+        }
+    }
+
+    pub fn add(mut self, order: CancelOrderRequest) -> Self {
+        self.request.push(order);
+        self
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        let mut value = serde_json::to_value(self)?;
+        if let Some(orders) = value.get_mut("request").and_then(|v| v.as_array_mut()) {
+            for order in orders.iter_mut() {
+                if let Some(order) = order.as_object_mut() {
+                    order.remove("category");
+                }
+            }
+        }
+        serde_json::to_string(&value)
+    }
+}
+
+impl From<CancelBatchOrderRequest> for Request {
+    fn from(request: CancelBatchOrderRequest) -> Request {
+        Request {
+            path: "/v5/order/cancel-batch".to_owned(),
+            method: Method::Post,
+            params: vec![],
+            credentials: None,
+            sign: true,
+            body: request.to_json().unwrap(),
+            recv_window: 5000
+        }
+    }
+}
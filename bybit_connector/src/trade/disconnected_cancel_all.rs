@@ -0,0 +1,42 @@
+use crate::http::{request::Request, Method};
+use serde::{Serialize, Deserialize};
+use serde_json::Result;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DisconnectedCancelAllRequest {
+    pub product: Option<String>,
+    pub time_window: u32,
+}
+
+impl DisconnectedCancelAllRequest {
+    pub fn new(time_window: u32) -> Self {
+        Self {
+            product: None,
+            time_window,
+        }
+    }
+
+    pub fn product(mut self, product: &str) -> Self {
+        self.product = Some(product.to_owned());
+        self
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        serde_json::to_string(&self)
+    }
+}
+
+impl From<DisconnectedCancelAllRequest> for Request {
+    fn from(request: DisconnectedCancelAllRequest) -> Request {
+        Request {
+            path: "/v5/order/disconnected-cancel-all".to_owned(),
+            method: Method::Post,
+            params: vec![],
+            credentials: None,
+            sign: true,
+            body: request.to_json().unwrap(),
+            recv_window: 5000
+        }
+    }
+}
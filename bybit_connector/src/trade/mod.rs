@@ -1,15 +1,25 @@
+use amend_order::AmendOrderRequest;
+use cancel_batch_order::CancelBatchOrderRequest;
 use cancel_order::CancelOrderRequest;
 use cancel_orders::CancelOrdersRequest;
+use disconnected_cancel_all::DisconnectedCancelAllRequest;
+use open_orders::OpenOrdersRequest;
 use switch_isolated::SetMarginTypeRequest;
 use crate::enums::{Category, OrderType, Side};
+use new_batch_order::NewBatchOrderRequest;
 use new_order::NewOrderRequest;
 use set_leverage::SetLeverageRequest;
 use set_margin_mode::SetMarginModeRequest;
 use switch_mode::SwitchModeRequest;
 
 pub mod new_order;
+pub mod new_batch_order;
+pub mod amend_order;
 pub mod cancel_order;
 pub mod cancel_orders;
+pub mod cancel_batch_order;
+pub mod disconnected_cancel_all;
+pub mod open_orders;
 pub mod set_margin_mode;
 pub mod set_leverage;
 pub mod switch_mode;
@@ -19,6 +29,14 @@ pub fn new_order(category: Category, symbol: &str, side: Side, order_type: Order
     NewOrderRequest::new(category, symbol, side, order_type, qty)
 }
 
+pub fn new_batch_order(category: Category) -> NewBatchOrderRequest {
+    NewBatchOrderRequest::new(category)
+}
+
+pub fn amend_order(category: Category, symbol: &str) -> AmendOrderRequest {
+    AmendOrderRequest::new(category, symbol)
+}
+
 pub fn cancel_order(category: Category, symbol: &str) -> CancelOrderRequest {
     CancelOrderRequest::new(category, symbol)
 }
@@ -27,6 +45,18 @@ pub fn cancel_orders(category: Category, symbol: &str) -> CancelOrdersRequest {
     CancelOrdersRequest::new(category, symbol)
 }
 
+pub fn cancel_batch_order(category: Category) -> CancelBatchOrderRequest {
+    CancelBatchOrderRequest::new(category)
+}
+
+pub fn disconnected_cancel_all(time_window: u32) -> DisconnectedCancelAllRequest {
+    DisconnectedCancelAllRequest::new(time_window)
+}
+
+pub fn open_orders(category: Category) -> OpenOrdersRequest {
+    OpenOrdersRequest::new(category)
+}
+
 pub fn set_leverage(category: Category, symbol: &str, buy_leverage: &str, sell_leverage: &str) -> SetLeverageRequest {
     SetLeverageRequest::new(category, symbol, buy_leverage, sell_leverage)
 }
@@ -0,0 +1,116 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{enums::Category, http::{request::Request, Method}};
+
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OpenOrdersRequest {
+    pub category: Category,
+    pub symbol: Option<String>,
+    pub settle_coin: Option<String>,
+    pub order_id: Option<String>,
+    pub order_link_id: Option<String>,
+    pub open_only: Option<usize>,
+    pub limit: Option<usize>,
+    pub cursor: Option<String>,
+}
+
+impl OpenOrdersRequest {
+    pub fn new(category: Category) -> Self {
+        Self {
+            category: category.to_owned(),
+            symbol: None,
+            settle_coin: None,
+            order_id: None,
+            order_link_id: None,
+            open_only: None,
+            limit: None,
+            cursor: None,
+        }
+    }
+
+    pub fn symbol(mut self, symbol: &str) -> Self {
+        self.symbol = Some(symbol.to_owned());
+        self
+    }
+
+    pub fn settle_coin(mut self, settle_coin: &str) -> Self {
+        self.settle_coin = Some(settle_coin.to_owned());
+        self
+    }
+
+    pub fn order_id(mut self, order_id: &str) -> Self {
+        self.order_id = Some(order_id.to_owned());
+        self
+    }
+
+    pub fn order_link_id(mut self, order_link_id: &str) -> Self {
+        self.order_link_id = Some(order_link_id.to_owned());
+        self
+    }
+
+    pub fn open_only(mut self, open_only: usize) -> Self {
+        self.open_only = Some(open_only);
+        self
+    }
+
+    pub fn limit(mut self, limit: usize) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    pub fn cursor(mut self, cursor: &str) -> Self {
+        self.cursor = Some(cursor.to_owned());
+        self
+    }
+
+    pub fn get_params(&self) -> Vec<(String, String)> {
+        let mut params = Vec::new();
+        params.push(("category".to_owned(), self.category.to_string()));
+
+        if let Some(symbol) = &self.symbol {
+            params.push(("symbol".to_owned(), symbol.clone()));
+        }
+
+        if let Some(settle_coin) = &self.settle_coin {
+            params.push(("settleCoin".to_owned(), settle_coin.clone()));
+        }
+
+        if let Some(order_id) = &self.order_id {
+            params.push(("orderId".to_owned(), order_id.clone()));
+        }
+
+        if let Some(order_link_id) = &self.order_link_id {
+            params.push(("orderLinkId".to_owned(), order_link_id.clone()));
+        }
+
+        if let Some(open_only) = self.open_only {
+            params.push(("openOnly".to_owned(), open_only.to_string()));
+        }
+
+        if let Some(limit) = self.limit {
+            params.push(("limit".to_owned(), limit.to_string()));
+        }
+
+        if let Some(cursor) = &self.cursor {
+            params.push(("cursor".to_owned(), cursor.clone()));
+        }
+        params
+    }
+}
+
+impl From<OpenOrdersRequest> for Request {
+    fn from(request: OpenOrdersRequest) -> Request {
+        let params = request.get_params();
+        Request {
+            path: "/v5/order/realtime".to_owned(),
+            method: Method::Get,
+            params,
+            credentials: None,
+            sign: true,
+            body: "".to_owned(),
+            recv_window: 5000,
+        }
+    }
+}
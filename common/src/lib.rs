@@ -69,16 +69,86 @@ pub mod c {
 }
 
 pub mod msmc {
-    use std::{error::Error, fmt::Debug, sync::{Arc, Mutex}, thread::{self, JoinHandle}, time::Duration};
-    use crossbeam::{channel::{unbounded, Receiver, Sender, TryRecvError}, select};
+    use std::{error::Error, fmt::Debug, sync::{atomic::{AtomicBool, Ordering}, Arc, Condvar, Mutex}, thread::{self, JoinHandle}, time::Duration};
+    use crossbeam::{channel::{unbounded, Receiver, RecvTimeoutError, Sender}, select};
 
     pub type Rx<T> = Receiver<T>;
     pub type Tx<T> = Sender<T>;
+
+    /// Counts the messages sent through subscriptions and not yet handled by the `stream` or `recv_timeout` of their
+    /// receiver, a backtest waits until every consumer is idle before replaying the next market data.
+    pub struct InFlight {
+        enabled: AtomicBool,
+        pending: Mutex<usize>,
+        idle: Condvar,
+    }
+
+    pub static IN_FLIGHT: InFlight = InFlight::new();
+
+    impl Default for InFlight {
+        fn default() -> Self {
+            Self::new()
+        }
+    }
+
+    impl InFlight {
+        pub const fn new() -> Self {
+            InFlight {
+                enabled: AtomicBool::new(false),
+                pending: Mutex::new(0),
+                idle: Condvar::new(),
+            }
+        }
+
+        pub fn enable(&self, enabled: bool) {
+            *self.pending.lock().unwrap() = 0;
+            self.enabled.store(enabled, Ordering::SeqCst);
+            self.idle.notify_all();
+        }
+
+        pub fn is_enabled(&self) -> bool {
+            self.enabled.load(Ordering::SeqCst)
+        }
+
+        pub fn pending(&self) -> usize {
+            *self.pending.lock().unwrap()
+        }
+
+        fn sent(&self, count: usize) {
+            if self.is_enabled() {
+                *self.pending.lock().unwrap() += count;
+            }
+        }
+
+        fn handled(&self, count: usize) {
+            if self.is_enabled() {
+                let mut pending = self.pending.lock().unwrap();
+                *pending = pending.saturating_sub(count);
+                if *pending == 0 {
+                    self.idle.notify_all();
+                }
+            }
+        }
+
+        pub fn wait_idle(&self) {
+            let mut pending = self.pending.lock().unwrap();
+            while self.is_enabled() && *pending > 0 {
+                pending = self.idle.wait(pending).unwrap();
+            }
+        }
+    }
     
+    /// A sender to a child subscription and whether that child is consumed, only the messages to a consumed child are
+    /// counted in flight so that a subscription nobody streams never blocks `wait_idle`.
+    pub type Subscribers<T> = Arc<Mutex<Vec<(Tx<T>, Arc<AtomicBool>)>>>;
+
     pub struct Subscription<T: Clone + Send + Debug> {
         pub name: String,
         pub receiver: Option<Rx<T>>,
-        pub subscribers: Arc<Mutex<Vec<Tx<T>>>>,
+        pub subscribers: Subscribers<T>,
+        parent: Option<Subscribers<T>>,
+        consumed: Arc<AtomicBool>,
+        in_flight: &'static InFlight,
     }
 
     #[derive(Debug)]
@@ -105,12 +175,26 @@ pub mod msmc {
     }
     impl std::error::Error for StreamError {}
 
+    impl <T: Clone + Send + Debug> Debug for Subscription<T> {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            f.debug_struct("Subscription").field("name", &self.name).finish()
+        }
+    }
+
     impl <T : Clone + Send + Debug> Subscription<T> {
         pub fn top() -> Subscription<T> {
+            Self::top_in(&IN_FLIGHT)
+        }
+
+        /// A top subscription whose tree counts its messages in `in_flight` instead of the global `IN_FLIGHT`.
+        pub fn top_in(in_flight: &'static InFlight) -> Subscription<T> {
             Subscription {
                 name : String::from("top"),
                 receiver: None,
                 subscribers: Arc::new(Mutex::new(vec![])),
+                parent: None,
+                consumed: Arc::new(AtomicBool::new(false)),
+                in_flight,
             }
         }
 
@@ -119,21 +203,40 @@ pub mod msmc {
                 name : String::from("unnamed"),
                 receiver: Some(rx),
                 subscribers: Arc::new(Mutex::new(vec![])),
+                parent: None,
+                consumed: Arc::new(AtomicBool::new(false)),
+                in_flight: &IN_FLIGHT,
             }
         }
 
         pub fn publish_to_under(&mut self, under: &mut Subscription<T>) {
             let (tx, rx) = unbounded::<T>();
-            let sub_ref = self.subscribers.clone();
-            sub_ref.lock().unwrap().push(tx);
+            let consumed = Arc::new(AtomicBool::new(false));
+            self.subscribers.lock().unwrap().push((tx, consumed.clone()));
             under.receiver = Some(rx);
+            under.parent = Some(self.subscribers.clone());
+            under.consumed = consumed;
+            under.in_flight = self.in_flight;
         }
 
         pub fn subscribe(&mut self) -> Subscription<T> {
-            let (tx, rx) = unbounded::<T>();
-            let sub_ref = self.subscribers.clone();
-            sub_ref.lock().unwrap().push(tx);
-            Subscription::new(rx)
+            let mut sub = Subscription::top_in(self.in_flight);
+            sub.name = String::from("unnamed");
+            self.publish_to_under(&mut sub);
+            sub
+        }
+
+        /// Marks the receiver as consumed or not under the lock of the parent senders, the messages already queued
+        /// are counted in flight from then on, or no longer.
+        fn set_consumed(parent: &Option<Subscribers<T>>, consumed: &AtomicBool, in_flight: &InFlight, rx: &Rx<T>, flag: bool) {
+            let _guard = parent.as_ref().map(|p| p.lock().unwrap());
+            if consumed.swap(flag, Ordering::SeqCst) != flag {
+                if flag {
+                    in_flight.sent(rx.len());
+                } else {
+                    in_flight.handled(rx.len());
+                }
+            }
         }
 
         pub fn stream<F>(&mut self, mut f: F) -> JoinHandle<()>
@@ -143,28 +246,29 @@ pub mod msmc {
             {
             let rx = self.receiver.take().unwrap();
             let sub_ref = self.subscribers.clone();
+            let parent = self.parent.clone();
+            let consumed = self.consumed.clone();
+            let in_flight = self.in_flight;
+            Self::set_consumed(&parent, &consumed, in_flight, &rx, true);
             let closure = move || {
                 let f = &mut f; 
                 loop {
                     let block_ret ;
-                    let ret = rx.try_recv();
+                    let ret = rx.recv_timeout(Duration::from_millis(100));
                     match ret {
                         Ok(opt) => {
                             block_ret = f(&Some(opt.clone()));
                             if let Ok(continue_flag) = block_ret {
                                 if continue_flag {
-                                    let subscripers = sub_ref.lock().unwrap();
-                                    for sub in subscripers.iter() {
-                                        let _ = sub.send(opt.clone());
-                                    }
+                                    Self::send_to(&sub_ref, in_flight, &opt);
                                 }
                             }
+                            in_flight.handled(1);
                         },
-                        Err(TryRecvError::Empty) => {
+                        Err(RecvTimeoutError::Timeout) => {
                             block_ret = f(&None);
-                            thread::sleep(Duration::from_millis(100));
                         },
-                        Err(TryRecvError::Disconnected) => {
+                        Err(RecvTimeoutError::Disconnected) => {
                             break;
                         },
                     }
@@ -178,26 +282,38 @@ pub mod msmc {
                         _ => {}
                     }
                 }
+                Self::set_consumed(&parent, &consumed, in_flight, &rx, false);
             };
             let handler = thread::spawn(closure);
             handler
         }
 
         pub fn send(&self, data : &T) {
-            let sub_ref = self.subscribers.clone();
-            for s in sub_ref.lock().unwrap().iter() {
-                let _ = s.send(data.clone());
+            Self::send_to(&self.subscribers, self.in_flight, data);
+        }
+
+        fn send_to(subscribers: &Subscribers<T>, in_flight: &InFlight, data: &T) {
+            for (s, consumed) in subscribers.lock().unwrap().iter() {
+                let counted = consumed.load(Ordering::SeqCst);
+                if counted {
+                    in_flight.sent(1);
+                }
+                if s.send(data.clone()).is_err() && counted {
+                    in_flight.handled(1);
+                }
             }
         }
 
         pub fn recv_timeout<F>(&self, secs: u64, f:&mut F) -> Result<T, String>
             where F : FnMut(&T) {
             let rx = self.receiver.as_ref().unwrap();
+            Self::set_consumed(&self.parent, &self.consumed, self.in_flight, rx, true);
             select! {
                 recv(rx) -> ret => {
                     if let Ok(t) = ret {
                         f(&t);
                         self.send(&t);
+                        self.in_flight.handled(1);
                         Ok(t)
                     } else {
                         Err(format!("{:?}", ret.unwrap_err()))
@@ -249,9 +365,10 @@ pub mod thread {
 
 #[cfg(test)]
 mod tests {
-    use std::{thread, time::Duration};
+    use std::{sync::{atomic::{AtomicUsize, Ordering}, Arc}, thread, time::Duration};
 
-    use crate::{error::AppError, msmc::Subscription};
+    use crossbeam::channel::unbounded;
+    use crate::{error::AppError, msmc::{InFlight, StreamError, Subscription}};
 
     #[test]
     fn test_subscription() -> Result<(), AppError> {
@@ -274,4 +391,51 @@ mod tests {
         });
         Ok(())
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_stream_forwards_until_idle() {
+        static COUNTER: InFlight = InFlight::new();
+        COUNTER.enable(true);
+        let mut top = Subscription::<u32>::top_in(&COUNTER);
+        let mut middle = top.subscribe();
+        let mut bottom = middle.subscribe();
+        let _unstreamed = middle.subscribe();
+        let (tx, rx) = unbounded();
+        let _ = middle.stream(|m| Ok(m.map(|v| v % 2 == 0).unwrap_or(true)));
+        let _ = bottom.stream(move |m| {
+            if let Some(v) = m {
+                thread::sleep(Duration::from_millis(10));
+                tx.send(*v).unwrap();
+            }
+            Ok(true)
+        });
+        for i in 0..4 {
+            top.send(&i);
+        }
+        COUNTER.wait_idle();
+        assert_eq!(rx.try_iter().collect::<Vec<u32>>(), vec![0, 2]);
+        assert_eq!(COUNTER.pending(), 0);
+        COUNTER.enable(false);
+    }
+
+    #[test]
+    fn test_stream_polls_when_idle_and_exits() {
+        let mut top = Subscription::<u32>::top();
+        let mut sub = top.subscribe();
+        let polls = Arc::new(AtomicUsize::new(0));
+        let polls_ref = polls.clone();
+        let handler = sub.stream(move |m| {
+            match m {
+                Some(_) => Err(StreamError::Exit),
+                None => {
+                    polls_ref.fetch_add(1, Ordering::SeqCst);
+                    Ok(true)
+                },
+            }
+        });
+        thread::sleep(Duration::from_millis(250));
+        top.send(&1);
+        handler.join().unwrap();
+        assert!(polls.load(Ordering::SeqCst) >= 2);
+    }
+}
@@ -0,0 +1,311 @@
+use std::collections::{HashMap, HashSet};
+use chrono::{Datelike, Duration, NaiveDate, NaiveDateTime, NaiveTime, Timelike, Utc, Weekday};
+use common::error::AppError;
+use serde::{Deserialize, Serialize};
+
+/// The exchanges run on China Standard Time.
+const EXCHANGE_UTC_OFFSET_HOURS: i64 = 8;
+/// Sessions starting at or after this hour are night sessions and belong to the next trading day.
+const NIGHT_START_HOUR: u32 = 18;
+/// Times before this hour are the part of the previous night session after midnight.
+const NIGHT_END_HOUR: u32 = 6;
+const DEFAULT_KEY: &str = "DEFAULT";
+
+const DAY: &[(&str, &str)] = &[("09:00", "10:15"), ("10:30", "11:30"), ("13:30", "15:00")];
+const CFFEX_DAY: &[(&str, &str)] = &[("09:30", "11:30"), ("13:00", "15:00")];
+const CFFEX_BOND_DAY: &[(&str, &str)] = &[("09:30", "11:30"), ("13:00", "15:15")];
+const NIGHT_0230: (&str, &str) = ("21:00", "02:30");
+const NIGHT_0100: (&str, &str) = ("21:00", "01:00");
+const NIGHT_2300: (&str, &str) = ("21:00", "23:00");
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CalendarConfig {
+    /// Session templates keyed by exchange ("DCE") or exchange and product ("SHFE.au"), e.g. [["21:00", "23:00"], ["09:00", "10:15"]].
+    /// They replace the built-in template of the same key.
+    pub sessions: Option<HashMap<String, Vec<(String, String)>>>,
+    /// The exchange holidays as "YYYYMMDD", weekends are always closed.
+    pub holidays: Option<Vec<String>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TradingSession {
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+}
+
+impl TradingSession {
+    fn parse(start: &str, end: &str) -> Result<Self, AppError> {
+        let parse = |s: &str| NaiveTime::parse_from_str(s, "%H:%M")
+            .or_else(|_| NaiveTime::parse_from_str(s, "%H:%M:%S"))
+            .map_err(|_| AppError::new(-200, &format!("Invalid session time {}, expected HH:MM", s)));
+        Ok(TradingSession { start: parse(start)?, end: parse(end)? })
+    }
+
+    pub fn is_night(&self) -> bool {
+        self.start.hour() >= NIGHT_START_HOUR
+    }
+}
+
+/// Trading sessions, holidays and trading days of the exchanges served by CTP.
+#[derive(Debug, Clone)]
+pub struct TradingCalendar {
+    sessions: HashMap<String, Vec<TradingSession>>,
+    holidays: HashSet<NaiveDate>,
+}
+
+fn default_templates() -> HashMap<String, Vec<(&'static str, &'static str)>> {
+    let mut templates = HashMap::new();
+    let with_night = |night: (&'static str, &'static str)| {
+        let mut sessions = vec![night];
+        sessions.extend_from_slice(DAY);
+        sessions
+    };
+    templates.insert(DEFAULT_KEY.to_string(), with_night(NIGHT_0230));
+    for exchange in ["SHFE", "INE", "GFEX"] {
+        templates.insert(exchange.to_string(), DAY.to_vec());
+    }
+    for exchange in ["DCE", "CZCE"] {
+        templates.insert(exchange.to_string(), with_night(NIGHT_2300));
+    }
+    templates.insert("CFFEX".to_string(), CFFEX_DAY.to_vec());
+    for product in ["T", "TF", "TS", "TL"] {
+        templates.insert(format!("CFFEX.{}", product), CFFEX_BOND_DAY.to_vec());
+    }
+    for product in ["SHFE.au", "SHFE.ag", "INE.sc"] {
+        templates.insert(product.to_string(), with_night(NIGHT_0230));
+    }
+    for product in ["SHFE.cu", "SHFE.al", "SHFE.zn", "SHFE.pb", "SHFE.ni", "SHFE.sn", "SHFE.ss", "SHFE.ao", "INE.bc"] {
+        templates.insert(product.to_string(), with_night(NIGHT_0100));
+    }
+    for product in ["SHFE.rb", "SHFE.hc", "SHFE.bu", "SHFE.ru", "SHFE.fu", "SHFE.sp", "SHFE.br", "INE.lu", "INE.nr"] {
+        templates.insert(product.to_string(), with_night(NIGHT_2300));
+    }
+    templates
+}
+
+/// The product of an instrument, e.g. "au" of "au2412".
+pub fn product_of(symbol: &str) -> &str {
+    let end = symbol.find(|c: char| !c.is_ascii_alphabetic()).unwrap_or(symbol.len());
+    &symbol[..end]
+}
+
+/// The current wall clock time of the exchanges.
+pub fn exchange_now() -> NaiveDateTime {
+    Utc::now().naive_utc() + Duration::hours(EXCHANGE_UTC_OFFSET_HOURS)
+}
+
+/// The milliseconds timestamp of an exchange wall clock time read as UTC, the convention of the CTP klines, the recorded ticks and their replay.
+pub fn exchange_timestamp(time: NaiveDateTime) -> u64 {
+    time.and_utc().timestamp_millis() as u64
+}
+
+fn is_night_time(time: NaiveTime) -> bool {
+    time.hour() >= NIGHT_START_HOUR || time.hour() < NIGHT_END_HOUR
+}
+
+/// The natural exchange time of a CTP update time. During the night session DCE reports the trading day as the
+/// action day, so the date of a night update is taken from the exchange clock `now` instead.
+pub fn natural_time(action_day: &str, update_time: &str, millis: i32, now: NaiveDateTime) -> Option<NaiveDateTime> {
+    let time = NaiveTime::parse_from_str(update_time, "%H:%M:%S").ok()? + Duration::milliseconds(millis as i64);
+    if !is_night_time(time) {
+        if let Ok(date) = NaiveDate::parse_from_str(action_day, "%Y%m%d") {
+            return Some(date.and_time(time));
+        }
+    }
+    let offset = time - now.time();
+    let date = if offset > Duration::hours(12) {
+        now.date() - Duration::days(1)
+    } else if offset < Duration::hours(-12) {
+        now.date() + Duration::days(1)
+    } else {
+        now.date()
+    };
+    Some(date.and_time(time))
+}
+
+impl TradingCalendar {
+    pub fn new(config: &CalendarConfig) -> Result<Self, AppError> {
+        let mut sessions = HashMap::new();
+        for (key, template) in default_templates() {
+            let parsed = template.iter().map(|(start, end)| TradingSession::parse(start, end)).collect::<Result<Vec<_>, _>>()?;
+            sessions.insert(key.to_uppercase(), parsed);
+        }
+        for (key, template) in config.sessions.clone().unwrap_or_default() {
+            let parsed = template.iter().map(|(start, end)| TradingSession::parse(start, end)).collect::<Result<Vec<_>, _>>()?;
+            sessions.insert(key.to_uppercase(), parsed);
+        }
+        let mut holidays = HashSet::new();
+        for holiday in config.holidays.clone().unwrap_or_default() {
+            let date = NaiveDate::parse_from_str(&holiday, "%Y%m%d").map_err(|_| AppError::new(-200, &format!("Invalid holiday {}, expected YYYYMMDD", holiday)))?;
+            holidays.insert(date);
+        }
+        Ok(TradingCalendar { sessions, holidays })
+    }
+
+    pub fn sessions(&self, symbol: &str, exchange_id: &str) -> &[TradingSession] {
+        let product_key = format!("{}.{}", exchange_id, product_of(symbol)).to_uppercase();
+        self.sessions.get(&product_key)
+            .or_else(|| self.sessions.get(&exchange_id.to_uppercase()))
+            .or_else(|| self.sessions.get(DEFAULT_KEY))
+            .map(|s| s.as_slice())
+            .unwrap_or_default()
+    }
+
+    pub fn is_trading_day(&self, date: NaiveDate) -> bool {
+        !matches!(date.weekday(), Weekday::Sat | Weekday::Sun) && !self.holidays.contains(&date)
+    }
+
+    pub fn next_trading_day(&self, date: NaiveDate) -> NaiveDate {
+        let mut next = date + Duration::days(1);
+        while !self.is_trading_day(next) {
+            next += Duration::days(1);
+        }
+        next
+    }
+
+    /// The trading day of an exchange time, the night session belongs to the next trading day.
+    pub fn trading_day(&self, time: NaiveDateTime) -> NaiveDate {
+        let date = time.date();
+        if time.hour() >= NIGHT_START_HOUR {
+            self.next_trading_day(date)
+        } else if time.hour() < NIGHT_END_HOUR {
+            self.next_trading_day(date - Duration::days(1))
+        } else if self.is_trading_day(date) {
+            date
+        } else {
+            self.next_trading_day(date)
+        }
+    }
+
+    /// The trading day reported by CTP unless it is impossible for the time, CZCE reports the natural day during the night session.
+    pub fn resolve_trading_day(&self, reported: &str, time: NaiveDateTime) -> NaiveDate {
+        match NaiveDate::parse_from_str(reported, "%Y%m%d") {
+            Ok(day) if !is_night_time(time.time()) || day > time.date() => day,
+            _ => self.trading_day(time),
+        }
+    }
+
+    /// There is no night session on the evening before a holiday, only a plain weekend may follow it.
+    pub fn has_night_session(&self, date: NaiveDate) -> bool {
+        if !self.is_trading_day(date) {
+            return false;
+        }
+        let next = self.next_trading_day(date);
+        let mut day = date + Duration::days(1);
+        while day < next {
+            if self.holidays.contains(&day) {
+                return false;
+            }
+            day += Duration::days(1);
+        }
+        true
+    }
+
+    fn session_range(&self, session: &TradingSession, time: NaiveDateTime) -> Option<(NaiveDateTime, NaiveDateTime)> {
+        let date = if session.start > session.end && time.time() <= session.end {
+            time.date() - Duration::days(1)
+        } else {
+            time.date()
+        };
+        let start = date.and_time(session.start);
+        let end = if session.start > session.end { (date + Duration::days(1)).and_time(session.end) } else { date.and_time(session.end) };
+        if time < start || time > end {
+            return None;
+        }
+        let open = if session.is_night() { self.has_night_session(date) } else { self.is_trading_day(date) };
+        if open { Some((start, end)) } else { None }
+    }
+
+    /// The start and end of the session of an instrument that contains the exchange time, both inclusive.
+    pub fn current_session(&self, symbol: &str, exchange_id: &str, time: NaiveDateTime) -> Option<(NaiveDateTime, NaiveDateTime)> {
+        self.sessions(symbol, exchange_id).iter().find_map(|s| self.session_range(s, time))
+    }
+
+    pub fn is_trading(&self, symbol: &str, exchange_id: &str, time: NaiveDateTime) -> bool {
+        self.current_session(symbol, exchange_id, time).is_some()
+    }
+
+    /// Whether any exchange or product is in a trading session.
+    pub fn is_any_trading(&self, time: NaiveDateTime) -> bool {
+        self.sessions.values().flatten().any(|s| self.session_range(s, time).is_some())
+    }
+
+    /// The close of the last day session of an instrument on its trading day.
+    pub fn day_close(&self, symbol: &str, exchange_id: &str, trading_day: NaiveDate) -> Option<NaiveDateTime> {
+        self.sessions(symbol, exchange_id).iter().filter(|s| !s.is_night()).map(|s| trading_day.and_time(s.end)).max()
+    }
+}
+
+impl Default for TradingCalendar {
+    fn default() -> Self {
+        TradingCalendar::new(&CalendarConfig::default()).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn time(s: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S").unwrap()
+    }
+
+    #[test]
+    fn test_sessions_and_trading_day() {
+        let mut sessions = HashMap::new();
+        sessions.insert("DCE.jd".to_string(), vec![("09:00".to_string(), "10:15".to_string()), ("10:30".to_string(), "11:30".to_string()), ("13:30".to_string(), "15:00".to_string())]);
+        let calendar = TradingCalendar::new(&CalendarConfig { sessions: Some(sessions), holidays: Some(vec!["20241001".to_string()]) }).unwrap();
+
+        // Gold trades until 02:30 on Saturday and DCE until 23:00.
+        assert!(calendar.is_trading("au2412", "SHFE", time("2024-09-21 02:00:00")));
+        assert!(!calendar.is_trading("m2501", "DCE", time("2024-09-20 23:30:00")));
+        assert!(calendar.is_trading("m2501", "DCE", time("2024-09-20 22:59:00")));
+        assert!(!calendar.is_trading("jd2501", "DCE", time("2024-09-20 21:30:00")));
+        assert!(!calendar.is_trading("rb2501", "SHFE", time("2024-09-23 10:20:00")));
+        assert!(calendar.is_trading("IF2410", "CFFEX", time("2024-09-23 13:10:00")));
+
+        assert_eq!(calendar.day_close("T2412", "CFFEX", NaiveDate::from_ymd_opt(2024, 10, 2).unwrap()), Some(time("2024-10-02 15:15:00")));
+        assert_eq!(calendar.current_session("cu2411", "SHFE", time("2024-09-23 00:30:00")), None);
+
+        // DCE reports the trading day as the action day at night, CZCE the natural day as the trading day.
+        let now = time("2024-09-21 00:00:02");
+        let dce = natural_time("20240923", "23:59:59", 500, now).unwrap();
+        assert_eq!(dce, time("2024-09-20 23:59:59") + Duration::milliseconds(500));
+        assert_eq!(calendar.resolve_trading_day("20240923", dce), NaiveDate::from_ymd_opt(2024, 9, 23).unwrap());
+        let czce = natural_time("20240921", "00:00:01", 0, now).unwrap();
+        assert_eq!(calendar.resolve_trading_day("20240921", czce), NaiveDate::from_ymd_opt(2024, 9, 23).unwrap());
+        assert_eq!(natural_time("20240923", "09:00:00", 0, now), Some(time("2024-09-23 09:00:00")));
+        assert_eq!(exchange_timestamp(time("2024-09-23 09:00:00")), 1727082000000);
+        assert_eq!(calendar.current_session("cu2411", "SHFE", time("2024-09-24 00:30:00")), Some((time("2024-09-23 21:00:00"), time("2024-09-24 01:00:00"))));
+    }
+
+    #[test]
+    fn test_night_session_rolls_to_next_trading_day() {
+        let calendar = TradingCalendar::default();
+        let monday = NaiveDate::from_ymd_opt(2024, 9, 23).unwrap();
+        assert_eq!(calendar.trading_day(time("2024-09-20 14:00:00")), NaiveDate::from_ymd_opt(2024, 9, 20).unwrap());
+        // Friday night and the small hours of Saturday belong to Monday.
+        assert_eq!(calendar.trading_day(time("2024-09-20 21:00:00")), monday);
+        assert_eq!(calendar.trading_day(time("2024-09-21 01:00:00")), monday);
+        assert_eq!(calendar.trading_day(time("2024-09-22 12:00:00")), monday);
+        assert_eq!(calendar.trading_day(time("2024-09-23 21:00:00")), NaiveDate::from_ymd_opt(2024, 9, 24).unwrap());
+        assert!(calendar.has_night_session(NaiveDate::from_ymd_opt(2024, 9, 20).unwrap()));
+        assert!(!calendar.has_night_session(NaiveDate::from_ymd_opt(2024, 9, 21).unwrap()));
+    }
+
+    #[test]
+    fn test_no_night_session_before_holiday() {
+        let calendar = TradingCalendar::new(&CalendarConfig { sessions: None, holidays: Some(vec!["20241001".to_string(), "20241002".to_string()]) }).unwrap();
+        let eve = NaiveDate::from_ymd_opt(2024, 9, 30).unwrap();
+        assert!(calendar.is_trading_day(eve));
+        assert!(!calendar.has_night_session(eve));
+        assert!(calendar.is_trading("au2412", "SHFE", time("2024-09-30 14:00:00")));
+        assert!(!calendar.is_trading("au2412", "SHFE", time("2024-09-30 21:30:00")));
+        assert!(!calendar.is_trading("au2412", "SHFE", time("2024-10-01 01:00:00")));
+        assert!(!calendar.is_trading("au2412", "SHFE", time("2024-10-01 10:00:00")));
+        // The night session of the holiday eve is skipped, trading resumes with the day session after the holidays.
+        assert_eq!(calendar.trading_day(time("2024-09-30 21:30:00")), NaiveDate::from_ymd_opt(2024, 10, 3).unwrap());
+        assert!(calendar.is_trading("au2412", "SHFE", time("2024-10-03 09:30:00")));
+        assert!(calendar.has_night_session(NaiveDate::from_ymd_opt(2024, 10, 3).unwrap()));
+    }
+}
@@ -222,3 +222,8 @@ fn reverse_hashmap(map: &HashMap<String, u8>) -> HashMap<u8, String> {
     }
     reversed_map
 }
+
+/// Whether an order of `status` is still working and can be canceled.
+pub fn is_working_status(status: &str) -> bool {
+    [ORDER_STATUS_UNKNOWN.code, ORDER_STATUS_NO_TRADED_QUEUEING.code, ORDER_STATUS_PART_TRADED_QUEUEING.code].contains(&status)
+}
@@ -5,15 +5,17 @@ use market::market_server::{MarketData, Tick};
 use libctp_sys::*;
 use common::{c::*, msmc::Subscription};
 use log::*;
-use chrono::NaiveDateTime;
+use std::sync::Arc;
+use crate::ctp_calendar::{exchange_now, exchange_timestamp, natural_time, TradingCalendar};
 
 pub struct Spi {
     subscription: Subscription<MarketData>,
+    calendar: Arc<TradingCalendar>,
 }
 
 impl Spi {
-    pub fn new(subscription: Subscription<MarketData>) -> Self {
-        Spi {subscription}
+    pub fn new(subscription: Subscription<MarketData>, calendar: Arc<TradingCalendar>) -> Self {
+        Spi {subscription, calendar}
     }
 }
 
@@ -23,8 +25,9 @@ impl Rust_CThostFtdcMdSpi_Trait for Spi {
         self.subscription.send(&MarketData::Connected);
     }
 
-    fn on_front_disconnected(&mut self, _nReason: ::std::os::raw::c_int) {
+    fn on_front_disconnected(&mut self, nReason: ::std::os::raw::c_int) {
         debug!("Cpi disconnected");
+        self.subscription.send(&MarketData::Disconnected(nReason));
     }
 
     fn on_heart_beat_warning(&mut self, _nTimeLapse: ::std::os::raw::c_int) {
@@ -61,13 +64,15 @@ impl Rust_CThostFtdcMdSpi_Trait for Spi {
             warn!("Cpi got empty data");
         } else {
             let pDepthMarketData = unsafe { &mut *pDepthMarketData };
-            let tick = _convert_tick(pDepthMarketData);
-            self.subscription.send(&MarketData::Tick(tick));
+            match _convert_tick(pDepthMarketData, &self.calendar) {
+                Some(tick) => self.subscription.send(&MarketData::Tick(tick)),
+                None => warn!("Cpi got a tick with invalid update time {}", c_char_to_string(pDepthMarketData.UpdateTime.as_ptr())),
+            }
         }
     }
 }
 
-fn _convert_tick(market_data: &CThostFtdcDepthMarketDataField) -> Tick {
+fn _convert_tick(market_data: &CThostFtdcDepthMarketDataField, calendar: &TradingCalendar) -> Option<Tick> {
     let bids = vec![
         vec![market_data.BidPrice1, market_data.BidVolume1 as f64], 
         vec![market_data.BidPrice2, market_data.BidVolume2 as f64],
@@ -82,28 +87,31 @@ fn _convert_tick(market_data: &CThostFtdcDepthMarketDataField) -> Tick {
         vec![market_data.AskPrice4, market_data.AskVolume4 as f64],
         vec![market_data.AskPrice5, market_data.AskVolume5 as f64],
         ];
-    let datetime = format!("{} {}", _format_date(&c_char_to_string(market_data.ActionDay.as_ptr())), c_char_to_string(market_data.UpdateTime.as_ptr()));
-    let timestamp = NaiveDateTime::parse_from_str(&datetime, "%Y-%m-%d %H:%M:%S").unwrap().and_utc().timestamp() as u64 * 1000;
+    let time = natural_time(
+        &c_char_to_string(market_data.ActionDay.as_ptr()),
+        &c_char_to_string(market_data.UpdateTime.as_ptr()),
+        market_data.UpdateMillisec,
+        exchange_now(),
+    )?;
+    let trading_day = calendar.resolve_trading_day(&c_char_to_string(market_data.TradingDay.as_ptr()), time);
 
     let tick = Tick {
         symbol: c_char_to_string(market_data.InstrumentID.as_ptr()),
-        datetime: datetime,
+        datetime: time.format("%Y-%m-%d %H:%M:%S").to_string(),
         open: market_data.OpenPrice,
         high: market_data.HighestPrice,
         low: market_data.LowestPrice,
         close: market_data.LastPrice,
         volume: market_data.Volume as f64,
         turnover: market_data.Turnover,
-        timestamp: timestamp,
+        timestamp: exchange_timestamp(time),
         bids: bids,
         asks: asks,
+        trading_day: trading_day.format("%Y%m%d").to_string(),
+        pre_settlement: market_data.PreSettlementPrice,
+        open_interest: market_data.OpenInterest,
+        upper_limit: market_data.UpperLimitPrice,
+        lower_limit: market_data.LowerLimitPrice,
     };
-    tick
-}
-
-fn _format_date(date_str: &str) -> String {
-    let year = &date_str[0..4];
-    let month = &date_str[4..6];
-    let day = &date_str[6..8];
-    format!("{}-{}-{}", year, month, day)
+    Some(tick)
 }
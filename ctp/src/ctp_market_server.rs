@@ -6,6 +6,9 @@ use market::kline::KLineCombiner;
 use market::sim_market_server::KLineLoader;
 use ureq::{Agent, AgentBuilder, Response};
 use crate::model::{self, CtpConfig, Symbol};
+use crate::ctp_calendar::{exchange_now, TradingCalendar};
+use chrono::{NaiveDate, NaiveDateTime};
+use log::*;
 
 use super::ctp_market_cpi::Spi;
 use market::market_server::{KLine, MarketData, MarketServer, Tick};
@@ -13,8 +16,8 @@ use libctp_sys::*;
 use std::ffi::{CStr, CString};
 use std::os::raw::*;
 use std::sync::atomic::{AtomicUsize, Ordering};
-use std::sync::{Arc, Mutex};
-use std::thread::JoinHandle;
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread::{self, JoinHandle};
 use common::msmc::*;
 use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, HashSet};
@@ -36,6 +39,7 @@ pub struct MDApi {
     api: Rust_CThostFtdcMdApi,
     spi: Option<SafePointer<Rust_CThostFtdcMdSpi>>,
     config: ApiConfig,
+    calendar: Arc<TradingCalendar>,
 }
 
 
@@ -45,7 +49,7 @@ impl MDApi {
         cs.to_string_lossy().into()
     }
 
-    pub fn new(config: ApiConfig) -> Self {
+    pub fn new(config: ApiConfig, calendar: Arc<TradingCalendar>) -> Self {
         let cs = std::ffi::CString::new(config.flow_path.as_bytes()).unwrap();
         let api = unsafe {
             Rust_CThostFtdcMdApi::new(CThostFtdcMdApi::CreateFtdcMdApi(
@@ -58,6 +62,7 @@ impl MDApi {
             api,
             spi: None,
             config: config.clone(),
+            calendar,
         }
     }
 
@@ -65,7 +70,7 @@ impl MDApi {
         let mut top = Subscription::top();
         let outer_subscription = top.subscribe();
 
-        self.register(Spi::new(top));
+        self.register(Spi::new(top, self.calendar.clone()));
 
         for addr in &self.config.front_addr {
             let cs = CString::new(addr.as_bytes()).unwrap();
@@ -223,6 +228,7 @@ impl Drop for MDApi {
 #[derive(Clone, Debug)]
 pub struct MarketTopic {
     pub symbol: String,
+    pub exchange_id: String,
     pub interval: String,
 }
 
@@ -279,25 +285,107 @@ impl KLineLoader for CtpKlineLoader {
 }
 
 pub struct CtpMarketServer {
-    mapi: Option<MDApi>,
-    topics: Vec<MarketTopic>,
+    mapi: Arc<Mutex<Option<MDApi>>>,
+    topics: Arc<RwLock<Vec<MarketTopic>>>,
     config: CtpConfig,
+    calendar: Arc<TradingCalendar>,
     handler: Option<JoinHandle<()>>,
     start_ticket: Arc<AtomicUsize>,
     subscription: Arc<Mutex<Subscription<MarketData>>>,
 }
 
+/// The kline combiners of the subscribed topics and the trading sessions they are combining.
+#[derive(Default)]
+struct KLineSessions {
+    combiners: HashMap<(String, String), KLineCombiner>,
+    session_ends: HashMap<String, NaiveDateTime>,
+    closed_until: HashMap<String, NaiveDateTime>,
+}
+
+impl KLineSessions {
+    fn close_session(&mut self, symbol: &str, end: NaiveDateTime) -> Vec<KLine> {
+        self.session_ends.remove(symbol);
+        self.closed_until.insert(symbol.to_string(), end);
+        self.combiners.iter_mut().filter(|((s, _), _)| s == symbol).filter_map(|(_, combiner)| combiner.close(true)).collect()
+    }
+}
+
 impl CtpMarketServer {
-    pub fn new(config: CtpConfig) -> Self {
+    pub fn new(config: CtpConfig, calendar: Arc<TradingCalendar>) -> Self {
         CtpMarketServer {
-            mapi: None,
+            mapi: Arc::new(Mutex::new(None)),
             config,
-            topics: Vec::new(),
+            calendar,
+            topics: Arc::new(RwLock::new(Vec::new())),
             handler: None,
             start_ticket: Arc::new(AtomicUsize::new(0)),
             subscription: Arc::new(Mutex::new(Subscription::top())),
         }
     }
+
+    fn subscribe_topics(mapi: &mut MDApi, topics: &[MarketTopic]) -> Result<(), String> {
+        let mut tick_set = HashSet::new();
+        for topic in topics.iter() {
+            if !tick_set.contains(topic.symbol.as_str()) {
+                mapi.subscribe_market_data(&[topic.symbol.as_str()], false)?;
+                tick_set.insert(topic.symbol.to_string());
+            }
+        }
+        Ok(())
+    }
+
+    /// Closes the klines at the end of each session and announces the end of the trading day.
+    fn watch_sessions(&self, start_ticket: usize, sessions: Arc<Mutex<KLineSessions>>) {
+        let start_ticket_ref = self.start_ticket.clone();
+        let subscription_ref = self.subscription.clone();
+        let topics_ref = self.topics.clone();
+        let calendar = self.calendar.clone();
+        let started = exchange_now();
+        thread::spawn(move || {
+            let mut last_ended: Option<NaiveDate> = None;
+            loop {
+                if start_ticket != start_ticket_ref.load(Ordering::SeqCst) - 1 {
+                    break;
+                }
+                let now = exchange_now();
+                let delay = chrono::Duration::seconds(SESSION_CLOSE_DELAY_SECS);
+                let mut closed = vec![];
+                {
+                    let mut sessions = sessions.lock().unwrap();
+                    let ended: Vec<(String, NaiveDateTime)> = sessions.session_ends.iter().filter(|(_, end)| now > **end + delay).map(|(s, end)| (s.clone(), *end)).collect();
+                    for (symbol, end) in ended {
+                        closed.extend(sessions.close_session(&symbol, end));
+                    }
+                }
+                let subscription = subscription_ref.lock().unwrap();
+                for kline in closed {
+                    subscription.send(&MarketData::Kline(kline));
+                }
+
+                let ended = ended_trading_day(&calendar, &topics_ref.read().unwrap(), now, started, last_ended);
+                if let Some(day) = ended {
+                    info!("The trading day {} is ended", day);
+                    subscription.send(&MarketData::TradeDayEnded(day.format("%Y%m%d").to_string()));
+                    last_ended = Some(day);
+                }
+                drop(subscription);
+                thread::sleep(std::time::Duration::from_secs(1));
+            }
+        });
+    }
+}
+
+const SESSION_CLOSE_DELAY_SECS: i64 = 5;
+
+/// The trading day that ends at `now` once the last day session of the subscribed instruments is closed, if it is not announced yet.
+/// A day closed before the server started is not announced.
+fn ended_trading_day(calendar: &TradingCalendar, topics: &[MarketTopic], now: NaiveDateTime, started: NaiveDateTime, last_ended: Option<NaiveDate>) -> Option<NaiveDate> {
+    let today = now.date();
+    if !calendar.is_trading_day(today) || last_ended == Some(today) {
+        return None;
+    }
+    let close = topics.iter().filter_map(|t| calendar.day_close(&t.symbol, &t.exchange_id, today)).max()?;
+    if now > close + chrono::Duration::seconds(SESSION_CLOSE_DELAY_SECS) && started < close { Some(today) } else { None }
 }
 
 impl MarketServer for CtpMarketServer {
@@ -313,25 +401,21 @@ impl MarketServer for CtpMarketServer {
             flow_path: "".into(),
             front_addr: vec![format!("tcp://{}", self.config.nm_addr.clone())],
             ..Default::default()
-        });
+        }, self.calendar.clone());
         let mut subscription = mapi.start().unwrap();
         subscription.name = "CTP MARKETSERVER".to_string();
         let outer_subscription = subscription.subscribe();
         self.subscription = Arc::new(Mutex::new(subscription));
-        
-        let mut tick_set = HashSet::new();
-        for topic in self.topics.iter() {
-            if !tick_set.contains(topic.symbol.as_str()) {
-                mapi.subscribe_market_data(&[topic.symbol.as_str()], false).unwrap();
-                tick_set.insert(topic.symbol.to_string());
-            }
-        }
-        self.mapi = Some(mapi);
+
+        Self::subscribe_topics(&mut mapi, &self.topics.read().unwrap()).map_err(|e| AppError::new(-200, &e))?;
+        *self.mapi.lock().unwrap() = Some(mapi);
 
         let topics = self.topics.clone();
+        let mapi_ref = self.mapi.clone();
+        let calendar = self.calendar.clone();
         let mut last_ticks = HashMap::<String, Tick>::new();
-        let mut combiner_map:HashMap<String, KLineCombiner> = HashMap::new();
-
+        let sessions = Arc::new(Mutex::new(KLineSessions::default()));
+        self.watch_sessions(start_ticket, sessions.clone());
 
         let subscription_ref = self.subscription.clone();
         let handler = self.subscription.lock().unwrap().stream(move |event| {
@@ -354,9 +438,30 @@ impl MarketServer for CtpMarketServer {
                             }
                             last_ticks.insert(t.symbol.to_string(), t.clone());
 
+                            let topics = topics.read().unwrap();
+                            let exchange_id = match topics.iter().find(|topic| topic.symbol == t.symbol) {
+                                Some(topic) => topic.exchange_id.clone(),
+                                None => return Ok(false),
+                            };
+                            let Ok(tick_time) = NaiveDateTime::parse_from_str(&t.datetime, "%Y-%m-%d %H:%M:%S") else {
+                                return Ok(false);
+                            };
+                            // The opening auction a minute before the session is combined into the first kline.
+                            let session = calendar.current_session(&t.symbol, &exchange_id, tick_time)
+                                .or_else(|| calendar.current_session(&t.symbol, &exchange_id, tick_time + chrono::Duration::minutes(1)));
+                            let mut sessions = sessions.lock().unwrap();
+                            let end = match session {
+                                Some((_, end)) if sessions.closed_until.get(&t.symbol).is_none_or(|closed| *closed < end) => end,
+                                _ => {
+                                    debug!("The tick of {} at {} is out of the trading session", t.symbol, t.datetime);
+                                    return Ok(false);
+                                },
+                            };
+                            sessions.session_ends.insert(t.symbol.clone(), end);
+
                             for topic in topics.iter() {
                                 if topic.symbol == t.symbol && topic.interval != "" {
-                                    let combiner = combiner_map.entry(format!("{}_{}", topic.symbol, topic.interval)).or_insert(KLineCombiner::new(topic.interval.as_str(), 100, Some(21)));
+                                    let combiner = sessions.combiners.entry((topic.symbol.clone(), topic.interval.clone())).or_insert(KLineCombiner::new(topic.interval.as_str(), 100, Some(21)));
                                     let kline = KLine {
                                         symbol: t.symbol.clone(),
                                         datetime: t.datetime.clone(),
@@ -379,6 +484,24 @@ impl MarketServer for CtpMarketServer {
                             }
                             return Ok(false);
                         },
+                        MarketData::Connected => {
+                            info!("The market front is reconnected, logging in again");
+                            if let Some(mapi) = mapi_ref.lock().unwrap().as_mut() {
+                                if let Err(e) = mapi.req_user_login() {
+                                    error!("The market server fails to log in again: {}", e);
+                                }
+                            }
+                        },
+                        MarketData::UserLogin => {
+                            if let Some(mapi) = mapi_ref.lock().unwrap().as_mut() {
+                                if let Err(e) = Self::subscribe_topics(mapi, &topics.read().unwrap()) {
+                                    error!("The market server fails to subscribe again: {}", e);
+                                }
+                            }
+                        },
+                        MarketData::Disconnected(reason) => {
+                            warn!("The market front is disconnected, reason {}", reason);
+                        },
                         _ => {
                         },
                     }
@@ -408,7 +531,8 @@ impl MarketServer for CtpMarketServer {
 
     fn subscribe_tick(&mut self, symbol: Symbol) -> Result<(), AppError> {
         let mut found = false;
-        for topic in self.topics.iter() {
+        let mut topics = self.topics.write().unwrap();
+        for topic in topics.iter() {
             if topic.symbol == symbol.symbol {
                 found = true;
                 break;
@@ -417,16 +541,18 @@ impl MarketServer for CtpMarketServer {
         if !found {
             let topic = MarketTopic {
                 symbol: symbol.symbol.clone(),
+                exchange_id: symbol.exchange_id.clone(),
                 interval: "".to_string(),
             };
-            self.topics.push(topic);
+            topics.push(topic);
         }
         Ok(())
     }
 
     fn subscribe_kline(&mut self, symbol: Symbol, interval: &str) -> Result<(), AppError> {
         let mut found = false;
-        for topic in self.topics.iter() {
+        let mut topics = self.topics.write().unwrap();
+        for topic in topics.iter() {
             if topic.symbol == symbol.symbol && topic.interval == interval {
                 
                 found = true;
@@ -436,10 +562,39 @@ impl MarketServer for CtpMarketServer {
         if !found {
             let topic = MarketTopic {
                 symbol: symbol.symbol.clone(),
+                exchange_id: symbol.exchange_id.clone(),
                 interval: interval.to_string(),
             };
-            self.topics.push(topic);
+            topics.push(topic);
         }
         Ok(())
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ctp_calendar::CalendarConfig;
+
+    fn time(s: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S").unwrap()
+    }
+
+    #[test]
+    fn test_trade_day_ended() {
+        let calendar = TradingCalendar::new(&CalendarConfig { sessions: None, holidays: Some(vec!["20241001".to_string()]) }).unwrap();
+        let topics = vec![
+            MarketTopic { symbol: "rb2501".to_string(), exchange_id: "SHFE".to_string(), interval: "1m".to_string() },
+            MarketTopic { symbol: "T2412".to_string(), exchange_id: "CFFEX".to_string(), interval: "1m".to_string() },
+        ];
+        let started = time("2024-09-23 08:00:00");
+        let monday = NaiveDate::from_ymd_opt(2024, 9, 23).unwrap();
+        // The day ends after the latest close of the subscribed instruments, the bond futures at 15:15.
+        assert_eq!(ended_trading_day(&calendar, &topics, time("2024-09-23 15:00:10"), started, None), None);
+        assert_eq!(ended_trading_day(&calendar, &topics, time("2024-09-23 15:15:10"), started, None), Some(monday));
+        assert_eq!(ended_trading_day(&calendar, &topics[..1], time("2024-09-23 15:00:10"), started, None), Some(monday));
+        assert_eq!(ended_trading_day(&calendar, &topics, time("2024-09-23 15:16:00"), started, Some(monday)), None);
+        assert_eq!(ended_trading_day(&calendar, &topics, time("2024-09-23 15:16:00"), time("2024-09-23 15:15:30"), None), None);
+        assert_eq!(ended_trading_day(&calendar, &topics, time("2024-10-01 15:16:00"), started, None), None);
+        assert_eq!(ended_trading_day(&calendar, &[], time("2024-09-23 15:16:00"), started, None), None);
+    }
+}
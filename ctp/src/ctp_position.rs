@@ -0,0 +1,173 @@
+use std::collections::HashMap;
+use trade::trade_server::{Execution, Order, Position};
+use super::ctp_code::*;
+use crate::model::WorkingOrder;
+
+struct FrozenClose {
+    symbol: String,
+    side: String,
+    offset: String,
+    volume: f64,
+}
+
+/// Positions of every instrument kept from `OnRtnTrade`, keyed by instrument and position direction.
+/// The periodic position query only reconciles the book.
+#[derive(Default)]
+pub struct PositionBook {
+    positions: HashMap<(String, String), Position>,
+    frozen: HashMap<(i32, i32, String), FrozenClose>,
+}
+
+/// The position direction touched by an order or a trade of `direction` and `offset`.
+pub fn position_direction(direction: &str, offset: &str) -> &'static str {
+    let open = offset == OFFSET_OPEN.code;
+    let long = direction == DIRECTION_LONG.code;
+    if open == long { POSITION_DIRECTION_LONG.code } else { POSITION_DIRECTION_SHORT.code }
+}
+
+impl PositionBook {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Applies a trade and returns the updated position.
+    pub fn on_trade(&mut self, trade: &Execution, volume_multiple: f64) -> Position {
+        let side = position_direction(&trade.side, &trade.offset);
+        let position = self.positions.entry((trade.symbol.clone(), side.to_string())).or_insert_with(|| Position {
+            symbol: trade.symbol.clone(),
+            position_side: side.to_string(),
+            side: side.to_string(),
+            ..Default::default()
+        });
+        if trade.offset == OFFSET_OPEN.code {
+            position.amount += trade.quantity;
+            position.today_amount += trade.quantity;
+            position.cost += trade.price * trade.quantity * volume_multiple;
+        } else {
+            let quantity = trade.quantity.min(position.amount);
+            let yesterday = position.amount - position.today_amount;
+            let from_today = if trade.offset == OFFSET_CLOSETODAY.code {
+                quantity
+            } else if trade.offset == OFFSET_CLOSEYESTERDAY.code {
+                0.0
+            } else {
+                (quantity - yesterday).max(0.0)
+            };
+            if position.amount > 0.0 {
+                position.cost -= position.cost * quantity / position.amount;
+            }
+            position.amount -= quantity;
+            position.today_amount = (position.today_amount - from_today).max(0.0).min(position.amount);
+        }
+        position.clone()
+    }
+
+    /// Tracks the working close orders, whose volume is frozen until they are traded or canceled.
+    /// Order refs are only unique within a session, so the orders are keyed by front id, session id and order ref.
+    pub fn on_order(&mut self, order: &Order, working_order: &WorkingOrder) {
+        let key = (working_order.front_id, working_order.session_id, working_order.order_ref.trim().to_string());
+        if order.offset != OFFSET_OPEN.code && is_working_status(&order.status) && order.total > order.traded {
+            self.frozen.insert(key, FrozenClose {
+                symbol: order.symbol.clone(),
+                side: position_direction(&order.side, &order.offset).to_string(),
+                offset: order.offset.clone(),
+                volume: order.total - order.traded,
+            });
+        } else {
+            self.frozen.remove(&key);
+        }
+    }
+
+    /// The (today, yesterday) volume of a position that is not frozen by working close orders.
+    pub fn closable(&self, symbol: &str, side: &str) -> (f64, f64) {
+        let (mut today, mut yesterday) = match self.positions.get(&(symbol.to_string(), side.to_string())) {
+            Some(p) => (p.today_amount, p.amount - p.today_amount),
+            None => (0.0, 0.0),
+        };
+        for (_, frozen) in self.frozen.iter().filter(|(_, f)| f.symbol == symbol && f.side == side) {
+            if frozen.offset == OFFSET_CLOSETODAY.code {
+                today -= frozen.volume;
+            } else if frozen.offset == OFFSET_CLOSEYESTERDAY.code {
+                yesterday -= frozen.volume;
+            } else {
+                let from_yesterday = frozen.volume.min(yesterday.max(0.0));
+                yesterday -= from_yesterday;
+                today -= frozen.volume - from_yesterday;
+            }
+        }
+        (today.max(0.0), yesterday.max(0.0))
+    }
+
+    /// Replaces the book with the queried position records and returns the positions whose volume differs from the book.
+    pub fn reconcile(&mut self, queried: &[Position]) -> Vec<Position> {
+        let mut merged = HashMap::<(String, String), Position>::new();
+        for p in queried.iter() {
+            match merged.get_mut(&(p.symbol.clone(), p.side.clone())) {
+                Some(m) => {
+                    m.amount += p.amount;
+                    m.today_amount += p.today_amount;
+                    m.cost += p.cost;
+                    m.unrealized_pnl += p.unrealized_pnl;
+                },
+                None => {
+                    merged.insert((p.symbol.clone(), p.side.clone()), p.clone());
+                },
+            }
+        }
+        let mut changed = vec![];
+        for (key, p) in merged.iter() {
+            match self.positions.get(key) {
+                Some(old) if old.amount == p.amount && old.today_amount == p.today_amount => {},
+                _ => changed.push(p.clone()),
+            }
+        }
+        for (key, old) in self.positions.iter() {
+            if old.amount > 0.0 && !merged.contains_key(key) {
+                changed.push(Position { amount: 0.0, today_amount: 0.0, cost: 0.0, unrealized_pnl: 0.0, ..old.clone() });
+            }
+        }
+        self.positions = merged;
+        changed
+    }
+
+    pub fn get_positions(&self, symbol: &str) -> Vec<Position> {
+        self.positions.values().filter(|p| p.symbol == symbol).cloned().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use trade::trade_server::Execution;
+
+    #[test]
+    fn test_frozen_close_of_every_session() {
+        let mut book = PositionBook::new();
+        book.on_trade(&Execution {
+            symbol: "rb2501".to_string(),
+            side: DIRECTION_LONG.code.to_string(),
+            offset: OFFSET_OPEN.code.to_string(),
+            price: 3500.0,
+            quantity: 2.0,
+            ..Default::default()
+        }, 10.0);
+        let order = Order {
+            symbol: "rb2501".to_string(),
+            client_order_id: "1".to_string(),
+            side: DIRECTION_SHORT.code.to_string(),
+            offset: OFFSET_CLOSETODAY.code.to_string(),
+            status: ORDER_STATUS_NO_TRADED_QUEUEING.code.to_string(),
+            total: 1.0,
+            ..Default::default()
+        };
+        // The same order ref is reused by another session of the account.
+        for session_id in [1, 2] {
+            book.on_order(&order, &WorkingOrder { front_id: 1, session_id, order_ref: "1".to_string(), ..Default::default() });
+        }
+        assert_eq!(book.closable("rb2501", POSITION_DIRECTION_LONG.code), (0.0, 0.0));
+
+        let canceled = Order { status: ORDER_STATUS_CANCELLED.code.to_string(), ..order };
+        book.on_order(&canceled, &WorkingOrder { front_id: 1, session_id: 2, order_ref: "1".to_string(), ..Default::default() });
+        assert_eq!(book.closable("rb2501", POSITION_DIRECTION_LONG.code), (1.0, 0.0));
+    }
+}
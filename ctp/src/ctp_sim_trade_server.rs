@@ -1,10 +1,11 @@
-use rust_decimal::prelude::FromPrimitive;
-use rust_decimal::Decimal;
-use trade::sim_trade_server::{SimNewOrderRequest, SimTradeConfig, SimTradeServer};
+use market::market_server::MarketData;
+use trade::sim_trade_server::{SimNewOrderRequest, SimOrderStatus, SimSymbolMargin, SimTradeConfig, SimTradeServer};
 
 use trade::trade_server::*;
 use common::{msmc::Subscription, error::AppError};
-use crate::model::{CancelOrderRequest, NewOrderRequest, Symbol, SymbolInfo};
+use trade::order::{ModifyOrderRequest, OrderRequest};
+use crate::model::{CancelOrderRequest, Symbol, SymbolInfo};
+use crate::ctp_trade_server::{ctp_offset, to_ctp_order_request};
 
 use super::ctp_code::*;
 
@@ -14,7 +15,13 @@ pub struct CtpSimTradeServer {
 
 impl CtpSimTradeServer {
     pub fn new(config: SimTradeConfig) -> Self {
-        let inner = SimTradeServer::new(config);
+        let order_status = SimOrderStatus {
+            new: ORDER_STATUS_NO_TRADED_QUEUEING.code.to_string(),
+            partially_filled: ORDER_STATUS_PART_TRADED_QUEUEING.code.to_string(),
+            filled: config.order_completed_status.clone(),
+            canceled: ORDER_STATUS_CANCELLED.code.to_string(),
+        };
+        let inner = SimTradeServer::new(config, order_status, None);
         CtpSimTradeServer {
             inner,
         }
@@ -22,7 +29,7 @@ impl CtpSimTradeServer {
 }
 
 impl TradeServer for CtpSimTradeServer {
-    type OrderRequest = NewOrderRequest;
+    type OrderRequest = OrderRequest;
     type CancelOrderRequest = CancelOrderRequest;
     type SymbolConfig = ();
     type SymbolInfo = SymbolInfo;
@@ -35,19 +42,22 @@ impl TradeServer for CtpSimTradeServer {
     fn start(&mut self) -> Result<Subscription<TradeEvent>, AppError> {
         self.inner.start()
     }
+
+    fn bind_market(&mut self, subscription: Subscription<MarketData>) -> Result<(), AppError> {
+        self.inner.bind_market(subscription)
+    }
+ 
+    fn new_order(&mut self, symbol: Symbol, request : OrderRequest) -> Result<OrderAck, AppError> {
+        let mut request = request;
+        request.offset = Some(ctp_offset(&request));
+        let ctp_request = to_ctp_order_request(&request)?;
+        let mut sim_order = SimNewOrderRequest::from_order(&symbol.symbol, &request)?;
+        sim_order.side = ctp_request.direction;
+        self.inner.new_order(symbol.symbol, sim_order)
+    }
  
-    fn new_order(&mut self, symbol: Symbol, request : NewOrderRequest) -> Result<(), AppError> {
-        let sim_order = SimNewOrderRequest{
-            symbol: symbol.symbol.clone(),
-            side: request.direction.to_string(),
-            position_side: request.direction.to_string(),
-            order_type: request.order_type.to_string(),
-            reduce_only:  request.offset == OFFSET_CLOSE.code,
-            quantity: Decimal::from_u32(request.volume_total),
-            price: Decimal::from_f64(request.limit_price as f64),
-            new_client_order_id: Some(request.order_ref.clone()),
-         };
-         self.inner.new_order(symbol.symbol, sim_order)
+    fn modify_order(&mut self, symbol: Symbol, request: ModifyOrderRequest) -> Result<OrderAck, AppError> {
+        self.inner.modify_order(symbol.symbol, request)
     }
  
     fn cancel_order(&mut self, symbol: Symbol, request: CancelOrderRequest) -> Result<(), AppError> {
@@ -58,6 +68,14 @@ impl TradeServer for CtpSimTradeServer {
         self.inner.cancel_orders(symbol.symbol)
     }
  
+    fn get_open_orders(&self, symbol: Symbol) -> Result<Vec<Order>, AppError> {
+        self.inner.get_open_orders(symbol.symbol)
+    }
+
+    fn get_order(&self, symbol: Symbol, client_order_id: &str) -> Result<Option<Order>, AppError> {
+        self.inner.get_order(symbol.symbol, client_order_id)
+    }
+
     fn get_positions(&self, symbol: Symbol) -> Result<Vec<Position>, AppError> {
         self.inner.get_positions(symbol.symbol)
     }
@@ -74,10 +92,16 @@ impl TradeServer for CtpSimTradeServer {
             volume_multiple: 20 as f64,
             price_tick: 2 as f64,
         };
+        self.inner.set_symbol_margin(&symbol_info.symbol, SimSymbolMargin {
+            margin_ratio: symbol_info.margin_ratio,
+            maint_margin_ratio: symbol_info.margin_ratio,
+            volume_multiple: symbol_info.volume_multiple,
+            brackets: vec![],
+        });
         Ok(symbol_info)
     }
 
     fn close(&self) {
         self.inner.close();
     }
-}
\ No newline at end of file
+}
@@ -2,23 +2,25 @@
 #![allow(non_camel_case_types)]
 #![allow(non_snake_case)]
 use libctp_sys::*;
-use log::info;
-use trade::trade_server::{Order, Position, Wallet};
+use log::{info, warn};
+use crate::ctp_calendar::{exchange_now, exchange_timestamp, natural_time};
+use trade::trade_server::{Execution, Order, Position, Wallet};
 use std::collections::HashMap;
 use std::os::raw::*;
 use common::{c::*, msmc::Subscription};
-use crate::model::{Session, SymbolInfo, ServerEvent};
+use crate::model::{Session, SymbolInfo, ServerEvent, WorkingOrder};
 
 use super::ctp_code::*;
 
 pub struct Spi {
     pub subscription: Subscription<ServerEvent>,
     pub position_queue : HashMap<i32, Vec<Position>>,
+    pub order_queue : HashMap<i32, Vec<(Order, WorkingOrder)>>,
 }
 
 impl Spi {
     pub fn new() -> Self {
-        Spi { subscription: Subscription::<ServerEvent>::top(), position_queue: HashMap::new() }
+        Spi { subscription: Subscription::<ServerEvent>::top(), position_queue: HashMap::new(), order_queue: HashMap::new() }
     }
 
     fn handle_result<F>(subscription: &Subscription<ServerEvent>, request_id: i32, pRspInfo: *mut CThostFtdcRspInfoField, f: &mut F)
@@ -33,6 +35,17 @@ impl Spi {
         f();
     }
 
+    /// Maps a CTP flag to its code, a flag without a code such as the forced close offsets takes the fallback.
+    fn rev_code(map: &HashMap<u8, String>, flag: c_char, name: &str, fallback: &str) -> String {
+        match map.get(&(flag as u8)) {
+            Some(code) => code.to_string(),
+            None => {
+                warn!("Unknown CTP {} flag {:?}, reported as {}", name, flag as u8 as char, fallback);
+                fallback.to_string()
+            },
+        }
+    }
+
     fn convert_order(pRspInfo: *mut CThostFtdcOrderField) -> Order {
         let pRspInfo = unsafe { &mut *pRspInfo };
         let order_type = OrderType {
@@ -42,21 +55,53 @@ impl Spi {
         };
         let order = Order {
             client_order_id: c_char_to_string(pRspInfo.OrderRef.as_ptr()),
-            side:  DIRECTION_REV.as_ref().get(&(pRspInfo.Direction as u8)).unwrap().to_string(),
-            offset: OFFSET_REV.as_ref().get(&(pRspInfo.CombOffsetFlag[0] as u8)).unwrap().to_string(),
+            side: Self::rev_code(&DIRECTION_REV, pRspInfo.Direction, "direction", "UNKNOWN"),
+            offset: Self::rev_code(&OFFSET_REV, pRspInfo.CombOffsetFlag[0], "offset", OFFSET_CLOSE.code),
             price: pRspInfo.LimitPrice,
             order_type: order_type.to_string(),
             total: pRspInfo.VolumeTotalOriginal as f64,   
             order_id: c_char_to_string(pRspInfo.OrderSysID.as_ptr()),
-            status: ORDER_STATUS_REV.as_ref().get(&(pRspInfo.OrderStatus as u8)).unwrap().to_string(),
+            status: Self::rev_code(&ORDER_STATUS_REV, pRspInfo.OrderStatus, "order status", ORDER_STATUS_UNKNOWN.code),
             traded: pRspInfo.VolumeTraded as f64,
             message: c_char_to_gbk_string(pRspInfo.StatusMsg.as_ptr()),
             symbol: c_char_to_string(pRspInfo.InstrumentID.as_ptr()),
             timestamp: 0,
+            ..Default::default()
         };
         order
     }
 
+    fn convert_working_order(pRspInfo: *mut CThostFtdcOrderField) -> WorkingOrder {
+        let pRspInfo = unsafe { &mut *pRspInfo };
+        WorkingOrder {
+            symbol: c_char_to_string(pRspInfo.InstrumentID.as_ptr()),
+            exchange_id: c_char_to_string(pRspInfo.ExchangeID.as_ptr()),
+            front_id: pRspInfo.FrontID,
+            session_id: pRspInfo.SessionID,
+            order_ref: c_char_to_string(pRspInfo.OrderRef.as_ptr()),
+            order_sys_id: c_char_to_string(pRspInfo.OrderSysID.as_ptr()),
+            status: Self::rev_code(&ORDER_STATUS_REV, pRspInfo.OrderStatus, "order status", ORDER_STATUS_UNKNOWN.code),
+        }
+    }
+
+    fn convert_trade(pRspInfo: *mut CThostFtdcTradeField) -> Execution {
+        let pRspInfo = unsafe { &mut *pRspInfo };
+        let time = natural_time(&c_char_to_string(pRspInfo.TradeDate.as_ptr()), &c_char_to_string(pRspInfo.TradeTime.as_ptr()), 0, exchange_now());
+        let timestamp = time.map(exchange_timestamp).unwrap_or(0);
+        Execution {
+            symbol: c_char_to_string(pRspInfo.InstrumentID.as_ptr()),
+            order_id: c_char_to_string(pRspInfo.OrderSysID.as_ptr()),
+            client_order_id: c_char_to_string(pRspInfo.OrderRef.as_ptr()),
+            trade_id: c_char_to_string(pRspInfo.TradeID.as_ptr()),
+            side: Self::rev_code(&DIRECTION_REV, pRspInfo.Direction, "direction", "UNKNOWN"),
+            offset: Self::rev_code(&OFFSET_REV, pRspInfo.OffsetFlag, "offset", OFFSET_CLOSE.code),
+            price: pRspInfo.Price,
+            quantity: pRspInfo.Volume as f64,
+            timestamp,
+            ..Default::default()
+        }
+    }
+
     fn convert_position(pRspInfo: *mut CThostFtdcInvestorPositionField) -> Position {
         let pRspInfo = unsafe { &mut *pRspInfo };
         let position = Position {
@@ -66,6 +111,7 @@ impl Spi {
             side: POSITION_DIRECTION_REV.as_ref().get(&(pRspInfo.PosiDirection as u8)).unwrap().to_string(),
             cost: pRspInfo.PositionCost,
             position_side: POSITION_DIRECTION_REV.as_ref().get(&(pRspInfo.PosiDirection as u8)).unwrap().to_string(),
+            unrealized_pnl: pRspInfo.PositionProfit,
         };
         position
     }
@@ -88,6 +134,7 @@ impl Spi {
             session_id: pRspInfo.SessionID,
             front_id: pRspInfo.FrontID,
             trading_day: c_char_to_string(pRspInfo.TradingDay.as_ptr()),
+            max_order_ref: c_char_to_string(pRspInfo.MaxOrderRef.as_ptr()).trim().parse().unwrap_or(0),
         };
         session
     }
@@ -180,8 +227,19 @@ impl Rust_CThostFtdcTraderSpi_Trait for Spi {
     }
 
     fn on_rtn_order(&mut self, pOrder: *mut CThostFtdcOrderField) {
+        if pOrder.is_null() {
+            return;
+        }
         let ret = Self::convert_order(pOrder);
-        let _ = self.subscription.send(&ServerEvent::OnOrder(ret));
+        self.subscription.send(&ServerEvent::OnOrder(ret, Self::convert_working_order(pOrder)));
+    }
+
+    fn on_rtn_trade(&mut self, pTrade: *mut CThostFtdcTradeField) {
+        if pTrade.is_null() {
+            return;
+        }
+        let ret = Self::convert_trade(pTrade);
+        self.subscription.send(&ServerEvent::OnTrade(ret));
     }
 
     fn on_rsp_qry_investor_position(&mut self, pInvestorPosition: *mut CThostFtdcInvestorPositionField, pRspInfo: *mut CThostFtdcRspInfoField, nRequestID: ::std::os::raw::c_int, bIsLast: bool) { 
@@ -197,6 +255,20 @@ impl Rust_CThostFtdcTraderSpi_Trait for Spi {
         });
     }
 
+    fn on_rsp_qry_order(&mut self, pOrder: *mut CThostFtdcOrderField, pRspInfo: *mut CThostFtdcRspInfoField, nRequestID: ::std::os::raw::c_int, bIsLast: bool) { 
+        Self::handle_result(&self.subscription, nRequestID, pRspInfo, &mut ||{
+            let orders = self.order_queue.entry(nRequestID).or_default();
+            if !pOrder.is_null() {
+                orders.push((Self::convert_order(pOrder), Self::convert_working_order(pOrder)));
+            }
+            if bIsLast {
+                if let Some(order_vec) = self.order_queue.remove(&nRequestID) {
+                    self.subscription.send(&ServerEvent::OrderQuery(order_vec));
+                }
+            }
+        });
+    }
+
     fn on_rsp_qry_trading_account(&mut self, pTradingAccount: *mut CThostFtdcTradingAccountField, pRspInfo: *mut CThostFtdcRspInfoField, nRequestID: ::std::os::raw::c_int, bIsLast: bool) { 
         if bIsLast {
             Self::handle_result(&self.subscription, nRequestID, pRspInfo, &mut ||{
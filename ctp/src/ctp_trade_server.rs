@@ -1,10 +1,10 @@
 #![allow(non_upper_case_globals)]
 #![allow(non_camel_case_types)]
 #![allow(non_snake_case)]
-use chrono::{Local, Timelike};
+use chrono::NaiveDateTime;
 use common::msmc::StreamError;
 use libctp_sys::*;
-use log::{error, info};
+use log::{error, info, warn};
 
 use std::collections::HashMap;
 use std::ffi::{CStr, CString};
@@ -15,12 +15,16 @@ use std::thread::{self, sleep, JoinHandle};
 use std::time::{Duration, Instant};
 use std::vec;
 use serde::{Deserialize, Serialize};
-use std::sync::{Arc, Mutex, RwLock};
+use std::sync::{Arc, Condvar, Mutex, RwLock};
+use market::market_server::MarketData;
 use trade::trade_server::*;
+use trade::order::{invalid_field, ModifyOrderRequest, OrderKind, OrderOffset, OrderRequest, OrderSide, TimeInForce};
 use common::{c::*, msmc::Subscription, error::AppError};
-use crate::model::{CancelOrderRequest, CtpConfig, NewOrderRequest, Session, Symbol, SymbolInfo, ServerEvent};
+use crate::model::{CancelOrderRequest, CtpConfig, NewOrderRequest, Session, Symbol, SymbolInfo, ServerEvent, WorkingOrder};
 
 use super::ctp_code::*;
+use super::ctp_calendar::{exchange_now, exchange_timestamp, TradingCalendar};
+use super::ctp_position::{position_direction, PositionBook};
 use super::ctp_trade_cpi::Spi;
 use std::cmp::min;
 
@@ -41,6 +45,7 @@ pub struct TDApi {
     config: CtpConfig,
     session: Option<Session>,
     request_id: AtomicI32,
+    order_ref: AtomicI32,
 }
 
 impl TDApi {
@@ -85,6 +90,7 @@ impl TDApi {
             config,
             session: None,
             request_id: AtomicI32::new(0),
+            order_ref: AtomicI32::new(0),
         }
     }
 
@@ -145,7 +151,20 @@ impl TDApi {
         })
     }
 
-    fn req_order_insert(&self, symbol: &str, exchange: &str, order: NewOrderRequest, unit_id: &str) -> Result<(), String> {
+    fn next_order_ref(&self) -> String {
+        (self.order_ref.fetch_add(1, Ordering::SeqCst) + 1).to_string()
+    }
+
+    fn req_order_insert(&self, symbol: &str, exchange: &str, mut order: NewOrderRequest, unit_id: &str) -> Result<String, String> {
+        if order.order_ref.is_empty() {
+            order.order_ref = self.next_order_ref();
+        } else {
+            let order_ref = order.order_ref.parse::<i32>().map_err(|_| format!("The OrderRef {} is not numeric", order.order_ref))?;
+            let current = self.order_ref.fetch_max(order_ref, Ordering::SeqCst);
+            if order_ref <= current {
+                return Err(format!("The OrderRef {} must be above the current {}", order_ref, current));
+            }
+        }
         let order_type = OrderType::from_str(&order.order_type)?;
         let direction = DIRECTION.as_ref().get(&order.direction).unwrap().clone();
         let mut comb_offset_flag: [i8; 5] = [0; 5];
@@ -192,25 +211,26 @@ impl TDApi {
         };
         Self::send_request(&mut move || unsafe {
             self.api.clone().lock().unwrap().ReqOrderInsert(&mut request, self.next_request_id())
-        })
+        })?;
+        Ok(order.order_ref)
     }
     
-    fn req_order_action(&self, symbol: &str, exchange: &str, request: CancelOrderRequest) -> Result<(), String> {
+    fn req_order_action(&self, order: &WorkingOrder) -> Result<(), String> {
         let mut request = CThostFtdcInputOrderActionField {
             BrokerID: string_to_c_char::<11>(self.config.broker_id.clone()),
             InvestorID: string_to_c_char::<13>(self.config.user_id.clone()),
 
             UserID: string_to_c_char::<16>(self.config.user_id.clone()),
-            InstrumentID: string_to_c_char::<81>(symbol.to_string()),
-            ExchangeID: string_to_c_char::<9>(exchange.to_string()),
-            OrderSysID: string_to_c_char::<21>("".to_string()),
-            OrderRef: string_to_c_char::<13>(request.order_id.to_string()),
+            InstrumentID: string_to_c_char::<81>(order.symbol.clone()),
+            ExchangeID: string_to_c_char::<9>(order.exchange_id.clone()),
+            OrderSysID: string_to_c_char::<21>(order.order_sys_id.clone()),
+            OrderRef: string_to_c_char::<13>(order.order_ref.clone()),
             OrderActionRef: 0 as c_int,
             RequestID: self.next_request_id() as c_int,
 
             ActionFlag: THOST_FTDC_AF_Delete as i8,
-            FrontID: self.session.as_ref().unwrap().front_id.clone(),
-            SessionID: self.session.as_ref().unwrap().session_id.clone(),
+            FrontID: order.front_id,
+            SessionID: order.session_id,
             InvestUnitID: string_to_c_char::<17>("".to_string()),
             LimitPrice: 0.0 as f64,
             VolumeChange: 0 as c_int,
@@ -239,6 +259,24 @@ impl TDApi {
         })
     }
 
+    fn req_qry_order(&self, symbol: &str, exchange: &str) -> Result<(), String> {
+        let mut request = CThostFtdcQryOrderField {
+            BrokerID: string_to_c_char::<11>(self.config.broker_id.clone()),
+            InvestorID: string_to_c_char::<13>(self.config.user_id.clone()),
+            reserve1: string_to_c_char::<31>("".to_string()),
+            ExchangeID: string_to_c_char::<9>(exchange.to_string()),
+            OrderSysID: string_to_c_char::<21>("".to_string()),
+            InsertTimeStart: string_to_c_char::<9>("".to_string()),
+            InsertTimeEnd: string_to_c_char::<9>("".to_string()),
+            InvestUnitID: string_to_c_char::<17>("".to_string()),
+            InstrumentID: string_to_c_char::<81>(symbol.to_string()),
+        };
+
+        Self::send_request(&mut move || unsafe {
+            self.api.clone().lock().unwrap().ReqQryOrder(&mut request, self.next_request_id())
+        })
+    }
+
     fn req_qry_trading_account(&self) -> Result<(), String> {
         let mut request = CThostFtdcQryTradingAccountField {
             BrokerID: string_to_c_char::<11>(self.config.broker_id.clone()),
@@ -326,6 +364,7 @@ impl TDApi {
                 match event {
                     ServerEvent::UserLogin(session) => {
                         self.session = Some(session.clone());
+                        self.order_ref.store(session.max_order_ref, Ordering::SeqCst);
                         should_break = true;
                     },
                     _ => {},
@@ -377,12 +416,16 @@ impl Drop for TDApi {
     }
 }
 
-#[allow(dead_code)]
+type OrderKey = (i32, i32, String);
+type Orders = (Mutex<HashMap<OrderKey, Order>>, Condvar);
+
+type WorkingOrders = HashMap<OrderKey, WorkingOrder>;
+
 pub struct CtpTradeServer {
     tapi: Arc<Mutex<TDApi>>,
-    config: CtpConfig,
     handler: Option<JoinHandle<()>>,
-    positions: Arc<RwLock<Vec<Position>>>,
+    positions: Arc<RwLock<PositionBook>>,
+    trade_seq: Arc<AtomicUsize>,
     account: Arc<RwLock<Wallet>>,
     start_ticket: Arc<AtomicUsize>,
     symbol_info_map: Arc<RwLock<HashMap<String, SymbolInfo>>>,
@@ -390,11 +433,15 @@ pub struct CtpTradeServer {
     position_checked: Arc<AtomicBool>,
     account_checked: Arc<AtomicBool>,
     subscription: Arc<Mutex<Subscription<TradeEvent>>>,
-    time_table: Vec<(String, String)>,
+    calendar: Arc<TradingCalendar>,
+    relogin: Arc<AtomicBool>,
+    relogin_session: Arc<Mutex<Option<Session>>>,
+    working_orders: Arc<RwLock<WorkingOrders>>,
+    orders: Arc<Orders>,
 }
 
 impl CtpTradeServer {
-    pub fn new(config: CtpConfig) -> Self {
+    pub fn new(config: CtpConfig, calendar: Arc<TradingCalendar>) -> Self {
         let tdapi = TDApi::new(CtpConfig {
             flow_path: "".into(),
             nm_addr: "".into(),
@@ -409,18 +456,11 @@ impl CtpTradeServer {
             ..Default::default()
         });
 
-        let time_table = vec![
-            ("0000".to_string(), "0230".to_string()),
-            ("0900".to_string(), "1015".to_string()),
-            ("1030".to_string(), "1130".to_string()),
-            ("1330".to_string(), "1500".to_string()),
-            ("2100".to_string(), "2400".to_string()),
-            ];
         CtpTradeServer {
             tapi: Arc::new(Mutex::new(tdapi)),
-            config,
             handler: None,
-            positions: Arc::new(RwLock::new(vec![])),
+            positions: Arc::new(RwLock::new(PositionBook::new())),
+            trade_seq: Arc::new(AtomicUsize::new(0)),
             account: Arc::new(RwLock::new(Wallet {..Default::default()})),
             start_ticket: Arc::new(AtomicUsize::new(0)),
             symbol_info_map: Arc::new(RwLock::new(HashMap::new())),
@@ -428,13 +468,126 @@ impl CtpTradeServer {
             position_checked:  Arc::new(AtomicBool::new(false)),
             account_checked:  Arc::new(AtomicBool::new(false)),
             subscription: Arc::new(Mutex::new(Subscription::top())),
-            time_table,
+            calendar,
+            relogin: Arc::new(AtomicBool::new(false)),
+            relogin_session: Arc::new(Mutex::new(None)),
+            working_orders: Arc::new(RwLock::new(HashMap::new())),
+            orders: Arc::new((Mutex::new(HashMap::new()), Condvar::new())),
+        }
+    }
+
+    /// The key of an order ref of this session or a `front:session:ref` order id.
+    fn order_key(&self, order_id: &str) -> Result<OrderKey, AppError> {
+        let order_id = order_id.trim();
+        match order_id.split(':').collect::<Vec<&str>>()[..] {
+            [front_id, session_id, order_ref] => Ok((
+                front_id.parse::<i32>().map_err(|_| invalid_field("orderId", "has an invalid front id"))?,
+                session_id.parse::<i32>().map_err(|_| invalid_field("orderId", "has an invalid session id"))?,
+                order_ref.trim().to_string(),
+            )),
+            _ => {
+                let tapi = self.tapi.lock().unwrap();
+                let session = tapi.session.as_ref().ok_or(AppError::new(-200, "The trade server is not logged in"))?;
+                Ok((session.front_id, session.session_id, order_id.to_string()))
+            },
+        }
+    }
+
+    /// Resolves a tracked order the same way as `find_working_order`, including the orders that are no longer working.
+    fn find_order(&self, symbol: &Symbol, order_id: &str) -> Result<Option<(OrderKey, Order)>, AppError> {
+        let key = self.order_key(order_id)?;
+        let orders = self.orders.0.lock().unwrap();
+        if let Some(order) = orders.get(&key) {
+            return Ok(Some((key, order.clone())));
         }
+        Ok(orders.iter().find(|(_, o)| o.symbol == symbol.symbol && !o.order_id.is_empty() && o.order_id.trim() == order_id.trim()).map(|(k, o)| (k.clone(), o.clone())))
     }
+
+    /// Resolves the order to cancel from an order ref of this session, a `front:session:ref` order id or an exchange order sys id.
+    fn find_working_order(&self, symbol: &Symbol, order_id: &str) -> Result<WorkingOrder, AppError> {
+        let key = self.order_key(order_id)?;
+        let order_id = order_id.trim();
+        let working_orders = self.working_orders.read().unwrap();
+        if let Some(order) = working_orders.get(&key) {
+            return Ok(order.clone());
+        }
+        match working_orders.values().find(|o| o.symbol == symbol.symbol && !o.order_sys_id.is_empty() && o.order_sys_id.trim() == order_id) {
+            Some(order) => Ok(order.clone()),
+            None => Err(AppError::new(-200, &format!("The working order {} of {} is not found", order_id, symbol.symbol))),
+        }
+    }
+}
+
+fn track_working_order(working_orders: &RwLock<WorkingOrders>, orders: &Orders, order: &Order, working_order: &WorkingOrder) {
+    let key = (working_order.front_id, working_order.session_id, working_order.order_ref.trim().to_string());
+    orders.0.lock().unwrap().insert(key.clone(), order.clone());
+    orders.1.notify_all();
+    if is_working_status(&working_order.status) {
+        working_orders.write().unwrap().insert(key, working_order.clone());
+    } else {
+        working_orders.write().unwrap().remove(&key);
+    }
+}
+
+const MODIFY_CANCEL_TIMEOUT: Duration = Duration::from_secs(10);
+const POSITION_RECONCILE_INTERVAL: Duration = Duration::from_secs(60);
+const QUERY_INTERVAL_SECS: u64 = 10;
+const RELOGIN_LEAD_MINUTES: i64 = 10;
+const RELOGIN_INTERVAL: Duration = Duration::from_secs(30);
+
+/// The fronts are only up around the sessions, log in again once a session is open or about to open.
+fn is_login_window(calendar: &TradingCalendar, now: NaiveDateTime) -> bool {
+    calendar.is_any_trading(now) || calendar.is_any_trading(now + chrono::Duration::minutes(RELOGIN_LEAD_MINUTES))
+}
+
+pub fn ctp_offset(request: &OrderRequest) -> OrderOffset {
+    match request.offset {
+        Some(offset) => offset,
+        None if request.reduce_only.unwrap_or(false) => OrderOffset::Close,
+        None => OrderOffset::Open,
+    }
+}
+
+pub fn to_ctp_order_request(request: &OrderRequest) -> Result<NewOrderRequest, AppError> {
+    let direction = match request.side {
+        OrderSide::Buy => DIRECTION_LONG.code,
+        OrderSide::Sell => DIRECTION_SHORT.code,
+    };
+    let offset = match ctp_offset(request) {
+        OrderOffset::Open => OFFSET_OPEN.code,
+        OrderOffset::Close => OFFSET_CLOSE.code,
+        OrderOffset::CloseToday => OFFSET_CLOSETODAY.code,
+        OrderOffset::CloseYesterday => OFFSET_CLOSEYESTERDAY.code,
+    };
+    let order_type = match (request.order_type, request.time_in_force) {
+        (OrderKind::Market, _) => "MARKET",
+        (OrderKind::Limit, None | Some(TimeInForce::Gtc)) => "LIMIT",
+        (OrderKind::Limit, Some(TimeInForce::Ioc)) => "FAK",
+        (OrderKind::Limit, Some(TimeInForce::Fok)) => "FOK",
+        (OrderKind::Limit, Some(TimeInForce::Gtx)) => return Err(invalid_field("timeInForce", "GTX is not supported by CTP")),
+        (kind, _) => return Err(invalid_field("orderType", &format!("{} is not supported by CTP", kind.code()))),
+    };
+    if request.quantity.fract() != 0.0 || request.quantity > u32::MAX as f64 {
+        return Err(invalid_field("quantity", "must be a whole number of lots"));
+    }
+    if let Some(client_order_id) = &request.client_order_id {
+        if !matches!(client_order_id.parse::<i32>(), Ok(v) if v > 0) {
+            return Err(invalid_field("clientOrderId", "must be a positive number, it is the CTP OrderRef"));
+        }
+    }
+    Ok(NewOrderRequest {
+        order_ref: request.client_order_id.clone().unwrap_or_default(),
+        offset: offset.to_string(),
+        order_type: order_type.to_string(),
+        volume_total: request.quantity as u32,
+        direction: direction.to_string(),
+        limit_price: request.price.unwrap_or(0.0),
+        stop_price: 0.0,
+    })
 }
 
 impl TradeServer for CtpTradeServer {
-    type OrderRequest = NewOrderRequest;
+    type OrderRequest = OrderRequest;
     type CancelOrderRequest = CancelOrderRequest;
     type SymbolConfig = ();
     type SymbolInfo = SymbolInfo;
@@ -449,12 +602,19 @@ impl TradeServer for CtpTradeServer {
         let start_ticket = self.start_ticket.fetch_add(1, Ordering::SeqCst);
         let start_ticket_ref = self.start_ticket.clone();
         let positions_ref = self.positions.clone();
+        let trade_seq = self.trade_seq.clone();
+        let reconcile_seq = Arc::new(AtomicUsize::new(0));
+        let reconcile_seq_ref = reconcile_seq.clone();
         let account_ref = self.account.clone();
         let symbol_info_map_ref = self.symbol_info_map.clone();
         let sync_wait_ref = self.sync_wait.clone();
         let position_checked = self.position_checked.clone();
         let account_checked = self.account_checked.clone();
         let subscription_ref = self.subscription.clone();
+        let working_orders = self.working_orders.clone();
+        let orders_ref = self.orders.clone();
+        let relogin = self.relogin.clone();
+        let relogin_session = self.relogin_session.clone();
         let handler = subscription.stream(move |event| {
             if start_ticket != start_ticket_ref.load(Ordering::SeqCst) - 1 {
                 return Err(StreamError::Exit);
@@ -462,22 +622,18 @@ impl TradeServer for CtpTradeServer {
             let sub = subscription_ref.lock().unwrap();
             match event {
                 Some(ServerEvent::PositionQuery(v)) => {
-                    if position_checked.load(Ordering::SeqCst) == true {
-                        let positions = positions_ref.write().unwrap();
-                        for p in v.iter() {
-                            let mut found = false;
-                            for p2 in positions.iter() {
-                                if p == p2 {
-                                    found = true;
-                                    break;
-                                } 
-                            }
-                            if !found {
-                                sub.send(&TradeEvent::PositionUpdate(p.clone()));
-                            }
+                    let checked = position_checked.load(Ordering::SeqCst);
+                    if checked && trade_seq.load(Ordering::SeqCst) != reconcile_seq_ref.load(Ordering::SeqCst) {
+                        info!("Trades arrived during the position query, the reconciliation is skipped");
+                        return Ok(true);
+                    }
+                    let changed = positions_ref.write().unwrap().reconcile(v);
+                    if checked {
+                        for p in changed.iter() {
+                            info!("The position {} {} is reconciled to {}", p.symbol, p.side, p.amount);
+                            sub.send(&TradeEvent::PositionUpdate(p.clone()));
                         }
                     }
-                    *positions_ref.write().unwrap() = v.clone();
                     position_checked.store(true, Ordering::SeqCst);
                 },
                 Some(ServerEvent::AccountQuery(v)) => {
@@ -494,9 +650,34 @@ impl TradeServer for CtpTradeServer {
                     symbol_info_map_ref.write().unwrap().insert(symbol_info.symbol.clone(), symbol_info.clone());
                     sync_wait_ref.store(false, Ordering::SeqCst);
                 },
-                Some(ServerEvent::OnOrder(order)) => {
+                Some(ServerEvent::OnOrder(order, working_order)) => {
+                    track_working_order(&working_orders, &orders_ref, order, working_order);
+                    positions_ref.write().unwrap().on_order(order, working_order);
                     sub.send(&TradeEvent::OrderUpdate(order.clone()));
                 },
+                Some(ServerEvent::OnTrade(execution)) => {
+                    trade_seq.fetch_add(1, Ordering::SeqCst);
+                    let volume_multiple = symbol_info_map_ref.read().unwrap().get(&execution.symbol).map(|s| s.volume_multiple).unwrap_or(1.0);
+                    let position = positions_ref.write().unwrap().on_trade(execution, volume_multiple);
+                    sub.send(&TradeEvent::Fill(execution.clone()));
+                    sub.send(&TradeEvent::PositionUpdate(position));
+                },
+                Some(ServerEvent::OrderQuery(orders)) => {
+                    for (order, working_order) in orders.iter() {
+                        track_working_order(&working_orders, &orders_ref, order, working_order);
+                        positions_ref.write().unwrap().on_order(order, working_order);
+                    }
+                },
+                Some(ServerEvent::Disconnected(reason)) => {
+                    warn!("The trade front is disconnected, reason {}", reason);
+                },
+                Some(ServerEvent::Connected) => {
+                    info!("The trade front is reconnected, logging in again");
+                    relogin.store(true, Ordering::SeqCst);
+                },
+                Some(ServerEvent::UserLogin(session)) => {
+                    *relogin_session.lock().unwrap() = Some(session.clone());
+                },
                 None => {},
                 _ => {
                     info!("TRADE SERVER {:?}", event);
@@ -507,27 +688,70 @@ impl TradeServer for CtpTradeServer {
         self.handler = Some(handler);
 
         let tapi_ref = self.tapi.clone();
-        let time_table = self.time_table.clone();
+        let calendar = self.calendar.clone();
+        let trade_seq = self.trade_seq.clone();
+        let start_ticket_ref = self.start_ticket.clone();
+        let relogin = self.relogin.clone();
+        let relogin_session = self.relogin_session.clone();
+        let position_checked = self.position_checked.clone();
+        let account_checked = self.account_checked.clone();
         let _ = thread::spawn(move || {
+            let mut last_reconcile: Option<Instant> = None;
+            let mut last_login: Option<Instant> = None;
+            let mut orders_queried = false;
+            let mut seconds = 0u64;
             loop {
-                if is_in_schedule(&time_table) {
-                    {
+                if start_ticket != start_ticket_ref.load(Ordering::SeqCst) - 1 {
+                    break;
+                }
+                let now = exchange_now();
+                let trading = calendar.is_any_trading(now);
+                if let Some(session) = relogin_session.lock().unwrap().take() {
+                    let mut tapi = tapi_ref.lock().unwrap();
+                    info!("The trade server is logged in again, front {} session {} trading day {}", session.front_id, session.session_id, session.trading_day);
+                    tapi.order_ref.store(session.max_order_ref, Ordering::SeqCst);
+                    tapi.session = Some(session);
+                    if let Err(e) = tapi.req_settlement_info_confirm(0) {
+                        error!("req_settlement_info_confirm: {:?}", e);
+                    }
+                    relogin.store(false, Ordering::SeqCst);
+                    orders_queried = false;
+                }
+                if relogin.load(Ordering::SeqCst) && is_login_window(&calendar, now) && last_login.is_none_or(|t| t.elapsed() >= RELOGIN_INTERVAL) {
+                    let mut tapi = tapi_ref.lock().unwrap();
+                    if let Err(e) = tapi.req_user_login() {
+                        error!("req_user_login: {:?}", e);
+                    }
+                    last_login = Some(Instant::now());
+                }
+                let checked = position_checked.load(Ordering::SeqCst) && account_checked.load(Ordering::SeqCst);
+                if (trading || !checked) && !relogin.load(Ordering::SeqCst) {
+                    if seconds.is_multiple_of(QUERY_INTERVAL_SECS) {
                         let tapi = tapi_ref.lock().unwrap();
                         let ret = tapi.req_qry_trading_account();
                         if ret.is_err() {
                             error!("req_qry_trading_account: {:?}", ret);
                         }
-                    }
-                    sleep(Duration::from_secs(5));
-                    {
+                    } else if !orders_queried {
+                        // The private topic is subscribed in quick mode, the orders placed before the login are only known from a query.
                         let tapi = tapi_ref.lock().unwrap();
+                        let ret = tapi.req_qry_order("", "");
+                        if ret.is_err() {
+                            error!("req_qry_order: {:?}", ret);
+                        }
+                        orders_queried = ret.is_ok();
+                    } else if seconds % QUERY_INTERVAL_SECS == QUERY_INTERVAL_SECS / 2 && last_reconcile.is_none_or(|t| t.elapsed() >= POSITION_RECONCILE_INTERVAL) {
+                        let tapi = tapi_ref.lock().unwrap();
+                        reconcile_seq.store(trade_seq.load(Ordering::SeqCst), Ordering::SeqCst);
                         let ret = tapi.req_qry_investor_position();
                         if ret.is_err() {
                             error!("req_qry_investor_position: {:?}", ret);
                         }
+                        last_reconcile = Some(Instant::now());
                     }
                 }
-                sleep(Duration::from_secs(5));
+                seconds += 1;
+                sleep(Duration::from_secs(1));
             }
         });
         let time = Instant::now();
@@ -549,62 +773,148 @@ impl TradeServer for CtpTradeServer {
         Ok(subscription)
     }
 
-    fn new_order(&mut self, symbol: Symbol, request: Self::OrderRequest) -> Result<(), AppError> {
-        let tapi = self.tapi.lock().unwrap();
-        if request.offset == OFFSET_CLOSE.code && symbol.exchange_id == "SHFE" {
-            let v = self.get_positions(symbol.clone())?;
-            let mut last_day = 0;
-
-            for p in v.iter() {
-                if p.side != request.direction {
-                    last_day = (last_day as f64 + (p.amount - p.today_amount)) as u32;
-                }
-            }
+    fn bind_market(&mut self, _subscription: Subscription<MarketData>) -> Result<(), AppError> {
+        Ok(())
+    }
 
-            let mut remain= request.volume_total;
+    fn new_order(&mut self, symbol: Symbol, request: Self::OrderRequest) -> Result<OrderAck, AppError> {
+        let request = to_ctp_order_request(&request)?;
+        let tapi = self.tapi.lock().unwrap();
+        let mut legs = vec![];
+        if request.offset == OFFSET_CLOSE.code && (symbol.exchange_id == "SHFE" || symbol.exchange_id == "INE") {
+            let side = position_direction(&request.direction, &request.offset);
+            let (_, yesterday) = self.positions.read().unwrap().closable(&symbol.symbol, side);
+            let last_day = min(yesterday as u32, request.volume_total);
             if last_day > 0 {
                 let mut last_day_order = request.clone();
                 last_day_order.offset = OFFSET_CLOSEYESTERDAY.code.to_string();
-                last_day_order.volume_total = min(last_day, request.volume_total);
-                remain -= last_day_order.volume_total;
-                let _ = tapi.req_order_insert(&symbol.symbol, &symbol.exchange_id, last_day_order, "").map_err(|e| AppError::new(-200, &e))?;
+                last_day_order.volume_total = last_day;
+                legs.push(last_day_order);
             }
-            if remain > 0 {
+            if request.volume_total > last_day {
                 let mut today_day_order = request.clone();
-                today_day_order.volume_total = remain;
-                let _ = tapi.req_order_insert(&symbol.symbol, &symbol.exchange_id, today_day_order, "").map_err(|e| AppError::new(-200, &e))?;
+                today_day_order.offset = OFFSET_CLOSETODAY.code.to_string();
+                today_day_order.volume_total = request.volume_total - last_day;
+                legs.push(today_day_order);
+            }
+            // Only the first leg keeps the requested OrderRef, the others take the next ones.
+            for leg in legs.iter_mut().skip(1) {
+                leg.order_ref = String::new();
             }
         } else {
-            let _ = tapi.req_order_insert(&symbol.symbol, &symbol.exchange_id, request.clone(), "").map_err(|e| AppError::new(-200, &e))?;
+            legs.push(request);
         }
-        Ok(())
+        let mut order_refs = vec![];
+        let mut leg_error = None;
+        for leg in legs {
+            match tapi.req_order_insert(&symbol.symbol, &symbol.exchange_id, leg, "") {
+                Ok(order_ref) => order_refs.push(order_ref),
+                Err(e) if order_refs.is_empty() => return Err(AppError::new(-200, &e)),
+                Err(e) => {
+                    error!("A close leg of {} failed after the order refs {:?} were sent: {}", symbol.symbol, order_refs, e);
+                    leg_error = Some(e);
+                    break;
+                },
+            }
+        }
+        if order_refs.len() > 1 {
+            info!("The close order of {} is split into order refs {:?}", symbol.symbol, order_refs);
+        }
+        let session = tapi.session.as_ref().ok_or(AppError::new(-200, "The trade server is not logged in"))?;
+        let order_ids: Vec<String> = order_refs.iter().map(|r| format!("{}:{}:{}", session.front_id, session.session_id, r)).collect();
+        Ok(OrderAck {
+            order_id: order_ids.first().cloned().unwrap_or_default(),
+            client_order_id: order_refs.first().cloned().unwrap_or_default(),
+            status: ORDER_STATUS_UNKNOWN.code.to_string(),
+            timestamp: exchange_timestamp(exchange_now()),
+            leg_order_ids: if order_ids.len() > 1 || leg_error.is_some() { order_ids } else { vec![] },
+            leg_error,
+        })
+    }
+
+    fn modify_order(&mut self, symbol: Symbol, request: ModifyOrderRequest) -> Result<OrderAck, AppError> {
+        request.validate()?;
+        let not_found = || AppError::new(-200, &format!("The order {} is not found", request.client_order_id));
+        let (key, order) = self.find_order(&symbol, &request.client_order_id)?.ok_or_else(not_found)?;
+        if order.order_type != "LIMIT" {
+            return Err(AppError::new(-200, &format!("Only a resting LIMIT order can be modified, the order {} is {}", order.client_order_id, order.order_type)));
+        }
+        if order.status != ORDER_STATUS_NO_TRADED_QUEUEING.code && order.status != ORDER_STATUS_PART_TRADED_QUEUEING.code {
+            return Err(AppError::new(-200, &format!("The order {} is not working, status {}", order.client_order_id, order.status)));
+        }
+        let quantity = request.quantity.unwrap_or(order.total);
+        if quantity <= order.traded {
+            return Err(invalid_field("quantity", &format!("must be greater than the traded quantity {}", order.traded)));
+        }
+
+        info!("CTP has no native amend, replacing the order {} of {}", order.client_order_id, symbol.symbol);
+        self.cancel_order(symbol.clone(), CancelOrderRequest { order_id: format!("{}:{}:{}", key.0, key.1, key.2) })?;
+        let (orders, order_updated) = &*self.orders;
+        let (orders, wait) = order_updated.wait_timeout_while(orders.lock().unwrap(), MODIFY_CANCEL_TIMEOUT, |orders| {
+            orders.get(&key).is_some_and(|o| is_working_status(&o.status))
+        }).unwrap();
+        if wait.timed_out() {
+            return Err(AppError::new(-200, &format!("The order {} is not canceled in time, the replacement is not submitted", order.client_order_id)));
+        }
+        let order = orders.get(&key).cloned().ok_or_else(not_found)?;
+        drop(orders);
+        if order.status != ORDER_STATUS_CANCELLED.code {
+            return Err(AppError::new(-200, &format!("The order {} is {} before it could be modified", order.client_order_id, order.status)));
+        }
+
+        let offset = match order.offset.as_str() {
+            "OPEN" => OrderOffset::Open,
+            "CLOSETODAY" => OrderOffset::CloseToday,
+            "CLOSEYESTERDAY" => OrderOffset::CloseYesterday,
+            _ => OrderOffset::Close,
+        };
+        self.new_order(symbol, OrderRequest {
+            side: if order.side == DIRECTION_LONG.code { OrderSide::Buy } else { OrderSide::Sell },
+            position_side: None,
+            offset: Some(offset),
+            order_type: OrderKind::Limit,
+            time_in_force: None,
+            quantity: quantity - order.traded,
+            price: Some(request.price.unwrap_or(order.price)),
+            stop_price: None,
+            reduce_only: None,
+            client_order_id: None,
+        })
     }
 
     fn cancel_order(&mut self, symbol: Symbol, request: CancelOrderRequest) -> Result<(), AppError> {
+        let order = self.find_working_order(&symbol, &request.order_id)?;
+        let tapi = self.tapi.lock().unwrap();
+        tapi.req_order_action(&order).map_err(|e| AppError::new(-200, &format!("Fail to cancel the order {} of {}: {}", request.order_id, symbol.symbol, e)))
+    }
+
+    fn cancel_orders(&mut self, symbol: Symbol) -> Result<(), AppError> {
+        let orders: Vec<WorkingOrder> = self.working_orders.read().unwrap().values().filter(|o| o.symbol == symbol.symbol).cloned().collect();
         let tapi = self.tapi.lock().unwrap();
-        let _ = tapi.req_order_action(&symbol.symbol, &symbol.exchange_id, request);
+        let mut errors = vec![];
+        for order in orders.iter() {
+            if let Err(e) = tapi.req_order_action(order) {
+                errors.push(format!("{}:{}:{} {}", order.front_id, order.session_id, order.order_ref, e));
+            }
+        }
+        if !errors.is_empty() {
+            return Err(AppError::new(-200, &format!("Fail to cancel {} of the {} working orders of {}: {}", errors.len(), orders.len(), symbol.symbol, errors.join(", "))));
+        }
+        info!("Sent the cancel requests of {} working orders of {}", orders.len(), symbol.symbol);
         Ok(())
     }
 
-    fn cancel_orders(&mut self, _symbol: Symbol) -> Result<(), AppError> {
-        Err(AppError::new(-200, "The service cancel_orders is not supported"))
+    fn get_open_orders(&self, symbol: Symbol) -> Result<Vec<Order>, AppError> {
+        let orders = self.orders.0.lock().unwrap();
+        Ok(orders.values().filter(|o| o.symbol == symbol.symbol && is_working_status(&o.status)).cloned().collect())
+    }
+
+    fn get_order(&self, symbol: Symbol, client_order_id: &str) -> Result<Option<Order>, AppError> {
+        Ok(self.find_order(&symbol, client_order_id)?.map(|(_, order)| order))
     }
 
     fn get_positions(&self, symbol: Symbol) -> Result<Vec<Position>, AppError> {
-        let positions = self.positions.as_ref().read().unwrap();
-        let mut position_map = HashMap::<String, Position>::new();
-
-        for p in positions.iter() {
-            if p.symbol == symbol.symbol {
-                let mut op = position_map.get_mut(&p.side);
-                if let Some(p1) = op.as_mut() {
-                    p1.amount = p1.amount + p.amount;
-                } else {
-                    position_map.insert(p.side.clone(), p.clone());
-                }
-            }
-        }
-        Ok(position_map.values().cloned().collect())
+        Ok(self.positions.read().unwrap().get_positions(&symbol.symbol))
     }
 
     fn get_account(&self, _account_id: &str) -> Result<Option<Wallet>, AppError> {
@@ -645,15 +955,34 @@ impl TradeServer for CtpTradeServer {
     }
 }
 
-fn is_in_schedule(schedule: &Vec<(String, String)>) -> bool {
-    let now = Local::now();
-    let time = now.time();
-    let time_str = format!("{:04}", time.hour() * 100 + time.minute());
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ctp_calendar::CalendarConfig;
 
-    for (start_str, end_str) in schedule {
-        if time_str >= *start_str && time_str <= *end_str {
-            return true;
-        }
+    fn time(s: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S").unwrap()
+    }
+
+    #[test]
+    fn test_login_window() {
+        let calendar = TradingCalendar::new(&CalendarConfig { sessions: None, holidays: Some(vec!["20241001".to_string()]) }).unwrap();
+        assert!(!is_login_window(&calendar, time("2024-09-23 08:40:00")));
+        assert!(is_login_window(&calendar, time("2024-09-23 08:52:00")));
+        assert!(is_login_window(&calendar, time("2024-09-23 10:00:00")));
+        assert!(!is_login_window(&calendar, time("2024-09-23 16:00:00")));
+        assert!(is_login_window(&calendar, time("2024-09-20 20:55:00")));
+        assert!(is_login_window(&calendar, time("2024-09-21 02:00:00")));
+        assert!(!is_login_window(&calendar, time("2024-09-21 10:00:00")));
+        // No night session on the eve of a holiday.
+        assert!(!is_login_window(&calendar, time("2024-09-30 20:55:00")));
     }
-    false
-}
\ No newline at end of file
+
+    #[test]
+    fn test_client_order_id_is_order_ref() {
+        let request = |id: &str| OrderRequest::from_json(&format!(r#"{{"side":"BUY","orderType":"LIMIT","quantity":1,"price":100,"clientOrderId":"{}"}}"#, id)).unwrap();
+        assert_eq!(to_ctp_order_request(&request("12")).unwrap().order_ref, "12");
+        assert!(to_ctp_order_request(&request("abc")).is_err());
+        assert!(to_ctp_order_request(&request("0")).is_err());
+    }
+}
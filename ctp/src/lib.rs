@@ -1,8 +1,10 @@
 pub mod model;
 pub mod ctp_code;
+pub mod ctp_calendar;
 mod ctp_market_cpi;
 pub mod ctp_market_server;
 mod ctp_trade_cpi;
 pub mod ctp_trade_server;
+mod ctp_position;
 pub mod ctp_sim_trade_server;
 pub mod ctp_sim_market_server;
@@ -3,8 +3,9 @@ use std::str::FromStr;
 use common::error::AppError;
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use serde_json::Value;
-use trade::trade_server::{Order, Position, SymbolRoute, Wallet};
+use trade::trade_server::{Execution, Order, Position, SymbolRoute, Wallet};
 use ureq::Response;
+use crate::ctp_calendar::CalendarConfig;
 
 
 pub fn get_resp_result<T: DeserializeOwned>(ret: Result<Response, AppError>, ignore_result: bool) -> Result<Option<T>, AppError> {
@@ -39,7 +40,10 @@ pub fn get_resp_result<T: DeserializeOwned>(ret: Result<Response, AppError>, ign
 impl SymbolRoute for ServerEvent {
     fn get_symbol(&self) -> String {
         match self {
-            ServerEvent::OnOrder(event) => {
+            ServerEvent::OnOrder(event, _) => {
+                event.symbol.to_string()
+            },
+            ServerEvent::OnTrade(event) => {
                 event.symbol.to_string()
             },
             _ => {
@@ -82,12 +86,31 @@ pub struct CancelOrderRequest {
     pub order_id: String,
 }
 
+impl From<String> for CancelOrderRequest {
+    fn from(order_id: String) -> Self {
+        CancelOrderRequest { order_id }
+    }
+}
+
 
 #[derive(Debug, Clone)]
 pub struct Session {
     pub session_id: i32,
     pub front_id: i32,
     pub trading_day: String,
+    pub max_order_ref: i32,
+}
+
+/// The keys of an order that `ReqOrderAction` accepts, FrontID/SessionID/OrderRef or ExchangeID/OrderSysID.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct WorkingOrder {
+    pub symbol: String,
+    pub exchange_id: String,
+    pub front_id: i32,
+    pub session_id: i32,
+    pub order_ref: String,
+    pub order_sys_id: String,
+    pub status: String,
 }
 
 #[derive(Debug, Clone)]
@@ -96,8 +119,9 @@ pub enum ServerEvent {
     UserLogin(Session),
     UserLogout,
     SettlementConfirmed,
-    OnOrder(Order),
-    OrderQuery(Vec<Order>),
+    OnOrder(Order, WorkingOrder),
+    OnTrade(Execution),
+    OrderQuery(Vec<(Order, WorkingOrder)>),
     PositionQuery(Vec<Position>),
     AccountQuery(Wallet),
     SymbolQuery(SymbolInfo),
@@ -121,6 +145,7 @@ pub struct CtpConfig {
     pub broker_id: String,
     pub user_id: String,
     pub password: String,
+    pub calendar: Option<CalendarConfig>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
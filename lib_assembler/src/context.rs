@@ -1,18 +1,15 @@
 use std::{str::FromStr, sync::{Arc, Mutex}};
 
 use binance::{bn_market_server::BnMarketServer, bn_trade_server::BnTradeServer, model::{BnMarketConfig, BnTradeConfig, SymbolConfig}};
-use binance_future_connector::trade::new_order::NewOrderRequest;
 use binance::{bn_sim_market_server::BnSimMarketServer, bn_sim_trade_server::BnSimTradeServer};
 
 use bybit::{bb_market_server::BbMarketServer, bb_sim_market_server::BbSimMarketServer, bb_sim_trade_server::BbSimTradeServer, bb_trade_server::BbTradeServer, model::{BbMarketConfig, BbTradeConfig}};
 use bybit::model::SymbolConfig as BbSymbolConfig;
-use bybit_connector::trade::new_order::NewOrderRequest as BbNewOrderRequest;
 use common::{error::AppError, msmc::Subscription};
-use crossbeam::channel::Receiver;
-use ctp::{ctp_market_server::CtpMarketServer, ctp_sim_market_server::CtpSimMarketServer, ctp_sim_trade_server::CtpSimTradeServer, ctp_trade_server::CtpTradeServer, model::{CancelOrderRequest, CtpConfig, NewOrderRequest as CtpNewOrderRequest, Symbol}};
-use market::{market_gateway::MarketGateway, market_server::{KLine, MarketData}, sim_market_server::SimMarketConfig};
+use ctp::{ctp_calendar::TradingCalendar, ctp_market_server::CtpMarketServer, ctp_sim_market_server::CtpSimMarketServer, ctp_sim_trade_server::CtpSimTradeServer, ctp_trade_server::CtpTradeServer, model::{CancelOrderRequest, CtpConfig, Symbol}};
+use market::{market_gateway::MarketGateway, market_server::{KLine, MarketData}, recorder::{MarketRecorder, RecorderConfig}, sim_market_server::SimMarketConfig};
 use serde_json::Value;
-use trade::{sim_trade_server::SimTradeConfig, trade_gateway::TradeGateway, trade_server::{Position, TradeEvent, Wallet}};
+use trade::{backtest_report::BacktestReport, algo_order::{AlgoKind, AlgoOrder, AlgoOrderRequest}, conditional_order::{BracketOrderRequest, ConditionalOrder, ConditionalOrderRequest}, order::{ModifyOrderRequest, OrderRequest}, kill_switch::{DeadMansSwitch, KillSwitchReport}, order_manager::ManagedOrder, risk::RiskConfig, sim_trade_server::SimTradeConfig, trade_gateway::TradeGateway, trade_server::{BatchOrderResult, Order, OrderAck, Position, TradeEvent, Wallet}};
 
 use crate::model::{BacktestConfig, BbRealConfig, BbSimConfig, BnRealConfig, BnSimConfig, CtpSimConfig};
 
@@ -141,7 +138,7 @@ impl MarketGateways {
             },
         }
     }
-    pub fn subscribe_kline(&mut self, symbol: String, interval: &str) -> Result<Receiver<MarketData>, AppError> {
+    pub fn subscribe_kline(&mut self, symbol: String, interval: &str) -> Result<Subscription<MarketData>, AppError> {
         match self {
             MarketGateways::BnSim(s) => {
                 return s.subscribe_kline(symbol, interval)
@@ -175,7 +172,7 @@ impl MarketGateways {
             },
         }
     }
-    pub fn subscribe_tick(&mut self, symbol: String) -> Result<Receiver<MarketData>, AppError> {
+    pub fn subscribe_tick(&mut self, symbol: String) -> Result<Subscription<MarketData>, AppError> {
         match self {
             MarketGateways::BnSim(s) => {
                 return s.subscribe_tick(symbol)
@@ -369,227 +366,980 @@ impl TradeGateways {
         }
     }
 
+    pub fn bind_market(&mut self, subscription: Subscription<MarketData>) -> Result<(), AppError> {
+        match self {
+            TradeGateways::BnSim(s) => {
+                return s.bind_market(subscription)
+            },
+            TradeGateways::BnBacktest(s) => {
+                return s.bind_market(subscription)
+            },
+            TradeGateways::BnReal(s) => {
+                return s.bind_market(subscription)
+            },
+            TradeGateways::BbReal(s) => {
+                return s.bind_market(subscription)
+            },
+            TradeGateways::BbSim(s) => {
+                return s.bind_market(subscription)
+            },
+            TradeGateways::BbBacktest(s) => {
+                return s.bind_market(subscription)
+            },
+            TradeGateways::CtpReal(s) => {
+                return s.bind_market(subscription)
+            },
+            TradeGateways::CtpSim(s) => {
+                return s.bind_market(subscription)
+            },
+            TradeGateways::CtpBacktest(s) => {
+                return s.bind_market(subscription)
+            },
+        }
+    }
+
     pub fn close(&self) {
         match self {
             TradeGateways::BnSim(s) => {
-                return s.close()
+                return s.close()
+            },
+            TradeGateways::BnBacktest(s) => {
+                return s.close()
+            },
+            TradeGateways::BnReal(s) => {
+                return s.close()
+            },
+            TradeGateways::BbReal(s) => {
+                return s.close()
+            },
+            TradeGateways::BbSim(s) => {
+                return s.close()
+            },
+            TradeGateways::BbBacktest(s) => {
+                return s.close()
+            },
+            TradeGateways::CtpReal(s) => {
+                return s.close()
+            },
+            TradeGateways::CtpSim(s) => {
+                return s.close()
+            },
+            TradeGateways::CtpBacktest(s) => {
+                return s.close()
+            },
+        }
+    }
+
+    pub fn register_symbol(&mut self, symbol: String) -> Result<Subscription<TradeEvent>, AppError> {
+        match self {
+            TradeGateways::BnSim(s) => {
+                return s.register_symbol(symbol)
+            },
+            TradeGateways::BnBacktest(s) => {
+                return s.register_symbol(symbol)
+            },
+            TradeGateways::BnReal(s) => {
+                return s.register_symbol(symbol)
+            },
+            TradeGateways::BbReal(s) => {
+                return s.register_symbol(symbol)
+            },
+            TradeGateways::BbSim(s) => {
+                return s.register_symbol(symbol)
+            },
+            TradeGateways::BbBacktest(s) => {
+                return s.register_symbol(symbol)
+            },
+            TradeGateways::CtpReal(s) => {
+                let symbol = Symbol::from_str(&symbol).map_err(|e| AppError::new(-200, &e.to_string()))?;
+                return s.register_symbol(symbol)
+            },
+            TradeGateways::CtpSim(s) => {
+                let symbol = Symbol::from_str(&symbol).map_err(|e| AppError::new(-200, &e.to_string()))?;
+                return s.register_symbol(symbol)
+            },
+            TradeGateways::CtpBacktest(s) => {
+                let symbol = Symbol::from_str(&symbol).map_err(|e| AppError::new(-200, &e.to_string()))?;
+                return s.register_symbol(symbol)
+            },
+        }
+    }
+
+    pub fn init_symbol(&mut self, symbol: String, config: &str) -> Result<Value, AppError> {
+        match self {
+            TradeGateways::BnSim(s) => {
+                let ret = serde_json::from_str::<SymbolConfig>(config).map_err(|e| AppError::new(-200, &e.to_string()))?;
+                serde_json::to_value(s.init_symbol(symbol, ret)?).map_err(|e| AppError::new(-200, &e.to_string()))
+            },
+            TradeGateways::BnBacktest(s) => {
+                let ret = serde_json::from_str::<SymbolConfig>(config).map_err(|e| AppError::new(-200, &e.to_string()))?;
+                serde_json::to_value(s.init_symbol(symbol, ret)?).map_err(|e| AppError::new(-200, &e.to_string()))
+            },
+            TradeGateways::BnReal(s) => {
+                let ret = serde_json::from_str::<SymbolConfig>(config).map_err(|e| AppError::new(-200, &e.to_string()))?;
+                serde_json::to_value(s.init_symbol(symbol, ret)?).map_err(|e| AppError::new(-200, &e.to_string()))
+            },
+            TradeGateways::BbReal(s) => {
+                let ret = serde_json::from_str::<BbSymbolConfig>(config).map_err(|e| AppError::new(-200, &e.to_string()))?;
+                serde_json::to_value(s.init_symbol(symbol, ret)?).map_err(|e| AppError::new(-200, &e.to_string()))
+            },
+            TradeGateways::BbSim(s) => {
+                let ret = serde_json::from_str::<BbSymbolConfig>(config).map_err(|e| AppError::new(-200, &e.to_string()))?;
+                serde_json::to_value(s.init_symbol(symbol, ret)?).map_err(|e| AppError::new(-200, &e.to_string()))
+            },
+            TradeGateways::BbBacktest(s) => {
+                let ret = serde_json::from_str::<BbSymbolConfig>(config).map_err(|e| AppError::new(-200, &e.to_string()))?;
+                serde_json::to_value(s.init_symbol(symbol, ret)?).map_err(|e| AppError::new(-200, &e.to_string()))
+            },
+            TradeGateways::CtpReal(s) => {
+                let symbol = Symbol::from_str(&symbol).map_err(|e| AppError::new(-200, &e.to_string()))?;
+                serde_json::to_value(s.init_symbol(symbol, ())?).map_err(|e| AppError::new(-200, &e.to_string()))
+            },
+            TradeGateways::CtpSim(s) => {
+                let symbol = Symbol::from_str(&symbol).map_err(|e| AppError::new(-200, &e.to_string()))?;
+                serde_json::to_value(s.init_symbol(symbol, ())?).map_err(|e| AppError::new(-200, &e.to_string()))
+            },
+            TradeGateways::CtpBacktest(s) => {
+                let symbol = Symbol::from_str(&symbol).map_err(|e| AppError::new(-200, &e.to_string()))?;
+                serde_json::to_value(s.init_symbol(symbol, ())?).map_err(|e| AppError::new(-200, &e.to_string()))
+            },
+        }
+    }
+
+    pub fn new_order(&mut self, symbol: String, request : &str) -> Result<OrderAck, AppError> {
+        let ret = OrderRequest::from_json(request)?;
+        match self {
+            TradeGateways::BnSim(s) => {
+                return s.new_order(symbol, ret)
+            },
+            TradeGateways::BnBacktest(s) => {
+                return s.new_order(symbol, ret)
+            },
+            TradeGateways::BnReal(s) => {
+                return s.new_order(symbol, ret)
+            },
+            TradeGateways::BbReal(s) => {
+                return s.new_order(symbol, ret)
+            },
+            TradeGateways::BbSim(s) => {
+                return s.new_order(symbol, ret)
+            },
+            TradeGateways::BbBacktest(s) => {
+                return s.new_order(symbol, ret)
+            },
+            TradeGateways::CtpReal(s) => {
+                let symbol = Symbol::from_str(&symbol).map_err(|e| AppError::new(-200, &e.to_string()))?;
+                return s.new_order(symbol, ret)
+            },
+            TradeGateways::CtpSim(s) => {
+                let symbol = Symbol::from_str(&symbol).map_err(|e| AppError::new(-200, &e.to_string()))?;
+                return s.new_order(symbol, ret)
+            },
+            TradeGateways::CtpBacktest(s) => {
+                let symbol = Symbol::from_str(&symbol).map_err(|e| AppError::new(-200, &e.to_string()))?;
+                return s.new_order(symbol, ret)
+            },
+        }
+    }
+
+    pub fn new_orders(&mut self, symbol: String, requests: &str) -> Result<Vec<BatchOrderResult>, AppError> {
+        let items = serde_json::from_str::<Vec<serde_json::Value>>(requests).map_err(|e| AppError::new(-200, &e.to_string()))?;
+        let mut ret = vec![];
+        for item in items.iter() {
+            ret.push(OrderRequest::from_json(&item.to_string())?);
+        }
+        match self {
+            TradeGateways::BnSim(s) => {
+                return s.new_orders(symbol, ret)
+            },
+            TradeGateways::BnBacktest(s) => {
+                return s.new_orders(symbol, ret)
+            },
+            TradeGateways::BnReal(s) => {
+                return s.new_orders(symbol, ret)
+            },
+            TradeGateways::BbReal(s) => {
+                return s.new_orders(symbol, ret)
+            },
+            TradeGateways::BbSim(s) => {
+                return s.new_orders(symbol, ret)
+            },
+            TradeGateways::BbBacktest(s) => {
+                return s.new_orders(symbol, ret)
+            },
+            TradeGateways::CtpReal(s) => {
+                let symbol = Symbol::from_str(&symbol).map_err(|e| AppError::new(-200, &e.to_string()))?;
+                return s.new_orders(symbol, ret)
+            },
+            TradeGateways::CtpSim(s) => {
+                let symbol = Symbol::from_str(&symbol).map_err(|e| AppError::new(-200, &e.to_string()))?;
+                return s.new_orders(symbol, ret)
+            },
+            TradeGateways::CtpBacktest(s) => {
+                let symbol = Symbol::from_str(&symbol).map_err(|e| AppError::new(-200, &e.to_string()))?;
+                return s.new_orders(symbol, ret)
+            },
+        }
+    }
+
+    pub fn modify_order(&mut self, symbol: String, request : &str) -> Result<OrderAck, AppError> {
+        let ret = ModifyOrderRequest::from_json(request)?;
+        match self {
+            TradeGateways::BnSim(s) => {
+                return s.modify_order(symbol, ret)
+            },
+            TradeGateways::BnBacktest(s) => {
+                return s.modify_order(symbol, ret)
+            },
+            TradeGateways::BnReal(s) => {
+                return s.modify_order(symbol, ret)
+            },
+            TradeGateways::BbReal(s) => {
+                return s.modify_order(symbol, ret)
+            },
+            TradeGateways::BbSim(s) => {
+                return s.modify_order(symbol, ret)
+            },
+            TradeGateways::BbBacktest(s) => {
+                return s.modify_order(symbol, ret)
+            },
+            TradeGateways::CtpReal(s) => {
+                let symbol = Symbol::from_str(&symbol).map_err(|e| AppError::new(-200, &e.to_string()))?;
+                return s.modify_order(symbol, ret)
+            },
+            TradeGateways::CtpSim(s) => {
+                let symbol = Symbol::from_str(&symbol).map_err(|e| AppError::new(-200, &e.to_string()))?;
+                return s.modify_order(symbol, ret)
+            },
+            TradeGateways::CtpBacktest(s) => {
+                let symbol = Symbol::from_str(&symbol).map_err(|e| AppError::new(-200, &e.to_string()))?;
+                return s.modify_order(symbol, ret)
+            },
+        }
+    }
+
+    pub fn cancel_order(&mut self,  symbol: String, request: &str) -> Result<(), AppError> {
+        match self {
+            TradeGateways::BnSim(s) => {
+                return s.cancel_order(symbol, request.to_string())
+            },
+            TradeGateways::BnBacktest(s) => {
+                return s.cancel_order(symbol, request.to_string())
+            },
+            TradeGateways::BnReal(s) => {
+                return s.cancel_order(symbol, request.to_string())
+            },
+            TradeGateways::BbReal(s) => {
+                return s.cancel_order(symbol, request.to_string())
+            },
+            TradeGateways::BbSim(s) => {
+                return s.cancel_order(symbol, request.to_string())
+            },
+            TradeGateways::BbBacktest(s) => {
+                return s.cancel_order(symbol, request.to_string())
+            },
+            TradeGateways::CtpReal(s) => {
+                let symbol = Symbol::from_str(&symbol).map_err(|e| AppError::new(-200, &e.to_string()))?;
+                let request = serde_json::from_str::<CancelOrderRequest>(request).map_err(|e| AppError::new(-200, &e.to_string()))?;
+                return s.cancel_order(symbol, request)
+            },
+            TradeGateways::CtpSim(s) => {
+                let symbol = Symbol::from_str(&symbol).map_err(|e| AppError::new(-200, &e.to_string()))?;
+                let request = serde_json::from_str::<CancelOrderRequest>(request).map_err(|e| AppError::new(-200, &e.to_string()))?;
+                return s.cancel_order(symbol, request)
+            },
+            TradeGateways::CtpBacktest(s) => {
+                let symbol = Symbol::from_str(&symbol).map_err(|e| AppError::new(-200, &e.to_string()))?;
+                let request = serde_json::from_str::<CancelOrderRequest>(request).map_err(|e| AppError::new(-200, &e.to_string()))?;
+                return s.cancel_order(symbol, request)
+            },
+        }
+    }
+
+    pub fn cancel_orders_by_id(&mut self, symbol: String, requests: &str) -> Result<Vec<BatchOrderResult>, AppError> {
+        match self {
+            TradeGateways::BnSim(s) => {
+                let requests = serde_json::from_str::<Vec<String>>(requests).map_err(|e| AppError::new(-200, &e.to_string()))?;
+                return s.cancel_orders_by_id(symbol, requests)
+            },
+            TradeGateways::BnBacktest(s) => {
+                let requests = serde_json::from_str::<Vec<String>>(requests).map_err(|e| AppError::new(-200, &e.to_string()))?;
+                return s.cancel_orders_by_id(symbol, requests)
+            },
+            TradeGateways::BnReal(s) => {
+                let requests = serde_json::from_str::<Vec<String>>(requests).map_err(|e| AppError::new(-200, &e.to_string()))?;
+                return s.cancel_orders_by_id(symbol, requests)
+            },
+            TradeGateways::BbReal(s) => {
+                let requests = serde_json::from_str::<Vec<String>>(requests).map_err(|e| AppError::new(-200, &e.to_string()))?;
+                return s.cancel_orders_by_id(symbol, requests)
+            },
+            TradeGateways::BbSim(s) => {
+                let requests = serde_json::from_str::<Vec<String>>(requests).map_err(|e| AppError::new(-200, &e.to_string()))?;
+                return s.cancel_orders_by_id(symbol, requests)
+            },
+            TradeGateways::BbBacktest(s) => {
+                let requests = serde_json::from_str::<Vec<String>>(requests).map_err(|e| AppError::new(-200, &e.to_string()))?;
+                return s.cancel_orders_by_id(symbol, requests)
+            },
+            TradeGateways::CtpReal(s) => {
+                let symbol = Symbol::from_str(&symbol).map_err(|e| AppError::new(-200, &e.to_string()))?;
+                let requests = serde_json::from_str::<Vec<CancelOrderRequest>>(requests).map_err(|e| AppError::new(-200, &e.to_string()))?;
+                return s.cancel_orders_by_id(symbol, requests)
+            },
+            TradeGateways::CtpSim(s) => {
+                let symbol = Symbol::from_str(&symbol).map_err(|e| AppError::new(-200, &e.to_string()))?;
+                let requests = serde_json::from_str::<Vec<CancelOrderRequest>>(requests).map_err(|e| AppError::new(-200, &e.to_string()))?;
+                return s.cancel_orders_by_id(symbol, requests)
+            },
+            TradeGateways::CtpBacktest(s) => {
+                let symbol = Symbol::from_str(&symbol).map_err(|e| AppError::new(-200, &e.to_string()))?;
+                let requests = serde_json::from_str::<Vec<CancelOrderRequest>>(requests).map_err(|e| AppError::new(-200, &e.to_string()))?;
+                return s.cancel_orders_by_id(symbol, requests)
+            },
+        }
+    }
+
+    pub fn cancel_orders(&mut self, symbol: String) -> Result<(), AppError> {
+        match self {
+            TradeGateways::BnSim(s) => {
+                return s.cancel_orders(symbol)
+            },
+            TradeGateways::BnBacktest(s) => {
+                return s.cancel_orders(symbol)
+            },
+            TradeGateways::BnReal(s) => {
+                return s.cancel_orders(symbol)
+            },
+            TradeGateways::BbReal(s) => {
+                return s.cancel_orders(symbol)
+            },
+            TradeGateways::BbSim(s) => {
+                return s.cancel_orders(symbol)
+            },
+            TradeGateways::BbBacktest(s) => {
+                return s.cancel_orders(symbol)
+            },
+            TradeGateways::CtpReal(s) => {
+                let symbol = Symbol::from_str(&symbol).map_err(|e| AppError::new(-200, &e.to_string()))?;
+                return s.cancel_orders(symbol)
+            },
+            TradeGateways::CtpSim(s) => {
+                let symbol = Symbol::from_str(&symbol).map_err(|e| AppError::new(-200, &e.to_string()))?;
+                return s.cancel_orders(symbol)
+            },
+            TradeGateways::CtpBacktest(s) => {
+                let symbol = Symbol::from_str(&symbol).map_err(|e| AppError::new(-200, &e.to_string()))?;
+                return s.cancel_orders(symbol)
+            },
+        }
+    }
+
+    pub fn get_open_orders(&mut self, symbol: String) -> Result<Vec<Order>, AppError> {
+        match self {
+            TradeGateways::BnSim(s) => {
+                return s.get_open_orders(symbol)
+            },
+            TradeGateways::BnBacktest(s) => {
+                return s.get_open_orders(symbol)
+            },
+            TradeGateways::BnReal(s) => {
+                return s.get_open_orders(symbol)
+            },
+            TradeGateways::BbReal(s) => {
+                return s.get_open_orders(symbol)
+            },
+            TradeGateways::BbSim(s) => {
+                return s.get_open_orders(symbol)
+            },
+            TradeGateways::BbBacktest(s) => {
+                return s.get_open_orders(symbol)
+            },
+            TradeGateways::CtpReal(s) => {
+                let symbol = Symbol::from_str(&symbol).map_err(|e| AppError::new(-200, &e.to_string()))?;
+                return s.get_open_orders(symbol)
+            },
+            TradeGateways::CtpSim(s) => {
+                let symbol = Symbol::from_str(&symbol).map_err(|e| AppError::new(-200, &e.to_string()))?;
+                return s.get_open_orders(symbol)
+            },
+            TradeGateways::CtpBacktest(s) => {
+                let symbol = Symbol::from_str(&symbol).map_err(|e| AppError::new(-200, &e.to_string()))?;
+                return s.get_open_orders(symbol)
+            },
+        }
+    }
+
+    pub fn get_order(&mut self, symbol: String, client_order_id: &str) -> Result<Option<Order>, AppError> {
+        match self {
+            TradeGateways::BnSim(s) => {
+                return s.get_order(symbol, client_order_id)
+            },
+            TradeGateways::BnBacktest(s) => {
+                return s.get_order(symbol, client_order_id)
+            },
+            TradeGateways::BnReal(s) => {
+                return s.get_order(symbol, client_order_id)
+            },
+            TradeGateways::BbReal(s) => {
+                return s.get_order(symbol, client_order_id)
+            },
+            TradeGateways::BbSim(s) => {
+                return s.get_order(symbol, client_order_id)
+            },
+            TradeGateways::BbBacktest(s) => {
+                return s.get_order(symbol, client_order_id)
+            },
+            TradeGateways::CtpReal(s) => {
+                let symbol = Symbol::from_str(&symbol).map_err(|e| AppError::new(-200, &e.to_string()))?;
+                return s.get_order(symbol, client_order_id)
+            },
+            TradeGateways::CtpSim(s) => {
+                let symbol = Symbol::from_str(&symbol).map_err(|e| AppError::new(-200, &e.to_string()))?;
+                return s.get_order(symbol, client_order_id)
+            },
+            TradeGateways::CtpBacktest(s) => {
+                let symbol = Symbol::from_str(&symbol).map_err(|e| AppError::new(-200, &e.to_string()))?;
+                return s.get_order(symbol, client_order_id)
+            },
+        }
+    }
+
+    pub fn new_conditional_order(&mut self, symbol: String, request: &str) -> Result<ConditionalOrder, AppError> {
+        let ret = ConditionalOrderRequest::from_json(request)?;
+        match self {
+            TradeGateways::BnSim(s) => {
+                return s.new_conditional_order(symbol, ret)
+            },
+            TradeGateways::BnBacktest(s) => {
+                return s.new_conditional_order(symbol, ret)
+            },
+            TradeGateways::BnReal(s) => {
+                return s.new_conditional_order(symbol, ret)
+            },
+            TradeGateways::BbReal(s) => {
+                return s.new_conditional_order(symbol, ret)
+            },
+            TradeGateways::BbSim(s) => {
+                return s.new_conditional_order(symbol, ret)
+            },
+            TradeGateways::BbBacktest(s) => {
+                return s.new_conditional_order(symbol, ret)
+            },
+            TradeGateways::CtpReal(s) => {
+                let symbol = Symbol::from_str(&symbol).map_err(|e| AppError::new(-200, &e.to_string()))?;
+                return s.new_conditional_order(symbol, ret)
+            },
+            TradeGateways::CtpSim(s) => {
+                let symbol = Symbol::from_str(&symbol).map_err(|e| AppError::new(-200, &e.to_string()))?;
+                return s.new_conditional_order(symbol, ret)
+            },
+            TradeGateways::CtpBacktest(s) => {
+                let symbol = Symbol::from_str(&symbol).map_err(|e| AppError::new(-200, &e.to_string()))?;
+                return s.new_conditional_order(symbol, ret)
+            },
+        }
+    }
+
+    pub fn new_oco_order(&mut self, symbol: String, requests: &str) -> Result<Vec<ConditionalOrder>, AppError> {
+        let items = serde_json::from_str::<Vec<serde_json::Value>>(requests).map_err(|e| AppError::new(-200, &e.to_string()))?;
+        let mut ret = vec![];
+        for item in items.iter() {
+            ret.push(ConditionalOrderRequest::from_json(&item.to_string())?);
+        }
+        match self {
+            TradeGateways::BnSim(s) => {
+                return s.new_oco_order(symbol, ret)
+            },
+            TradeGateways::BnBacktest(s) => {
+                return s.new_oco_order(symbol, ret)
+            },
+            TradeGateways::BnReal(s) => {
+                return s.new_oco_order(symbol, ret)
+            },
+            TradeGateways::BbReal(s) => {
+                return s.new_oco_order(symbol, ret)
+            },
+            TradeGateways::BbSim(s) => {
+                return s.new_oco_order(symbol, ret)
+            },
+            TradeGateways::BbBacktest(s) => {
+                return s.new_oco_order(symbol, ret)
+            },
+            TradeGateways::CtpReal(s) => {
+                let symbol = Symbol::from_str(&symbol).map_err(|e| AppError::new(-200, &e.to_string()))?;
+                return s.new_oco_order(symbol, ret)
+            },
+            TradeGateways::CtpSim(s) => {
+                let symbol = Symbol::from_str(&symbol).map_err(|e| AppError::new(-200, &e.to_string()))?;
+                return s.new_oco_order(symbol, ret)
+            },
+            TradeGateways::CtpBacktest(s) => {
+                let symbol = Symbol::from_str(&symbol).map_err(|e| AppError::new(-200, &e.to_string()))?;
+                return s.new_oco_order(symbol, ret)
+            },
+        }
+    }
+
+    pub fn new_bracket_order(&mut self, symbol: String, request: &str) -> Result<Vec<ConditionalOrder>, AppError> {
+        let ret = BracketOrderRequest::from_json(request)?;
+        match self {
+            TradeGateways::BnSim(s) => {
+                return s.new_bracket_order(symbol, ret)
+            },
+            TradeGateways::BnBacktest(s) => {
+                return s.new_bracket_order(symbol, ret)
+            },
+            TradeGateways::BnReal(s) => {
+                return s.new_bracket_order(symbol, ret)
+            },
+            TradeGateways::BbReal(s) => {
+                return s.new_bracket_order(symbol, ret)
+            },
+            TradeGateways::BbSim(s) => {
+                return s.new_bracket_order(symbol, ret)
+            },
+            TradeGateways::BbBacktest(s) => {
+                return s.new_bracket_order(symbol, ret)
+            },
+            TradeGateways::CtpReal(s) => {
+                let symbol = Symbol::from_str(&symbol).map_err(|e| AppError::new(-200, &e.to_string()))?;
+                return s.new_bracket_order(symbol, ret)
+            },
+            TradeGateways::CtpSim(s) => {
+                let symbol = Symbol::from_str(&symbol).map_err(|e| AppError::new(-200, &e.to_string()))?;
+                return s.new_bracket_order(symbol, ret)
+            },
+            TradeGateways::CtpBacktest(s) => {
+                let symbol = Symbol::from_str(&symbol).map_err(|e| AppError::new(-200, &e.to_string()))?;
+                return s.new_bracket_order(symbol, ret)
+            },
+        }
+    }
+
+    pub fn cancel_conditional_order(&mut self, id: &str) -> Result<ConditionalOrder, AppError> {
+        match self {
+            TradeGateways::BnSim(s) => {
+                return s.cancel_conditional_order(id)
             },
             TradeGateways::BnBacktest(s) => {
-                return s.close()
+                return s.cancel_conditional_order(id)
             },
             TradeGateways::BnReal(s) => {
-                return s.close()
+                return s.cancel_conditional_order(id)
             },
             TradeGateways::BbReal(s) => {
-                return s.close()
+                return s.cancel_conditional_order(id)
             },
             TradeGateways::BbSim(s) => {
-                return s.close()
+                return s.cancel_conditional_order(id)
             },
             TradeGateways::BbBacktest(s) => {
-                return s.close()
+                return s.cancel_conditional_order(id)
             },
             TradeGateways::CtpReal(s) => {
-                return s.close()
+                return s.cancel_conditional_order(id)
             },
             TradeGateways::CtpSim(s) => {
-                return s.close()
+                return s.cancel_conditional_order(id)
             },
             TradeGateways::CtpBacktest(s) => {
-                return s.close()
+                return s.cancel_conditional_order(id)
             },
         }
     }
 
-    pub fn register_symbol(&mut self, symbol: String) -> Result<Receiver<TradeEvent>, AppError> {
+    pub fn get_conditional_orders(&mut self, symbol: String) -> Result<Vec<ConditionalOrder>, AppError> {
         match self {
             TradeGateways::BnSim(s) => {
-                return s.register_symbol(symbol)
+                return s.get_conditional_orders(symbol)
             },
             TradeGateways::BnBacktest(s) => {
-                return s.register_symbol(symbol)
+                return s.get_conditional_orders(symbol)
             },
             TradeGateways::BnReal(s) => {
-                return s.register_symbol(symbol)
+                return s.get_conditional_orders(symbol)
             },
             TradeGateways::BbReal(s) => {
-                return s.register_symbol(symbol)
+                return s.get_conditional_orders(symbol)
             },
             TradeGateways::BbSim(s) => {
-                return s.register_symbol(symbol)
+                return s.get_conditional_orders(symbol)
             },
             TradeGateways::BbBacktest(s) => {
-                return s.register_symbol(symbol)
+                return s.get_conditional_orders(symbol)
             },
             TradeGateways::CtpReal(s) => {
                 let symbol = Symbol::from_str(&symbol).map_err(|e| AppError::new(-200, &e.to_string()))?;
-                return s.register_symbol(symbol)
+                return s.get_conditional_orders(symbol)
             },
             TradeGateways::CtpSim(s) => {
                 let symbol = Symbol::from_str(&symbol).map_err(|e| AppError::new(-200, &e.to_string()))?;
-                return s.register_symbol(symbol)
+                return s.get_conditional_orders(symbol)
             },
             TradeGateways::CtpBacktest(s) => {
                 let symbol = Symbol::from_str(&symbol).map_err(|e| AppError::new(-200, &e.to_string()))?;
-                return s.register_symbol(symbol)
+                return s.get_conditional_orders(symbol)
             },
         }
     }
 
-    pub fn init_symbol(&mut self, symbol: String, config: &str) -> Result<Value, AppError> {
+    pub fn new_algo_order(&mut self, symbol: String, request: AlgoOrderRequest, history: Vec<KLine>) -> Result<AlgoOrder, AppError> {
         match self {
             TradeGateways::BnSim(s) => {
-                let ret = serde_json::from_str::<SymbolConfig>(config).map_err(|e| AppError::new(-200, &e.to_string()))?;
-                serde_json::to_value(s.init_symbol(symbol, ret)?).map_err(|e| AppError::new(-200, &e.to_string()))
+                return s.new_algo_order(symbol, request, history)
             },
             TradeGateways::BnBacktest(s) => {
-                let ret = serde_json::from_str::<SymbolConfig>(config).map_err(|e| AppError::new(-200, &e.to_string()))?;
-                serde_json::to_value(s.init_symbol(symbol, ret)?).map_err(|e| AppError::new(-200, &e.to_string()))
+                return s.new_algo_order(symbol, request, history)
             },
             TradeGateways::BnReal(s) => {
-                let ret = serde_json::from_str::<SymbolConfig>(config).map_err(|e| AppError::new(-200, &e.to_string()))?;
-                serde_json::to_value(s.init_symbol(symbol, ret)?).map_err(|e| AppError::new(-200, &e.to_string()))
+                return s.new_algo_order(symbol, request, history)
             },
             TradeGateways::BbReal(s) => {
-                let ret = serde_json::from_str::<BbSymbolConfig>(config).map_err(|e| AppError::new(-200, &e.to_string()))?;
-                serde_json::to_value(s.init_symbol(symbol, ret)?).map_err(|e| AppError::new(-200, &e.to_string()))
+                return s.new_algo_order(symbol, request, history)
             },
             TradeGateways::BbSim(s) => {
-                let ret = serde_json::from_str::<BbSymbolConfig>(config).map_err(|e| AppError::new(-200, &e.to_string()))?;
-                serde_json::to_value(s.init_symbol(symbol, ret)?).map_err(|e| AppError::new(-200, &e.to_string()))
+                return s.new_algo_order(symbol, request, history)
             },
             TradeGateways::BbBacktest(s) => {
-                let ret = serde_json::from_str::<BbSymbolConfig>(config).map_err(|e| AppError::new(-200, &e.to_string()))?;
-                serde_json::to_value(s.init_symbol(symbol, ret)?).map_err(|e| AppError::new(-200, &e.to_string()))
+                return s.new_algo_order(symbol, request, history)
             },
             TradeGateways::CtpReal(s) => {
                 let symbol = Symbol::from_str(&symbol).map_err(|e| AppError::new(-200, &e.to_string()))?;
-                serde_json::to_value(s.init_symbol(symbol, ())?).map_err(|e| AppError::new(-200, &e.to_string()))
+                return s.new_algo_order(symbol, request, history)
             },
             TradeGateways::CtpSim(s) => {
                 let symbol = Symbol::from_str(&symbol).map_err(|e| AppError::new(-200, &e.to_string()))?;
-                serde_json::to_value(s.init_symbol(symbol, ())?).map_err(|e| AppError::new(-200, &e.to_string()))
+                return s.new_algo_order(symbol, request, history)
             },
             TradeGateways::CtpBacktest(s) => {
                 let symbol = Symbol::from_str(&symbol).map_err(|e| AppError::new(-200, &e.to_string()))?;
-                serde_json::to_value(s.init_symbol(symbol, ())?).map_err(|e| AppError::new(-200, &e.to_string()))
+                return s.new_algo_order(symbol, request, history)
             },
         }
     }
 
-    pub fn new_order(&mut self, symbol: String, request : &str) -> Result<(), AppError> {
+    pub fn cancel_algo_order(&mut self, id: &str) -> Result<AlgoOrder, AppError> {
         match self {
             TradeGateways::BnSim(s) => {
-                let ret = serde_json::from_str::<NewOrderRequest>(request).map_err(|e| AppError::new(-200, &e.to_string()))?;
-                return s.new_order(symbol, ret)
+                return s.cancel_algo_order(id)
             },
             TradeGateways::BnBacktest(s) => {
-                let ret = serde_json::from_str::<NewOrderRequest>(request).map_err(|e| AppError::new(-200, &e.to_string()))?;
-                return s.new_order(symbol, ret)
+                return s.cancel_algo_order(id)
             },
             TradeGateways::BnReal(s) => {
-                let ret = serde_json::from_str::<NewOrderRequest>(request).map_err(|e| AppError::new(-200, &e.to_string()))?;
-                return s.new_order(symbol, ret)
+                return s.cancel_algo_order(id)
             },
             TradeGateways::BbReal(s) => {
-                let ret = serde_json::from_str::<BbNewOrderRequest>(request).map_err(|e| AppError::new(-200, &e.to_string()))?;
-                return s.new_order(symbol, ret)
+                return s.cancel_algo_order(id)
             },
             TradeGateways::BbSim(s) => {
-                let ret = serde_json::from_str::<BbNewOrderRequest>(request).map_err(|e| AppError::new(-200, &e.to_string()))?;
-                return s.new_order(symbol, ret)
+                return s.cancel_algo_order(id)
             },
             TradeGateways::BbBacktest(s) => {
-                let ret = serde_json::from_str::<BbNewOrderRequest>(request).map_err(|e| AppError::new(-200, &e.to_string()))?;
-                return s.new_order(symbol, ret)
+                return s.cancel_algo_order(id)
             },
             TradeGateways::CtpReal(s) => {
-                let symbol = Symbol::from_str(&symbol).map_err(|e| AppError::new(-200, &e.to_string()))?;
-                let ret = serde_json::from_str::<CtpNewOrderRequest>(request).map_err(|e| AppError::new(-200, &e.to_string()))?;
-                return s.new_order(symbol, ret)
+                return s.cancel_algo_order(id)
             },
             TradeGateways::CtpSim(s) => {
-                let symbol = Symbol::from_str(&symbol).map_err(|e| AppError::new(-200, &e.to_string()))?;
-                let ret = serde_json::from_str::<CtpNewOrderRequest>(request).map_err(|e| AppError::new(-200, &e.to_string()))?;
-                return s.new_order(symbol, ret)
+                return s.cancel_algo_order(id)
             },
             TradeGateways::CtpBacktest(s) => {
-                let symbol = Symbol::from_str(&symbol).map_err(|e| AppError::new(-200, &e.to_string()))?;
-                let ret = serde_json::from_str::<CtpNewOrderRequest>(request).map_err(|e| AppError::new(-200, &e.to_string()))?;
-                return s.new_order(symbol, ret)
+                return s.cancel_algo_order(id)
             },
         }
     }
 
-    pub fn cancel_order(&mut self,  symbol: String, request: &str) -> Result<(), AppError> {
+    pub fn get_algo_orders(&mut self, symbol: String) -> Result<Vec<AlgoOrder>, AppError> {
         match self {
             TradeGateways::BnSim(s) => {
-                return s.cancel_order(symbol, request.to_string())
+                return s.get_algo_orders(symbol)
             },
             TradeGateways::BnBacktest(s) => {
-                return s.cancel_order(symbol, request.to_string())
+                return s.get_algo_orders(symbol)
             },
             TradeGateways::BnReal(s) => {
-                return s.cancel_order(symbol, request.to_string())
+                return s.get_algo_orders(symbol)
             },
             TradeGateways::BbReal(s) => {
-                return s.cancel_order(symbol, request.to_string())
+                return s.get_algo_orders(symbol)
             },
             TradeGateways::BbSim(s) => {
-                return s.cancel_order(symbol, request.to_string())
+                return s.get_algo_orders(symbol)
             },
             TradeGateways::BbBacktest(s) => {
-                return s.cancel_order(symbol, request.to_string())
+                return s.get_algo_orders(symbol)
             },
             TradeGateways::CtpReal(s) => {
                 let symbol = Symbol::from_str(&symbol).map_err(|e| AppError::new(-200, &e.to_string()))?;
-                let request = serde_json::from_str::<CancelOrderRequest>(request).map_err(|e| AppError::new(-200, &e.to_string()))?;
-                return s.cancel_order(symbol, request)
+                return s.get_algo_orders(symbol)
             },
             TradeGateways::CtpSim(s) => {
                 let symbol = Symbol::from_str(&symbol).map_err(|e| AppError::new(-200, &e.to_string()))?;
-                let request = serde_json::from_str::<CancelOrderRequest>(request).map_err(|e| AppError::new(-200, &e.to_string()))?;
-                return s.cancel_order(symbol, request)
+                return s.get_algo_orders(symbol)
             },
             TradeGateways::CtpBacktest(s) => {
                 let symbol = Symbol::from_str(&symbol).map_err(|e| AppError::new(-200, &e.to_string()))?;
-                let request = serde_json::from_str::<CancelOrderRequest>(request).map_err(|e| AppError::new(-200, &e.to_string()))?;
-                return s.cancel_order(symbol, request)
+                return s.get_algo_orders(symbol)
             },
         }
     }
 
-    pub fn cancel_orders(&mut self, symbol: String) -> Result<(), AppError> {
+    pub fn set_risk_config(&mut self, config: &str) -> Result<(), AppError> {
+        let ret = serde_json::from_str::<RiskConfig>(config).map_err(|e| AppError::new(-200, &e.to_string()))?;
         match self {
             TradeGateways::BnSim(s) => {
-                return s.cancel_orders(symbol)
+                s.set_risk_config(ret);
             },
             TradeGateways::BnBacktest(s) => {
-                return s.cancel_orders(symbol)
+                s.set_risk_config(ret);
             },
             TradeGateways::BnReal(s) => {
-                return s.cancel_orders(symbol)
+                s.set_risk_config(ret);
             },
             TradeGateways::BbReal(s) => {
-                return s.cancel_orders(symbol)
+                s.set_risk_config(ret);
             },
             TradeGateways::BbSim(s) => {
-                return s.cancel_orders(symbol)
+                s.set_risk_config(ret);
             },
             TradeGateways::BbBacktest(s) => {
-                return s.cancel_orders(symbol)
+                s.set_risk_config(ret);
             },
             TradeGateways::CtpReal(s) => {
-                let symbol = Symbol::from_str(&symbol).map_err(|e| AppError::new(-200, &e.to_string()))?;
-                return s.cancel_orders(symbol)
+                s.set_risk_config(ret);
             },
             TradeGateways::CtpSim(s) => {
-                let symbol = Symbol::from_str(&symbol).map_err(|e| AppError::new(-200, &e.to_string()))?;
-                return s.cancel_orders(symbol)
+                s.set_risk_config(ret);
             },
             TradeGateways::CtpBacktest(s) => {
-                let symbol = Symbol::from_str(&symbol).map_err(|e| AppError::new(-200, &e.to_string()))?;
-                return s.cancel_orders(symbol)
+                s.set_risk_config(ret);
+            },
+        }
+        Ok(())
+    }
+
+    pub fn get_risk_config(&mut self) -> Result<RiskConfig, AppError> {
+        match self {
+            TradeGateways::BnSim(s) => {
+                return Ok(s.get_risk_config())
+            },
+            TradeGateways::BnBacktest(s) => {
+                return Ok(s.get_risk_config())
+            },
+            TradeGateways::BnReal(s) => {
+                return Ok(s.get_risk_config())
+            },
+            TradeGateways::BbReal(s) => {
+                return Ok(s.get_risk_config())
+            },
+            TradeGateways::BbSim(s) => {
+                return Ok(s.get_risk_config())
+            },
+            TradeGateways::BbBacktest(s) => {
+                return Ok(s.get_risk_config())
+            },
+            TradeGateways::CtpReal(s) => {
+                return Ok(s.get_risk_config())
+            },
+            TradeGateways::CtpSim(s) => {
+                return Ok(s.get_risk_config())
+            },
+            TradeGateways::CtpBacktest(s) => {
+                return Ok(s.get_risk_config())
+            },
+        }
+    }
+
+    pub fn kill_switch(&mut self, flatten: bool) -> Result<KillSwitchReport, AppError> {
+        match self {
+            TradeGateways::BnSim(s) => {
+                return s.kill_switch(flatten)
+            },
+            TradeGateways::BnBacktest(s) => {
+                return s.kill_switch(flatten)
+            },
+            TradeGateways::BnReal(s) => {
+                return s.kill_switch(flatten)
+            },
+            TradeGateways::BbReal(s) => {
+                return s.kill_switch(flatten)
+            },
+            TradeGateways::BbSim(s) => {
+                return s.kill_switch(flatten)
+            },
+            TradeGateways::BbBacktest(s) => {
+                return s.kill_switch(flatten)
+            },
+            TradeGateways::CtpReal(s) => {
+                return s.kill_switch(flatten)
+            },
+            TradeGateways::CtpSim(s) => {
+                return s.kill_switch(flatten)
+            },
+            TradeGateways::CtpBacktest(s) => {
+                return s.kill_switch(flatten)
+            },
+        }
+    }
+
+    pub fn arm_dead_mans_switch(&mut self, timeout: u64, flatten: bool) -> Result<DeadMansSwitch, AppError> {
+        match self {
+            TradeGateways::BnSim(s) => {
+                return s.arm_dead_mans_switch(timeout, flatten)
+            },
+            TradeGateways::BnBacktest(s) => {
+                return s.arm_dead_mans_switch(timeout, flatten)
+            },
+            TradeGateways::BnReal(s) => {
+                return s.arm_dead_mans_switch(timeout, flatten)
+            },
+            TradeGateways::BbReal(s) => {
+                return s.arm_dead_mans_switch(timeout, flatten)
+            },
+            TradeGateways::BbSim(s) => {
+                return s.arm_dead_mans_switch(timeout, flatten)
+            },
+            TradeGateways::BbBacktest(s) => {
+                return s.arm_dead_mans_switch(timeout, flatten)
+            },
+            TradeGateways::CtpReal(s) => {
+                return s.arm_dead_mans_switch(timeout, flatten)
+            },
+            TradeGateways::CtpSim(s) => {
+                return s.arm_dead_mans_switch(timeout, flatten)
+            },
+            TradeGateways::CtpBacktest(s) => {
+                return s.arm_dead_mans_switch(timeout, flatten)
+            },
+        }
+    }
+
+    pub fn heartbeat(&mut self) -> Result<DeadMansSwitch, AppError> {
+        match self {
+            TradeGateways::BnSim(s) => {
+                return s.heartbeat()
+            },
+            TradeGateways::BnBacktest(s) => {
+                return s.heartbeat()
+            },
+            TradeGateways::BnReal(s) => {
+                return s.heartbeat()
+            },
+            TradeGateways::BbReal(s) => {
+                return s.heartbeat()
+            },
+            TradeGateways::BbSim(s) => {
+                return s.heartbeat()
+            },
+            TradeGateways::BbBacktest(s) => {
+                return s.heartbeat()
+            },
+            TradeGateways::CtpReal(s) => {
+                return s.heartbeat()
+            },
+            TradeGateways::CtpSim(s) => {
+                return s.heartbeat()
+            },
+            TradeGateways::CtpBacktest(s) => {
+                return s.heartbeat()
+            },
+        }
+    }
+
+    pub fn disarm_dead_mans_switch(&mut self) -> Result<DeadMansSwitch, AppError> {
+        match self {
+            TradeGateways::BnSim(s) => {
+                return s.disarm_dead_mans_switch()
+            },
+            TradeGateways::BnBacktest(s) => {
+                return s.disarm_dead_mans_switch()
+            },
+            TradeGateways::BnReal(s) => {
+                return s.disarm_dead_mans_switch()
+            },
+            TradeGateways::BbReal(s) => {
+                return s.disarm_dead_mans_switch()
+            },
+            TradeGateways::BbSim(s) => {
+                return s.disarm_dead_mans_switch()
+            },
+            TradeGateways::BbBacktest(s) => {
+                return s.disarm_dead_mans_switch()
+            },
+            TradeGateways::CtpReal(s) => {
+                return s.disarm_dead_mans_switch()
+            },
+            TradeGateways::CtpSim(s) => {
+                return s.disarm_dead_mans_switch()
+            },
+            TradeGateways::CtpBacktest(s) => {
+                return s.disarm_dead_mans_switch()
+            },
+        }
+    }
+
+    pub fn get_session_orders(&mut self, symbol: &str) -> Result<Vec<ManagedOrder>, AppError> {
+        match self {
+            TradeGateways::BnSim(s) => {
+                return Ok(s.get_session_orders(symbol))
+            },
+            TradeGateways::BnBacktest(s) => {
+                return Ok(s.get_session_orders(symbol))
+            },
+            TradeGateways::BnReal(s) => {
+                return Ok(s.get_session_orders(symbol))
+            },
+            TradeGateways::BbReal(s) => {
+                return Ok(s.get_session_orders(symbol))
+            },
+            TradeGateways::BbSim(s) => {
+                return Ok(s.get_session_orders(symbol))
+            },
+            TradeGateways::BbBacktest(s) => {
+                return Ok(s.get_session_orders(symbol))
+            },
+            TradeGateways::CtpReal(s) => {
+                return Ok(s.get_session_orders(symbol))
+            },
+            TradeGateways::CtpSim(s) => {
+                return Ok(s.get_session_orders(symbol))
+            },
+            TradeGateways::CtpBacktest(s) => {
+                return Ok(s.get_session_orders(symbol))
+            },
+        }
+    }
+
+    pub fn get_session_order(&mut self, symbol: &str, client_order_id: &str) -> Result<Option<ManagedOrder>, AppError> {
+        match self {
+            TradeGateways::BnSim(s) => {
+                return Ok(s.get_session_order(symbol, client_order_id))
+            },
+            TradeGateways::BnBacktest(s) => {
+                return Ok(s.get_session_order(symbol, client_order_id))
+            },
+            TradeGateways::BnReal(s) => {
+                return Ok(s.get_session_order(symbol, client_order_id))
+            },
+            TradeGateways::BbReal(s) => {
+                return Ok(s.get_session_order(symbol, client_order_id))
+            },
+            TradeGateways::BbSim(s) => {
+                return Ok(s.get_session_order(symbol, client_order_id))
+            },
+            TradeGateways::BbBacktest(s) => {
+                return Ok(s.get_session_order(symbol, client_order_id))
+            },
+            TradeGateways::CtpReal(s) => {
+                return Ok(s.get_session_order(symbol, client_order_id))
+            },
+            TradeGateways::CtpSim(s) => {
+                return Ok(s.get_session_order(symbol, client_order_id))
+            },
+            TradeGateways::CtpBacktest(s) => {
+                return Ok(s.get_session_order(symbol, client_order_id))
             },
         }
     }
@@ -660,11 +1410,38 @@ impl TradeGateways {
             },
         }
     }
+
+    pub fn get_backtest_report(&self) -> Result<BacktestReport, AppError> {
+        match self {
+            TradeGateways::BnSim(s) => {
+                return Ok(s.get_server().inner.get_report())
+            },
+            TradeGateways::BnBacktest(s) => {
+                return Ok(s.get_server().inner.get_report())
+            },
+            TradeGateways::BbSim(s) => {
+                return Ok(s.get_server().inner.get_report())
+            },
+            TradeGateways::BbBacktest(s) => {
+                return Ok(s.get_server().inner.get_report())
+            },
+            TradeGateways::CtpSim(s) => {
+                return Ok(s.get_server().inner.get_report())
+            },
+            TradeGateways::CtpBacktest(s) => {
+                return Ok(s.get_server().inner.get_report())
+            },
+            _ => {
+                return Err(AppError::new(-200, "The backtest report is only available in sim and backtest mode"))
+            },
+        }
+    }
 }
 
 
 pub static mut MARKET_GATEWAY: Option<Arc<Mutex<MarketGateways>>> = None;
 pub static mut TRADE_GATEWAY: Option<Arc<Mutex<TradeGateways>>> = None;
+pub static MARKET_RECORDER: Mutex<Option<MarketRecorder>> = Mutex::new(None);
 
 pub fn get_market_gateway() -> Arc<Mutex<MarketGateways>> {
     unsafe {
@@ -678,6 +1455,31 @@ pub fn get_trade_gateway() -> Arc<Mutex<TradeGateways>> {
     }
 }
 
+pub fn start_recorder(config: &str) -> Result<(), AppError> {
+    let config = serde_json::from_str::<RecorderConfig>(config).map_err(|e| AppError::new(-200, &e.to_string()))?;
+    stop_recorder();
+    let subscription = get_market_gateway().lock().unwrap().get_tick_sub();
+    let recorder = MarketRecorder::start(config, subscription)?;
+    *MARKET_RECORDER.lock().unwrap() = Some(recorder);
+    Ok(())
+}
+
+pub fn new_algo_order(symbol: &str, request: &str) -> Result<AlgoOrder, AppError> {
+    let request = AlgoOrderRequest::from_json(request)?;
+    let history = if request.algo == AlgoKind::Vwap {
+        get_market_gateway().lock().unwrap().load_kline(symbol.to_string(), &request.kline_interval(), request.kline_lookback())?
+    } else {
+        vec![]
+    };
+    get_trade_gateway().lock().unwrap().new_algo_order(symbol.to_string(), request, history)
+}
+
+pub fn stop_recorder() {
+    if let Some(mut recorder) = MARKET_RECORDER.lock().unwrap().take() {
+        recorder.close();
+    }
+}
+
 pub fn init(exchange: &str, mode: &str, config: &str) -> Result<(), AppError>{
     match exchange {
         "binance" => {
@@ -713,6 +1515,10 @@ pub fn init(exchange: &str, mode: &str, config: &str) -> Result<(), AppError>{
                         asset: config.asset,
                         balance: config.balance,
                         order_completed_status: config.order_completed_status.clone(),
+                        leverage: Some(config.leverage),
+                        fill_ratio: config.fill_ratio,
+                        fee: config.fee.clone(),
+                        slippage: config.slippage.clone(),
                     });
                     unsafe {
                         MARKET_GATEWAY = Some(Arc::new(Mutex::new(MarketGateways::BnSim(MarketGateway::new(Box::new(market_server))))));
@@ -729,11 +1535,18 @@ pub fn init(exchange: &str, mode: &str, config: &str) -> Result<(), AppError>{
                         end_time: config.end_time,
                         interval: config.interval,
                         lines_per_sec: config.lines_per_sec,
+                        clock_mode: config.clock_mode,
+                        kline_store: config.kline_store.clone(),
+                        tick_replay: config.tick_replay.clone(),
                     });
                     let trade_server = BnSimTradeServer::new(SimTradeConfig {
                         asset: config.asset,
                         balance: config.balance,
                         order_completed_status: config.order_completed_status.clone(),
+                        leverage: Some(config.leverage),
+                        fill_ratio: config.fill_ratio,
+                        fee: config.fee.clone(),
+                        slippage: config.slippage.clone(),
                     });
                     unsafe {
                         MARKET_GATEWAY = Some(Arc::new(Mutex::new(MarketGateways::BnBacktest(MarketGateway::new(Box::new(market_server))))));
@@ -777,6 +1590,10 @@ pub fn init(exchange: &str, mode: &str, config: &str) -> Result<(), AppError>{
                         order_completed_status: config.order_completed_status.clone(),
                         asset: config.asset.clone(),
                         balance: config.balance,
+                        leverage: None,
+                        fill_ratio: config.fill_ratio,
+                        fee: config.fee.clone(),
+                        slippage: config.slippage.clone(),
                     });  
                     unsafe {
                         MARKET_GATEWAY = Some(Arc::new(Mutex::new(MarketGateways::BbSim(MarketGateway::new(Box::new(market_server))))));
@@ -792,11 +1609,18 @@ pub fn init(exchange: &str, mode: &str, config: &str) -> Result<(), AppError>{
                         end_time: config.end_time,
                         interval: config.interval,
                         lines_per_sec: config.lines_per_sec,
+                        clock_mode: config.clock_mode,
+                        kline_store: config.kline_store.clone(),
+                        tick_replay: config.tick_replay.clone(),
                     });
                     let trade_server = BbSimTradeServer::new(SimTradeConfig {       
                         order_completed_status: config.order_completed_status.clone(),
                         asset: config.asset.clone(),
                         balance: config.balance,
+                        leverage: Some(config.leverage),
+                        fill_ratio: config.fill_ratio,
+                        fee: config.fee.clone(),
+                        slippage: config.slippage.clone(),
                     });  
                     unsafe {
                         MARKET_GATEWAY = Some(Arc::new(Mutex::new(MarketGateways::BbBacktest(MarketGateway::new(Box::new(market_server))))));
@@ -813,8 +1637,9 @@ pub fn init(exchange: &str, mode: &str, config: &str) -> Result<(), AppError>{
                 "real" => {
                     let config = serde_json::from_str::<CtpConfig>(config).map_err(|e| AppError::new(-200, &e.to_string()))?;
                     log::init(log::Level::from_str(&config.log_level.to_uppercase()).unwrap(), false);
-                    let market_server = CtpMarketServer::new(config.clone());
-                    let trade_server = CtpTradeServer::new(config.clone());
+                    let calendar = Arc::new(TradingCalendar::new(&config.calendar.clone().unwrap_or_default())?);
+                    let market_server = CtpMarketServer::new(config.clone(), calendar.clone());
+                    let trade_server = CtpTradeServer::new(config.clone(), calendar);
                     unsafe {
                         MARKET_GATEWAY = Some(Arc::new(Mutex::new(MarketGateways::CtpReal(MarketGateway::new(Box::new(market_server))))));
                         TRADE_GATEWAY = Some(Arc::new(Mutex::new(TradeGateways::CtpReal(TradeGateway::new(Box::new(trade_server))))));
@@ -823,6 +1648,7 @@ pub fn init(exchange: &str, mode: &str, config: &str) -> Result<(), AppError>{
                 "sim" => {
                     let config = serde_json::from_str::<CtpSimConfig>(config).map_err(|e| AppError::new(-200, &e.to_string()))?;
                     log::init(log::Level::from_str(&config.log_level.to_uppercase()).unwrap(), false);
+                    let calendar = Arc::new(TradingCalendar::new(&config.calendar.clone().unwrap_or_default())?);
                     let market_server = CtpMarketServer::new(CtpConfig {
                         log_level: config.log_level.clone(),
                         flow_path: config.flow_path.clone(),
@@ -835,11 +1661,16 @@ pub fn init(exchange: &str, mode: &str, config: &str) -> Result<(), AppError>{
                         broker_id: config.broker_id.clone(),
                         user_id: config.user_id.clone(),
                         password: config.password.clone(),
-                    });
+                        calendar: config.calendar.clone(),
+                    }, calendar);
                     let trade_server = CtpSimTradeServer::new(SimTradeConfig {       
                         order_completed_status: config.order_completed_status.clone(),
                         asset: config.asset.clone(),
                         balance: config.balance,
+                        leverage: None,
+                        fill_ratio: config.fill_ratio,
+                        fee: config.fee.clone(),
+                        slippage: config.slippage.clone(),
                     });
                     unsafe {
                         MARKET_GATEWAY = Some(Arc::new(Mutex::new(MarketGateways::CtpSim(MarketGateway::new(Box::new(market_server))))));
@@ -855,11 +1686,18 @@ pub fn init(exchange: &str, mode: &str, config: &str) -> Result<(), AppError>{
                         end_time: config.end_time,
                         interval: config.interval,
                         lines_per_sec: config.lines_per_sec,
+                        clock_mode: config.clock_mode,
+                        kline_store: config.kline_store.clone(),
+                        tick_replay: config.tick_replay.clone(),
                     });
                     let trade_server = CtpSimTradeServer::new(SimTradeConfig {       
                         order_completed_status: config.order_completed_status.clone(),
                         asset: config.asset.clone(),
                         balance: config.balance,
+                        leverage: Some(config.leverage),
+                        fill_ratio: config.fill_ratio,
+                        fee: config.fee.clone(),
+                        slippage: config.slippage.clone(),
                     });  
                     unsafe {
                         MARKET_GATEWAY = Some(Arc::new(Mutex::new(MarketGateways::CtpBacktest(MarketGateway::new(Box::new(market_server))))));
@@ -2,6 +2,9 @@ use std::ffi::CString;
 
 use binance_future_connector::{market_stream::enums::{Level, UpdateSpeed}, trade::enums::{MarginAssetMode, PositionMode}};
 use serde::{Serialize, Deserialize};
+use market::{backtest_clock::ClockMode, kline_store::KLineStoreConfig, tick_replay::TickReplayConfig};
+use trade::cost_model::{FeeModel, SlippageModel};
+use ctp::ctp_calendar::CalendarConfig;
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(rename_all = "camelCase")]
@@ -71,10 +74,16 @@ pub struct BacktestConfig {
     pub end_time: u64,
     pub interval: u64,
     pub lines_per_sec: u64,
+    pub clock_mode: Option<ClockMode>,
+    pub kline_store: Option<KLineStoreConfig>,
+    pub tick_replay: Option<TickReplayConfig>,
     pub asset: String,
     pub balance: u64,
     pub leverage: u64,
     pub order_completed_status: String,
+    pub fill_ratio: Option<f64>,
+    pub fee: Option<FeeModel>,
+    pub slippage: Option<SlippageModel>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize,)]
@@ -100,6 +109,9 @@ pub struct BnSimConfig {
     pub balance: u64,
     pub leverage: u64,
     pub order_completed_status: String,
+    pub fill_ratio: Option<f64>,
+    pub fee: Option<FeeModel>,
+    pub slippage: Option<SlippageModel>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize,)]
@@ -122,6 +134,9 @@ pub struct BbSimConfig {
     pub asset: String,
     pub balance: u64,
     pub order_completed_status: String,
+    pub fill_ratio: Option<f64>,
+    pub fee: Option<FeeModel>,
+    pub slippage: Option<SlippageModel>,
 }
 
 
@@ -142,4 +157,8 @@ pub struct CtpSimConfig {
     pub asset: String,
     pub balance: u64,
     pub order_completed_status: String,
+    pub fill_ratio: Option<f64>,
+    pub fee: Option<FeeModel>,
+    pub slippage: Option<SlippageModel>,
+    pub calendar: Option<CalendarConfig>,
 }
\ No newline at end of file
@@ -1,14 +1,21 @@
 use std::os::raw::*;
 use std::ffi::CString;
-use std::thread;
 use common::c::*;
+use market::backtest_clock::BACKTEST_CLOCK;
 use market::market_server::{KLine, MarketData};
 use serde_json::Value;
-use trade::trade_server::{Position, TradeEvent, Wallet};
+use trade::backtest_report::BacktestReport;
+use trade::algo_order::AlgoOrder;
+use trade::conditional_order::ConditionalOrder;
+use trade::kill_switch::{DeadMansSwitch, KillSwitchReport};
+use trade::order_manager::ManagedOrder;
+use trade::risk::RiskConfig;
+use trade::trade_server::{BatchOrderResult, Order, OrderAck, Position, TradeEvent, Wallet};
 use crate::model::ServiceResult;
 use crate::context;
 use log::*;
 
+const BACKTEST_ENDED_EVENT: &str = "{\"event\":\"BACKTEST_ENDED\"}";
 
 #[no_mangle]
 pub extern "C" fn init(exchange: *const c_char, mode: *const c_char, config: *const c_char) -> Box<CString> {
@@ -48,6 +55,7 @@ pub extern "C" fn init(exchange: *const c_char, mode: *const c_char, config: *co
 #[no_mangle]
 pub extern "C" fn close() -> Box<CString> {
     let result = ServiceResult::<usize>::new(0, "", None);
+    context::stop_recorder();
     let market_gateway_ref = context::get_market_gateway();
     let market_gateway = market_gateway_ref.lock().unwrap();
     let _ = market_gateway.close();
@@ -79,6 +87,7 @@ pub extern "C" fn start() -> Box<CString> {
 
     let market_gateway_ref = context::get_market_gateway();
     let mut market_gateway = market_gateway_ref.lock().unwrap();
+    BACKTEST_CLOCK.hold();
     let ret = market_gateway.start();
     if ret.is_err() {
         result.error_code = -1;
@@ -91,8 +100,45 @@ pub extern "C" fn start() -> Box<CString> {
         if ret.is_err() {
             result.error_code = -1;
             result.message = format!("{:?}", ret.unwrap_err());
+        } else {
+            let ret = trade_gateway.bind_market(market_gateway.get_tick_sub());
+            if ret.is_err() {
+                result.error_code = -1;
+                result.message = format!("{:?}", ret.unwrap_err());
+            }
         }
     }
+    BACKTEST_CLOCK.release();
+    result.to_c_json()
+}
+
+#[no_mangle]
+pub extern "C" fn start_recorder(config: *const c_char) -> Box<CString> {
+    let mut result = ServiceResult::<String>::new(0, "", None);
+    let config_rust = c_char_to_string(config);
+    let ret = context::start_recorder(&config_rust);
+    if ret.is_err() {
+        result.error_code = -1;
+        result.message = format!("{:?}", ret.unwrap_err());
+    }
+    result.to_c_json()
+}
+
+#[no_mangle]
+pub extern "C" fn stop_recorder() -> Box<CString> {
+    let result = ServiceResult::<String>::new(0, "", None);
+    context::stop_recorder();
+    result.to_c_json()
+}
+
+#[no_mangle]
+pub extern "C" fn step(count: i32) -> Box<CString> {
+    let mut result = ServiceResult::<String>::new(0, "", None);
+    let ret = BACKTEST_CLOCK.step(count.max(1) as u64);
+    if ret.is_err() {
+        result.error_code = -1;
+        result.message = format!("{:?}", ret.unwrap_err());
+    }
     result.to_c_json()
 }
 
@@ -108,21 +154,29 @@ pub extern "C" fn subscribe_kline(sub_id : *const c_char, symbol : *const c_char
 
     let ret = gateway.subscribe_kline(symbol_rust.clone(), interval_rust.as_str());
     if ret.is_ok() {
-        let rx  = ret.unwrap();
+        let mut sub = ret.unwrap();
         let sub_id_rust = CString::new(c_char_to_string(sub_id)).expect("CString failed");
-        thread::spawn(move || {
-            loop {
-                if let Ok(data) = rx.recv() {
-                    match data {
-                        MarketData::Kline(k) => {
-                            let json = serde_json::to_string(&k).unwrap();
-                            let json_rust = CString::new(json).expect("CString failed");
-                            callback(sub_id_rust.as_ptr(), json_rust.as_ptr());
-                        },
-                        _ => {},
-                    }
+        let _ = sub.stream(move |event| {
+            if let Some(data) = event {
+                match data {
+                    MarketData::Kline(k) => {
+                        let json = serde_json::to_string(&k).unwrap();
+                        let json_rust = CString::new(json).expect("CString failed");
+                        callback(sub_id_rust.as_ptr(), json_rust.as_ptr());
+                    },
+                    MarketData::BacktestEnded => {
+                        let json_rust = CString::new(BACKTEST_ENDED_EVENT).expect("CString failed");
+                        callback(sub_id_rust.as_ptr(), json_rust.as_ptr());
+                    },
+                    MarketData::TradeDayEnded(trading_day) => {
+                        let json = serde_json::json!({"event": "TRADE_DAY_ENDED", "tradingDay": trading_day}).to_string();
+                        let json_rust = CString::new(json).expect("CString failed");
+                        callback(sub_id_rust.as_ptr(), json_rust.as_ptr());
+                    },
+                    _ => {},
                 }
             }
+            Ok(false)
         });
     } else {
         result.error_code = -1;
@@ -153,21 +207,29 @@ pub extern "C" fn subscribe_tick(sub_id : *const c_char, symbol : *const c_char,
     let mut gateway = gateway_ref.lock().unwrap();
     let ret = gateway.subscribe_tick(symbol_rust);
     if ret.is_ok() {
-        let rx  = ret.unwrap();
+        let mut sub = ret.unwrap();
         let sub_id_rust = CString::new(c_char_to_string(sub_id)).expect("CString failed");
-        thread::spawn(move || {
-            loop {
-                if let Ok(data) = rx.recv() {
-                    match data {
-                        MarketData::Tick(tick) => {
-                            let json = serde_json::to_string(&tick).unwrap();
-                            let json_rust = CString::new(json).expect("CString failed");
-                            callback(sub_id_rust.as_ptr(), json_rust.as_ptr());
-                        },
-                        _ => {},
-                    }
+        let _ = sub.stream(move |event| {
+            if let Some(data) = event {
+                match data {
+                    MarketData::Tick(tick) => {
+                        let json = serde_json::to_string(&tick).unwrap();
+                        let json_rust = CString::new(json).expect("CString failed");
+                        callback(sub_id_rust.as_ptr(), json_rust.as_ptr());
+                    },
+                    MarketData::BacktestEnded => {
+                        let json_rust = CString::new(BACKTEST_ENDED_EVENT).expect("CString failed");
+                        callback(sub_id_rust.as_ptr(), json_rust.as_ptr());
+                    },
+                    MarketData::TradeDayEnded(trading_day) => {
+                        let json = serde_json::json!({"event": "TRADE_DAY_ENDED", "tradingDay": trading_day}).to_string();
+                        let json_rust = CString::new(json).expect("CString failed");
+                        callback(sub_id_rust.as_ptr(), json_rust.as_ptr());
+                    },
+                    _ => {},
                 }
             }
+            Ok(false)
         });
     } else {
         result.error_code = -1;
@@ -179,16 +241,54 @@ pub extern "C" fn subscribe_tick(sub_id : *const c_char, symbol : *const c_char,
 
 #[no_mangle]
 pub extern "C" fn new_order(symbol : *const c_char, order_request: *const c_char) -> Box<CString> {
-    let mut result = ServiceResult::<String>::new(0, "", None);
+    let mut result = ServiceResult::<OrderAck>::new(0, "", None);
     let symbol_rust = c_char_to_string(symbol);
     let order_request_rust = c_char_to_string(order_request);
 
     let gateway_ref = context::get_trade_gateway();
     let mut gateway = gateway_ref.lock().unwrap();
-    let ret = gateway.new_order(symbol_rust, &order_request_rust);
-    if ret.is_err() {
-        result.error_code = -1;
-        result.message = format!("{:?}", ret.unwrap_err());
+    match gateway.new_order(symbol_rust, &order_request_rust) {
+        Ok(ack) => result.data = Some(ack),
+        Err(e) => {
+            result.error_code = -1;
+            result.message = format!("{:?}", e);
+        },
+    }
+    result.to_c_json()
+}
+
+#[no_mangle]
+pub extern "C" fn new_orders(symbol : *const c_char, order_requests: *const c_char) -> Box<CString> {
+    let mut result = ServiceResult::<Vec<BatchOrderResult>>::new(0, "", None);
+    let symbol_rust = c_char_to_string(symbol);
+    let order_requests_rust = c_char_to_string(order_requests);
+
+    let gateway_ref = context::get_trade_gateway();
+    let mut gateway = gateway_ref.lock().unwrap();
+    match gateway.new_orders(symbol_rust, &order_requests_rust) {
+        Ok(results) => result.data = Some(results),
+        Err(e) => {
+            result.error_code = -1;
+            result.message = format!("{:?}", e);
+        },
+    }
+    result.to_c_json()
+}
+
+#[no_mangle]
+pub extern "C" fn modify_order(symbol : *const c_char, modify_request: *const c_char) -> Box<CString> {
+    let mut result = ServiceResult::<OrderAck>::new(0, "", None);
+    let symbol_rust = c_char_to_string(symbol);
+    let modify_request_rust = c_char_to_string(modify_request);
+
+    let gateway_ref = context::get_trade_gateway();
+    let mut gateway = gateway_ref.lock().unwrap();
+    match gateway.modify_order(symbol_rust, &modify_request_rust) {
+        Ok(ack) => result.data = Some(ack),
+        Err(e) => {
+            result.error_code = -1;
+            result.message = format!("{:?}", e);
+        },
     }
     result.to_c_json()
 }
@@ -210,6 +310,24 @@ pub extern "C" fn cancel_order(symbol : *const c_char, order_id : *const c_char)
     result.to_c_json()
 }
 
+#[no_mangle]
+pub extern "C" fn cancel_orders_by_id(symbol : *const c_char, cancel_requests: *const c_char) -> Box<CString> {
+    let mut result = ServiceResult::<Vec<BatchOrderResult>>::new(0, "", None);
+    let symbol_rust = c_char_to_string(symbol);
+    let cancel_requests_rust = c_char_to_string(cancel_requests);
+
+    let gateway_ref = context::get_trade_gateway();
+    let mut gateway = gateway_ref.lock().unwrap();
+    match gateway.cancel_orders_by_id(symbol_rust, &cancel_requests_rust) {
+        Ok(results) => result.data = Some(results),
+        Err(e) => {
+            result.error_code = -1;
+            result.message = format!("{:?}", e);
+        },
+    }
+    result.to_c_json()
+}
+
 #[no_mangle]
 pub extern "C" fn cancel_orders(symbol : *const c_char) -> Box<CString> {
     let mut result = ServiceResult::<String>::new(0, "", None);
@@ -226,6 +344,211 @@ pub extern "C" fn cancel_orders(symbol : *const c_char) -> Box<CString> {
     result.to_c_json()
 }
 
+#[no_mangle]
+pub extern "C" fn new_conditional_order(symbol : *const c_char, request : *const c_char) -> Box<CString> {
+    let mut result = ServiceResult::<ConditionalOrder>::new(0, "", None);
+    let symbol_rust = c_char_to_string(symbol);
+    let request_rust = c_char_to_string(request);
+
+    let gateway_ref = context::get_trade_gateway();
+    let mut gateway = gateway_ref.lock().unwrap();
+    match gateway.new_conditional_order(symbol_rust, &request_rust) {
+        Ok(data) => result.data = Some(data),
+        Err(e) => {
+            result.error_code = -1;
+            result.message = format!("{:?}", e);
+        },
+    }
+    result.to_c_json()
+}
+
+#[no_mangle]
+pub extern "C" fn new_oco_order(symbol : *const c_char, requests : *const c_char) -> Box<CString> {
+    let mut result = ServiceResult::<Vec<ConditionalOrder>>::new(0, "", None);
+    let symbol_rust = c_char_to_string(symbol);
+    let requests_rust = c_char_to_string(requests);
+
+    let gateway_ref = context::get_trade_gateway();
+    let mut gateway = gateway_ref.lock().unwrap();
+    match gateway.new_oco_order(symbol_rust, &requests_rust) {
+        Ok(data) => result.data = Some(data),
+        Err(e) => {
+            result.error_code = -1;
+            result.message = format!("{:?}", e);
+        },
+    }
+    result.to_c_json()
+}
+
+#[no_mangle]
+pub extern "C" fn new_bracket_order(symbol : *const c_char, request : *const c_char) -> Box<CString> {
+    let mut result = ServiceResult::<Vec<ConditionalOrder>>::new(0, "", None);
+    let symbol_rust = c_char_to_string(symbol);
+    let request_rust = c_char_to_string(request);
+
+    let gateway_ref = context::get_trade_gateway();
+    let mut gateway = gateway_ref.lock().unwrap();
+    match gateway.new_bracket_order(symbol_rust, &request_rust) {
+        Ok(data) => result.data = Some(data),
+        Err(e) => {
+            result.error_code = -1;
+            result.message = format!("{:?}", e);
+        },
+    }
+    result.to_c_json()
+}
+
+#[no_mangle]
+pub extern "C" fn cancel_conditional_order(id : *const c_char) -> Box<CString> {
+    let mut result = ServiceResult::<ConditionalOrder>::new(0, "", None);
+    let id_rust = c_char_to_string(id);
+
+    let gateway_ref = context::get_trade_gateway();
+    let mut gateway = gateway_ref.lock().unwrap();
+    match gateway.cancel_conditional_order(&id_rust) {
+        Ok(data) => result.data = Some(data),
+        Err(e) => {
+            result.error_code = -1;
+            result.message = format!("{:?}", e);
+        },
+    }
+    result.to_c_json()
+}
+
+#[no_mangle]
+pub extern "C" fn get_conditional_orders(symbol : *const c_char) -> Box<CString> {
+    let mut result = ServiceResult::<Vec<ConditionalOrder>>::new(0, "", None);
+    let symbol_rust = c_char_to_string(symbol);
+
+    let gateway_ref = context::get_trade_gateway();
+    let mut gateway = gateway_ref.lock().unwrap();
+    match gateway.get_conditional_orders(symbol_rust) {
+        Ok(data) => result.data = Some(data),
+        Err(e) => {
+            result.error_code = -1;
+            result.message = format!("{:?}", e);
+        },
+    }
+    result.to_c_json()
+}
+
+#[no_mangle]
+pub extern "C" fn new_algo_order(symbol : *const c_char, request : *const c_char) -> Box<CString> {
+    let mut result = ServiceResult::<AlgoOrder>::new(0, "", None);
+    let symbol_rust = c_char_to_string(symbol);
+    let request_rust = c_char_to_string(request);
+    match context::new_algo_order(&symbol_rust, &request_rust) {
+        Ok(data) => result.data = Some(data),
+        Err(e) => {
+            result.error_code = -1;
+            result.message = format!("{:?}", e);
+        },
+    }
+    result.to_c_json()
+}
+
+#[no_mangle]
+pub extern "C" fn cancel_algo_order(id : *const c_char) -> Box<CString> {
+    let mut result = ServiceResult::<AlgoOrder>::new(0, "", None);
+    let id_rust = c_char_to_string(id);
+
+    let gateway_ref = context::get_trade_gateway();
+    let mut gateway = gateway_ref.lock().unwrap();
+    match gateway.cancel_algo_order(&id_rust) {
+        Ok(data) => result.data = Some(data),
+        Err(e) => {
+            result.error_code = -1;
+            result.message = format!("{:?}", e);
+        },
+    }
+    result.to_c_json()
+}
+
+#[no_mangle]
+pub extern "C" fn get_algo_orders(symbol : *const c_char) -> Box<CString> {
+    let mut result = ServiceResult::<Vec<AlgoOrder>>::new(0, "", None);
+    let symbol_rust = c_char_to_string(symbol);
+
+    let gateway_ref = context::get_trade_gateway();
+    let mut gateway = gateway_ref.lock().unwrap();
+    match gateway.get_algo_orders(symbol_rust) {
+        Ok(data) => result.data = Some(data),
+        Err(e) => {
+            result.error_code = -1;
+            result.message = format!("{:?}", e);
+        },
+    }
+    result.to_c_json()
+}
+
+#[no_mangle]
+pub extern "C" fn get_open_orders(symbol : *const c_char) -> Box<CString> {
+    let mut result = ServiceResult::<Vec<Order>>::new(0, "", None);
+    let symbol_rust = c_char_to_string(symbol);
+    let gateway_ref = context::get_trade_gateway();
+    let mut gateway = gateway_ref.lock().unwrap();
+    match gateway.get_open_orders(symbol_rust) {
+        Ok(orders) => result.data = Some(orders),
+        Err(e) => {
+            result.error_code = -1;
+            result.message = format!("{:?}", e);
+        },
+    }
+    result.to_c_json()
+}
+
+#[no_mangle]
+pub extern "C" fn get_order(symbol : *const c_char, client_order_id : *const c_char) -> Box<CString> {
+    let mut result = ServiceResult::<Option<Order>>::new(0, "", None);
+    let symbol_rust = c_char_to_string(symbol);
+    let client_order_id_rust = c_char_to_string(client_order_id);
+    let gateway_ref = context::get_trade_gateway();
+    let mut gateway = gateway_ref.lock().unwrap();
+    match gateway.get_order(symbol_rust, &client_order_id_rust) {
+        Ok(order) => result.data = Some(order),
+        Err(e) => {
+            result.error_code = -1;
+            result.message = format!("{:?}", e);
+        },
+    }
+    result.to_c_json()
+}
+
+#[no_mangle]
+pub extern "C" fn get_session_orders(symbol : *const c_char) -> Box<CString> {
+    let mut result = ServiceResult::<Vec<ManagedOrder>>::new(0, "", None);
+    let symbol_rust = c_char_to_string(symbol);
+
+    let gateway_ref = context::get_trade_gateway();
+    let mut gateway = gateway_ref.lock().unwrap();
+    match gateway.get_session_orders(&symbol_rust) {
+        Ok(data) => result.data = Some(data),
+        Err(e) => {
+            result.error_code = -1;
+            result.message = format!("{:?}", e);
+        },
+    }
+    result.to_c_json()
+}
+
+#[no_mangle]
+pub extern "C" fn get_session_order(symbol : *const c_char, client_order_id : *const c_char) -> Box<CString> {
+    let mut result = ServiceResult::<Option<ManagedOrder>>::new(0, "", None);
+    let symbol_rust = c_char_to_string(symbol);
+    let client_order_id_rust = c_char_to_string(client_order_id);
+
+    let gateway_ref = context::get_trade_gateway();
+    let mut gateway = gateway_ref.lock().unwrap();
+    match gateway.get_session_order(&symbol_rust, &client_order_id_rust) {
+        Ok(data) => result.data = Some(data),
+        Err(e) => {
+            result.error_code = -1;
+            result.message = format!("{:?}", e);
+        },
+    }
+    result.to_c_json()
+}
+
 #[no_mangle]
 pub extern "C" fn get_positions(symbol : *const c_char) -> Box<CString> {
     let mut result = ServiceResult::<Vec<Position>>::new(0, "", None);
@@ -244,6 +567,100 @@ pub extern "C" fn get_positions(symbol : *const c_char) -> Box<CString> {
     result.to_c_json()
 }
 
+#[no_mangle]
+pub extern "C" fn set_risk_config(config : *const c_char) -> Box<CString> {
+    let mut result = ServiceResult::<()>::new(0, "", None);
+    let config_rust = c_char_to_string(config);
+
+    let gateway_ref = context::get_trade_gateway();
+    let mut gateway = gateway_ref.lock().unwrap();
+    if let Err(e) = gateway.set_risk_config(&config_rust) {
+        result.error_code = -1;
+        result.message = format!("{:?}", e);
+    }
+    result.to_c_json()
+}
+
+#[no_mangle]
+pub extern "C" fn get_risk_config() -> Box<CString> {
+    let mut result = ServiceResult::<RiskConfig>::new(0, "", None);
+
+    let gateway_ref = context::get_trade_gateway();
+    let mut gateway = gateway_ref.lock().unwrap();
+    match gateway.get_risk_config() {
+        Ok(data) => result.data = Some(data),
+        Err(e) => {
+            result.error_code = -1;
+            result.message = format!("{:?}", e);
+        },
+    }
+    result.to_c_json()
+}
+
+#[no_mangle]
+pub extern "C" fn kill_switch(flatten: i32) -> Box<CString> {
+    let mut result = ServiceResult::<KillSwitchReport>::new(0, "", None);
+
+    let gateway_ref = context::get_trade_gateway();
+    let mut gateway = gateway_ref.lock().unwrap();
+    match gateway.kill_switch(flatten != 0) {
+        Ok(data) => result.data = Some(data),
+        Err(e) => {
+            result.error_code = -1;
+            result.message = format!("{:?}", e);
+        },
+    }
+    result.to_c_json()
+}
+
+#[no_mangle]
+pub extern "C" fn arm_dead_mans_switch(timeout: i32, flatten: i32) -> Box<CString> {
+    let mut result = ServiceResult::<DeadMansSwitch>::new(0, "", None);
+
+    let gateway_ref = context::get_trade_gateway();
+    let mut gateway = gateway_ref.lock().unwrap();
+    match gateway.arm_dead_mans_switch(timeout.max(0) as u64, flatten != 0) {
+        Ok(data) => result.data = Some(data),
+        Err(e) => {
+            result.error_code = -1;
+            result.message = format!("{:?}", e);
+        },
+    }
+    result.to_c_json()
+}
+
+#[no_mangle]
+pub extern "C" fn heartbeat() -> Box<CString> {
+    let mut result = ServiceResult::<DeadMansSwitch>::new(0, "", None);
+
+    let gateway_ref = context::get_trade_gateway();
+    let mut gateway = gateway_ref.lock().unwrap();
+    match gateway.heartbeat() {
+        Ok(data) => result.data = Some(data),
+        Err(e) => {
+            result.error_code = -1;
+            result.message = format!("{:?}", e);
+        },
+    }
+    result.to_c_json()
+}
+
+#[no_mangle]
+pub extern "C" fn disarm_dead_mans_switch() -> Box<CString> {
+    let mut result = ServiceResult::<DeadMansSwitch>::new(0, "", None);
+
+    let gateway_ref = context::get_trade_gateway();
+    let mut gateway = gateway_ref.lock().unwrap();
+    match gateway.disarm_dead_mans_switch() {
+        Ok(data) => result.data = Some(data),
+        Err(e) => {
+            result.error_code = -1;
+            result.message = format!("{:?}", e);
+        },
+    }
+    result.to_c_json()
+}
+
 #[no_mangle]
 pub extern "C" fn get_account(asset : *const c_char) -> Box<CString> {
     let mut result = ServiceResult::<Option<Wallet>>::new(0, "", None);
@@ -260,6 +677,31 @@ pub extern "C" fn get_account(asset : *const c_char) -> Box<CString> {
     result.to_c_json()
 }
 
+#[no_mangle]
+pub extern "C" fn get_backtest_report(csv_dir : *const c_char) -> Box<CString> {
+    let mut result = ServiceResult::<BacktestReport>::new(0, "", None);
+    let csv_dir_rust = c_char_to_string(csv_dir);
+    let gateway_ref = context::get_trade_gateway();
+    let gateway = gateway_ref.lock().unwrap();
+    let ret = gateway.get_backtest_report();
+    match ret {
+        Ok(report) => {
+            if csv_dir_rust != "" {
+                if let Err(e) = report.write_csv(&csv_dir_rust) {
+                    result.error_code = -1;
+                    result.message = format!("{:?}", e);
+                }
+            }
+            result.data = Some(report);
+        },
+        Err(e) => {
+            result.error_code = -1;
+            result.message = format!("{:?}", e);
+        },
+    }
+    result.to_c_json()
+}
+
 #[no_mangle]
 pub extern "C" fn init_symbol_trade(sub_id: *const c_char, symbol: *const c_char, config: *const c_char, callback: extern "C" fn(*const c_char, *const c_char, *const c_char)) -> Box<CString> {
     let mut result = ServiceResult::<Value>::new(0, "", None);
@@ -283,35 +725,42 @@ pub extern "C" fn init_symbol_trade(sub_id: *const c_char, symbol: *const c_char
     if result.error_code == 0 {
         let ret = gateway.register_symbol(symbol_rust.clone());
         if ret.is_ok() {
-            let rx = ret.unwrap();
+            let mut sub = ret.unwrap();
             let sub_id_rust = CString::new(c_char_to_string(sub_id)).expect("CString failed");
-            thread::spawn(move || {
-                loop {
-                    if let Ok(data) = rx.recv() {
-                        match data {
-                            TradeEvent::OrderUpdate(order) => {
-                                if symbol_rust == order.symbol {
-                                    let json = serde_json::to_string(&order).unwrap();
-                                    let json_rust = CString::new(json).expect("CString failed");
-                                    let _type = CString::new("ORDER".to_string()).expect("CString failed");
-                                    callback(sub_id_rust.as_ptr(), _type.as_ptr(), json_rust.as_ptr());
-                                }
-                            },
-                            TradeEvent::PositionUpdate(position) => {
-                                let json = serde_json::to_string(&position).unwrap();
+            let _ = sub.stream(move |event| {
+                if let Some(data) = event {
+                    match data {
+                        TradeEvent::OrderUpdate(order) => {
+                            if symbol_rust == order.symbol {
+                                let json = serde_json::to_string(&order).unwrap();
                                 let json_rust = CString::new(json).expect("CString failed");
-                                let _type = CString::new("POSITION".to_string()).expect("CString failed");
+                                let _type = CString::new("ORDER".to_string()).expect("CString failed");
                                 callback(sub_id_rust.as_ptr(), _type.as_ptr(), json_rust.as_ptr());
                             }
-                            TradeEvent::AccountUpdate(wallet) => {
-                                let json = serde_json::to_string(&wallet).unwrap();
+                        },
+                        TradeEvent::PositionUpdate(position) => {
+                            let json = serde_json::to_string(&position).unwrap();
+                            let json_rust = CString::new(json).expect("CString failed");
+                            let _type = CString::new("POSITION".to_string()).expect("CString failed");
+                            callback(sub_id_rust.as_ptr(), _type.as_ptr(), json_rust.as_ptr());
+                        }
+                        TradeEvent::AccountUpdate(wallet) => {
+                            let json = serde_json::to_string(&wallet).unwrap();
+                            let json_rust = CString::new(json).expect("CString failed");
+                            let _type = CString::new("ACCOUNT".to_string()).expect("CString failed");
+                            callback(sub_id_rust.as_ptr(), _type.as_ptr(), json_rust.as_ptr());
+                        },
+                        TradeEvent::Fill(execution) => {
+                            if symbol_rust == execution.symbol {
+                                let json = serde_json::to_string(&execution).unwrap();
                                 let json_rust = CString::new(json).expect("CString failed");
-                                let _type = CString::new("ACCOUNT".to_string()).expect("CString failed");
+                                let _type = CString::new("FILL".to_string()).expect("CString failed");
                                 callback(sub_id_rust.as_ptr(), _type.as_ptr(), json_rust.as_ptr());
-                            },
-                        }
+                            }
+                        },
                     }
                 }
+                Ok(false)
             });
         }
         else {
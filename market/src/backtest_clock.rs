@@ -0,0 +1,155 @@
+use std::sync::{atomic::{AtomicBool, AtomicU64, Ordering}, Condvar, Mutex};
+use common::{error::AppError, msmc::IN_FLIGHT};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ClockMode {
+    Sleep,
+    Lockstep,
+    Step,
+}
+
+pub struct BacktestClock {
+    enabled: AtomicBool,
+    step_mode: AtomicBool,
+    sequence: AtomicU64,
+    permits: Mutex<u64>,
+    permit: Condvar,
+    held: Mutex<bool>,
+    released: Condvar,
+}
+
+pub static BACKTEST_CLOCK: BacktestClock = BacktestClock::new();
+
+impl Default for BacktestClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BacktestClock {
+    pub const fn new() -> Self {
+        BacktestClock {
+            enabled: AtomicBool::new(false),
+            step_mode: AtomicBool::new(false),
+            sequence: AtomicU64::new(0),
+            permits: Mutex::new(0),
+            permit: Condvar::new(),
+            held: Mutex::new(false),
+            released: Condvar::new(),
+        }
+    }
+
+    pub fn enable(&self, mode: ClockMode) {
+        *self.permits.lock().unwrap() = 0;
+        self.sequence.store(0, Ordering::SeqCst);
+        self.step_mode.store(mode == ClockMode::Step, Ordering::SeqCst);
+        self.enabled.store(mode != ClockMode::Sleep, Ordering::SeqCst);
+        IN_FLIGHT.enable(mode != ClockMode::Sleep);
+    }
+
+    pub fn disable(&self) {
+        self.enabled.store(false, Ordering::SeqCst);
+        self.step_mode.store(false, Ordering::SeqCst);
+        IN_FLIGHT.enable(false);
+        self.permit.notify_all();
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::SeqCst)
+    }
+
+    /// Moves to the next replayed market data, the orders placed while it is handled are matched from the next one.
+    pub fn advance(&self) {
+        if self.is_enabled() {
+            self.sequence.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    /// The sequence of the market data being handled, always 0 when the clock is disabled.
+    pub fn sequence(&self) -> u64 {
+        if self.is_enabled() { self.sequence.load(Ordering::SeqCst) } else { 0 }
+    }
+
+    /// Waits until every subscriber has handled the market data and the events it caused.
+    pub fn wait_idle(&self) {
+        IN_FLIGHT.wait_idle();
+    }
+
+    /// Holds the replay back before its first market data until `release`, so that every consumer is bound in time.
+    pub fn hold(&self) {
+        *self.held.lock().unwrap() = true;
+    }
+
+    pub fn release(&self) {
+        *self.held.lock().unwrap() = false;
+        self.released.notify_all();
+    }
+
+    pub fn wait_released(&self) {
+        let mut held = self.held.lock().unwrap();
+        while *held {
+            held = self.released.wait(held).unwrap();
+        }
+    }
+
+    pub fn wait_permit(&self) {
+        let mut permits = self.permits.lock().unwrap();
+        while self.is_enabled() && self.step_mode.load(Ordering::SeqCst) && *permits == 0 {
+            permits = self.permit.wait(permits).unwrap();
+        }
+        if *permits > 0 {
+            *permits -= 1;
+        }
+    }
+
+    pub fn step(&self, count: u64) -> Result<(), AppError> {
+        if !self.is_enabled() || !self.step_mode.load(Ordering::SeqCst) {
+            return Err(AppError::new(-200, "The backtest clock is not running in STEP mode"));
+        }
+        *self.permits.lock().unwrap() += count;
+        self.permit.notify_all();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{thread, time::Duration};
+    use common::msmc::Subscription;
+    use super::*;
+
+    #[test]
+    fn test_wait_idle_and_step() {
+        let clock = BacktestClock::new();
+        clock.enable(ClockMode::Step);
+        assert!(clock.step(1).is_ok());
+        clock.wait_permit();
+        clock.advance();
+        assert_eq!(clock.sequence(), 1);
+
+        let mut top = Subscription::<u32>::top();
+        let mut sub = top.subscribe();
+        let _ = sub.stream(|_| {
+            thread::sleep(Duration::from_millis(50));
+            Ok(true)
+        });
+        top.send(&1);
+        top.send(&2);
+        clock.wait_idle();
+        assert_eq!(IN_FLIGHT.pending(), 0);
+
+        clock.hold();
+        thread::scope(|s| {
+            let waiter = s.spawn(|| clock.wait_released());
+            thread::sleep(Duration::from_millis(50));
+            assert!(!waiter.is_finished());
+            clock.release();
+        });
+
+        clock.disable();
+        assert_eq!(clock.sequence(), 0);
+        assert!(clock.step(1).is_err());
+    }
+}
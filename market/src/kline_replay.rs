@@ -0,0 +1,158 @@
+use std::collections::VecDeque;
+use chrono::NaiveDateTime;
+use common::error::AppError;
+use crate::kline_store::interval_millis;
+use crate::market_server::KLine;
+use crate::sim_market_server::KLineLoader;
+
+const PAGE_SIZE: u32 = 500;
+
+pub fn close_time(kline: &KLine) -> u64 {
+    let open_time = NaiveDateTime::parse_from_str(&kline.datetime, "%Y-%m-%d %H:%M:%S").map(|v| v.and_utc().timestamp_millis() as u64);
+    match (open_time, interval_millis(&kline.interval)) {
+        (Ok(open_time), Some(interval)) => open_time + interval,
+        _ => kline.timestamp,
+    }
+}
+
+struct KLineCursor {
+    symbol: String,
+    interval: String,
+    interval_ms: u64,
+    emit_tick: bool,
+    emit_kline: bool,
+    buffer: VecDeque<KLine>,
+    next_start: u64,
+    last_timestamp: Option<u64>,
+    exhausted: bool,
+}
+
+impl KLineCursor {
+    fn head(&mut self, loader: &dyn KLineLoader) -> Result<Option<&KLine>, AppError> {
+        while self.buffer.is_empty() && !self.exhausted {
+            let klines = loader.load_kline(&self.symbol, &self.interval, PAGE_SIZE, Some(self.next_start), None)?;
+            let last_timestamp = self.last_timestamp;
+            let fresh: Vec<KLine> = klines.into_iter().filter(|k| last_timestamp.map(|t| k.timestamp > t).unwrap_or(true)).collect();
+            match fresh.last() {
+                Some(last) => {
+                    self.next_start = last.timestamp + 1;
+                    self.last_timestamp = Some(last.timestamp);
+                    self.buffer.extend(fresh);
+                },
+                None => self.exhausted = true,
+            }
+        }
+        Ok(self.buffer.front())
+    }
+}
+
+pub struct ReplayedKLine {
+    pub kline: KLine,
+    pub emit_tick: bool,
+    pub emit_kline: bool,
+}
+
+pub struct KLineReplay {
+    cursors: Vec<KLineCursor>,
+    loader: Box<dyn KLineLoader>,
+}
+
+impl KLineReplay {
+    pub fn new(loader: Box<dyn KLineLoader>, topics: &[(String, String)], start_time: u64) -> Self {
+        let mut cursors: Vec<KLineCursor> = vec![];
+        for (symbol, interval) in topics.iter() {
+            let (interval, emit_kline) = if interval.is_empty() { ("1m".to_string(), false) } else { (interval.clone(), true) };
+            match cursors.iter_mut().find(|c| c.symbol == *symbol && c.interval == interval) {
+                Some(cursor) => cursor.emit_kline |= emit_kline,
+                None => cursors.push(KLineCursor {
+                    symbol: symbol.clone(),
+                    interval_ms: interval_millis(&interval).unwrap_or(u64::MAX),
+                    interval,
+                    emit_tick: false,
+                    emit_kline,
+                    buffer: VecDeque::new(),
+                    next_start: start_time,
+                    last_timestamp: None,
+                    exhausted: false,
+                }),
+            }
+        }
+        for i in 0..cursors.len() {
+            let finest = cursors.iter().filter(|c| c.symbol == cursors[i].symbol).map(|c| c.interval_ms).min();
+            let finest_index = cursors.iter().position(|c| c.symbol == cursors[i].symbol && Some(c.interval_ms) == finest);
+            cursors[i].emit_tick = finest_index == Some(i);
+        }
+        KLineReplay { cursors, loader }
+    }
+
+    pub fn next_close_time(&mut self) -> Result<Option<u64>, AppError> {
+        let mut next: Option<u64> = None;
+        for cursor in self.cursors.iter_mut() {
+            if let Some(kline) = cursor.head(self.loader.as_ref())? {
+                let close = close_time(kline);
+                next = Some(next.map(|v| v.min(close)).unwrap_or(close));
+            }
+        }
+        Ok(next)
+    }
+
+    pub fn next_until(&mut self, time: u64) -> Result<Option<ReplayedKLine>, AppError> {
+        let mut selected: Option<(u64, u64, usize)> = None;
+        for (i, cursor) in self.cursors.iter_mut().enumerate() {
+            let interval_ms = cursor.interval_ms;
+            if let Some(kline) = cursor.head(self.loader.as_ref())? {
+                let key = (close_time(kline), interval_ms, i);
+                if key.0 <= time && selected.map(|v| key < v).unwrap_or(true) {
+                    selected = Some(key);
+                }
+            }
+        }
+        Ok(selected.map(|(_, _, i)| {
+            let cursor = &mut self.cursors[i];
+            ReplayedKLine {
+                kline: cursor.buffer.pop_front().unwrap(),
+                emit_tick: cursor.emit_tick,
+                emit_kline: cursor.emit_kline,
+            }
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct MemoryLoader;
+
+    impl KLineLoader for MemoryLoader {
+        fn load_kline(&self, symbol: &str, interval: &str, count: u32, start_time: Option<u64>, _end_time: Option<u64>) -> Result<Vec<KLine>, AppError> {
+            let step = interval_millis(interval).unwrap();
+            let start = start_time.unwrap_or(0);
+            let first = start.div_ceil(step);
+            Ok((first..first + count as u64).take_while(|i| i * step < 2 * 60 * 60 * 1000).map(|i| KLine {
+                symbol: symbol.to_string(),
+                interval: interval.to_string(),
+                datetime: chrono::DateTime::from_timestamp((i * step / 1000) as i64, 0).unwrap().format("%Y-%m-%d %H:%M:%S").to_string(),
+                close: i as f64,
+                timestamp: i * step,
+                ..Default::default()
+            }).collect())
+        }
+    }
+
+    #[test]
+    fn test_aligned_replay() {
+        let topics = vec![("A".to_string(), "1h".to_string()), ("A".to_string(), "1m".to_string()), ("A".to_string(), "".to_string())];
+        let mut replay = KLineReplay::new(Box::new(MemoryLoader), &topics, 0);
+        let mut emitted = vec![];
+        while let Some(time) = replay.next_close_time().unwrap() {
+            while let Some(v) = replay.next_until(time).unwrap() {
+                emitted.push((v.kline.interval.clone(), close_time(&v.kline), v.emit_tick));
+            }
+        }
+        assert_eq!(emitted.len(), 122);
+        assert_eq!(emitted[59], ("1m".to_string(), 3600000, true));
+        assert_eq!(emitted[60], ("1h".to_string(), 3600000, false));
+        assert!(emitted.windows(2).all(|w| w[0].1 <= w[1].1));
+    }
+}
@@ -0,0 +1,357 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use common::error::AppError;
+use serde::{Deserialize, Serialize};
+use log::*;
+use crate::market_server::KLine;
+use crate::sim_market_server::KLineLoader;
+
+const BINARY_MAGIC: &[u8; 4] = b"QTKL";
+const BINARY_VERSION: u8 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum KLineFileFormat {
+    Csv,
+    Binary,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KLineStoreConfig {
+    pub dir: String,
+    pub format: KLineFileFormat,
+    pub offline: bool,
+}
+
+pub fn build_kline_loader(store: &Option<KLineStoreConfig>, remote: Box<dyn KLineLoader>) -> Box<dyn KLineLoader> {
+    match store {
+        Some(config) if config.offline => Box::new(FileKLineLoader::new(&config.dir, config.format)),
+        Some(config) => Box::new(CachedKLineLoader::new(remote, &config.dir, config.format)),
+        None => remote,
+    }
+}
+
+pub fn interval_millis(interval: &str) -> Option<u64> {
+    let period: u64 = interval.get(0..interval.len().checked_sub(1)?)?.parse().ok()?;
+    let unit = match interval.chars().last()? {
+        's' => 1000,
+        'm' => 60 * 1000,
+        'h' => 60 * 60 * 1000,
+        'd' => 24 * 60 * 60 * 1000,
+        'w' => 7 * 24 * 60 * 60 * 1000,
+        _ => return None,
+    };
+    Some(period * unit)
+}
+
+pub fn find_gaps(klines: &[KLine], interval: &str) -> Vec<(u64, u64)> {
+    let step = match interval_millis(interval) {
+        Some(v) => v,
+        None => return vec![],
+    };
+    klines.windows(2)
+        .filter(|w| w[1].timestamp > w[0].timestamp + step)
+        .map(|w| (w[0].timestamp, w[1].timestamp))
+        .collect()
+}
+
+pub fn select_klines(klines: &[KLine], count: u32, start_time: Option<u64>, end_time: Option<u64>) -> Vec<KLine> {
+    let in_range = klines.iter().filter(|k| {
+        start_time.map(|s| k.timestamp >= s).unwrap_or(true) && end_time.map(|e| k.timestamp <= e).unwrap_or(true)
+    });
+    if start_time.is_some() || end_time.is_none() {
+        in_range.take(count as usize).cloned().collect()
+    } else {
+        let selected: Vec<KLine> = in_range.cloned().collect();
+        let skip = selected.len().saturating_sub(count as usize);
+        selected.into_iter().skip(skip).collect()
+    }
+}
+
+pub struct KLineFile {
+    pub dir: String,
+    pub format: KLineFileFormat,
+}
+
+impl KLineFile {
+    pub fn path(&self, symbol: &str, interval: &str, extension: &str) -> PathBuf {
+        Path::new(&self.dir).join(format!("{}_{}.{}", symbol, interval, extension))
+    }
+
+    fn data_path(&self, symbol: &str, interval: &str) -> PathBuf {
+        match self.format {
+            KLineFileFormat::Csv => self.path(symbol, interval, "csv"),
+            KLineFileFormat::Binary => self.path(symbol, interval, "bin"),
+        }
+    }
+
+    pub fn read(&self, symbol: &str, interval: &str) -> Result<Vec<KLine>, AppError> {
+        let path = self.data_path(symbol, interval);
+        if !path.exists() {
+            return Ok(vec![]);
+        }
+        let data = fs::read(&path).map_err(|e| AppError::new(-200, &format!("{:?} {}", path, e)))?;
+        match self.format {
+            KLineFileFormat::Csv => decode_csv(symbol, interval, &data),
+            KLineFileFormat::Binary => decode_binary(symbol, interval, &data),
+        }
+    }
+
+    pub fn write(&self, symbol: &str, interval: &str, klines: &[KLine]) -> Result<(), AppError> {
+        fs::create_dir_all(&self.dir).map_err(|e| AppError::new(-200, &e.to_string()))?;
+        let data = match self.format {
+            KLineFileFormat::Csv => encode_csv(klines),
+            KLineFileFormat::Binary => encode_binary(klines),
+        };
+        let path = self.data_path(symbol, interval);
+        fs::write(&path, data).map_err(|e| AppError::new(-200, &format!("{:?} {}", path, e)))
+    }
+
+    pub fn read_coverage(&self, symbol: &str, interval: &str) -> Vec<(u64, u64)> {
+        let content = fs::read_to_string(self.path(symbol, interval, "range")).unwrap_or_default();
+        content.lines().filter_map(|line| {
+            let (from, to) = line.split_once(',')?;
+            Some((from.trim().parse().ok()?, to.trim().parse().ok()?))
+        }).collect()
+    }
+
+    pub fn write_coverage(&self, symbol: &str, interval: &str, coverage: &[(u64, u64)]) -> Result<(), AppError> {
+        let content: String = coverage.iter().map(|(from, to)| format!("{},{}\n", from, to)).collect();
+        fs::write(self.path(symbol, interval, "range"), content).map_err(|e| AppError::new(-200, &e.to_string()))
+    }
+}
+
+pub struct FileKLineLoader {
+    file: KLineFile,
+}
+
+impl FileKLineLoader {
+    pub fn new(dir: &str, format: KLineFileFormat) -> Self {
+        FileKLineLoader {
+            file: KLineFile { dir: dir.to_string(), format },
+        }
+    }
+}
+
+impl KLineLoader for FileKLineLoader {
+    fn load_kline(&self, symbol: &str, interval: &str, count: u32, start_time: Option<u64>, end_time: Option<u64>) -> Result<Vec<KLine>, AppError> {
+        let klines = self.file.read(symbol, interval)?;
+        let selected = select_klines(&klines, count, start_time, end_time);
+        for (from, to) in find_gaps(&selected, interval) {
+            warn!("Gap found in {} {} klines between {} and {}", symbol, interval, from, to);
+        }
+        Ok(selected)
+    }
+}
+
+pub struct CachedKLineLoader {
+    inner: Box<dyn KLineLoader>,
+    file: KLineFile,
+    lock: Mutex<()>,
+}
+
+impl CachedKLineLoader {
+    pub fn new(inner: Box<dyn KLineLoader>, dir: &str, format: KLineFileFormat) -> Self {
+        CachedKLineLoader {
+            inner,
+            file: KLineFile { dir: dir.to_string(), format },
+            lock: Mutex::new(()),
+        }
+    }
+
+    fn is_covered(coverage: &[(u64, u64)], from: u64, to: u64) -> bool {
+        coverage.iter().any(|(start, end)| *start <= from && to <= *end)
+    }
+
+    fn serve_from_cache(&self, klines: &[KLine], coverage: &[(u64, u64)], count: u32, start_time: Option<u64>, end_time: Option<u64>) -> Option<Vec<KLine>> {
+        let selected = select_klines(klines, count, start_time, end_time);
+        let from = start_time.unwrap_or(selected.first()?.timestamp);
+        let to = if selected.len() < count as usize { end_time? } else { selected.last()?.timestamp.max(from) };
+        if Self::is_covered(coverage, from, to) {
+            Some(selected)
+        } else {
+            None
+        }
+    }
+}
+
+impl KLineLoader for CachedKLineLoader {
+    fn load_kline(&self, symbol: &str, interval: &str, count: u32, start_time: Option<u64>, end_time: Option<u64>) -> Result<Vec<KLine>, AppError> {
+        let _guard = self.lock.lock().unwrap();
+        let cached = self.file.read(symbol, interval)?;
+        let mut coverage = self.file.read_coverage(symbol, interval);
+        if let Some(selected) = self.serve_from_cache(&cached, &coverage, count, start_time, end_time) {
+            return Ok(selected);
+        }
+
+        let fetched = self.inner.load_kline(symbol, interval, count, start_time, end_time)?;
+        if let (Some(first), Some(last)) = (fetched.first(), fetched.last()) {
+            let from = start_time.unwrap_or(first.timestamp).min(first.timestamp);
+            let to = end_time.unwrap_or(last.timestamp).min(last.timestamp);
+            coverage.push((from, to));
+            coverage.sort();
+            let mut merged_coverage: Vec<(u64, u64)> = vec![];
+            for range in coverage {
+                match merged_coverage.last_mut() {
+                    Some(last) if range.0 <= last.1 => last.1 = last.1.max(range.1),
+                    _ => merged_coverage.push(range),
+                }
+            }
+
+            let mut merged = cached;
+            merged.retain(|k| fetched.iter().all(|f| f.timestamp != k.timestamp));
+            merged.extend(fetched.iter().cloned());
+            merged.sort_by_key(|k| k.timestamp);
+            self.file.write(symbol, interval, &merged)?;
+            self.file.write_coverage(symbol, interval, &merged_coverage)?;
+        }
+        for (from, to) in find_gaps(&fetched, interval) {
+            warn!("Gap found in {} {} klines between {} and {}", symbol, interval, from, to);
+        }
+        Ok(fetched)
+    }
+}
+
+fn encode_csv(klines: &[KLine]) -> Vec<u8> {
+    let mut content = "timestamp,datetime,open,high,low,close,volume,turnover\n".to_string();
+    for k in klines {
+        content.push_str(&format!("{},{},{},{},{},{},{},{}\n", k.timestamp, k.datetime, k.open, k.high, k.low, k.close, k.volume, k.turnover));
+    }
+    content.into_bytes()
+}
+
+fn decode_csv(symbol: &str, interval: &str, data: &[u8]) -> Result<Vec<KLine>, AppError> {
+    let content = String::from_utf8_lossy(data);
+    let mut klines = vec![];
+    for (i, line) in content.lines().enumerate().skip(1) {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let fields: Vec<&str> = line.split(',').map(|v| v.trim()).collect();
+        if fields.len() < 8 {
+            return Err(AppError::new(-200, &format!("Invalid kline at line {}: {}", i + 1, line)));
+        }
+        let number = |idx: usize| fields[idx].parse::<f64>().map_err(|e| AppError::new(-200, &format!("Invalid kline at line {}: {}", i + 1, e)));
+        klines.push(KLine {
+            symbol: symbol.to_string(),
+            interval: interval.to_string(),
+            timestamp: fields[0].parse::<u64>().map_err(|e| AppError::new(-200, &format!("Invalid kline at line {}: {}", i + 1, e)))?,
+            datetime: fields[1].to_string(),
+            open: number(2)?,
+            high: number(3)?,
+            low: number(4)?,
+            close: number(5)?,
+            volume: number(6)?,
+            turnover: number(7)?,
+        });
+    }
+    klines.sort_by_key(|k| k.timestamp);
+    Ok(klines)
+}
+
+fn encode_binary(klines: &[KLine]) -> Vec<u8> {
+    let mut data = Vec::with_capacity(13 + klines.len() * 72);
+    data.extend_from_slice(BINARY_MAGIC);
+    data.push(BINARY_VERSION);
+    data.extend_from_slice(&(klines.len() as u64).to_le_bytes());
+    klines.iter().for_each(|k| data.extend_from_slice(&k.timestamp.to_le_bytes()));
+    let columns: [fn(&KLine) -> f64; 6] = [|k| k.open, |k| k.high, |k| k.low, |k| k.close, |k| k.volume, |k| k.turnover];
+    for column in columns.iter() {
+        klines.iter().for_each(|k| data.extend_from_slice(&column(k).to_le_bytes()));
+    }
+    for k in klines {
+        data.extend_from_slice(&(k.datetime.len() as u16).to_le_bytes());
+        data.extend_from_slice(k.datetime.as_bytes());
+    }
+    data
+}
+
+fn decode_binary(symbol: &str, interval: &str, data: &[u8]) -> Result<Vec<KLine>, AppError> {
+    let invalid = || AppError::new(-200, &format!("Invalid kline file of {} {}", symbol, interval));
+    if data.len() < 13 || &data[0..4] != BINARY_MAGIC || data[4] != BINARY_VERSION {
+        return Err(invalid());
+    }
+    let count = u64::from_le_bytes(data[5..13].try_into().unwrap()) as usize;
+    let mut offset = 13;
+    let read_u64 = |offset: &mut usize| -> Result<[u8; 8], AppError> {
+        let bytes = data.get(*offset..*offset + 8).ok_or_else(invalid)?;
+        *offset += 8;
+        Ok(bytes.try_into().unwrap())
+    };
+
+    let mut klines: Vec<KLine> = Vec::with_capacity(count);
+    for _ in 0..count {
+        klines.push(KLine {
+            symbol: symbol.to_string(),
+            interval: interval.to_string(),
+            timestamp: u64::from_le_bytes(read_u64(&mut offset)?),
+            ..Default::default()
+        });
+    }
+    for column in 0..6 {
+        for k in klines.iter_mut() {
+            let value = f64::from_le_bytes(read_u64(&mut offset)?);
+            match column {
+                0 => k.open = value,
+                1 => k.high = value,
+                2 => k.low = value,
+                3 => k.close = value,
+                4 => k.volume = value,
+                _ => k.turnover = value,
+            }
+        }
+    }
+    for k in klines.iter_mut() {
+        let len = u16::from_le_bytes(data.get(offset..offset + 2).ok_or_else(invalid)?.try_into().unwrap()) as usize;
+        offset += 2;
+        k.datetime = String::from_utf8_lossy(data.get(offset..offset + len).ok_or_else(invalid)?).to_string();
+        offset += len;
+    }
+    Ok(klines)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use super::*;
+
+    struct CountingLoader {
+        calls: Arc<AtomicUsize>,
+    }
+
+    impl KLineLoader for CountingLoader {
+        fn load_kline(&self, symbol: &str, interval: &str, count: u32, start_time: Option<u64>, _end_time: Option<u64>) -> Result<Vec<KLine>, AppError> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            let start = start_time.unwrap_or(0);
+            Ok((0..count as u64).filter(|i| *i != 2).map(|i| KLine {
+                symbol: symbol.to_string(),
+                interval: interval.to_string(),
+                datetime: "2024-01-01 00:00:00".to_string(),
+                close: i as f64,
+                timestamp: start + i * 60000,
+                ..Default::default()
+            }).collect())
+        }
+    }
+
+    #[test]
+    fn test_cache_and_reload() {
+        let dir = std::env::temp_dir().join(format!("kline_store_{}", std::process::id()));
+        let dir = dir.to_str().unwrap();
+        for format in [KLineFileFormat::Csv, KLineFileFormat::Binary] {
+            let calls = Arc::new(AtomicUsize::new(0));
+            let loader = CachedKLineLoader::new(Box::new(CountingLoader { calls: calls.clone() }), dir, format);
+            let first = loader.load_kline("BTCUSDT", "1m", 5, Some(0), None).unwrap();
+            assert_eq!(find_gaps(&first, "1m"), vec![(60000, 180000)]);
+            let second = loader.load_kline("BTCUSDT", "1m", 3, Some(60000), None).unwrap();
+            assert_eq!(calls.load(Ordering::SeqCst), 1);
+            assert_eq!(second.iter().map(|k| k.timestamp).collect::<Vec<u64>>(), vec![60000, 180000, 240000]);
+
+            let offline = FileKLineLoader::new(dir, format).load_kline("BTCUSDT", "1m", 2, None, Some(240000)).unwrap();
+            assert_eq!(offline.iter().map(|k| k.close).collect::<Vec<f64>>(), vec![3.0, 4.0]);
+        }
+        let _ = fs::remove_dir_all(dir);
+    }
+}
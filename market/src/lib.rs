@@ -1,4 +1,9 @@
 pub mod kline;
 pub mod market_gateway;
 pub mod market_server;
-pub mod sim_market_server;
\ No newline at end of file
+pub mod sim_market_server;
+pub mod backtest_clock;
+pub mod kline_store;
+pub mod tick_replay;
+pub mod recorder;
+pub mod kline_replay;
\ No newline at end of file
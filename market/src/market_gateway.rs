@@ -3,13 +3,12 @@ use crate::market_server::KLine;
 use super::market_server::{MarketData, MarketServer};
 use common::{error::AppError, msmc::{StreamError, Subscription}};
 use std::{sync::{atomic::{AtomicUsize, Ordering}, Arc, Mutex}, thread::JoinHandle, vec};
-use crossbeam::channel::{self, Receiver, Sender};
 
-#[derive(Clone, Debug)]
+#[derive(Clone)]
 struct Subscriber {
     symbol: String,
     interval: String,
-    sender: Sender<MarketData>,
+    subscription: Arc<Subscription<MarketData>>,
 }
 
 pub struct MarketGateway<S: MarketServer> {
@@ -45,28 +44,30 @@ impl<S: MarketServer> MarketGateway<S> {
         self.server.load_kline(symbol, interval, count)
     }
 
-    pub fn subscribe_kline(&mut self, symbol: S::Symbol, interval: &str) -> Result<Receiver<MarketData>, AppError> {
+    pub fn subscribe_kline(&mut self, symbol: S::Symbol, interval: &str) -> Result<Subscription<MarketData>, AppError> {
         let _ = self.server.subscribe_kline(symbol.clone(), interval);
 
-        let (tx, rx) = channel::unbounded::<MarketData>();
+        let mut top = Subscription::<MarketData>::top();
+        let sub = top.subscribe();
         self.subscribers.push(Subscriber {
             symbol: symbol.to_string(),
             interval: interval.to_string(),
-            sender: tx,
+            subscription: Arc::new(top),
         });
-        Ok(rx)
+        Ok(sub)
     }
 
-    pub fn subscribe_tick(&mut self, symbol: S::Symbol) -> Result<Receiver<MarketData>, AppError> {
+    pub fn subscribe_tick(&mut self, symbol: S::Symbol) -> Result<Subscription<MarketData>, AppError> {
         let _ = self.server.subscribe_tick(symbol.clone());
 
-        let (tx, rx) = channel::unbounded::<MarketData>();
+        let mut top = Subscription::<MarketData>::top();
+        let sub = top.subscribe();
         self.subscribers.push(Subscriber {
             symbol: symbol.to_string(),
             interval: "".to_string(),
-            sender: tx,
+            subscription: Arc::new(top),
         });
-        Ok(rx)
+        Ok(sub)
     }
   
     pub fn start(&mut self) -> Result<(), AppError> {
@@ -88,20 +89,20 @@ impl<S: MarketServer> MarketGateway<S> {
                         MarketData::Tick(t) => {
                             for sub in subscribers.iter() {
                                 if t.symbol == sub.symbol && sub.interval == "" {
-                                    let _ = sub.sender.send(MarketData::Tick(t.clone()));
+                                    sub.subscription.send(data);
                                 }
                             }
                         },
                         MarketData::Kline(k) => {
                             for sub in subscribers.iter() {
                                 if k.symbol == sub.symbol && k.interval == sub.interval {
-                                    let _ = sub.sender.send(MarketData::Kline(k.clone()));
+                                    sub.subscription.send(data);
                                 }
                             }
                         },
-                        MarketData::MarketClosed => {
+                        MarketData::MarketClosed | MarketData::TradeDayEnded(_) | MarketData::BacktestEnded => {
                             for sub in subscribers.iter() {
-                                let _ = sub.sender.send(MarketData::MarketClosed);
+                                sub.subscription.send(data);
                             }
                         },
                         _ => {},
@@ -15,6 +15,16 @@ pub struct Tick {
     pub bids: Vec<Vec<f64>>,
     pub asks: Vec<Vec<f64>>,
     pub timestamp: u64,
+    #[serde(default)]
+    pub trading_day: String,
+    #[serde(default)]
+    pub pre_settlement: f64,
+    #[serde(default)]
+    pub open_interest: f64,
+    #[serde(default)]
+    pub upper_limit: f64,
+    #[serde(default)]
+    pub lower_limit: f64,
 }
 
 #[derive(Debug, Clone, Default, Serialize, Deserialize,)]
@@ -45,6 +55,7 @@ pub enum MarketData {
     UserLogin,
     TradeDayEnded(String),
     MarketClosed,
+    BacktestEnded,
     Disconnected(i32),
     Tick(Tick),
     Kline(KLine),
@@ -0,0 +1,156 @@
+use std::collections::HashMap;
+use std::fs::{self, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::sync::{atomic::{AtomicBool, Ordering}, Arc};
+use std::thread::JoinHandle;
+use std::time::{SystemTime, UNIX_EPOCH};
+use chrono::DateTime;
+use common::{error::AppError, msmc::{StreamError, Subscription}};
+use flate2::{write::GzEncoder, Compression};
+use serde::{Deserialize, Serialize};
+use log::*;
+use crate::market_server::MarketData;
+use crate::tick_replay::MarketRecord;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecorderConfig {
+    pub dir: String,
+    pub compress: Option<bool>,
+}
+
+struct DailyFile {
+    day: String,
+    writer: Box<dyn Write + Send>,
+}
+
+pub struct RecordWriter {
+    config: RecorderConfig,
+    files: HashMap<String, DailyFile>,
+}
+
+impl RecordWriter {
+    pub fn new(config: RecorderConfig) -> Result<Self, AppError> {
+        fs::create_dir_all(&config.dir).map_err(|e| AppError::new(-200, &format!("{} {}", config.dir, e)))?;
+        Ok(RecordWriter {
+            config,
+            files: HashMap::new(),
+        })
+    }
+
+    fn open(&self, symbol: &str, day: &str) -> Result<Box<dyn Write + Send>, AppError> {
+        let compress = self.config.compress.unwrap_or(true);
+        let name = format!("{}_{}.jsonl{}", symbol, day, if compress { ".gz" } else { "" });
+        let path = Path::new(&self.config.dir).join(name);
+        let file = OpenOptions::new().create(true).append(true).open(&path).map_err(|e| AppError::new(-200, &format!("{:?} {}", path, e)))?;
+        if compress {
+            Ok(Box::new(GzEncoder::new(BufWriter::new(file), Compression::default())))
+        } else {
+            Ok(Box::new(BufWriter::new(file)))
+        }
+    }
+
+    pub fn write(&mut self, record: &MarketRecord) -> Result<(), AppError> {
+        let symbol = record.symbol().to_string();
+        let day = DateTime::from_timestamp((record.recv_ns / 1_000_000_000) as i64, 0).unwrap().format("%Y%m%d").to_string();
+        let rotate = self.files.get(&symbol).map(|f| f.day != day).unwrap_or(true);
+        if rotate {
+            let writer = self.open(&symbol, &day)?;
+            if let Some(mut old) = self.files.insert(symbol.clone(), DailyFile { day, writer }) {
+                let _ = old.writer.flush();
+            }
+        }
+        let line = serde_json::to_string(record).map_err(|e| AppError::new(-200, &e.to_string()))?;
+        let file = self.files.get_mut(&symbol).unwrap();
+        writeln!(file.writer, "{}", line).map_err(|e| AppError::new(-200, &e.to_string()))
+    }
+
+    pub fn flush(&mut self) {
+        for file in self.files.values_mut() {
+            let _ = file.writer.flush();
+        }
+    }
+
+    pub fn close(&mut self) {
+        self.flush();
+        self.files.clear();
+    }
+}
+
+pub struct MarketRecorder {
+    closed: Arc<AtomicBool>,
+    handler: Option<JoinHandle<()>>,
+}
+
+impl MarketRecorder {
+    pub fn start(config: RecorderConfig, mut subscription: Subscription<MarketData>) -> Result<Self, AppError> {
+        let mut writer = RecordWriter::new(config)?;
+        let closed = Arc::new(AtomicBool::new(false));
+        let closed_ref = closed.clone();
+        let handler = subscription.stream(move |event| {
+            if closed_ref.load(Ordering::SeqCst) {
+                writer.close();
+                return Err(StreamError::Exit);
+            }
+            match event {
+                Some(data) => {
+                    let recv_ns = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_nanos() as u64;
+                    let record = match data {
+                        MarketData::Tick(t) => Some(MarketRecord { recv_ns, tick: Some(t.clone()), kline: None }),
+                        MarketData::Kline(k) => Some(MarketRecord { recv_ns, tick: None, kline: Some(k.clone()) }),
+                        _ => None,
+                    };
+                    if let Some(record) = record {
+                        if let Err(e) = writer.write(&record) {
+                            error!("Failed to record market data: {:?}", e);
+                        }
+                    }
+                },
+                None => {
+                    writer.flush();
+                },
+            }
+            Ok(false)
+        });
+        Ok(MarketRecorder {
+            closed,
+            handler: Some(handler),
+        })
+    }
+
+    pub fn close(&mut self) {
+        self.closed.store(true, Ordering::SeqCst);
+        if let Some(handler) = self.handler.take() {
+            let _ = handler.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::market_server::{KLine, Tick};
+    use crate::tick_replay::{FileTickLoader, TickLoader};
+    use super::*;
+
+    #[test]
+    fn test_record_and_replay() {
+        let dir = std::env::temp_dir().join(format!("recorder_{}", std::process::id()));
+        let dir = dir.to_str().unwrap().to_string();
+        let mut writer = RecordWriter::new(RecorderConfig { dir: dir.clone(), compress: Some(true) }).unwrap();
+        let day_ns = 24 * 60 * 60 * 1_000_000_000u64;
+        let tick = Tick { symbol: "BTCUSDT".to_string(), close: 1.5, bids: vec![vec![1.4, 2.0]], timestamp: 1000, ..Default::default() };
+        let kline = KLine { symbol: "BTCUSDT".to_string(), interval: "1m".to_string(), close: 1.6, timestamp: 2000, ..Default::default() };
+        writer.write(&MarketRecord { recv_ns: day_ns - 1, tick: Some(tick.clone()), kline: None }).unwrap();
+        writer.write(&MarketRecord { recv_ns: day_ns + 1, tick: None, kline: Some(kline) }).unwrap();
+        writer.close();
+
+        let loader = FileTickLoader::new(&dir);
+        assert_eq!(loader.files("BTCUSDT").unwrap().len(), 2);
+        let records: Vec<MarketRecord> = loader.load_tick("BTCUSDT", 0, u64::MAX / 1_000_000).unwrap().map(|r| r.unwrap()).collect();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].recv_ns, day_ns - 1);
+        assert_eq!(serde_json::to_string(records[0].tick.as_ref().unwrap()).unwrap(), serde_json::to_string(&tick).unwrap());
+        assert_eq!(records[1].kline.as_ref().unwrap().close, 1.6);
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
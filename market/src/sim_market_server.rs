@@ -2,9 +2,12 @@
 
 use common::error::AppError;
 use serde::{Deserialize, Serialize};
+use crate::backtest_clock::{ClockMode, BACKTEST_CLOCK};
+use crate::kline_replay::KLineReplay;
+use crate::kline_store::{build_kline_loader, KLineStoreConfig};
+use crate::tick_replay::{FileTickLoader, TickKLineBuilder, TickLoader, TickReplay, TickReplayConfig};
 use crate::market_server::{KLine, MarketData, MarketServer, Tick};
 use common::msmc::*;
-use std::collections::HashMap;
 use std::sync::{Arc, Mutex};
 use std::thread;
 use std::time::Duration;
@@ -15,6 +18,9 @@ pub struct SimMarketConfig {
     pub end_time: u64,
     pub interval: u64,
     pub lines_per_sec: u64,
+    pub clock_mode: Option<ClockMode>,
+    pub kline_store: Option<KLineStoreConfig>,
+    pub tick_replay: Option<TickReplayConfig>,
 }
 
 #[derive(Debug, Clone, Default)]
@@ -27,22 +33,98 @@ pub trait KLineLoader:Send + Sync {
     fn load_kline(&self, symbol: &str, interval: &str, count: u32, start_time: Option<u64>, end_time: Option<u64>) -> Result<Vec<KLine>, AppError>;
 }
 
+/// Sends the market data, in lockstep the next one is only sent after every subscriber has handled it.
+fn publish(subscription: &Subscription<MarketData>, data: MarketData) {
+    subscription.send(&data);
+    BACKTEST_CLOCK.wait_idle();
+}
+
 pub struct SimMarketServer {
     config: SimMarketConfig,
     topics: Vec<MarketTopic>,
     subscription: Arc<Mutex<Subscription<MarketData>>>,
     kline_loader: Option<Box<dyn KLineLoader>>,
+    tick_loader: Option<Box<dyn TickLoader>>,
 }
 
 impl SimMarketServer {
     pub fn new(config: SimMarketConfig, kline_loader: Box<dyn KLineLoader>) -> Self {
+        let kline_loader = build_kline_loader(&config.kline_store, kline_loader);
+        let tick_loader: Option<Box<dyn TickLoader>> = match &config.tick_replay {
+            Some(replay) => Some(Box::new(FileTickLoader::new(&replay.dir))),
+            None => None,
+        };
         SimMarketServer {
             config,
             topics: Vec::new(),
             subscription: Arc::new(Mutex::new(Subscription::top())),
             kline_loader: Some(kline_loader),
+            tick_loader,
         }
     }
+
+    pub fn set_tick_loader(&mut self, tick_loader: Box<dyn TickLoader>) {
+        self.tick_loader = Some(tick_loader);
+    }
+
+    fn start_tick_replay(&mut self, tick_loader: Box<dyn TickLoader>) -> Result<(), AppError> {
+        let config = self.config.clone();
+        let replay_config = config.tick_replay.clone();
+        let mut symbols: Vec<String> = vec![];
+        let mut kline_topics: Vec<(String, String)> = vec![];
+        for topic in self.topics.iter() {
+            if !symbols.contains(&topic.symbol) {
+                symbols.push(topic.symbol.clone());
+            }
+            if !topic.interval.is_empty() {
+                kline_topics.push((topic.symbol.clone(), topic.interval.clone()));
+            }
+        }
+        let mut replay = TickReplay::new(tick_loader.as_ref(), &symbols, config.start_time, config.end_time)?;
+        let mut builder = TickKLineBuilder::new(
+            &kline_topics,
+            replay_config.as_ref().and_then(|v| v.cumulative_volume).unwrap_or(false),
+            replay_config.as_ref().and_then(|v| v.starting_hour),
+        );
+        let derive_kline = replay_config.as_ref().and_then(|v| v.derive_kline).unwrap_or(true);
+        let subscription_ref = self.subscription.clone();
+        BACKTEST_CLOCK.enable(config.clock_mode.unwrap_or(ClockMode::Sleep));
+        thread::spawn(move|| {
+            BACKTEST_CLOCK.wait_released();
+            loop {
+                let record = match replay.next_record() {
+                    Ok(Some(r)) => r,
+                    Ok(None) => break,
+                    Err(e) => panic!("Error when replaying ticks: {:?}", e),
+                };
+                BACKTEST_CLOCK.wait_permit();
+                BACKTEST_CLOCK.advance();
+                {
+                    let subscrption = subscription_ref.lock().unwrap();
+                    if let Some(tick) = record.tick {
+                        let klines = if derive_kline { builder.on_tick(&tick) } else { vec![] };
+                        publish(&subscrption, MarketData::Tick(tick));
+                        for kline in klines {
+                            publish(&subscrption, MarketData::Kline(kline));
+                        }
+                    }
+                    if let Some(kline) = record.kline {
+                        publish(&subscrption, MarketData::Kline(kline));
+                    }
+                }
+                if !BACKTEST_CLOCK.is_enabled() && config.lines_per_sec > 0 && 1000 / config.lines_per_sec > 0 {
+                    thread::sleep(Duration::from_millis(1000 / config.lines_per_sec));
+                }
+            }
+            BACKTEST_CLOCK.advance();
+            let subscrption = subscription_ref.lock().unwrap();
+            for kline in builder.close() {
+                publish(&subscrption, MarketData::Kline(kline));
+            }
+            publish(&subscrption, MarketData::BacktestEnded);
+        });
+        Ok(())
+    }
 }
 
 impl MarketServer for SimMarketServer {
@@ -105,81 +187,69 @@ impl MarketServer for SimMarketServer {
 
     fn start(&mut self) -> Result<Subscription<MarketData>, AppError> {
         let sub = self.subscription.lock().unwrap().subscribe();
+        if let Some(tick_loader) = self.tick_loader.take() {
+            self.start_tick_replay(tick_loader)?;
+            return Ok(sub);
+        }
 
         let config = self.config.clone();
-        let topics = self.topics.clone();
+        let topics: Vec<(String, String)> = self.topics.iter().map(|t| (t.symbol.clone(), t.interval.clone())).collect();
         let subscription_ref = self.subscription.clone();
-        let kline_loader = self.kline_loader.take().unwrap();
+        let mut replay = KLineReplay::new(self.kline_loader.take().unwrap(), &topics, config.start_time);
+        BACKTEST_CLOCK.enable(config.clock_mode.unwrap_or(ClockMode::Sleep));
         thread::spawn(move|| {
+            BACKTEST_CLOCK.wait_released();
             let mut temp = config.start_time;
-            let mut kline_store: HashMap<String, (Vec<KLine>, usize)> = HashMap::new();
-            while temp <= config.end_time {
-                for topic in topics.iter() {
-                    let ret = visit(&mut kline_store, topic.symbol.clone(), &topic.interval.clone(), temp, &kline_loader);
-                    if let Ok(kline) = ret {
-                        if let Some(v) = kline {
-                            let subscrption = subscription_ref.lock().unwrap();
-                            let tick = Tick {
-                                symbol: v.symbol.clone(),
-                                datetime: v.datetime.clone(),
-                                open: 0 as f64,
-                                high: 0 as f64,
-                                low: 0 as f64,
-                                close: v.close,
-                                volume: 0 as f64,
-                                turnover: 0 as f64,
-                                bids: vec![],
-                                asks: vec![],
-                                timestamp: v.timestamp,
-                            };
-                            subscrption.send(&MarketData::Tick(tick));
-                            subscrption.send(&MarketData::Kline(v));
-                        }
-                    } else {
-                        panic!("Error when running bn_sim_market_server");
+            loop {
+                let next = match replay.next_close_time() {
+                    Ok(Some(v)) if v <= config.end_time => v,
+                    Ok(_) => break,
+                    Err(e) => panic!("Error when running sim_market_server: {:?}", e),
+                };
+                if next > temp {
+                    temp = if config.interval > 0 { temp + (next - temp).div_ceil(config.interval) * config.interval } else { next };
+                }
+                BACKTEST_CLOCK.wait_permit();
+                loop {
+                    let v = match replay.next_until(temp.min(config.end_time)) {
+                        Ok(Some(v)) => v,
+                        Ok(None) => break,
+                        Err(e) => panic!("Error when running sim_market_server: {:?}", e),
+                    };
+                    BACKTEST_CLOCK.advance();
+                    let subscrption = subscription_ref.lock().unwrap();
+                    if v.emit_tick {
+                        let tick = Tick {
+                            symbol: v.kline.symbol.clone(),
+                            datetime: v.kline.datetime.clone(),
+                            open: 0 as f64,
+                            high: 0 as f64,
+                            low: 0 as f64,
+                            close: v.kline.close,
+                            volume: 0 as f64,
+                            turnover: 0 as f64,
+                            bids: vec![],
+                            asks: vec![],
+                            timestamp: v.kline.timestamp,
+                            ..Default::default()
+                        };
+                        publish(&subscrption, MarketData::Tick(tick));
+                    }
+                    if v.emit_kline {
+                        publish(&subscrption, MarketData::Kline(v.kline));
                     }
                 }
-                temp = temp + config.interval;
-                if config.lines_per_sec > 0 && 1000 / config.lines_per_sec > 0 {
+                if !BACKTEST_CLOCK.is_enabled() && config.lines_per_sec > 0 && 1000 / config.lines_per_sec > 0 {
                     thread::sleep(Duration::from_millis(1000 / config.lines_per_sec));
                 }
             }
+            BACKTEST_CLOCK.advance();
+            publish(&subscription_ref.lock().unwrap(), MarketData::BacktestEnded);
         });
         Ok(sub)
     }
 
     fn close(&self) {
+        BACKTEST_CLOCK.disable();
     }
 }
-
-
-fn visit(klines_store: &mut HashMap<String, (Vec<KLine>, usize)>, symbol: String, interval: &str, current_time: u64, kline_loader: &Box<dyn KLineLoader>) -> Result<Option<KLine>, AppError> {
-    let item = klines_store.get_mut(&symbol);
-    let mut need_more = true;
-    if let Some(v) = item {
-        if v.0.len() > 0 {
-            need_more = v.1 >= v.0.len();
-        } else {
-            need_more = false;
-        }
-    }
-    if need_more {
-        let klines = kline_loader.load_kline(&symbol, interval, 500, Some(current_time), None)?;
-        klines_store.insert(symbol.clone(), (klines, 0));
-    }
-
-    let item = klines_store.get_mut(&symbol);
-
-    if let Some(v) = item {
-        if v.0.len() > 0 && v.1 < v.0.len() {
-            let kline = v.0.get(v.1);
-            if let Some(value) = kline {
-                if value.timestamp <= current_time {
-                    v.1 = v.1 + 1;
-                    return Ok(Some(value.clone()));
-                }
-            }
-        }
-    }
-    Ok(None)
-}
\ No newline at end of file
@@ -0,0 +1,282 @@
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, Read};
+use flate2::read::MultiGzDecoder;
+use std::path::{Path, PathBuf};
+use common::error::AppError;
+use serde::{Deserialize, Serialize};
+use log::*;
+use crate::kline::KLineCombiner;
+use crate::market_server::{KLine, Tick};
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MarketRecord {
+    pub recv_ns: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tick: Option<Tick>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kline: Option<KLine>,
+}
+
+impl MarketRecord {
+    pub fn from_tick(tick: Tick) -> Self {
+        MarketRecord {
+            recv_ns: tick.timestamp * 1_000_000,
+            tick: Some(tick),
+            kline: None,
+        }
+    }
+
+    pub fn symbol(&self) -> &str {
+        match (&self.tick, &self.kline) {
+            (Some(t), _) => &t.symbol,
+            (None, Some(k)) => &k.symbol,
+            _ => "",
+        }
+    }
+}
+
+pub type TickIter = Box<dyn Iterator<Item = Result<MarketRecord, AppError>> + Send>;
+
+pub trait TickLoader: Send + Sync {
+    fn load_tick(&self, symbol: &str, start_time: u64, end_time: u64) -> Result<TickIter, AppError>;
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TickReplayConfig {
+    pub dir: String,
+    pub cumulative_volume: Option<bool>,
+    pub starting_hour: Option<u32>,
+    pub derive_kline: Option<bool>,
+}
+
+pub struct FileTickLoader {
+    dir: String,
+}
+
+impl FileTickLoader {
+    pub fn new(dir: &str) -> Self {
+        FileTickLoader {
+            dir: dir.to_string(),
+        }
+    }
+
+    pub fn files(&self, symbol: &str) -> Result<Vec<PathBuf>, AppError> {
+        let entries = fs::read_dir(&self.dir).map_err(|e| AppError::new(-200, &format!("{} {}", self.dir, e)))?;
+        let mut files: Vec<PathBuf> = entries.filter_map(|e| e.ok()).map(|e| e.path()).filter(|path| {
+            let name = path.file_name().and_then(|v| v.to_str()).unwrap_or("");
+            let name = name.strip_suffix(".gz").unwrap_or(name);
+            name == format!("{}.jsonl", symbol) || (name.starts_with(&format!("{}_", symbol)) && name.ends_with(".jsonl"))
+        }).collect();
+        files.sort();
+        Ok(files)
+    }
+}
+
+impl TickLoader for FileTickLoader {
+    fn load_tick(&self, symbol: &str, start_time: u64, end_time: u64) -> Result<TickIter, AppError> {
+        let files = self.files(symbol)?;
+        if files.is_empty() {
+            warn!("No tick file found for {} in {}", symbol, self.dir);
+        }
+        let iter = files.into_iter().flat_map(move |path| read_tick_file(&path))
+            .filter(move |ret| match ret {
+                Ok(record) => record.recv_ns / 1_000_000 >= start_time && record.recv_ns / 1_000_000 <= end_time,
+                Err(_) => true,
+            });
+        Ok(Box::new(iter))
+    }
+}
+
+pub fn parse_record(line: &str) -> Result<MarketRecord, serde_json::Error> {
+    match serde_json::from_str::<MarketRecord>(line) {
+        Ok(record) => Ok(record),
+        Err(_) => serde_json::from_str::<Tick>(line).map(MarketRecord::from_tick),
+    }
+}
+
+fn read_tick_file(path: &Path) -> TickIter {
+    let file = match File::open(path) {
+        Ok(f) => f,
+        Err(e) => return Box::new(std::iter::once(Err(AppError::new(-200, &format!("{:?} {}", path, e))))),
+    };
+    let reader: Box<dyn Read + Send> = if path.extension().map(|v| v == "gz").unwrap_or(false) {
+        Box::new(MultiGzDecoder::new(file))
+    } else {
+        Box::new(file)
+    };
+    let path = path.to_path_buf();
+    Box::new(BufReader::new(reader).lines().enumerate().filter_map(move |(i, line)| {
+        match line {
+            Ok(line) if line.trim().is_empty() => None,
+            Ok(line) => Some(parse_record(&line).map_err(|e| AppError::new(-200, &format!("{:?} line {}: {}", path, i + 1, e)))),
+            Err(e) => Some(Err(AppError::new(-200, &format!("{:?} line {}: {}", path, i + 1, e)))),
+        }
+    }))
+}
+
+struct TickCursor {
+    symbol: String,
+    iter: TickIter,
+    head: Option<MarketRecord>,
+}
+
+impl TickCursor {
+    fn advance(&mut self) -> Result<(), AppError> {
+        let prev = self.head.take().map(|r| r.recv_ns).unwrap_or(0);
+        if let Some(ret) = self.iter.next() {
+            let record = ret?;
+            if record.recv_ns < prev {
+                warn!("Out of order tick of {} at {} after {}", self.symbol, record.recv_ns, prev);
+            }
+            self.head = Some(record);
+        }
+        Ok(())
+    }
+}
+
+pub struct TickReplay {
+    cursors: Vec<TickCursor>,
+}
+
+impl TickReplay {
+    pub fn new(loader: &dyn TickLoader, symbols: &[String], start_time: u64, end_time: u64) -> Result<Self, AppError> {
+        let mut cursors = vec![];
+        for symbol in symbols.iter() {
+            let mut cursor = TickCursor {
+                symbol: symbol.clone(),
+                iter: loader.load_tick(symbol, start_time, end_time)?,
+                head: None,
+            };
+            cursor.advance()?;
+            cursors.push(cursor);
+        }
+        Ok(TickReplay { cursors })
+    }
+
+    pub fn next_record(&mut self) -> Result<Option<MarketRecord>, AppError> {
+        let next = self.cursors.iter_mut()
+            .filter(|c| c.head.is_some())
+            .min_by_key(|c| c.head.as_ref().unwrap().recv_ns);
+        match next {
+            Some(cursor) => {
+                let record = cursor.head.clone();
+                cursor.advance()?;
+                Ok(record)
+            },
+            None => Ok(None),
+        }
+    }
+}
+
+pub struct TickKLineBuilder {
+    cumulative_volume: bool,
+    combiners: Vec<(String, KLineCombiner)>,
+    last_ticks: Vec<Tick>,
+}
+
+impl TickKLineBuilder {
+    pub fn new(topics: &[(String, String)], cumulative_volume: bool, starting_hour: Option<u32>) -> Self {
+        TickKLineBuilder {
+            cumulative_volume,
+            combiners: topics.iter().map(|(symbol, interval)| (symbol.clone(), KLineCombiner::new(interval, 100, starting_hour))).collect(),
+            last_ticks: vec![],
+        }
+    }
+
+    pub fn on_tick(&mut self, t: &Tick) -> Vec<KLine> {
+        let (mut volume, mut turnover) = (t.volume, t.turnover);
+        if self.cumulative_volume {
+            let prev = self.last_ticks.iter_mut().find(|p| p.symbol == t.symbol);
+            match prev {
+                Some(prev) => {
+                    volume = if t.volume >= prev.volume { t.volume - prev.volume } else { t.volume };
+                    turnover = if t.turnover >= prev.turnover { t.turnover - prev.turnover } else { t.turnover };
+                    *prev = t.clone();
+                },
+                None => {
+                    volume = 0.0;
+                    turnover = 0.0;
+                    self.last_ticks.push(t.clone());
+                },
+            }
+        }
+
+        let mut ret = vec![];
+        for (symbol, combiner) in self.combiners.iter_mut() {
+            if *symbol == t.symbol {
+                let kline = KLine {
+                    symbol: t.symbol.clone(),
+                    datetime: t.datetime.clone(),
+                    interval: combiner.interval.clone(),
+                    open: t.close,
+                    high: t.close,
+                    low: t.close,
+                    close: t.close,
+                    volume,
+                    turnover,
+                    timestamp: t.timestamp,
+                };
+                if let Some(kline) = combiner.combine_tick(&kline, true) {
+                    ret.push(kline);
+                }
+            }
+        }
+        ret
+    }
+
+    pub fn close(&mut self) -> Vec<KLine> {
+        self.combiners.iter_mut().filter_map(|(_, combiner)| combiner.close(true)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+    use super::*;
+
+    fn tick(symbol: &str, datetime: &str, timestamp: u64, close: f64, volume: f64) -> Tick {
+        Tick {
+            symbol: symbol.to_string(),
+            datetime: datetime.to_string(),
+            close,
+            volume,
+            bids: vec![vec![close - 1.0, 1.0]],
+            asks: vec![vec![close + 1.0, 1.0]],
+            timestamp,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_replay_and_combine() {
+        let dir = std::env::temp_dir().join(format!("tick_replay_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let write = |name: &str, ticks: Vec<Tick>| {
+            let mut file = File::create(dir.join(name)).unwrap();
+            for t in ticks {
+                writeln!(file, "{}", serde_json::to_string(&t).unwrap()).unwrap();
+            }
+        };
+        write("A.jsonl", vec![tick("A", "2024-01-01 00:00:10", 10000, 10.0, 1.0), tick("A", "2024-01-01 00:01:05", 65000, 12.0, 2.0)]);
+        write("B_20240101.jsonl", vec![tick("B", "2024-01-01 00:00:30", 30000, 20.0, 3.0)]);
+
+        let loader = FileTickLoader::new(dir.to_str().unwrap());
+        let mut replay = TickReplay::new(&loader, &["A".to_string(), "B".to_string()], 0, 100000).unwrap();
+        let mut builder = TickKLineBuilder::new(&[("A".to_string(), "1m".to_string())], false, None);
+        let mut order = vec![];
+        let mut klines = vec![];
+        while let Some(record) = replay.next_record().unwrap() {
+            let t = record.tick.unwrap();
+            assert_eq!(t.bids.len(), 1);
+            order.push(t.symbol.clone());
+            klines.extend(builder.on_tick(&t));
+        }
+        klines.extend(builder.close());
+        assert_eq!(order, vec!["A", "B", "A"]);
+        assert_eq!(klines.len(), 2);
+        assert_eq!(klines[0].datetime, "2024-01-01 00:00:00");
+        assert_eq!(klines[1].close, 12.0);
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
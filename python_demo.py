@@ -20,6 +20,9 @@ def init_unit_lock(unit_id):
 @CFUNCTYPE(None, c_char_p, c_char_p)
 def tick_callback(unit_id, result):
     data = json.loads(result.decode('utf-8'))
+    if 'event' in data:
+        logger.info(f"tick_callback >>> {data['event']}")
+        return
     lock = unit_lock[unit_id.decode('utf-8')]
     with lock:
         func = ticks.get(unit_id.decode('utf-8'))
@@ -30,6 +33,9 @@ def tick_callback(unit_id, result):
 @CFUNCTYPE(None, c_char_p, c_char_p)
 def kline_callback(unit_id, result):
     data = json.loads(result.decode('utf-8'))
+    if 'event' in data:
+        logger.info(f"kline_callback >>> {data['event']}")
+        return
     lock = unit_lock[unit_id.decode('utf-8')]
     with lock:
         func, init_data, init_count = klines.get(unit_id.decode('utf-8'))
@@ -55,10 +61,13 @@ def event_callback(unit_id, _type, result):
     data = json.loads(result.decode('utf-8'))
     lock = unit_lock[unit_id.decode('utf-8')]
     with lock:
-        on_order_func, on_position_func = events.get(unit_id.decode('utf-8'))
+        on_order_func, on_position_func, on_fill_func = events.get(unit_id.decode('utf-8'))
         if _type == "ORDER":
             if on_order_func:
                 on_order_func(data)
+        elif _type == "FILL":
+            if on_fill_func:
+                on_fill_func(data)
         else:
             if _type == "POSITION":
                 if on_position_func:
@@ -93,18 +102,92 @@ class Gateway:
         self.rust_lib.new_order.argtypes = [c_char_p, c_char_p]
         self.rust_lib.new_order.restype = c_void_p
 
+        self.rust_lib.new_orders.argtypes = [c_char_p, c_char_p]
+        self.rust_lib.new_orders.restype = c_void_p
+
+        self.rust_lib.modify_order.argtypes = [c_char_p, c_char_p]
+        self.rust_lib.modify_order.restype = c_void_p
+
         self.rust_lib.cancel_order.argtypes = [c_char_p, c_char_p]
         self.rust_lib.cancel_order.restype = c_void_p
 
+        self.rust_lib.cancel_orders_by_id.argtypes = [c_char_p, c_char_p]
+        self.rust_lib.cancel_orders_by_id.restype = c_void_p
+
         self.rust_lib.cancel_orders.argtypes = [c_char_p]
         self.rust_lib.cancel_orders.restype = c_void_p
 
         self.rust_lib.get_positions.argtypes = [c_char_p]
         self.rust_lib.get_positions.restype = c_void_p
 
+        self.rust_lib.get_open_orders.argtypes = [c_char_p]
+        self.rust_lib.get_open_orders.restype = c_void_p
+
+        self.rust_lib.get_order.argtypes = [c_char_p, c_char_p]
+        self.rust_lib.get_order.restype = c_void_p
+
+        self.rust_lib.get_session_orders.argtypes = [c_char_p]
+        self.rust_lib.get_session_orders.restype = c_void_p
+
+        self.rust_lib.get_session_order.argtypes = [c_char_p, c_char_p]
+        self.rust_lib.get_session_order.restype = c_void_p
+
+        self.rust_lib.new_conditional_order.argtypes = [c_char_p, c_char_p]
+        self.rust_lib.new_conditional_order.restype = c_void_p
+
+        self.rust_lib.new_oco_order.argtypes = [c_char_p, c_char_p]
+        self.rust_lib.new_oco_order.restype = c_void_p
+
+        self.rust_lib.new_bracket_order.argtypes = [c_char_p, c_char_p]
+        self.rust_lib.new_bracket_order.restype = c_void_p
+
+        self.rust_lib.cancel_conditional_order.argtypes = [c_char_p]
+        self.rust_lib.cancel_conditional_order.restype = c_void_p
+
+        self.rust_lib.get_conditional_orders.argtypes = [c_char_p]
+        self.rust_lib.get_conditional_orders.restype = c_void_p
+
+        self.rust_lib.new_algo_order.argtypes = [c_char_p, c_char_p]
+        self.rust_lib.new_algo_order.restype = c_void_p
+
+        self.rust_lib.cancel_algo_order.argtypes = [c_char_p]
+        self.rust_lib.cancel_algo_order.restype = c_void_p
+
+        self.rust_lib.get_algo_orders.argtypes = [c_char_p]
+        self.rust_lib.get_algo_orders.restype = c_void_p
+
+        self.rust_lib.set_risk_config.argtypes = [c_char_p]
+        self.rust_lib.set_risk_config.restype = c_void_p
+
+        self.rust_lib.get_risk_config.argtypes = []
+        self.rust_lib.get_risk_config.restype = c_void_p
+
+        self.rust_lib.kill_switch.argtypes = [c_int]
+        self.rust_lib.kill_switch.restype = c_void_p
+
+        self.rust_lib.arm_dead_mans_switch.argtypes = [c_int, c_int]
+        self.rust_lib.arm_dead_mans_switch.restype = c_void_p
+
+        self.rust_lib.heartbeat.argtypes = []
+        self.rust_lib.heartbeat.restype = c_void_p
+
+        self.rust_lib.disarm_dead_mans_switch.argtypes = []
+        self.rust_lib.disarm_dead_mans_switch.restype = c_void_p
+
         self.rust_lib.get_account.argtypes = [c_char_p]
         self.rust_lib.get_account.restype = c_void_p
 
+        self.rust_lib.step.argtypes = [c_int]
+        self.rust_lib.step.restype = c_void_p
+
+        self.rust_lib.get_backtest_report.argtypes = [c_char_p]
+        self.rust_lib.get_backtest_report.restype = c_void_p
+
+        self.rust_lib.start_recorder.argtypes = [c_char_p]
+        self.rust_lib.start_recorder.restype = c_void_p
+
+        self.rust_lib.stop_recorder.restype = c_void_p
+
     def handle_data(self, service_result: dict):
         if service_result['errorCode'] != 0:
             raise Exception(service_result['message'])
@@ -142,6 +225,21 @@ class Gateway:
         json_str = ctypes.cast(result, ctypes.POINTER(ctypes.c_char_p)).contents.value.decode('utf-8')
         return self.handle_data(json.loads(json_str))
 
+    def new_orders(self, unit_id: str, symbol: str, orders: list):
+        symbol = c_char_p(symbol.encode('utf-8'))
+        request = c_char_p(json.dumps([asdict(order) for order in orders]).encode('utf-8'))
+        result = self.rust_lib.new_orders(symbol, request)
+        json_str = ctypes.cast(result, ctypes.POINTER(ctypes.c_char_p)).contents.value.decode('utf-8')
+        return self.handle_data(json.loads(json_str))
+
+    def modify_order(self, unit_id: str, symbol: str, client_order_id: str, quantity: float = None, price: float = None):
+        symbol = c_char_p(symbol.encode('utf-8'))
+        json_str = json.dumps({'clientOrderId': client_order_id, 'quantity': quantity, 'price': price})
+        request = c_char_p(json_str.encode('utf-8'))
+        result = self.rust_lib.modify_order(symbol, request)
+        json_str = ctypes.cast(result, ctypes.POINTER(ctypes.c_char_p)).contents.value.decode('utf-8')
+        return self.handle_data(json.loads(json_str))
+
     def cancel_order(self, unit_id: str, symbol: str, order_id: str):
         symbol = c_char_p(symbol.encode('utf-8'))
         order_id = c_char_p(order_id.encode('utf-8'))
@@ -149,6 +247,13 @@ class Gateway:
         json_str = ctypes.cast(result, ctypes.POINTER(ctypes.c_char_p)).contents.value.decode('utf-8')
         return self.handle_data(json.loads(json_str))
 
+    def cancel_orders_by_id(self, unit_id: str, symbol: str, order_ids: list):
+        symbol = c_char_p(symbol.encode('utf-8'))
+        request = c_char_p(json.dumps(order_ids).encode('utf-8'))
+        result = self.rust_lib.cancel_orders_by_id(symbol, request)
+        json_str = ctypes.cast(result, ctypes.POINTER(ctypes.c_char_p)).contents.value.decode('utf-8')
+        return self.handle_data(json.loads(json_str))
+
     def cancel_orders(self, unit_id: str, symbol: str):
         symbol = c_char_p(symbol.encode('utf-8'))
         result = self.rust_lib.cancel_orders(symbol)
@@ -161,12 +266,143 @@ class Gateway:
         json_str = ctypes.cast(result, ctypes.POINTER(ctypes.c_char_p)).contents.value.decode('utf-8')
         return self.handle_data(json.loads(json_str))
 
+    def step(self, count: int = 1):
+        result = self.rust_lib.step(count)
+        json_str = ctypes.cast(result, ctypes.POINTER(ctypes.c_char_p)).contents.value.decode('utf-8')
+        return self.handle_data(json.loads(json_str))
+
+    def get_backtest_report(self, csv_dir: str = ""):
+        csv_dir = c_char_p(csv_dir.encode('utf-8'))
+        result = self.rust_lib.get_backtest_report(csv_dir)
+        json_str = ctypes.cast(result, ctypes.POINTER(ctypes.c_char_p)).contents.value.decode('utf-8')
+        return self.handle_data(json.loads(json_str))
+
+    def start_recorder(self, dir: str, compress: bool = True):
+        config = c_char_p(json.dumps({'dir': dir, 'compress': compress}).encode('utf-8'))
+        result = self.rust_lib.start_recorder(config)
+        json_str = ctypes.cast(result, ctypes.POINTER(ctypes.c_char_p)).contents.value.decode('utf-8')
+        return self.handle_data(json.loads(json_str))
+
+    def stop_recorder(self):
+        result = self.rust_lib.stop_recorder()
+        json_str = ctypes.cast(result, ctypes.POINTER(ctypes.c_char_p)).contents.value.decode('utf-8')
+        return self.handle_data(json.loads(json_str))
+
     def get_positions(self, unit_id: str, symbol: str):
         symbol = c_char_p(symbol.encode('utf-8'))
         result = self.rust_lib.get_positions(symbol)
         json_str = ctypes.cast(result, ctypes.POINTER(ctypes.c_char_p)).contents.value.decode('utf-8')
         return self.handle_data(json.loads(json_str))
 
+    def get_open_orders(self, unit_id: str, symbol: str):
+        symbol = c_char_p(symbol.encode('utf-8'))
+        result = self.rust_lib.get_open_orders(symbol)
+        json_str = ctypes.cast(result, ctypes.POINTER(ctypes.c_char_p)).contents.value.decode('utf-8')
+        return self.handle_data(json.loads(json_str))
+
+    def get_order(self, unit_id: str, symbol: str, client_order_id: str):
+        symbol = c_char_p(symbol.encode('utf-8'))
+        client_order_id = c_char_p(client_order_id.encode('utf-8'))
+        result = self.rust_lib.get_order(symbol, client_order_id)
+        json_str = ctypes.cast(result, ctypes.POINTER(ctypes.c_char_p)).contents.value.decode('utf-8')
+        return self.handle_data(json.loads(json_str))
+
+    def get_session_orders(self, unit_id: str, symbol: str = ""):
+        symbol = c_char_p(symbol.encode('utf-8'))
+        result = self.rust_lib.get_session_orders(symbol)
+        json_str = ctypes.cast(result, ctypes.POINTER(ctypes.c_char_p)).contents.value.decode('utf-8')
+        return self.handle_data(json.loads(json_str))
+
+    def get_session_order(self, unit_id: str, symbol: str, client_order_id: str):
+        symbol = c_char_p(symbol.encode('utf-8'))
+        client_order_id = c_char_p(client_order_id.encode('utf-8'))
+        result = self.rust_lib.get_session_order(symbol, client_order_id)
+        json_str = ctypes.cast(result, ctypes.POINTER(ctypes.c_char_p)).contents.value.decode('utf-8')
+        return self.handle_data(json.loads(json_str))
+
+    def new_conditional_order(self, unit_id: str, symbol: str, request: dict):
+        symbol = c_char_p(symbol.encode('utf-8'))
+        request = c_char_p(json.dumps(request).encode('utf-8'))
+        result = self.rust_lib.new_conditional_order(symbol, request)
+        json_str = ctypes.cast(result, ctypes.POINTER(ctypes.c_char_p)).contents.value.decode('utf-8')
+        return self.handle_data(json.loads(json_str))
+
+    def new_oco_order(self, unit_id: str, symbol: str, requests: list):
+        symbol = c_char_p(symbol.encode('utf-8'))
+        requests = c_char_p(json.dumps(requests).encode('utf-8'))
+        result = self.rust_lib.new_oco_order(symbol, requests)
+        json_str = ctypes.cast(result, ctypes.POINTER(ctypes.c_char_p)).contents.value.decode('utf-8')
+        return self.handle_data(json.loads(json_str))
+
+    def new_bracket_order(self, unit_id: str, symbol: str, request: dict):
+        symbol = c_char_p(symbol.encode('utf-8'))
+        request = c_char_p(json.dumps(request).encode('utf-8'))
+        result = self.rust_lib.new_bracket_order(symbol, request)
+        json_str = ctypes.cast(result, ctypes.POINTER(ctypes.c_char_p)).contents.value.decode('utf-8')
+        return self.handle_data(json.loads(json_str))
+
+    def cancel_conditional_order(self, unit_id: str, conditional_id: str):
+        conditional_id = c_char_p(conditional_id.encode('utf-8'))
+        result = self.rust_lib.cancel_conditional_order(conditional_id)
+        json_str = ctypes.cast(result, ctypes.POINTER(ctypes.c_char_p)).contents.value.decode('utf-8')
+        return self.handle_data(json.loads(json_str))
+
+    def get_conditional_orders(self, unit_id: str, symbol: str):
+        symbol = c_char_p(symbol.encode('utf-8'))
+        result = self.rust_lib.get_conditional_orders(symbol)
+        json_str = ctypes.cast(result, ctypes.POINTER(ctypes.c_char_p)).contents.value.decode('utf-8')
+        return self.handle_data(json.loads(json_str))
+
+    def new_algo_order(self, unit_id: str, symbol: str, request: dict):
+        symbol = c_char_p(symbol.encode('utf-8'))
+        request = c_char_p(json.dumps(request).encode('utf-8'))
+        result = self.rust_lib.new_algo_order(symbol, request)
+        json_str = ctypes.cast(result, ctypes.POINTER(ctypes.c_char_p)).contents.value.decode('utf-8')
+        return self.handle_data(json.loads(json_str))
+
+    def cancel_algo_order(self, unit_id: str, algo_id: str):
+        algo_id = c_char_p(algo_id.encode('utf-8'))
+        result = self.rust_lib.cancel_algo_order(algo_id)
+        json_str = ctypes.cast(result, ctypes.POINTER(ctypes.c_char_p)).contents.value.decode('utf-8')
+        return self.handle_data(json.loads(json_str))
+
+    def get_algo_orders(self, unit_id: str, symbol: str):
+        symbol = c_char_p(symbol.encode('utf-8'))
+        result = self.rust_lib.get_algo_orders(symbol)
+        json_str = ctypes.cast(result, ctypes.POINTER(ctypes.c_char_p)).contents.value.decode('utf-8')
+        return self.handle_data(json.loads(json_str))
+
+    def set_risk_config(self, unit_id: str, config: dict):
+        config = c_char_p(json.dumps(config).encode('utf-8'))
+        result = self.rust_lib.set_risk_config(config)
+        json_str = ctypes.cast(result, ctypes.POINTER(ctypes.c_char_p)).contents.value.decode('utf-8')
+        return self.handle_data(json.loads(json_str))
+
+    def get_risk_config(self, unit_id: str):
+        result = self.rust_lib.get_risk_config()
+        json_str = ctypes.cast(result, ctypes.POINTER(ctypes.c_char_p)).contents.value.decode('utf-8')
+        return self.handle_data(json.loads(json_str))
+
+    def kill_switch(self, unit_id: str, flatten: bool = False):
+        result = self.rust_lib.kill_switch(c_int(1 if flatten else 0))
+        json_str = ctypes.cast(result, ctypes.POINTER(ctypes.c_char_p)).contents.value.decode('utf-8')
+        return self.handle_data(json.loads(json_str))
+
+    def arm_dead_mans_switch(self, unit_id: str, timeout: int, flatten: bool = False):
+        result = self.rust_lib.arm_dead_mans_switch(c_int(timeout), c_int(1 if flatten else 0))
+        json_str = ctypes.cast(result, ctypes.POINTER(ctypes.c_char_p)).contents.value.decode('utf-8')
+        return self.handle_data(json.loads(json_str))
+
+    def heartbeat(self, unit_id: str):
+        result = self.rust_lib.heartbeat()
+        json_str = ctypes.cast(result, ctypes.POINTER(ctypes.c_char_p)).contents.value.decode('utf-8')
+        return self.handle_data(json.loads(json_str))
+
+    def disarm_dead_mans_switch(self, unit_id: str):
+        result = self.rust_lib.disarm_dead_mans_switch()
+        json_str = ctypes.cast(result, ctypes.POINTER(ctypes.c_char_p)).contents.value.decode('utf-8')
+        return self.handle_data(json.loads(json_str))
+
     def subscribe_tick(self, unit_id: str, symbol: str, func=None):
         init_unit_lock(unit_id)
 
@@ -193,11 +429,11 @@ class Gateway:
         init_data.extend(data)
         return init_data
 
-    def init_symbol_trade(self, unit_id: str, symbol: str, config, on_order_func=None, on_position_func=None):
+    def init_symbol_trade(self, unit_id: str, symbol: str, config, on_order_func=None, on_position_func=None, on_fill_func=None):
         init_unit_lock(unit_id)
 
         symbol = c_char_p(symbol.encode('utf-8'))
-        events[unit_id] = (on_order_func, on_position_func)
+        events[unit_id] = (on_order_func, on_position_func, on_fill_func)
 
         json_str = json.dumps(asdict(config))
         config = c_char_p(json_str.encode('utf-8'))
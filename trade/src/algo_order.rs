@@ -0,0 +1,461 @@
+use std::collections::HashMap;
+use common::error::AppError;
+use market::market_server::KLine;
+use serde::{Deserialize, Serialize};
+use crate::conditional_order::is_order_closed;
+use crate::order::{field, invalid_field, parse_object, required, OrderKind, OrderRequest};
+use crate::trade_server::{Execution, Order};
+
+const ALGO_FIELDS: [&str; 9] = ["id", "algo", "order", "duration", "slices", "visibleQuantity", "lotSize", "interval", "lookback"];
+const DAY_MILLIS: u64 = 86_400_000;
+const MAX_UNMATCHED: usize = 1000;
+const EPSILON: f64 = 1e-9;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum AlgoKind {
+    Twap,
+    Vwap,
+    Iceberg,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum AlgoStatus {
+    Running,
+    Completed,
+    Canceled,
+}
+
+/// A parent order worked by child orders: TWAP and VWAP over `duration` seconds in `slices`,
+/// ICEBERG showing `visibleQuantity` at a time.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AlgoOrderRequest {
+    pub id: Option<String>,
+    pub algo: AlgoKind,
+    pub order: OrderRequest,
+    pub duration: Option<u64>,
+    pub slices: Option<u32>,
+    pub visible_quantity: Option<f64>,
+    pub lot_size: Option<f64>,
+    pub interval: Option<String>,
+    pub lookback: Option<u32>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AlgoOrder {
+    pub id: String,
+    pub symbol: String,
+    pub algo: AlgoKind,
+    pub status: AlgoStatus,
+    pub order: OrderRequest,
+    pub duration: u64,
+    pub slices: u32,
+    pub visible_quantity: Option<f64>,
+    pub lot_size: Option<f64>,
+    pub sent: f64,
+    pub traded: f64,
+    pub avg_price: f64,
+    pub fee: f64,
+    pub next_slice: u32,
+    pub start_time: Option<u64>,
+    pub weights: Vec<f64>,
+    pub children: Vec<String>,
+    pub message: String,
+    pub timestamp: u64,
+    #[serde(skip)]
+    history: Vec<KLine>,
+    #[serde(skip)]
+    filled: f64,
+    #[serde(skip)]
+    filled_notional: f64,
+}
+
+#[derive(Debug, Clone)]
+struct ChildOrder {
+    algo_id: String,
+    quantity: f64,
+    traded: f64,
+    closed: bool,
+}
+
+impl AlgoOrderRequest {
+    pub fn from_json(json: &str) -> Result<Self, AppError> {
+        let map = &parse_object(json, &ALGO_FIELDS)?;
+        let order = map.get("order").ok_or_else(|| invalid_field("order", "is required"))?;
+        let request = AlgoOrderRequest {
+            id: field(map, "id")?,
+            algo: required(map, "algo")?,
+            order: OrderRequest::from_json(&order.to_string())?,
+            duration: field(map, "duration")?,
+            slices: field(map, "slices")?,
+            visible_quantity: field(map, "visibleQuantity")?,
+            lot_size: field(map, "lotSize")?,
+            interval: field(map, "interval")?,
+            lookback: field(map, "lookback")?,
+        };
+        request.validate()?;
+        Ok(request)
+    }
+
+    pub fn validate(&self) -> Result<(), AppError> {
+        self.order.validate()?;
+        if !matches!(self.order.order_type, OrderKind::Limit | OrderKind::Market) {
+            return Err(invalid_field("orderType", "must be LIMIT or MARKET for an algo order"));
+        }
+        if matches!(self.lot_size, Some(v) if !v.is_finite() || v <= 0.0) {
+            return Err(invalid_field("lotSize", "must be positive"));
+        }
+        match self.algo {
+            AlgoKind::Twap | AlgoKind::Vwap => {
+                if self.duration.unwrap_or(0) == 0 {
+                    return Err(invalid_field("duration", "must be positive"));
+                }
+                if self.slices.unwrap_or(0) == 0 {
+                    return Err(invalid_field("slices", "must be positive"));
+                }
+            },
+            AlgoKind::Iceberg => {
+                match self.visible_quantity {
+                    Some(v) if v.is_finite() && v > 0.0 && v <= self.order.quantity => {},
+                    _ => return Err(invalid_field("visibleQuantity", "must be positive and not greater than the quantity")),
+                }
+            },
+        }
+        Ok(())
+    }
+
+    pub fn kline_interval(&self) -> String {
+        self.interval.clone().unwrap_or("1m".to_string())
+    }
+
+    pub fn kline_lookback(&self) -> u32 {
+        self.lookback.unwrap_or(1440)
+    }
+}
+
+/// Weights of each slice by the historical volume traded at the same time of day, equal weights without history.
+pub fn volume_profile(history: &[KLine], start_time: u64, duration: u64, slices: u32) -> Vec<f64> {
+    let slice_millis = (duration * 1000 / slices as u64).max(1);
+    let mut volumes = vec![0.0; slices as usize];
+    for kline in history.iter() {
+        for (i, volume) in volumes.iter_mut().enumerate() {
+            let window = (start_time + i as u64 * slice_millis) % DAY_MILLIS;
+            if (kline.timestamp % DAY_MILLIS + DAY_MILLIS - window) % DAY_MILLIS < slice_millis {
+                *volume += kline.volume;
+            }
+        }
+    }
+    let total: f64 = volumes.iter().sum();
+    if total <= 0.0 {
+        return vec![1.0 / slices as f64; slices as usize];
+    }
+    volumes.iter().map(|v| v / total).collect()
+}
+
+fn round_lot(quantity: f64, lot_size: Option<f64>) -> f64 {
+    match lot_size {
+        Some(lot) => (quantity / lot + EPSILON).floor() * lot,
+        None => quantity,
+    }
+}
+
+impl AlgoOrder {
+    pub fn to_order(&self) -> Order {
+        let status = match self.status {
+            AlgoStatus::Canceled => "CANCELED",
+            AlgoStatus::Completed => "FILLED",
+            AlgoStatus::Running if self.traded > 0.0 => "PARTIALLY_FILLED",
+            AlgoStatus::Running => "NEW",
+        };
+        Order {
+            order_id: self.id.clone(),
+            client_order_id: self.id.clone(),
+            order_type: format!("{:?}", self.algo).to_uppercase(),
+            symbol: self.symbol.clone(),
+            status: status.to_string(),
+            price: self.avg_price,
+            offset: self.order.offset.map(|o| format!("{:?}", o).to_uppercase()).unwrap_or_default(),
+            traded: self.traded,
+            total: self.order.quantity,
+            side: self.order.side.code().to_string(),
+            message: self.message.clone(),
+            timestamp: self.timestamp,
+            fee: self.fee,
+            slippage: 0.0,
+        }
+    }
+
+    fn remaining(&self) -> f64 {
+        self.order.quantity - self.sent
+    }
+
+    fn slice_quantity(&mut self, timestamp: u64, working: bool) -> Option<f64> {
+        if self.status != AlgoStatus::Running || self.remaining() <= EPSILON {
+            return None;
+        }
+        let start_time = *self.start_time.get_or_insert(timestamp);
+        let quantity = match self.algo {
+            AlgoKind::Iceberg if working => return None,
+            AlgoKind::Iceberg => self.visible_quantity.unwrap_or(self.order.quantity),
+            _ if self.next_slice >= self.slices => {
+                if working {
+                    return None;
+                }
+                self.remaining()
+            },
+            _ => {
+                if self.weights.is_empty() {
+                    self.weights = match self.algo {
+                        AlgoKind::Vwap => volume_profile(&self.history, start_time, self.duration, self.slices),
+                        _ => vec![1.0 / self.slices as f64; self.slices as usize],
+                    };
+                    self.history.clear();
+                }
+                let slice_millis = self.duration * 1000 / self.slices as u64;
+                let mut due = self.next_slice;
+                while due < self.slices && timestamp >= start_time + due as u64 * slice_millis {
+                    due += 1;
+                }
+                if due == self.next_slice {
+                    return None;
+                }
+                self.next_slice = due;
+                if due >= self.slices {
+                    self.remaining()
+                } else {
+                    self.order.quantity * self.weights[..due as usize].iter().sum::<f64>() - self.sent
+                }
+            },
+        };
+        let quantity = if quantity >= self.remaining() - EPSILON { self.remaining() } else { round_lot(quantity, self.lot_size) };
+        if quantity <= EPSILON {
+            return None;
+        }
+        Some(quantity)
+    }
+}
+
+#[derive(Default)]
+pub struct AlgoOrderEngine {
+    orders: Vec<AlgoOrder>,
+    children: HashMap<String, ChildOrder>,
+    unmatched: HashMap<String, Order>,
+    unmatched_fills: HashMap<String, Vec<Execution>>,
+    next_id: u64,
+}
+
+impl AlgoOrderEngine {
+    pub fn new() -> Self {
+        AlgoOrderEngine::default()
+    }
+
+    pub fn add(&mut self, symbol: &str, request: AlgoOrderRequest, history: Vec<KLine>) -> Result<AlgoOrder, AppError> {
+        request.validate()?;
+        let id = match request.id.clone() {
+            Some(id) => id,
+            None => {
+                self.next_id += 1;
+                format!("A{}", self.next_id)
+            },
+        };
+        if self.orders.iter().any(|o| o.id == id) {
+            return Err(invalid_field("id", &format!("the algo order {} already exists", id)));
+        }
+        let mut order = request.order.clone();
+        order.client_order_id = None;
+        let algo = AlgoOrder {
+            id,
+            symbol: symbol.to_string(),
+            algo: request.algo,
+            status: AlgoStatus::Running,
+            order,
+            duration: request.duration.unwrap_or(0),
+            slices: request.slices.unwrap_or(1),
+            visible_quantity: request.visible_quantity,
+            lot_size: request.lot_size,
+            sent: 0.0,
+            traded: 0.0,
+            avg_price: 0.0,
+            fee: 0.0,
+            next_slice: 0,
+            start_time: None,
+            weights: vec![],
+            children: vec![],
+            message: "".to_string(),
+            timestamp: 0,
+            history,
+            filled: 0.0,
+            filled_notional: 0.0,
+        };
+        self.orders.push(algo.clone());
+        Ok(algo)
+    }
+
+    pub fn cancel(&mut self, id: &str) -> Result<(AlgoOrder, Vec<String>), AppError> {
+        let algo = self.orders.iter_mut().find(|o| o.id == id).ok_or_else(|| AppError::new(-200, &format!("The algo order {} is not found", id)))?;
+        if algo.status != AlgoStatus::Running {
+            return Err(AppError::new(-200, &format!("The algo order {} is already {:?}", id, algo.status)));
+        }
+        algo.status = AlgoStatus::Canceled;
+        let working = algo.children.iter().filter(|c| self.children.get(*c).is_some_and(|c| !c.closed)).cloned().collect();
+        Ok((algo.clone(), working))
+    }
+
+    pub fn get_orders(&self, symbol: &str) -> Vec<AlgoOrder> {
+        self.orders.iter().filter(|o| o.symbol == symbol).cloned().collect()
+    }
+
+    /// Child orders which are due at `timestamp`, the quantity is counted as sent until `on_placed` reports otherwise.
+    pub fn on_time(&mut self, symbol: &str, timestamp: u64) -> Vec<(String, OrderRequest)> {
+        let mut ret = vec![];
+        for algo in self.orders.iter_mut() {
+            if algo.symbol != symbol {
+                continue;
+            }
+            let working = algo.children.iter().any(|c| self.children.get(c).is_some_and(|c| !c.closed));
+            if let Some(quantity) = algo.slice_quantity(timestamp, working) {
+                algo.sent += quantity;
+                algo.timestamp = timestamp;
+                let mut order = algo.order.clone();
+                order.quantity = quantity;
+                ret.push((algo.id.clone(), order));
+            }
+        }
+        ret
+    }
+
+    pub fn on_placed(&mut self, algo_id: &str, quantity: f64, result: Result<String, AppError>) -> Option<Order> {
+        let algo = self.orders.iter_mut().find(|o| o.id == algo_id)?;
+        match result {
+            Ok(client_order_id) => {
+                algo.children.push(client_order_id.clone());
+                self.children.insert(client_order_id.clone(), ChildOrder { algo_id: algo_id.to_string(), quantity, traded: 0.0, closed: false });
+                let mut ret = None;
+                for fill in self.unmatched_fills.remove(&client_order_id).unwrap_or_default() {
+                    ret = self.on_execution(&fill);
+                }
+                if let Some(update) = self.unmatched.remove(&client_order_id) {
+                    ret = self.on_order_update(&update);
+                }
+                ret
+            },
+            Err(e) => {
+                algo.sent -= quantity;
+                algo.message = e.message;
+                Some(algo.to_order())
+            },
+        }
+    }
+
+    pub fn on_order_update(&mut self, update: &Order) -> Option<Order> {
+        let child = match self.children.get_mut(&update.client_order_id) {
+            Some(c) => c,
+            None => {
+                if self.unmatched.len() >= MAX_UNMATCHED {
+                    self.unmatched.clear();
+                }
+                self.unmatched.insert(update.client_order_id.clone(), update.clone());
+                return None;
+            },
+        };
+        let algo = self.orders.iter_mut().find(|o| o.id == child.algo_id)?;
+        let traded = update.traded.max(child.traded);
+        let delta = traded - child.traded;
+        if delta > 0.0 {
+            algo.traded += delta;
+        }
+        child.traded = traded;
+        if !child.closed && (traded >= child.quantity - EPSILON || is_order_closed(&update.status)) {
+            child.closed = true;
+            algo.sent -= child.quantity - traded;
+        }
+        algo.timestamp = algo.timestamp.max(update.timestamp);
+        if algo.status == AlgoStatus::Running && algo.traded >= algo.order.quantity - EPSILON {
+            algo.status = AlgoStatus::Completed;
+        }
+        Some(algo.to_order())
+    }
+
+    /// The average price and fee of the parent are built from the fills of its children, order updates of live
+    /// venues do not carry them.
+    pub fn on_execution(&mut self, fill: &Execution) -> Option<Order> {
+        let child = match self.children.get(&fill.client_order_id) {
+            Some(c) => c,
+            None => {
+                if self.unmatched_fills.len() >= MAX_UNMATCHED {
+                    self.unmatched_fills.clear();
+                }
+                self.unmatched_fills.entry(fill.client_order_id.clone()).or_default().push(fill.clone());
+                return None;
+            },
+        };
+        let algo = self.orders.iter_mut().find(|o| o.id == child.algo_id)?;
+        algo.filled += fill.quantity;
+        algo.filled_notional += fill.price * fill.quantity;
+        if algo.filled > 0.0 {
+            algo.avg_price = algo.filled_notional / algo.filled;
+        }
+        algo.fee += fill.fee;
+        algo.timestamp = algo.timestamp.max(fill.timestamp);
+        Some(algo.to_order())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fill(client_order_id: &str, traded: f64, status: &str) -> Order {
+        Order { client_order_id: client_order_id.to_string(), traded, status: status.to_string(), total: traded, ..Default::default() }
+    }
+
+    fn execution(client_order_id: &str, quantity: f64, price: f64) -> Execution {
+        Execution { client_order_id: client_order_id.to_string(), quantity, price, fee: quantity * 0.1, ..Default::default() }
+    }
+
+    #[test]
+    fn test_twap_vwap_and_iceberg() {
+        let mut engine = AlgoOrderEngine::new();
+        let twap = AlgoOrderRequest::from_json(r#"{"id":"t","algo":"TWAP","duration":60,"slices":3,"lotSize":1,"order":{"side":"BUY","orderType":"MARKET","quantity":10}}"#).unwrap();
+        engine.add("BTCUSDT", twap, vec![]).unwrap();
+        let children = engine.on_time("BTCUSDT", 1_000);
+        assert_eq!(children[0].1.quantity, 3.0);
+        engine.on_placed("t", 3.0, Ok("c1".to_string()));
+        assert!(engine.on_time("BTCUSDT", 10_000).is_empty());
+        let children = engine.on_time("BTCUSDT", 21_000);
+        assert_eq!(children[0].1.quantity, 3.0);
+        engine.on_placed("t", 3.0, Err(AppError::new(-200, "rejected")));
+        let children = engine.on_time("BTCUSDT", 41_000);
+        assert_eq!(children[0].1.quantity, 7.0);
+        engine.on_placed("t", 7.0, Ok("c3".to_string()));
+        engine.on_order_update(&fill("c1", 3.0, "FILLED"));
+        let parent = engine.on_order_update(&fill("c3", 7.0, "FILLED")).unwrap();
+        assert_eq!((parent.status.as_str(), parent.traded, parent.price), ("FILLED", 10.0, 0.0));
+        engine.on_execution(&execution("c1", 3.0, 100.0));
+        let parent = engine.on_execution(&execution("c3", 7.0, 110.0)).unwrap();
+        assert!((parent.price - 107.0).abs() < 1e-9 && (parent.fee - 1.0).abs() < 1e-9);
+
+        let history = vec![
+            KLine { timestamp: 0, volume: 30.0, ..Default::default() },
+            KLine { timestamp: 30_000, volume: 10.0, ..Default::default() },
+        ];
+        assert_eq!(volume_profile(&history, DAY_MILLIS, 60, 2), vec![0.75, 0.25]);
+
+        let iceberg = AlgoOrderRequest::from_json(r#"{"id":"i","algo":"ICEBERG","visibleQuantity":2,"order":{"side":"SELL","orderType":"LIMIT","quantity":5,"price":100}}"#).unwrap();
+        engine.add("ETHUSDT", iceberg, vec![]).unwrap();
+        assert_eq!(engine.on_time("ETHUSDT", 1)[0].1.quantity, 2.0);
+        engine.on_execution(&execution("i1", 2.0, 100.0));
+        engine.on_order_update(&fill("i1", 2.0, "FILLED"));
+        assert_eq!(engine.on_placed("i", 2.0, Ok("i1".to_string())).unwrap().price, 100.0);
+        assert_eq!(engine.on_time("ETHUSDT", 2)[0].1.quantity, 2.0);
+        engine.on_placed("i", 2.0, Ok("i2".to_string()));
+        assert!(engine.on_time("ETHUSDT", 3).is_empty());
+        engine.on_order_update(&fill("i2", 1.0, "CANCELED"));
+        assert_eq!(engine.on_time("ETHUSDT", 4)[0].1.quantity, 2.0);
+        let (parent, working) = engine.cancel("i").unwrap();
+        assert_eq!((parent.traded, working.len()), (3.0, 0));
+    }
+}
@@ -0,0 +1,223 @@
+use std::fs;
+use std::path::Path;
+use common::error::AppError;
+use serde::{Deserialize, Serialize};
+use crate::matching_engine::Fill;
+
+const MILLIS_PER_YEAR: f64 = 365.0 * 24.0 * 60.0 * 60.0 * 1000.0;
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EquityPoint {
+    pub timestamp: u64,
+    pub balance: f64,
+    pub equity: f64,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TradeRecord {
+    pub timestamp: u64,
+    pub symbol: String,
+    pub order_id: String,
+    pub client_order_id: String,
+    pub side: String,
+    pub price: f64,
+    pub quantity: f64,
+    pub notional: f64,
+    pub fee: f64,
+    pub realized_pnl: f64,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BacktestReport {
+    pub finished: bool,
+    pub initial_balance: f64,
+    pub final_equity: f64,
+    pub total_return: f64,
+    pub max_drawdown: f64,
+    pub sharpe_ratio: f64,
+    pub sortino_ratio: f64,
+    pub win_rate: f64,
+    /// `None` when there is no losing trade.
+    pub profit_factor: Option<f64>,
+    pub turnover: f64,
+    pub total_fee: f64,
+    pub trade_count: usize,
+    pub equity_curve: Vec<EquityPoint>,
+    pub trades: Vec<TradeRecord>,
+}
+
+impl BacktestReport {
+    pub fn write_csv(&self, dir: &str) -> Result<(), AppError> {
+        fs::create_dir_all(dir).map_err(|e| AppError::new(-200, &e.to_string()))?;
+
+        let mut equity_csv = "timestamp,balance,equity\n".to_string();
+        for p in self.equity_curve.iter() {
+            equity_csv.push_str(&format!("{},{},{}\n", p.timestamp, p.balance, p.equity));
+        }
+        fs::write(Path::new(dir).join("equity_curve.csv"), equity_csv).map_err(|e| AppError::new(-200, &e.to_string()))?;
+
+        let mut trades_csv = "timestamp,symbol,order_id,client_order_id,side,price,quantity,notional,fee,realized_pnl\n".to_string();
+        for t in self.trades.iter() {
+            trades_csv.push_str(&format!("{},{},{},{},{},{},{},{},{},{}\n", t.timestamp, t.symbol, t.order_id, t.client_order_id, t.side, t.price, t.quantity, t.notional, t.fee, t.realized_pnl));
+        }
+        fs::write(Path::new(dir).join("trades.csv"), trades_csv).map_err(|e| AppError::new(-200, &e.to_string()))?;
+        Ok(())
+    }
+}
+
+pub struct BacktestRecorder {
+    pub initial_balance: f64,
+    pub finished: bool,
+    pub equity_curve: Vec<EquityPoint>,
+    pub trades: Vec<TradeRecord>,
+}
+
+impl BacktestRecorder {
+    pub fn new(initial_balance: f64) -> Self {
+        BacktestRecorder {
+            initial_balance,
+            finished: false,
+            equity_curve: Vec::new(),
+            trades: Vec::new(),
+        }
+    }
+
+    pub fn record_equity(&mut self, timestamp: u64, balance: f64, equity: f64) {
+        let point = EquityPoint { timestamp, balance, equity };
+        match self.equity_curve.last_mut() {
+            Some(last) if last.timestamp == timestamp => *last = point,
+            _ => self.equity_curve.push(point),
+        }
+    }
+
+    pub fn record_trade(&mut self, fill: &Fill, realized_pnl: f64, volume_multiple: f64) {
+        self.trades.push(TradeRecord {
+            timestamp: fill.timestamp,
+            symbol: fill.order.symbol.clone(),
+            order_id: fill.order.order_id.clone(),
+            client_order_id: fill.order.client_order_id.clone(),
+            side: fill.order.side.clone(),
+            price: fill.price,
+            quantity: fill.quantity,
+            notional: fill.price * fill.quantity * volume_multiple,
+            fee: fill.fee,
+            realized_pnl,
+        });
+    }
+
+    pub fn finish(&mut self) {
+        self.finished = true;
+    }
+
+    pub fn report(&self) -> BacktestReport {
+        let final_equity = self.equity_curve.last().map(|p| p.equity).unwrap_or(self.initial_balance);
+
+        let mut peak = self.initial_balance;
+        let mut max_drawdown: f64 = 0.0;
+        for p in self.equity_curve.iter() {
+            peak = peak.max(p.equity);
+            if peak > 0.0 {
+                max_drawdown = max_drawdown.max((peak - p.equity) / peak);
+            }
+        }
+
+        let returns: Vec<f64> = self.equity_curve.windows(2)
+            .filter(|w| w[0].equity > 0.0)
+            .map(|w| w[1].equity / w[0].equity - 1.0)
+            .collect();
+        let periods_per_year = match (self.equity_curve.first(), self.equity_curve.last()) {
+            (Some(first), Some(last)) if last.timestamp > first.timestamp => {
+                let mean_interval = (last.timestamp - first.timestamp) as f64 / (self.equity_curve.len() - 1) as f64;
+                MILLIS_PER_YEAR / mean_interval
+            },
+            _ => 0.0,
+        };
+        let mean = if returns.is_empty() { 0.0 } else { returns.iter().sum::<f64>() / returns.len() as f64 };
+        let std = deviation(&returns, mean, |_| true);
+        let downside_std = deviation(&returns, 0.0, |r| r < 0.0);
+
+        let net_pnls: Vec<f64> = self.trades.iter().map(|t| t.realized_pnl - t.fee).collect();
+        let closing: Vec<f64> = self.trades.iter().filter(|t| t.realized_pnl != 0.0).map(|t| t.realized_pnl - t.fee).collect();
+        let wins = closing.iter().filter(|v| **v > 0.0).count();
+        let gross_profit: f64 = net_pnls.iter().filter(|v| **v > 0.0).sum();
+        let gross_loss: f64 = net_pnls.iter().filter(|v| **v < 0.0).map(|v| -v).sum();
+
+        BacktestReport {
+            finished: self.finished,
+            initial_balance: self.initial_balance,
+            final_equity,
+            total_return: if self.initial_balance > 0.0 { final_equity / self.initial_balance - 1.0 } else { 0.0 },
+            max_drawdown,
+            sharpe_ratio: if std > 0.0 { mean / std * periods_per_year.sqrt() } else { 0.0 },
+            sortino_ratio: if downside_std > 0.0 { mean / downside_std * periods_per_year.sqrt() } else { 0.0 },
+            win_rate: if closing.is_empty() { 0.0 } else { wins as f64 / closing.len() as f64 },
+            profit_factor: if gross_loss > 0.0 { Some(gross_profit / gross_loss) } else { None },
+            turnover: self.trades.iter().map(|t| t.notional).sum(),
+            total_fee: self.trades.iter().map(|t| t.fee).sum(),
+            trade_count: self.trades.len(),
+            equity_curve: self.equity_curve.clone(),
+            trades: self.trades.clone(),
+        }
+    }
+}
+
+fn deviation(values: &Vec<f64>, mean: f64, filter: impl Fn(f64) -> bool) -> f64 {
+    if values.is_empty() {
+        return 0.0;
+    }
+    let sum: f64 = values.iter().filter(|v| filter(**v)).map(|v| (v - mean).powi(2)).sum();
+    (sum / values.len() as f64).sqrt()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_drawdown_and_return() {
+        let mut recorder = BacktestRecorder::new(100.0);
+        recorder.record_equity(0, 100.0, 100.0);
+        recorder.record_equity(60000, 100.0, 120.0);
+        recorder.record_equity(60000, 100.0, 125.0);
+        recorder.record_equity(120000, 100.0, 100.0);
+        recorder.record_equity(180000, 110.0, 110.0);
+        let report = recorder.report();
+        assert_eq!(report.equity_curve.len(), 4);
+        assert_eq!(report.max_drawdown, 0.2);
+        assert!((report.total_return - 0.1).abs() < 1e-9);
+        assert!(!report.finished);
+    }
+
+    fn trade(timestamp: u64, realized_pnl: f64, fee: f64) -> TradeRecord {
+        TradeRecord { timestamp, realized_pnl, fee, ..Default::default() }
+    }
+
+    #[test]
+    fn test_net_trade_stats() {
+        let mut recorder = BacktestRecorder::new(100.0);
+        recorder.trades = vec![trade(0, 0.0, 1.0), trade(1, 10.0, 1.0), trade(2, 0.5, 1.0)];
+        let report = recorder.report();
+        // The second closing trade wins gross but loses after its fee.
+        assert_eq!(report.win_rate, 0.5);
+        assert_eq!(report.profit_factor, Some(9.0 / 1.5));
+
+        recorder.trades = vec![trade(1, 10.0, 1.0)];
+        assert_eq!(recorder.report().profit_factor, None);
+    }
+
+    #[test]
+    fn test_annualize_by_mean_interval() {
+        let mut recorder = BacktestRecorder::new(100.0);
+        for (timestamp, equity) in [(0, 100.0), (1000, 101.0), (60000, 100.0), (120000, 102.0)] {
+            recorder.record_equity(timestamp, 100.0, equity);
+        }
+        let returns = [0.01, 100.0 / 101.0 - 1.0, 0.02];
+        let mean = returns.iter().sum::<f64>() / 3.0;
+        let std = (returns.iter().map(|r| (r - mean).powi(2)).sum::<f64>() / 3.0).sqrt();
+        let expected = mean / std * (MILLIS_PER_YEAR / 40000.0).sqrt();
+        assert!((recorder.report().sharpe_ratio - expected).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,363 @@
+use common::error::AppError;
+use serde::{Deserialize, Serialize};
+use crate::order::{field, invalid_field, parse_object, required, OrderKind, OrderRequest, OrderSide};
+use crate::trade_server::Order;
+use crate::order_manager::OrderStatus;
+
+const CONDITIONAL_FIELDS: [&str; 6] = ["id", "trigger", "triggerPrice", "trailingAmount", "trailingPercent", "order"];
+const BRACKET_FIELDS: [&str; 3] = ["entry", "takeProfit", "stopLoss"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum TriggerKind {
+    Stop,
+    TakeProfit,
+    TrailingStop,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ConditionalStatus {
+    Waiting,
+    Active,
+    Triggered,
+    Canceled,
+    Rejected,
+}
+
+/// A client-side trigger which fires `order` (LIMIT or MARKET) when the market price crosses it.
+/// STOP with a LIMIT order is a stop-limit, TRAILING_STOP follows the best price by an amount or percent.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConditionalOrderRequest {
+    pub id: Option<String>,
+    pub trigger: TriggerKind,
+    pub trigger_price: Option<f64>,
+    pub trailing_amount: Option<f64>,
+    pub trailing_percent: Option<f64>,
+    pub order: OrderRequest,
+}
+
+/// An entry order whose take-profit and stop-loss exits are armed as an OCO group once the entry fills.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BracketOrderRequest {
+    pub entry: OrderRequest,
+    pub take_profit: Option<ConditionalOrderRequest>,
+    pub stop_loss: Option<ConditionalOrderRequest>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConditionalOrder {
+    pub id: String,
+    pub symbol: String,
+    pub trigger: TriggerKind,
+    pub trigger_price: Option<f64>,
+    pub trailing_amount: Option<f64>,
+    pub trailing_percent: Option<f64>,
+    pub extreme_price: Option<f64>,
+    pub group_id: Option<String>,
+    pub parent_id: Option<String>,
+    pub status: ConditionalStatus,
+    pub order: OrderRequest,
+    pub client_order_id: Option<String>,
+    pub message: String,
+}
+
+impl ConditionalOrderRequest {
+    pub fn from_json(json: &str) -> Result<Self, AppError> {
+        let map = &parse_object(json, &CONDITIONAL_FIELDS)?;
+        let order = map.get("order").ok_or_else(|| invalid_field("order", "is required"))?;
+        let request = ConditionalOrderRequest {
+            id: field(map, "id")?,
+            trigger: required(map, "trigger")?,
+            trigger_price: field(map, "triggerPrice")?,
+            trailing_amount: field(map, "trailingAmount")?,
+            trailing_percent: field(map, "trailingPercent")?,
+            order: OrderRequest::from_json(&order.to_string())?,
+        };
+        request.validate()?;
+        Ok(request)
+    }
+
+    pub fn validate(&self) -> Result<(), AppError> {
+        self.order.validate()?;
+        if !matches!(self.order.order_type, OrderKind::Limit | OrderKind::Market) {
+            return Err(invalid_field("orderType", "must be LIMIT or MARKET for a conditional order"));
+        }
+        let positive = |name: &str, value: Option<f64>| match value {
+            Some(v) if !v.is_finite() || v <= 0.0 => Err(invalid_field(name, "must be positive")),
+            _ => Ok(()),
+        };
+        positive("triggerPrice", self.trigger_price)?;
+        positive("trailingAmount", self.trailing_amount)?;
+        positive("trailingPercent", self.trailing_percent)?;
+        if self.trigger == TriggerKind::TrailingStop {
+            if self.trigger_price.is_some() {
+                return Err(invalid_field("triggerPrice", "is not supported for TRAILING_STOP"));
+            }
+            if self.trailing_amount.is_some() == self.trailing_percent.is_some() {
+                return Err(invalid_field("trailingAmount", "exactly one of trailingAmount and trailingPercent is required"));
+            }
+        } else if self.trigger_price.is_none() {
+            return Err(invalid_field("triggerPrice", "is required"));
+        }
+        Ok(())
+    }
+}
+
+impl BracketOrderRequest {
+    pub fn from_json(json: &str) -> Result<Self, AppError> {
+        let map = &parse_object(json, &BRACKET_FIELDS)?;
+        let exit = |name: &str| match map.get(name) {
+            None | Some(serde_json::Value::Null) => Ok(None),
+            Some(v) => ConditionalOrderRequest::from_json(&v.to_string()).map(Some),
+        };
+        let entry = map.get("entry").ok_or_else(|| invalid_field("entry", "is required"))?;
+        let request = BracketOrderRequest {
+            entry: OrderRequest::from_json(&entry.to_string())?,
+            take_profit: exit("takeProfit")?,
+            stop_loss: exit("stopLoss")?,
+        };
+        request.validate()?;
+        Ok(request)
+    }
+
+    pub fn validate(&self) -> Result<(), AppError> {
+        self.entry.validate()?;
+        if self.take_profit.is_none() && self.stop_loss.is_none() {
+            return Err(invalid_field("takeProfit", "at least one of takeProfit and stopLoss is required"));
+        }
+        for exit in self.exits() {
+            exit.validate()?;
+            if exit.order.side == self.entry.side {
+                return Err(invalid_field("side", "the exit orders must be on the opposite side of the entry"));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn exits(&self) -> Vec<ConditionalOrderRequest> {
+        self.take_profit.iter().chain(self.stop_loss.iter()).cloned().collect()
+    }
+}
+
+impl ConditionalOrder {
+    pub fn is_live(&self) -> bool {
+        matches!(self.status, ConditionalStatus::Waiting | ConditionalStatus::Active)
+    }
+
+    fn on_price(&mut self, price: f64) -> bool {
+        let side = self.order.side;
+        if self.trigger == TriggerKind::TrailingStop {
+            let extreme = match (self.extreme_price, side) {
+                (Some(v), OrderSide::Sell) => v.max(price),
+                (Some(v), OrderSide::Buy) => v.min(price),
+                (None, _) => price,
+            };
+            let offset = self.trailing_amount.unwrap_or_else(|| extreme * self.trailing_percent.unwrap_or(0.0) / 100.0);
+            self.extreme_price = Some(extreme);
+            self.trigger_price = Some(if side == OrderSide::Sell { extreme - offset } else { extreme + offset });
+        }
+        let trigger_price = match self.trigger_price {
+            Some(v) => v,
+            None => return false,
+        };
+        match (self.trigger, side) {
+            (TriggerKind::TakeProfit, OrderSide::Buy) => price <= trigger_price,
+            (TriggerKind::TakeProfit, OrderSide::Sell) => price >= trigger_price,
+            (_, OrderSide::Buy) => price >= trigger_price,
+            (_, OrderSide::Sell) => price <= trigger_price,
+        }
+    }
+}
+
+pub(crate) fn is_order_closed(status: &str) -> bool {
+    matches!(OrderStatus::parse(status), Some(OrderStatus::Canceled | OrderStatus::Rejected | OrderStatus::Expired))
+}
+
+#[derive(Default)]
+pub struct ConditionalOrderEngine {
+    orders: Vec<ConditionalOrder>,
+    next_id: u64,
+}
+
+impl ConditionalOrderEngine {
+    pub fn new() -> Self {
+        ConditionalOrderEngine::default()
+    }
+
+    fn next_id(&mut self, prefix: &str) -> String {
+        self.next_id += 1;
+        format!("{}{}", prefix, self.next_id)
+    }
+
+    fn insert(&mut self, symbol: &str, request: ConditionalOrderRequest, group_id: Option<String>, parent_id: Option<String>) -> ConditionalOrder {
+        let id = request.id.clone().unwrap_or_else(|| self.next_id("C"));
+        let order = ConditionalOrder {
+            id,
+            symbol: symbol.to_string(),
+            trigger: request.trigger,
+            trigger_price: request.trigger_price,
+            trailing_amount: request.trailing_amount,
+            trailing_percent: request.trailing_percent,
+            extreme_price: None,
+            status: if parent_id.is_some() { ConditionalStatus::Waiting } else { ConditionalStatus::Active },
+            group_id,
+            parent_id,
+            order: request.order,
+            client_order_id: None,
+            message: "".to_string(),
+        };
+        self.orders.push(order.clone());
+        order
+    }
+
+    fn check(&self, requests: &[ConditionalOrderRequest]) -> Result<(), AppError> {
+        for (i, request) in requests.iter().enumerate() {
+            request.validate()?;
+            if let Some(id) = &request.id {
+                if self.orders.iter().any(|o| &o.id == id) || requests[..i].iter().any(|r| r.id.as_ref() == Some(id)) {
+                    return Err(invalid_field("id", &format!("the conditional order {} already exists", id)));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn add(&mut self, symbol: &str, request: ConditionalOrderRequest) -> Result<ConditionalOrder, AppError> {
+        self.check(std::slice::from_ref(&request))?;
+        Ok(self.insert(symbol, request, None, None))
+    }
+
+    pub fn add_oco(&mut self, symbol: &str, requests: Vec<ConditionalOrderRequest>) -> Result<Vec<ConditionalOrder>, AppError> {
+        if requests.len() < 2 {
+            return Err(AppError::new(-200, "An OCO group requires at least two conditional orders"));
+        }
+        self.check(&requests)?;
+        let group_id = self.next_id("G");
+        Ok(requests.into_iter().map(|r| self.insert(symbol, r, Some(group_id.clone()), None)).collect())
+    }
+
+    pub fn add_bracket_exits(&mut self, symbol: &str, parent_id: &str, requests: Vec<ConditionalOrderRequest>) -> Result<Vec<ConditionalOrder>, AppError> {
+        self.check(&requests)?;
+        let group_id = self.next_id("G");
+        Ok(requests.into_iter().map(|r| self.insert(symbol, r, Some(group_id.clone()), Some(parent_id.to_string()))).collect())
+    }
+
+    pub fn cancel(&mut self, id: &str) -> Result<ConditionalOrder, AppError> {
+        let order = self.orders.iter_mut().find(|o| o.id == id).ok_or_else(|| AppError::new(-200, &format!("The conditional order {} is not found", id)))?;
+        if !order.is_live() {
+            return Err(AppError::new(-200, &format!("The conditional order {} is already {:?}", id, order.status)));
+        }
+        order.status = ConditionalStatus::Canceled;
+        Ok(order.clone())
+    }
+
+    pub fn get_orders(&self, symbol: &str) -> Vec<ConditionalOrder> {
+        self.orders.iter().filter(|o| o.symbol == symbol).cloned().collect()
+    }
+
+    pub fn on_price(&mut self, symbol: &str, price: f64) -> Vec<ConditionalOrder> {
+        let mut fired: Vec<ConditionalOrder> = vec![];
+        for order in self.orders.iter_mut() {
+            // Only the first member of a group fires, the others are canceled below.
+            let group_fired = order.group_id.is_some() && fired.iter().any(|f| f.group_id == order.group_id);
+            if order.symbol == symbol && order.status == ConditionalStatus::Active && !group_fired && order.on_price(price) {
+                order.status = ConditionalStatus::Triggered;
+                fired.push(order.clone());
+            }
+        }
+        for f in fired.iter() {
+            for order in self.orders.iter_mut() {
+                if order.is_live() && order.group_id.is_some() && order.group_id == f.group_id {
+                    order.status = ConditionalStatus::Canceled;
+                    order.message = format!("Canceled by {}", f.id);
+                }
+            }
+        }
+        fired
+    }
+
+    pub fn on_fired(&mut self, id: &str, result: Result<String, AppError>) {
+        if let Some(order) = self.orders.iter_mut().find(|o| o.id == id) {
+            match result {
+                Ok(client_order_id) => order.client_order_id = Some(client_order_id),
+                Err(e) => {
+                    order.status = ConditionalStatus::Rejected;
+                    order.message = e.message;
+                },
+            }
+        }
+    }
+
+    pub fn on_order_update(&mut self, update: &Order) {
+        let filled = update.total > 0.0 && update.traded >= update.total;
+        let closed = is_order_closed(&update.status);
+        if !filled && !closed {
+            return;
+        }
+        for order in self.orders.iter_mut() {
+            if order.status != ConditionalStatus::Waiting || order.symbol != update.symbol || order.parent_id.as_deref() != Some(update.client_order_id.as_str()) {
+                continue;
+            }
+            if filled || update.traded > 0.0 {
+                order.status = ConditionalStatus::Active;
+                order.order.quantity = order.order.quantity.min(update.traded);
+            } else {
+                order.status = ConditionalStatus::Canceled;
+                order.message = format!("The entry order {} is {}", update.client_order_id, update.status);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(json: &str) -> ConditionalOrderRequest {
+        ConditionalOrderRequest::from_json(json).unwrap()
+    }
+
+    #[test]
+    fn test_triggers_and_groups() {
+        let mut engine = ConditionalOrderEngine::new();
+        assert!(ConditionalOrderRequest::from_json(r#"{"trigger":"STOP","order":{"side":"SELL","orderType":"MARKET","quantity":1}}"#).is_err());
+        assert!(ConditionalOrderRequest::from_json(r#"{"trigger":"TRAILING_STOP","order":{"side":"SELL","orderType":"MARKET","quantity":1}}"#).is_err());
+
+        engine.add("BTCUSDT", request(r#"{"id":"s1","trigger":"STOP","triggerPrice":95,"order":{"side":"SELL","orderType":"LIMIT","quantity":1,"price":94}}"#)).unwrap();
+        engine.add("BTCUSDT", request(r#"{"id":"t1","trigger":"TRAILING_STOP","trailingPercent":10,"order":{"side":"SELL","orderType":"MARKET","quantity":1}}"#)).unwrap();
+        assert!(engine.add("BTCUSDT", request(r#"{"id":"s1","trigger":"STOP","triggerPrice":95,"order":{"side":"SELL","orderType":"MARKET","quantity":1}}"#)).is_err());
+        assert!(engine.on_price("BTCUSDT", 100.0).is_empty());
+        assert!(engine.on_price("BTCUSDT", 120.0).is_empty());
+        let fired = engine.on_price("BTCUSDT", 107.0);
+        assert_eq!(fired.iter().map(|o| o.id.as_str()).collect::<Vec<&str>>(), vec!["t1"]);
+        assert_eq!(fired[0].trigger_price, Some(108.0));
+        assert_eq!(engine.on_price("BTCUSDT", 95.0)[0].id, "s1");
+
+        let oco = engine.add_oco("ETHUSDT", vec![
+            request(r#"{"trigger":"TAKE_PROFIT","triggerPrice":110,"order":{"side":"SELL","orderType":"MARKET","quantity":1}}"#),
+            request(r#"{"trigger":"STOP","triggerPrice":90,"order":{"side":"SELL","orderType":"MARKET","quantity":1}}"#),
+        ]).unwrap();
+        assert_eq!(engine.on_price("ETHUSDT", 111.0)[0].id, oco[0].id);
+        assert_eq!(engine.get_orders("ETHUSDT")[1].status, ConditionalStatus::Canceled);
+
+        let both = engine.add_oco("SOLUSDT", vec![
+            request(r#"{"trigger":"STOP","triggerPrice":90,"order":{"side":"SELL","orderType":"MARKET","quantity":1}}"#),
+            request(r#"{"trigger":"STOP","triggerPrice":95,"order":{"side":"SELL","orderType":"MARKET","quantity":1}}"#),
+        ]).unwrap();
+        let fired = engine.on_price("SOLUSDT", 85.0);
+        assert_eq!(fired.iter().map(|o| o.id.clone()).collect::<Vec<String>>(), vec![both[0].id.clone()]);
+        assert_eq!(engine.get_orders("SOLUSDT")[1].status, ConditionalStatus::Canceled);
+
+        let bracket = BracketOrderRequest::from_json(r#"{"entry":{"side":"BUY","orderType":"LIMIT","quantity":2,"price":100},"stopLoss":{"trigger":"STOP","triggerPrice":90,"order":{"side":"SELL","orderType":"MARKET","quantity":2}}}"#).unwrap();
+        let exits = engine.add_bracket_exits("XRPUSDT", "e1", bracket.exits()).unwrap();
+        assert!(engine.on_price("XRPUSDT", 80.0).is_empty());
+        engine.on_order_update(&Order { symbol: "XRPUSDT".to_string(), client_order_id: "e1".to_string(), status: "CANCELED".to_string(), traded: 1.0, total: 2.0, ..Default::default() });
+        let fired = engine.on_price("XRPUSDT", 80.0);
+        assert_eq!(fired[0].id, exits[0].id);
+        assert_eq!(fired[0].order.quantity, 1.0);
+    }
+}
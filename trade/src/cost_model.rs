@@ -0,0 +1,47 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum FeeModel {
+    Percentage { maker: f64, taker: f64 },
+    PerLot { maker: f64, taker: f64 },
+    PerTurnover { maker: f64, taker: f64 },
+}
+
+impl FeeModel {
+    pub fn fee(&self, is_maker: bool, price: f64, quantity: f64, volume_multiple: f64) -> f64 {
+        match self {
+            FeeModel::Percentage { maker, taker } | FeeModel::PerTurnover { maker, taker } => {
+                let rate = if is_maker { maker } else { taker };
+                price * quantity * volume_multiple * rate
+            },
+            FeeModel::PerLot { maker, taker } => {
+                let rate = if is_maker { maker } else { taker };
+                quantity * rate
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum SlippageModel {
+    FixedTicks { ticks: f64, tick_size: f64 },
+    Percentage { rate: f64 },
+    Depth,
+}
+
+impl SlippageModel {
+    pub fn is_depth(&self) -> bool {
+        matches!(self, SlippageModel::Depth)
+    }
+
+    pub fn apply(&self, is_buy: bool, price: f64) -> f64 {
+        let offset = match self {
+            SlippageModel::FixedTicks { ticks, tick_size } => ticks * tick_size,
+            SlippageModel::Percentage { rate } => price * rate,
+            SlippageModel::Depth => 0.0,
+        };
+        if is_buy { price + offset } else { price - offset }
+    }
+}
@@ -0,0 +1,105 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+use serde::{Deserialize, Serialize};
+use crate::order::{OrderKind, OrderOffset, OrderPositionSide, OrderRequest, OrderSide};
+use crate::trade_server::{OrderAck, Position};
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct KillSwitchReport {
+    pub symbols: Vec<String>,
+    pub conditional_orders: usize,
+    pub algo_orders: usize,
+    pub flatten_orders: Vec<OrderAck>,
+    pub errors: Vec<String>,
+}
+
+pub fn flatten_request(position: &Position) -> Option<OrderRequest> {
+    if position.amount <= 0.0 {
+        return None;
+    }
+    let long = matches!(position.side.to_uppercase().as_str(), "BUY" | "LONG");
+    let position_side = match position.position_side.to_uppercase().as_str() {
+        "LONG" | "1" => Some(OrderPositionSide::Long),
+        "SHORT" | "2" => Some(OrderPositionSide::Short),
+        _ => None,
+    };
+    Some(OrderRequest {
+        side: if long { OrderSide::Sell } else { OrderSide::Buy },
+        position_side: Some(position_side.unwrap_or(OrderPositionSide::Both)),
+        offset: position_side.map(|_| OrderOffset::Close),
+        order_type: OrderKind::Market,
+        time_in_force: None,
+        quantity: position.amount,
+        price: None,
+        stop_price: None,
+        reduce_only: if position_side.is_none() { Some(true) } else { None },
+        client_order_id: None,
+    })
+}
+
+pub fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis() as u64).unwrap_or(0)
+}
+
+/// Local watchdog state: fires once when no heartbeat arrives within `timeout` milliseconds.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeadMansSwitch {
+    pub armed: bool,
+    pub timeout: u64,
+    pub flatten: bool,
+    pub last_heartbeat: u64,
+    pub native: bool,
+}
+
+impl DeadMansSwitch {
+    pub fn arm(&mut self, timeout: u64, flatten: bool, now: u64) {
+        self.armed = true;
+        self.timeout = timeout;
+        self.flatten = flatten;
+        self.last_heartbeat = now;
+    }
+
+    pub fn disarm(&mut self) {
+        self.armed = false;
+        self.native = false;
+    }
+
+    pub fn heartbeat(&mut self, now: u64) {
+        self.last_heartbeat = now;
+    }
+
+    pub fn is_expired(&self, now: u64) -> bool {
+        self.armed && now >= self.last_heartbeat + self.timeout
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_flatten_and_expiry() {
+        let hedge = Position { symbol: "BTCUSDT".to_string(), position_side: "LONG".to_string(), side: "BUY".to_string(), amount: 2.0, ..Default::default() };
+        let request = flatten_request(&hedge).unwrap();
+        assert_eq!(request.side, OrderSide::Sell);
+        assert_eq!(request.offset, Some(OrderOffset::Close));
+        assert_eq!(request.resolved_position_side(), Some(OrderPositionSide::Long));
+
+        let one_way = Position { symbol: "BTCUSDT".to_string(), position_side: "0".to_string(), side: "Sell".to_string(), amount: 1.5, ..Default::default() };
+        let request = flatten_request(&one_way).unwrap();
+        assert_eq!(request.side, OrderSide::Buy);
+        assert!(request.is_reduce_only());
+        assert_eq!(request.resolved_position_side(), Some(OrderPositionSide::Both));
+        assert!(flatten_request(&Position::default()).is_none());
+
+        let mut switch = DeadMansSwitch::default();
+        assert!(!switch.is_expired(10_000));
+        switch.arm(1000, false, 0);
+        switch.heartbeat(800);
+        assert!(!switch.is_expired(1500));
+        assert!(switch.is_expired(1800));
+        switch.disarm();
+        assert!(!switch.is_expired(5000));
+    }
+}
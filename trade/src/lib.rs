@@ -1,4 +1,13 @@
 
 pub mod trade_server;
 pub mod trade_gateway;
-pub mod sim_trade_server;
\ No newline at end of file
+pub mod sim_trade_server;
+pub mod matching_engine;
+pub mod cost_model;
+pub mod backtest_report;
+pub mod order;
+pub mod conditional_order;
+pub mod algo_order;
+pub mod risk;
+pub mod kill_switch;
+pub mod order_manager;
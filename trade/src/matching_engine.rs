@@ -0,0 +1,491 @@
+use std::collections::HashMap;
+use market::market_server::{KLine, MarketData, Tick};
+use crate::cost_model::{FeeModel, SlippageModel};
+use crate::trade_server::Order;
+
+#[derive(Debug, Clone, Default)]
+pub struct MatchingConfig {
+    pub fill_ratio: Option<f64>,
+    pub fee: Option<FeeModel>,
+    pub slippage: Option<SlippageModel>,
+}
+
+#[derive(Debug, Clone)]
+pub struct RestingOrder {
+    pub order: Order,
+    pub position_side: String,
+    pub reduce_only: bool,
+    pub limit_price: Option<f64>,
+    pub volume_multiple: f64,
+    pub resting: bool,
+    /// The backtest clock sequence when the order was placed, it is only matched against later market data.
+    pub sequence: u64,
+}
+
+impl RestingOrder {
+    pub fn is_buy(&self) -> bool {
+        is_buy_side(&self.order.side)
+    }
+
+    pub fn remaining(&self) -> f64 {
+        self.order.total - self.order.traded
+    }
+
+    fn is_eligible(&self, sequence: u64) -> bool {
+        sequence == 0 || self.sequence < sequence
+    }
+
+    /// The price priority of the order, lower is matched first. Market orders come before any limit order.
+    fn priority(&self) -> f64 {
+        match self.limit_price {
+            None => f64::NEG_INFINITY,
+            Some(price) => if self.is_buy() { -price } else { price },
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Fill {
+    pub order: Order,
+    pub position_side: String,
+    pub reduce_only: bool,
+    pub price: f64,
+    pub quantity: f64,
+    pub fee: f64,
+    pub is_maker: bool,
+    pub timestamp: u64,
+}
+
+pub fn is_buy_side(side: &str) -> bool {
+    let side = side.to_uppercase();
+    side == "BUY" || side == "LONG"
+}
+
+pub struct OrderBook {
+    pub symbol: String,
+    orders: Vec<RestingOrder>,
+}
+
+impl OrderBook {
+    pub fn new(symbol: &str) -> Self {
+        OrderBook {
+            symbol: symbol.to_string(),
+            orders: Vec::new(),
+        }
+    }
+
+    /// Queues the order by price-time priority, behind the orders of the same price.
+    pub fn add(&mut self, order: RestingOrder) {
+        let priority = order.priority();
+        let idx = self.orders.partition_point(|o| o.priority() <= priority);
+        self.orders.insert(idx, order);
+    }
+
+    pub fn cancel(&mut self, id: &str) -> Option<RestingOrder> {
+        let idx = self.orders.iter().position(|o| o.order.client_order_id == id || o.order.order_id == id)?;
+        Some(self.orders.remove(idx))
+    }
+
+    pub fn cancel_all(&mut self) -> Vec<RestingOrder> {
+        self.orders.drain(..).collect()
+    }
+
+    /// Amends the order, it loses its time priority when repriced or increased.
+    pub fn modify(&mut self, id: &str, price: Option<f64>, quantity: Option<f64>, sequence: u64) -> Option<RestingOrder> {
+        let idx = self.orders.iter().position(|o| o.order.client_order_id == id || o.order.order_id == id)?;
+        let mut order = self.orders.remove(idx);
+        let requeue = price.is_some_and(|p| Some(p) != order.limit_price) || quantity.is_some_and(|q| q > order.order.total);
+        if let Some(price) = price {
+            order.limit_price = Some(price);
+            if order.order.traded == 0.0 {
+                order.order.price = price;
+            }
+            order.resting = false;
+        }
+        if let Some(quantity) = quantity {
+            order.order.total = quantity;
+        }
+        let modified = order.clone();
+        if requeue {
+            order.sequence = sequence;
+            self.add(order);
+        } else {
+            self.orders.insert(idx, order);
+        }
+        Some(modified)
+    }
+
+    pub fn get_orders(&self) -> &Vec<RestingOrder> {
+        &self.orders
+    }
+
+    pub fn match_tick(&mut self, tick: &Tick, config: &MatchingConfig, sequence: u64) -> Vec<Fill> {
+        let mut asks = book_levels(&tick.asks);
+        let mut bids = book_levels(&tick.bids);
+        let has_book = !asks.is_empty() || !bids.is_empty();
+        let walk_book = match &config.slippage {
+            Some(slippage) => slippage.is_depth(),
+            None => true,
+        };
+
+        let mut fills = vec![];
+        for order in self.orders.iter_mut().filter(|o| o.is_eligible(sequence)) {
+            let levels = if order.is_buy() { &mut asks } else { &mut bids };
+            let ret = if has_book && walk_book {
+                take_levels(levels, order.is_buy(), order.limit_price, order.remaining())
+            } else if has_book {
+                levels.first()
+                    .filter(|l| crosses(order.is_buy(), order.limit_price, l.0))
+                    .map(|l| (l.0, l.0, order.remaining()))
+            } else if crosses(order.is_buy(), order.limit_price, tick.close) {
+                Some((tick.close, tick.close, order.remaining()))
+            } else {
+                None
+            };
+            if let Some((reference, price, quantity)) = ret {
+                fills.push(fill(order, reference, price, quantity, tick.timestamp, config));
+            }
+        }
+        self.orders.retain(|o| o.remaining() > 0.0);
+        self.orders.iter_mut().filter(|o| o.is_eligible(sequence)).for_each(|o| o.resting = true);
+        fills
+    }
+
+    pub fn match_kline(&mut self, kline: &KLine, config: &MatchingConfig, sequence: u64) -> Vec<Fill> {
+        let cap = match config.fill_ratio {
+            Some(ratio) if kline.volume > 0.0 => kline.volume * ratio,
+            _ => f64::MAX,
+        };
+        let mut liquidity = [cap, cap];
+
+        let mut fills = vec![];
+        for order in self.orders.iter_mut().filter(|o| o.is_eligible(sequence)) {
+            let side = order.is_buy() as usize;
+            if liquidity[side] <= 0.0 {
+                continue;
+            }
+            let price = match order.limit_price {
+                None => Some(kline.open),
+                Some(limit) => {
+                    if order.is_buy() && kline.low <= limit {
+                        Some(limit.min(kline.open))
+                    } else if !order.is_buy() && kline.high >= limit {
+                        Some(limit.max(kline.open))
+                    } else {
+                        None
+                    }
+                },
+            };
+            if let Some(p) = price {
+                let quantity = order.remaining().min(liquidity[side]);
+                liquidity[side] -= quantity;
+                fills.push(fill(order, p, p, quantity, kline.timestamp, config));
+            }
+        }
+        self.orders.retain(|o| o.remaining() > 0.0);
+        self.orders.iter_mut().filter(|o| o.is_eligible(sequence)).for_each(|o| o.resting = true);
+        fills
+    }
+}
+
+pub struct MatchingEngine {
+    books: HashMap<String, OrderBook>,
+    config: MatchingConfig,
+}
+
+impl MatchingEngine {
+    pub fn new(config: MatchingConfig) -> Self {
+        MatchingEngine {
+            books: HashMap::new(),
+            config,
+        }
+    }
+
+    pub fn add(&mut self, order: RestingOrder) {
+        let symbol = order.order.symbol.clone();
+        self.books.entry(symbol.clone()).or_insert_with(|| OrderBook::new(&symbol)).add(order);
+    }
+
+    pub fn cancel(&mut self, symbol: &str, id: &str) -> Option<RestingOrder> {
+        self.books.get_mut(symbol)?.cancel(id)
+    }
+
+    pub fn modify(&mut self, symbol: &str, id: &str, price: Option<f64>, quantity: Option<f64>, sequence: u64) -> Option<RestingOrder> {
+        self.books.get_mut(symbol)?.modify(id, price, quantity, sequence)
+    }
+
+    pub fn cancel_all(&mut self, symbol: &str) -> Vec<RestingOrder> {
+        match self.books.get_mut(symbol) {
+            Some(book) => book.cancel_all(),
+            None => vec![],
+        }
+    }
+
+    pub fn cancel_everything(&mut self) -> Vec<RestingOrder> {
+        self.books.values_mut().flat_map(|book| book.cancel_all()).collect()
+    }
+
+    pub fn get_orders(&self, symbol: &str) -> Vec<RestingOrder> {
+        match self.books.get(symbol) {
+            Some(book) => book.get_orders().clone(),
+            None => vec![],
+        }
+    }
+
+    pub fn get_all_orders(&self) -> Vec<RestingOrder> {
+        self.books.values().flat_map(|book| book.get_orders().clone()).collect()
+    }
+
+    /// Fills the whole order at `price` as a taker, e.g. a liquidation, charging the slippage and fee of the cost model.
+    pub fn fill_at(&self, order: &mut RestingOrder, price: f64, timestamp: u64) -> Fill {
+        let quantity = order.remaining();
+        fill(order, price, price, quantity, timestamp, &self.config)
+    }
+
+    /// Matches the resting orders placed before the market data of `sequence`, every order when it is 0.
+    pub fn on_market_data(&mut self, data: &MarketData, sequence: u64) -> Vec<Fill> {
+        match data {
+            MarketData::Tick(t) => {
+                match self.books.get_mut(&t.symbol) {
+                    Some(book) => book.match_tick(t, &self.config, sequence),
+                    None => vec![],
+                }
+            },
+            MarketData::Kline(k) => {
+                match self.books.get_mut(&k.symbol) {
+                    Some(book) => book.match_kline(k, &self.config, sequence),
+                    None => vec![],
+                }
+            },
+            _ => vec![],
+        }
+    }
+}
+
+fn fill(order: &mut RestingOrder, reference: f64, price: f64, quantity: f64, timestamp: u64, config: &MatchingConfig) -> Fill {
+    let is_buy = order.is_buy();
+    let is_maker = order.resting && order.limit_price.is_some();
+    let mut price = price;
+    if !is_maker {
+        if let Some(slippage) = &config.slippage {
+            price = slippage.apply(is_buy, price);
+            if let Some(limit) = order.limit_price {
+                price = if is_buy { price.min(limit) } else { price.max(limit) };
+            }
+        }
+    }
+    let fee = match &config.fee {
+        Some(model) => model.fee(is_maker, price, quantity, order.volume_multiple),
+        None => 0.0,
+    };
+
+    let prev_amount = order.order.traded * order.order.price;
+    order.order.traded += quantity;
+    order.order.price = (prev_amount + price * quantity) / order.order.traded;
+    order.order.fee += fee;
+    order.order.slippage += (price - reference).abs() * quantity * order.volume_multiple;
+    order.order.timestamp = timestamp;
+    Fill {
+        order: order.order.clone(),
+        position_side: order.position_side.clone(),
+        reduce_only: order.reduce_only,
+        price,
+        quantity,
+        fee,
+        is_maker,
+        timestamp,
+    }
+}
+
+fn crosses(is_buy: bool, limit_price: Option<f64>, price: f64) -> bool {
+    match limit_price {
+        None => true,
+        Some(limit) => if is_buy { price <= limit } else { price >= limit },
+    }
+}
+
+fn book_levels(levels: &Vec<Vec<f64>>) -> Vec<(f64, f64)> {
+    levels.iter()
+        .filter(|l| l.len() >= 2 && l[0] > 0.0 && l[0] < f64::MAX && l[1] > 0.0)
+        .map(|l| (l[0], l[1]))
+        .collect()
+}
+
+fn take_levels(levels: &mut Vec<(f64, f64)>, is_buy: bool, limit_price: Option<f64>, quantity: f64) -> Option<(f64, f64, f64)> {
+    let best = levels.first()?.0;
+    let mut remain = quantity;
+    let mut amount = 0.0;
+    for level in levels.iter_mut() {
+        if remain <= 0.0 {
+            break;
+        }
+        if !crosses(is_buy, limit_price, level.0) {
+            break;
+        }
+        let taken = remain.min(level.1);
+        level.1 -= taken;
+        remain -= taken;
+        amount += taken * level.0;
+    }
+    levels.retain(|l| l.1 > 0.0);
+    let filled = quantity - remain;
+    if filled > 0.0 {
+        Some((best, amount / filled, filled))
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limit_order(id: &str, side: &str, price: Option<f64>, quantity: f64) -> RestingOrder {
+        RestingOrder {
+            order: Order {
+                order_id: id.to_string(),
+                client_order_id: id.to_string(),
+                symbol: "BTCUSDT".to_string(),
+                side: side.to_string(),
+                price: price.unwrap_or(0.0),
+                total: quantity,
+                ..Default::default()
+            },
+            position_side: "BOTH".to_string(),
+            reduce_only: false,
+            limit_price: price,
+            volume_multiple: 1.0,
+            resting: false,
+            sequence: 0,
+        }
+    }
+
+    #[test]
+    fn test_partial_fill_against_book() {
+        let mut engine = MatchingEngine::new(MatchingConfig::default());
+        engine.add(limit_order("1", "BUY", Some(100.0), 3.0));
+        let tick = Tick {
+            symbol: "BTCUSDT".to_string(),
+            close: 100.0,
+            asks: vec![vec![99.0, 1.0], vec![100.0, 1.0], vec![101.0, 5.0]],
+            ..Default::default()
+        };
+        let fills = engine.on_market_data(&MarketData::Tick(tick), 0);
+        assert_eq!(fills.len(), 1);
+        assert_eq!(fills[0].quantity, 2.0);
+        assert_eq!(fills[0].price, 99.5);
+        assert_eq!(engine.get_orders("BTCUSDT")[0].remaining(), 1.0);
+    }
+
+    #[test]
+    fn test_kline_cross_and_cancel() {
+        let mut engine = MatchingEngine::new(MatchingConfig::default());
+        engine.add(limit_order("1", "SELL", Some(110.0), 1.0));
+        engine.add(limit_order("2", "BUY", None, 1.0));
+        let kline = KLine {
+            symbol: "BTCUSDT".to_string(),
+            open: 100.0,
+            high: 105.0,
+            low: 95.0,
+            close: 101.0,
+            ..Default::default()
+        };
+        let fills = engine.on_market_data(&MarketData::Kline(kline), 0);
+        assert_eq!(fills.len(), 1);
+        assert_eq!(fills[0].order.client_order_id, "2");
+        assert_eq!(fills[0].price, 100.0);
+        assert!(engine.cancel("BTCUSDT", "1").is_some());
+        assert!(engine.get_orders("BTCUSDT").is_empty());
+    }
+
+    #[test]
+    fn test_price_time_priority() {
+        let mut engine = MatchingEngine::new(MatchingConfig {
+            fill_ratio: Some(0.5),
+            ..Default::default()
+        });
+        engine.add(limit_order("1", "BUY", Some(98.0), 1.0));
+        engine.add(limit_order("2", "BUY", Some(99.0), 1.0));
+        engine.add(limit_order("3", "BUY", Some(99.0), 1.0));
+        engine.add(limit_order("4", "SELL", Some(101.0), 2.0));
+        engine.modify("BTCUSDT", "2", Some(99.0), Some(2.0), 0);
+        let kline = KLine {
+            symbol: "BTCUSDT".to_string(),
+            open: 100.0,
+            high: 102.0,
+            low: 97.0,
+            close: 100.0,
+            volume: 4.0,
+            ..Default::default()
+        };
+        let fills = engine.on_market_data(&MarketData::Kline(kline), 0);
+        let filled: Vec<(&str, f64)> = fills.iter().map(|f| (f.order.client_order_id.as_str(), f.quantity)).collect();
+        assert_eq!(filled, vec![("3", 1.0), ("2", 1.0), ("4", 2.0)]);
+    }
+
+    #[test]
+    fn test_average_fill_price() {
+        let mut engine = MatchingEngine::new(MatchingConfig::default());
+        engine.add(limit_order("1", "SELL", Some(100.0), 3.0));
+        let tick = Tick {
+            symbol: "BTCUSDT".to_string(),
+            close: 100.0,
+            bids: vec![vec![102.0, 1.0], vec![100.0, 1.0]],
+            ..Default::default()
+        };
+        let fills = engine.on_market_data(&MarketData::Tick(tick.clone()), 0);
+        assert_eq!(fills[0].order.price, 101.0);
+        let fills = engine.on_market_data(&MarketData::Tick(Tick { bids: vec![vec![104.0, 1.0]], ..tick }), 0);
+        assert_eq!(fills[0].order.price, 102.0);
+        assert_eq!(fills[0].order.traded, 3.0);
+    }
+
+    #[test]
+    fn test_taker_slippage_and_fee() {
+        let mut engine = MatchingEngine::new(MatchingConfig {
+            fill_ratio: None,
+            fee: Some(FeeModel::Percentage { maker: 0.0002, taker: 0.0005 }),
+            slippage: Some(SlippageModel::FixedTicks { ticks: 2.0, tick_size: 0.5 }),
+        });
+        engine.add(limit_order("1", "BUY", None, 2.0));
+        engine.add(limit_order("2", "SELL", Some(120.0), 1.0));
+        let kline = KLine {
+            symbol: "BTCUSDT".to_string(),
+            open: 100.0,
+            high: 105.0,
+            low: 95.0,
+            close: 101.0,
+            ..Default::default()
+        };
+        let fills = engine.on_market_data(&MarketData::Kline(kline.clone()), 0);
+        assert_eq!(fills.len(), 1);
+        assert!(!fills[0].is_maker);
+        assert_eq!(fills[0].price, 101.0);
+        assert_eq!(fills[0].order.slippage, 2.0);
+        assert_eq!(fills[0].fee, 101.0 * 2.0 * 0.0005);
+
+        let fills = engine.on_market_data(&MarketData::Kline(KLine { high: 125.0, ..kline }), 0);
+        assert!(fills[0].is_maker);
+        assert_eq!(fills[0].price, 120.0);
+        assert_eq!(fills[0].fee, 120.0 * 0.0002);
+    }
+
+    #[test]
+    fn test_orders_match_from_next_sequence() {
+        let mut engine = MatchingEngine::new(MatchingConfig::default());
+        engine.add(RestingOrder { sequence: 2, ..limit_order("1", "BUY", None, 1.0) });
+        let kline = KLine {
+            symbol: "BTCUSDT".to_string(),
+            open: 100.0,
+            high: 105.0,
+            low: 95.0,
+            close: 101.0,
+            ..Default::default()
+        };
+        assert!(engine.on_market_data(&MarketData::Kline(kline.clone()), 2).is_empty());
+        assert!(!engine.get_orders("BTCUSDT")[0].resting);
+        let fills = engine.on_market_data(&MarketData::Kline(kline), 3);
+        assert_eq!(fills.len(), 1);
+        assert!(!fills[0].is_maker);
+    }
+}
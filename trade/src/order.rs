@@ -0,0 +1,248 @@
+use std::str::FromStr;
+use common::error::AppError;
+use rust_decimal::Decimal;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+const FIELDS: [&str; 10] = ["side", "positionSide", "offset", "orderType", "timeInForce", "quantity", "price", "stopPrice", "reduceOnly", "clientOrderId"];
+const MODIFY_FIELDS: [&str; 3] = ["clientOrderId", "quantity", "price"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum OrderSide {
+    Buy,
+    Sell,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum OrderPositionSide {
+    Long,
+    Short,
+    Both,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum OrderOffset {
+    Open,
+    Close,
+    CloseToday,
+    CloseYesterday,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum OrderKind {
+    Limit,
+    Market,
+    Stop,
+    StopMarket,
+    TakeProfit,
+    TakeProfitMarket,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum TimeInForce {
+    Gtc,
+    Ioc,
+    Fok,
+    Gtx,
+}
+
+impl OrderSide {
+    pub fn code(&self) -> &'static str {
+        match self {
+            OrderSide::Buy => "BUY",
+            OrderSide::Sell => "SELL",
+        }
+    }
+}
+
+impl OrderPositionSide {
+    pub fn code(&self) -> &'static str {
+        match self {
+            OrderPositionSide::Long => "LONG",
+            OrderPositionSide::Short => "SHORT",
+            OrderPositionSide::Both => "BOTH",
+        }
+    }
+}
+
+impl OrderKind {
+    pub fn code(&self) -> &'static str {
+        match self {
+            OrderKind::Limit => "LIMIT",
+            OrderKind::Market => "MARKET",
+            OrderKind::Stop => "STOP",
+            OrderKind::StopMarket => "STOP_MARKET",
+            OrderKind::TakeProfit => "TAKE_PROFIT",
+            OrderKind::TakeProfitMarket => "TAKE_PROFIT_MARKET",
+        }
+    }
+
+    pub fn requires_price(&self) -> bool {
+        matches!(self, OrderKind::Limit | OrderKind::Stop | OrderKind::TakeProfit)
+    }
+
+    pub fn requires_stop_price(&self) -> bool {
+        matches!(self, OrderKind::Stop | OrderKind::StopMarket | OrderKind::TakeProfit | OrderKind::TakeProfitMarket)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OrderRequest {
+    pub side: OrderSide,
+    pub position_side: Option<OrderPositionSide>,
+    pub offset: Option<OrderOffset>,
+    pub order_type: OrderKind,
+    pub time_in_force: Option<TimeInForce>,
+    pub quantity: f64,
+    pub price: Option<f64>,
+    pub stop_price: Option<f64>,
+    pub reduce_only: Option<bool>,
+    pub client_order_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ModifyOrderRequest {
+    pub client_order_id: String,
+    pub quantity: Option<f64>,
+    pub price: Option<f64>,
+}
+
+pub fn invalid_field(field: &str, reason: &str) -> AppError {
+    AppError::new(-200, &format!("Invalid order field `{}`: {}", field, reason))
+}
+
+pub fn to_decimal(field: &str, value: f64) -> Result<Decimal, AppError> {
+    Decimal::from_str(&value.to_string()).map_err(|e| invalid_field(field, &e.to_string()))
+}
+
+pub(crate) fn parse_object(json: &str, fields: &[&str]) -> Result<Map<String, Value>, AppError> {
+    let value: Value = serde_json::from_str(json).map_err(|e| AppError::new(-200, &e.to_string()))?;
+    let map = value.as_object().ok_or_else(|| AppError::new(-200, "The order request must be a JSON object"))?;
+    if let Some(name) = map.keys().find(|k| !fields.contains(&k.as_str())) {
+        return Err(invalid_field(name, "unknown field"));
+    }
+    Ok(map.clone())
+}
+
+pub(crate) fn field<T: DeserializeOwned>(map: &Map<String, Value>, name: &str) -> Result<Option<T>, AppError> {
+    match map.get(name) {
+        None | Some(Value::Null) => Ok(None),
+        Some(v) => serde_json::from_value(v.clone()).map(Some).map_err(|e| invalid_field(name, &e.to_string())),
+    }
+}
+
+pub(crate) fn required<T: DeserializeOwned>(map: &Map<String, Value>, name: &str) -> Result<T, AppError> {
+    field(map, name)?.ok_or_else(|| invalid_field(name, "is required"))
+}
+
+impl OrderRequest {
+    pub fn from_json(json: &str) -> Result<Self, AppError> {
+        let map = &parse_object(json, &FIELDS)?;
+        let request = OrderRequest {
+            side: required(map, "side")?,
+            position_side: field(map, "positionSide")?,
+            offset: field(map, "offset")?,
+            order_type: required(map, "orderType")?,
+            time_in_force: field(map, "timeInForce")?,
+            quantity: required(map, "quantity")?,
+            price: field(map, "price")?,
+            stop_price: field(map, "stopPrice")?,
+            reduce_only: field(map, "reduceOnly")?,
+            client_order_id: field(map, "clientOrderId")?,
+        };
+        request.validate()?;
+        Ok(request)
+    }
+
+    pub fn validate(&self) -> Result<(), AppError> {
+        if !self.quantity.is_finite() || self.quantity <= 0.0 {
+            return Err(invalid_field("quantity", "must be positive"));
+        }
+        match self.price {
+            Some(price) if !price.is_finite() || price <= 0.0 => return Err(invalid_field("price", "must be positive")),
+            None if self.order_type.requires_price() => return Err(invalid_field("price", &format!("is required for {} orders", self.order_type.code()))),
+            _ => {},
+        }
+        match self.stop_price {
+            Some(price) if !price.is_finite() || price <= 0.0 => return Err(invalid_field("stopPrice", "must be positive")),
+            None if self.order_type.requires_stop_price() => return Err(invalid_field("stopPrice", &format!("is required for {} orders", self.order_type.code()))),
+            _ => {},
+        }
+        Ok(())
+    }
+
+    pub fn is_reduce_only(&self) -> bool {
+        self.reduce_only.unwrap_or(false) || matches!(self.offset, Some(o) if o != OrderOffset::Open)
+    }
+
+    pub fn resolved_position_side(&self) -> Option<OrderPositionSide> {
+        if self.position_side.is_some() {
+            return self.position_side;
+        }
+        match (self.offset, self.side) {
+            (Some(OrderOffset::Open), OrderSide::Buy) => Some(OrderPositionSide::Long),
+            (Some(OrderOffset::Open), OrderSide::Sell) => Some(OrderPositionSide::Short),
+            (Some(_), OrderSide::Buy) => Some(OrderPositionSide::Short),
+            (Some(_), OrderSide::Sell) => Some(OrderPositionSide::Long),
+            (None, _) => None,
+        }
+    }
+}
+
+impl ModifyOrderRequest {
+    pub fn from_json(json: &str) -> Result<Self, AppError> {
+        let map = &parse_object(json, &MODIFY_FIELDS)?;
+        let request = ModifyOrderRequest {
+            client_order_id: required(map, "clientOrderId")?,
+            quantity: field(map, "quantity")?,
+            price: field(map, "price")?,
+        };
+        request.validate()?;
+        Ok(request)
+    }
+
+    pub fn validate(&self) -> Result<(), AppError> {
+        if self.client_order_id.is_empty() {
+            return Err(invalid_field("clientOrderId", "is required"));
+        }
+        if self.quantity.is_none() && self.price.is_none() {
+            return Err(AppError::new(-200, "Invalid modify request: either price or quantity must be modified"));
+        }
+        if matches!(self.quantity, Some(v) if !v.is_finite() || v <= 0.0) {
+            return Err(invalid_field("quantity", "must be positive"));
+        }
+        if matches!(self.price, Some(v) if !v.is_finite() || v <= 0.0) {
+            return Err(invalid_field("price", "must be positive"));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validation_names_field() {
+        let ret = OrderRequest::from_json(r#"{"side":"BUY","orderType":"LIMIT","quantity":1.0}"#);
+        assert!(ret.unwrap_err().message.contains("`price`"));
+        let ret = OrderRequest::from_json(r#"{"side":"HOLD","orderType":"MARKET","quantity":1.0}"#);
+        assert!(ret.unwrap_err().message.contains("`side`"));
+        let ret = OrderRequest::from_json(r#"{"side":"SELL","orderType":"MARKET","quantity":1.0,"qty":1}"#);
+        assert!(ret.unwrap_err().message.contains("`qty`"));
+
+        let request = OrderRequest::from_json(r#"{"side":"SELL","offset":"CLOSE","orderType":"MARKET","quantity":2}"#).unwrap();
+        assert!(request.is_reduce_only());
+        assert_eq!(request.resolved_position_side(), Some(OrderPositionSide::Long));
+
+        let ret = ModifyOrderRequest::from_json(r#"{"clientOrderId":"c1"}"#);
+        assert_eq!(ret.unwrap_err().message, "Invalid modify request: either price or quantity must be modified");
+    }
+}
@@ -0,0 +1,160 @@
+use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use crate::trade_server::Order;
+use log::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum OrderStatus {
+    New,
+    PartiallyFilled,
+    Filled,
+    Canceled,
+    Rejected,
+    Expired,
+}
+
+impl OrderStatus {
+    /// Normalizes the raw status of Binance, Bybit, CTP and the simulators.
+    pub fn parse(status: &str) -> Option<Self> {
+        let status = status.to_uppercase().replace(['_', ' '], "");
+        match status.as_str() {
+            "NEW" | "CREATED" | "UNTRIGGERED" | "TRIGGERED" | "NOTRADEDQUEUEING" | "NOTRADEDNOTQUEUEING" | "UNKNOWN" => Some(OrderStatus::New),
+            "PARTIALLYFILLED" | "PARTTRADEDQUEUEING" => Some(OrderStatus::PartiallyFilled),
+            "FILLED" | "ALLTRADED" => Some(OrderStatus::Filled),
+            "CANCELED" | "CANCELLED" | "PARTIALLYFILLEDCANCELED" | "PARTTRADEDNOTQUEUEING" => Some(OrderStatus::Canceled),
+            "REJECTED" => Some(OrderStatus::Rejected),
+            "EXPIRED" | "EXPIREDINMATCH" | "DEACTIVATED" => Some(OrderStatus::Expired),
+            _ => None,
+        }
+    }
+
+    pub fn is_closed(&self) -> bool {
+        !matches!(self, OrderStatus::New | OrderStatus::PartiallyFilled)
+    }
+
+    pub fn can_transition(&self, next: OrderStatus) -> bool {
+        match self {
+            OrderStatus::New => true,
+            OrderStatus::PartiallyFilled => !matches!(next, OrderStatus::New | OrderStatus::Rejected),
+            _ => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ManagedOrder {
+    #[serde(flatten)]
+    pub order: Order,
+    pub state: OrderStatus,
+    pub created: u64,
+    pub updates: usize,
+}
+
+impl ManagedOrder {
+    fn accepts(&self, state: OrderStatus, order: &Order) -> bool {
+        if self.state == state {
+            return order.traded > self.order.traded
+                || (order.traded == self.order.traded && order.timestamp >= self.order.timestamp && (order.price != self.order.price || order.total != self.order.total));
+        }
+        // A triggered stop order expires and comes back as a new order of another type.
+        if state == OrderStatus::New && self.state == OrderStatus::Expired && order.order_type != self.order.order_type {
+            return true;
+        }
+        self.state.can_transition(state)
+    }
+}
+
+/// The session record of every order reported by the trade server, keyed by symbol and client order id.
+#[derive(Default)]
+pub struct OrderManager {
+    orders: HashMap<(String, String), ManagedOrder>,
+}
+
+impl OrderManager {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns false when the update is stale, a duplicate or an invalid transition and should be dropped.
+    pub fn on_order_update(&mut self, order: &Order) -> bool {
+        let state = match OrderStatus::parse(&order.status) {
+            Some(state) => state,
+            None => {
+                warn!("Unknown order status {} of {}", order.status, order.client_order_id);
+                return true;
+            },
+        };
+        let key = (order.symbol.clone(), order.client_order_id.clone());
+        match self.orders.get_mut(&key) {
+            Some(existing) => {
+                if !existing.accepts(state, order) {
+                    debug!("Dropped the {:?} update of {} in state {:?}", state, order.client_order_id, existing.state);
+                    return false;
+                }
+                let traded = existing.order.traded.max(order.traded);
+                existing.order = order.clone();
+                existing.order.traded = traded;
+                existing.state = state;
+                existing.updates += 1;
+            },
+            None => {
+                self.orders.insert(key, ManagedOrder {
+                    order: order.clone(),
+                    state,
+                    created: order.timestamp,
+                    updates: 1,
+                });
+            },
+        }
+        true
+    }
+
+    pub fn get_order(&self, symbol: &str, client_order_id: &str) -> Option<ManagedOrder> {
+        self.orders.get(&(symbol.to_string(), client_order_id.to_string())).cloned()
+    }
+
+    /// All orders of `symbol` in creation order, an empty symbol returns the orders of every symbol.
+    pub fn get_orders(&self, symbol: &str) -> Vec<ManagedOrder> {
+        let mut orders: Vec<ManagedOrder> = self.orders.values().filter(|o| symbol.is_empty() || o.order.symbol == symbol).cloned().collect();
+        orders.sort_by_key(|o| o.created);
+        orders
+    }
+
+    pub fn get_open_orders(&self, symbol: &str) -> Vec<ManagedOrder> {
+        self.get_orders(symbol).into_iter().filter(|o| !o.state.is_closed()).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn update(status: &str, traded: f64, timestamp: u64) -> Order {
+        Order { symbol: "BTCUSDT".to_string(), client_order_id: "c1".to_string(), order_type: "LIMIT".to_string(), status: status.to_string(), price: 100.0, total: 2.0, traded, timestamp, ..Default::default() }
+    }
+
+    #[test]
+    fn test_order_state_machine() {
+        assert_eq!(OrderStatus::parse("PartiallyFilled"), Some(OrderStatus::PartiallyFilled));
+        assert_eq!(OrderStatus::parse("ALL_TRADED"), Some(OrderStatus::Filled));
+        assert_eq!(OrderStatus::parse("Cancelled"), Some(OrderStatus::Canceled));
+        assert_eq!(OrderStatus::parse("EXPIRED_IN_MATCH"), Some(OrderStatus::Expired));
+        assert_eq!(OrderStatus::parse("HOLD"), None);
+
+        let mut manager = OrderManager::new();
+        assert!(manager.on_order_update(&update("NEW", 0.0, 1)));
+        assert!(manager.on_order_update(&update("PARTIALLY_FILLED", 1.0, 3)));
+        assert!(!manager.on_order_update(&update("NEW", 0.0, 2)));
+        assert!(!manager.on_order_update(&update("PARTIALLY_FILLED", 0.5, 2)));
+        assert!(manager.on_order_update(&update("FILLED", 2.0, 4)));
+        assert!(!manager.on_order_update(&update("FILLED", 2.0, 4)));
+        assert!(!manager.on_order_update(&update("CANCELED", 0.0, 5)));
+
+        let order = manager.get_order("BTCUSDT", "c1").unwrap();
+        assert_eq!((order.state, order.order.traded, order.updates), (OrderStatus::Filled, 2.0, 3));
+        assert!(manager.get_open_orders("").is_empty());
+        assert_eq!(manager.get_orders("BTCUSDT").len(), 1);
+    }
+}
@@ -0,0 +1,252 @@
+use std::{collections::{HashMap, HashSet, VecDeque}, time::{SystemTime, UNIX_EPOCH}};
+use common::error::AppError;
+use market::backtest_clock::BACKTEST_CLOCK;
+use serde::{Deserialize, Serialize};
+use crate::conditional_order::is_order_closed;
+use crate::order::{ModifyOrderRequest, OrderKind, OrderRequest};
+use crate::trade_server::{Order, Position, TradeEvent};
+
+pub const RISK_MAX_ORDER_SIZE: i32 = -301;
+pub const RISK_MAX_NOTIONAL: i32 = -302;
+pub const RISK_MAX_POSITION: i32 = -303;
+pub const RISK_MAX_OPEN_ORDERS: i32 = -304;
+pub const RISK_ORDER_RATE: i32 = -305;
+pub const RISK_PRICE_BAND: i32 = -306;
+pub const RISK_DAILY_LOSS: i32 = -307;
+
+const DAY_MILLIS: u64 = 86_400_000;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RiskConfig {
+    pub max_order_size: Option<f64>,
+    pub max_notional: Option<f64>,
+    pub max_position: Option<f64>,
+    pub max_open_orders: Option<usize>,
+    pub max_orders_per_second: Option<usize>,
+    pub price_band_percent: Option<f64>,
+    pub max_daily_loss: Option<f64>,
+}
+
+/// Pre-trade checks applied by the `TradeGateway` before an order reaches the trade server.
+#[derive(Default)]
+pub struct RiskManager {
+    config: RiskConfig,
+    last_prices: HashMap<String, f64>,
+    open_orders: HashSet<(String, String)>,
+    sent: VecDeque<u64>,
+    market_time: u64,
+    day: u64,
+    trading_day: Option<String>,
+    day_start_balance: Option<f64>,
+    balance: Option<f64>,
+}
+
+fn reject(symbol: &str, code: i32, message: String) -> AppError {
+    AppError::new(code, &format!("Risk check rejected the order of {}: {}", symbol, message))
+}
+
+impl RiskManager {
+    pub fn new(config: RiskConfig) -> Self {
+        RiskManager {
+            config,
+            ..Default::default()
+        }
+    }
+
+    pub fn set_config(&mut self, config: RiskConfig) {
+        self.config = config;
+    }
+
+    pub fn get_config(&self) -> RiskConfig {
+        self.config.clone()
+    }
+
+    fn now(&self) -> u64 {
+        if BACKTEST_CLOCK.is_enabled() {
+            return self.market_time;
+        }
+        SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis() as u64).unwrap_or(0)
+    }
+
+    fn daily_loss(&self) -> f64 {
+        match (self.day_start_balance, self.balance) {
+            (Some(start), Some(balance)) => start - balance,
+            _ => 0.0,
+        }
+    }
+
+    pub fn on_price(&mut self, symbol: &str, price: f64, timestamp: u64) {
+        self.last_prices.insert(symbol.to_string(), price);
+        self.market_time = self.market_time.max(timestamp);
+    }
+
+    pub fn on_trade_event(&mut self, event: &TradeEvent) {
+        match event {
+            TradeEvent::AccountUpdate(wallet) => {
+                // The markets without a trading day session roll the daily loss at UTC midnight.
+                let day = self.now() / DAY_MILLIS;
+                if self.day_start_balance.is_none() || (self.trading_day.is_none() && day != self.day) {
+                    self.day = day;
+                    self.day_start_balance = Some(wallet.balance);
+                }
+                self.balance = Some(wallet.balance);
+            },
+            TradeEvent::OrderUpdate(order) => self.on_order_update(order),
+            _ => {},
+        }
+    }
+
+    fn on_order_update(&mut self, order: &Order) {
+        let key = (order.symbol.clone(), order.client_order_id.clone());
+        if is_order_closed(&order.status) || (order.total > 0.0 && order.traded >= order.total) {
+            self.open_orders.remove(&key);
+        } else {
+            self.open_orders.insert(key);
+        }
+    }
+
+    /// Starts the daily loss of the next trading day from the balance at the end of `trading_day`.
+    pub fn on_trade_day_ended(&mut self, trading_day: &str) {
+        self.trading_day = Some(trading_day.to_string());
+        self.day_start_balance = self.balance;
+    }
+
+    pub fn on_accepted(&mut self, symbol: &str, client_order_id: &str) {
+        self.open_orders.insert((symbol.to_string(), client_order_id.to_string()));
+        if self.config.max_orders_per_second.is_some() {
+            let now = self.now();
+            self.sent.push_back(now);
+        }
+    }
+
+    pub fn check(&mut self, symbol: &str, request: &OrderRequest, positions: &[Position]) -> Result<(), AppError> {
+        let config = &self.config;
+        let reduce_only = request.is_reduce_only();
+        if let Some(max) = config.max_daily_loss {
+            let loss = self.daily_loss();
+            if !reduce_only && loss >= max {
+                return Err(reject(symbol, RISK_DAILY_LOSS, format!("The daily loss {} reached the limit {}", loss, max)));
+            }
+        }
+        if let Some(max) = config.max_order_size {
+            if request.quantity > max {
+                return Err(reject(symbol, RISK_MAX_ORDER_SIZE, format!("The quantity {} exceeds the max order size {}", request.quantity, max)));
+            }
+        }
+        let last_price = self.last_prices.get(symbol).cloned();
+        let limit_price = if request.order_type == OrderKind::Market { None } else { request.price };
+        if let (Some(band), Some(price), Some(last)) = (config.price_band_percent, limit_price, last_price) {
+            let deviation = (price - last).abs() / last * 100.0;
+            if deviation > band {
+                return Err(reject(symbol, RISK_PRICE_BAND, format!("The price {} deviates {:.2}% from the last price {}, the band is {}%", price, deviation, last, band)));
+            }
+        }
+        if let (Some(max), Some(price)) = (config.max_notional, limit_price.or(last_price)) {
+            let notional = request.quantity * price;
+            if notional > max {
+                return Err(reject(symbol, RISK_MAX_NOTIONAL, format!("The notional {} exceeds the max notional {}", notional, max)));
+            }
+        }
+        if let Some(max) = config.max_position {
+            let position: f64 = positions.iter().filter(|p| p.symbol == symbol).map(|p| p.amount.abs()).sum();
+            if !reduce_only && position + request.quantity > max {
+                return Err(reject(symbol, RISK_MAX_POSITION, format!("The position {} plus the quantity {} exceeds the max position {}", position, request.quantity, max)));
+            }
+        }
+        if let Some(max) = config.max_open_orders {
+            if self.open_orders.len() >= max {
+                return Err(reject(symbol, RISK_MAX_OPEN_ORDERS, format!("The open orders {} reached the limit {}", self.open_orders.len(), max)));
+            }
+        }
+        if let Some(max) = config.max_orders_per_second {
+            let now = self.now();
+            while self.sent.front().is_some_and(|t| *t + 1000 <= now) {
+                self.sent.pop_front();
+            }
+            if self.sent.len() >= max {
+                return Err(reject(symbol, RISK_ORDER_RATE, format!("The order rate exceeds {} orders per second", max)));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn check_modify(&self, symbol: &str, request: &ModifyOrderRequest) -> Result<(), AppError> {
+        if let (Some(max), Some(quantity)) = (self.config.max_order_size, request.quantity) {
+            if quantity > max {
+                return Err(reject(symbol, RISK_MAX_ORDER_SIZE, format!("The quantity {} exceeds the max order size {}", quantity, max)));
+            }
+        }
+        if let (Some(band), Some(price), Some(last)) = (self.config.price_band_percent, request.price, self.last_prices.get(symbol)) {
+            let deviation = (price - last).abs() / last * 100.0;
+            if deviation > band {
+                return Err(reject(symbol, RISK_PRICE_BAND, format!("The price {} deviates {:.2}% from the last price {}, the band is {}%", price, deviation, last, band)));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::trade_server::Wallet;
+
+    #[test]
+    fn test_risk_checks() {
+        let mut risk = RiskManager::new(RiskConfig {
+            max_order_size: Some(10.0),
+            max_notional: Some(500.0),
+            max_position: Some(8.0),
+            max_open_orders: Some(2),
+            max_orders_per_second: None,
+            price_band_percent: Some(5.0),
+            max_daily_loss: Some(100.0),
+        });
+        let order = |quantity: f64, price: f64| OrderRequest::from_json(&format!(r#"{{"side":"BUY","orderType":"LIMIT","quantity":{},"price":{}}}"#, quantity, price)).unwrap();
+        risk.on_price("BTCUSDT", 100.0, 0);
+        assert_eq!(risk.check("BTCUSDT", &order(11.0, 100.0), &[]).unwrap_err().code, RISK_MAX_ORDER_SIZE);
+        assert_eq!(risk.check("BTCUSDT", &order(1.0, 106.0), &[]).unwrap_err().code, RISK_PRICE_BAND);
+        assert_eq!(risk.check("BTCUSDT", &order(6.0, 100.0), &[]).unwrap_err().code, RISK_MAX_NOTIONAL);
+        let positions = vec![Position { symbol: "BTCUSDT".to_string(), amount: 4.0, ..Default::default() }];
+        assert_eq!(risk.check("BTCUSDT", &order(5.0, 99.0), &positions).unwrap_err().code, RISK_MAX_POSITION);
+        assert!(risk.check("BTCUSDT", &order(1.0, 100.0), &positions).is_ok());
+        risk.on_accepted("BTCUSDT", "c1");
+        risk.on_accepted("BTCUSDT", "c2");
+        assert_eq!(risk.check("BTCUSDT", &order(1.0, 100.0), &[]).unwrap_err().code, RISK_MAX_OPEN_ORDERS);
+        risk.on_trade_event(&TradeEvent::OrderUpdate(Order { symbol: "BTCUSDT".to_string(), client_order_id: "c1".to_string(), status: "CANCELED".to_string(), ..Default::default() }));
+        assert!(risk.check("BTCUSDT", &order(1.0, 100.0), &[]).is_ok());
+
+        risk.on_trade_event(&TradeEvent::AccountUpdate(Wallet { balance: 1000.0, ..Default::default() }));
+        risk.on_trade_event(&TradeEvent::AccountUpdate(Wallet { balance: 880.0, ..Default::default() }));
+        assert_eq!(risk.check("BTCUSDT", &order(1.0, 100.0), &[]).unwrap_err().code, RISK_DAILY_LOSS);
+    }
+
+    #[test]
+    fn test_daily_loss_rolls_on_trade_day_end() {
+        let mut risk = RiskManager::new(RiskConfig { max_daily_loss: Some(100.0), ..Default::default() });
+        let order = OrderRequest::from_json(r#"{"side":"BUY","orderType":"MARKET","quantity":1.0}"#).unwrap();
+        risk.on_trade_event(&TradeEvent::AccountUpdate(Wallet { balance: 1000.0, ..Default::default() }));
+        risk.on_trade_event(&TradeEvent::AccountUpdate(Wallet { balance: 880.0, ..Default::default() }));
+        assert_eq!(risk.check("rb2501", &order, &[]).unwrap_err().code, RISK_DAILY_LOSS);
+        risk.on_trade_day_ended("20250103");
+        assert!(risk.check("rb2501", &order, &[]).is_ok());
+        risk.on_trade_event(&TradeEvent::AccountUpdate(Wallet { balance: 790.0, ..Default::default() }));
+        assert!(risk.check("rb2501", &order, &[]).is_ok());
+        risk.on_trade_event(&TradeEvent::AccountUpdate(Wallet { balance: 780.0, ..Default::default() }));
+        assert_eq!(risk.check("rb2501", &order, &[]).unwrap_err().code, RISK_DAILY_LOSS);
+    }
+
+    #[test]
+    fn test_order_rate_counts_accepted_orders() {
+        let mut risk = RiskManager::new(RiskConfig { max_orders_per_second: Some(2), ..Default::default() });
+        let order = OrderRequest::from_json(r#"{"side":"BUY","orderType":"MARKET","quantity":1.0}"#).unwrap();
+        for _ in 0..3 {
+            assert!(risk.check("BTCUSDT", &order, &[]).is_ok());
+        }
+        risk.on_accepted("BTCUSDT", "c1");
+        assert!(risk.check("BTCUSDT", &order, &[]).is_ok());
+        risk.on_accepted("BTCUSDT", "c2");
+        assert_eq!(risk.check("BTCUSDT", &order, &[]).unwrap_err().code, RISK_ORDER_RATE);
+    }
+}
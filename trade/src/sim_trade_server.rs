@@ -1,8 +1,14 @@
-use std::sync::{Arc, Mutex, RwLock};
+use std::collections::{HashMap, VecDeque};
+use std::sync::{atomic::{AtomicU64, Ordering}, Arc, Mutex, RwLock};
 use common::{error::AppError, msmc::Subscription};
-use rust_decimal::{prelude::FromPrimitive, Decimal};
+use market::{backtest_clock::BACKTEST_CLOCK, market_server::MarketData};
+use rust_decimal::{prelude::ToPrimitive, Decimal};
 use serde::{Deserialize, Serialize};
-use crate::trade_server::{Order, Position, TradeEvent, TradeServer, Wallet};
+use crate::backtest_report::{BacktestRecorder, BacktestReport};
+use crate::cost_model::{FeeModel, SlippageModel};
+use crate::matching_engine::{is_buy_side, Fill, MatchingConfig, MatchingEngine, RestingOrder};
+use crate::order::{invalid_field, to_decimal, ModifyOrderRequest, OrderKind, OrderPositionSide, OrderRequest};
+use crate::trade_server::{Execution, Order, OrderAck, Position, TradeEvent, TradeServer, Wallet};
 
 
 #[derive(Debug, Clone, Serialize, Deserialize,)]
@@ -10,6 +16,10 @@ pub struct SimTradeConfig {
     pub order_completed_status: String,
     pub asset: String,
     pub balance: u64,
+    pub leverage: Option<u64>,
+    pub fill_ratio: Option<f64>,
+    pub fee: Option<FeeModel>,
+    pub slippage: Option<SlippageModel>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize,)]
@@ -24,20 +34,460 @@ pub struct SimNewOrderRequest {
     pub new_client_order_id: Option<String>,
 }
 
+impl SimNewOrderRequest {
+    pub fn from_order(symbol: &str, request: &OrderRequest) -> Result<Self, AppError> {
+        if !matches!(request.order_type, OrderKind::Limit | OrderKind::Market) {
+            return Err(invalid_field("orderType", &format!("{} is not supported by the simulator", request.order_type.code())));
+        }
+        Ok(SimNewOrderRequest {
+            symbol: symbol.to_string(),
+            side: request.side.code().to_string(),
+            position_side: request.resolved_position_side().unwrap_or(OrderPositionSide::Both).code().to_string(),
+            order_type: request.order_type.code().to_string(),
+            reduce_only: request.is_reduce_only(),
+            quantity: Some(to_decimal("quantity", request.quantity)?),
+            price: request.price.map(|v| to_decimal("price", v)).transpose()?,
+            new_client_order_id: request.client_order_id.clone(),
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SimOrderStatus {
+    pub new: String,
+    pub partially_filled: String,
+    pub filled: String,
+    pub canceled: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FundingRate {
+    pub symbol: String,
+    pub funding_time: u64,
+    pub funding_rate: f64,
+}
+
+pub trait FundingRateLoader: Send + Sync {
+    fn load_funding_rate(&self, symbol: &str, start_time: u64, limit: u32) -> Result<Vec<FundingRate>, AppError>;
+}
+
+#[derive(Debug, Clone)]
+pub struct SimMarginBracket {
+    pub notional_cap: f64,
+    pub maint_margin_ratio: f64,
+}
+
+#[derive(Debug, Clone)]
+pub struct SimSymbolMargin {
+    pub margin_ratio: f64,
+    pub maint_margin_ratio: f64,
+    pub volume_multiple: f64,
+    pub brackets: Vec<SimMarginBracket>,
+}
+
+impl SimSymbolMargin {
+    pub fn from_leverage(leverage: u64) -> Self {
+        let margin_ratio = 1.0 / leverage.max(1) as f64;
+        SimSymbolMargin {
+            margin_ratio,
+            maint_margin_ratio: margin_ratio / 4.0,
+            volume_multiple: 1.0,
+            brackets: vec![],
+        }
+    }
+
+    pub fn maint_ratio(&self, notional: f64) -> f64 {
+        match self.brackets.iter().find(|b| notional <= b.notional_cap) {
+            Some(b) => b.maint_margin_ratio,
+            None => self.maint_margin_ratio,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct SimAccount {
+    pub asset: String,
+    pub leverage: u64,
+    pub positions: Arc<RwLock<Vec<Position>>>,
+    pub assets: Arc<RwLock<Vec<Wallet>>>,
+    pub margins: Arc<RwLock<HashMap<String, SimSymbolMargin>>>,
+    pub last_prices: Arc<RwLock<HashMap<String, f64>>>,
+    pub funding_loader: Option<Arc<dyn FundingRateLoader>>,
+    pub fundings: Arc<Mutex<HashMap<String, (VecDeque<FundingRate>, u64)>>>,
+    pub orders: Arc<RwLock<HashMap<(String, String), Order>>>,
+}
+
+impl SimAccount {
+    pub fn record_order(&self, order: &Order) {
+        if !order.client_order_id.is_empty() {
+            self.orders.write().unwrap().insert((order.symbol.clone(), order.client_order_id.clone()), order.clone());
+        }
+    }
+
+    pub fn get_order(&self, symbol: &str, client_order_id: &str) -> Option<Order> {
+        self.orders.read().unwrap().get(&(symbol.to_string(), client_order_id.to_string())).cloned()
+    }
+
+    pub fn symbol_margin(&self, symbol: &str) -> SimSymbolMargin {
+        match self.margins.read().unwrap().get(symbol) {
+            Some(m) => m.clone(),
+            None => SimSymbolMargin::from_leverage(self.leverage),
+        }
+    }
+
+    pub fn last_price(&self, symbol: &str) -> Option<f64> {
+        self.last_prices.read().unwrap().get(symbol).cloned()
+    }
+
+    pub fn update_price(&self, data: &MarketData) {
+        let (symbol, price) = match data {
+            MarketData::Tick(t) => (t.symbol.clone(), t.close),
+            MarketData::Kline(k) => (k.symbol.clone(), k.close),
+            _ => return,
+        };
+        if price > 0.0 {
+            self.last_prices.write().unwrap().insert(symbol, price);
+        }
+    }
+
+    pub fn order_margin(&self, symbol: &str, limit_price: Option<f64>, quantity: f64) -> Option<f64> {
+        let price = match limit_price {
+            Some(p) => p,
+            None => self.last_price(symbol)?,
+        };
+        let margin = self.symbol_margin(symbol);
+        Some(price * quantity * margin.volume_multiple * margin.margin_ratio)
+    }
+
+    pub fn used_margin(&self, open_orders: &Vec<RestingOrder>) -> f64 {
+        let mut used = 0.0;
+        for p in self.positions.read().unwrap().iter() {
+            let margin = self.symbol_margin(&p.symbol);
+            used += p.cost * p.amount * margin.volume_multiple * margin.margin_ratio;
+        }
+        for o in open_orders.iter().filter(|o| !o.reduce_only) {
+            used += self.order_margin(&o.order.symbol, o.limit_price, o.remaining()).unwrap_or(0.0);
+        }
+        used
+    }
+
+    pub fn refresh_wallet(&self, open_orders: &Vec<RestingOrder>, realized_pnl: f64) -> Option<Wallet> {
+        let used = self.used_margin(open_orders);
+        let unrealized_pnl = self.unrealized_pnl();
+        let mut assets = self.assets.write().unwrap();
+        let wallet = assets.iter_mut().find(|a| a.asset == self.asset)?;
+        wallet.balance += realized_pnl;
+        wallet.available_balance = wallet.balance + unrealized_pnl - used;
+        Some(wallet.clone())
+    }
+
+    pub fn unrealized_pnl(&self) -> f64 {
+        self.positions.read().unwrap().iter().map(|p| p.unrealized_pnl).sum()
+    }
+
+    pub fn mark_to_market(&self) {
+        let mut positions = self.positions.write().unwrap();
+        for p in positions.iter_mut() {
+            let mark_price = self.last_price(&p.symbol).unwrap_or(p.cost);
+            let direction = if is_buy_side(&p.side) { 1.0 } else { -1.0 };
+            p.unrealized_pnl = (mark_price - p.cost) * p.amount * self.symbol_margin(&p.symbol).volume_multiple * direction;
+        }
+    }
+
+    pub fn is_liquidatable(&self) -> bool {
+        let positions = self.positions.read().unwrap();
+        if positions.is_empty() {
+            return false;
+        }
+        let mut maint_margin = 0.0;
+        let mut unrealized_pnl = 0.0;
+        for p in positions.iter() {
+            let margin = self.symbol_margin(&p.symbol);
+            let notional = self.last_price(&p.symbol).unwrap_or(p.cost) * p.amount * margin.volume_multiple;
+            maint_margin += notional * margin.maint_ratio(notional);
+            unrealized_pnl += p.unrealized_pnl;
+        }
+        drop(positions);
+        self.balance() + unrealized_pnl < maint_margin
+    }
+
+    /// The market orders closing every position at the liquidation, with the mark prices they are filled at.
+    pub fn liquidation_orders(&self, timestamp: u64) -> Vec<(RestingOrder, f64)> {
+        let positions = self.positions.read().unwrap();
+        positions.iter().filter(|p| p.amount > 0.0).map(|p| {
+            let order = RestingOrder {
+                order: Order {
+                    order_id: format!("LIQUIDATION-{}-{}", p.position_side, timestamp),
+                    symbol: p.symbol.clone(),
+                    order_type: "LIQUIDATION".to_string(),
+                    side: if is_buy_side(&p.side) { "SELL".to_string() } else { "BUY".to_string() },
+                    offset: "CLOSE".to_string(),
+                    total: p.amount,
+                    timestamp,
+                    ..Default::default()
+                },
+                position_side: p.position_side.clone(),
+                reduce_only: true,
+                limit_price: None,
+                volume_multiple: self.symbol_margin(&p.symbol).volume_multiple,
+                resting: false,
+                sequence: 0,
+            };
+            (order, self.last_price(&p.symbol).unwrap_or(p.cost))
+        }).collect()
+    }
+
+    pub fn apply_funding(&self, symbol: &str, timestamp: u64) -> Option<f64> {
+        let loader = self.funding_loader.as_ref()?;
+        let mut fundings = self.fundings.lock().unwrap();
+        let (queue, next_load) = fundings.entry(symbol.to_string()).or_insert((VecDeque::new(), timestamp));
+        if queue.is_empty() && timestamp >= *next_load {
+            match loader.load_funding_rate(symbol, *next_load, 100) {
+                Ok(rates) => {
+                    *next_load = match rates.last() {
+                        Some(r) => r.funding_time + 1,
+                        None => timestamp + FUNDING_RELOAD_INTERVAL,
+                    };
+                    queue.extend(rates);
+                },
+                Err(_) => *next_load = timestamp + FUNDING_RELOAD_INTERVAL,
+            }
+        }
+
+        let mut payment = None;
+        while let Some(funding_rate) = queue.front().filter(|r| r.funding_time <= timestamp).map(|r| r.funding_rate) {
+            let mark_price = match self.last_price(symbol) {
+                Some(v) => v,
+                None => break,
+            };
+            let volume_multiple = self.symbol_margin(symbol).volume_multiple;
+            for p in self.positions.read().unwrap().iter().filter(|p| p.symbol == symbol) {
+                let direction = if is_buy_side(&p.side) { 1.0 } else { -1.0 };
+                *payment.get_or_insert(0.0) -= p.amount * mark_price * volume_multiple * funding_rate * direction;
+            }
+            queue.pop_front();
+        }
+        payment
+    }
+
+    pub fn balance(&self) -> f64 {
+        let assets = self.assets.read().unwrap();
+        match assets.iter().find(|a| a.asset == self.asset) {
+            Some(w) => w.balance,
+            None => 0.0,
+        }
+    }
+
+    pub fn available_balance(&self) -> f64 {
+        let assets = self.assets.read().unwrap();
+        match assets.iter().find(|a| a.asset == self.asset) {
+            Some(w) => w.available_balance,
+            None => 0.0,
+        }
+    }
+
+    /// The amount of the position a reduce-only order of `side` can close.
+    pub fn closable(&self, symbol: &str, position_side: &str, side: &str) -> f64 {
+        let positions = self.positions.read().unwrap();
+        let position = positions.iter().find(|p| p.symbol == symbol && p.position_side == position_side && is_buy_side(&p.side) != is_buy_side(side));
+        position.map(|p| p.amount).unwrap_or(0.0)
+    }
+
+    pub fn apply_fill(&self, fill: &Fill) -> (Position, f64) {
+        let volume_multiple = self.symbol_margin(&fill.order.symbol).volume_multiple;
+        let mut positions = self.positions.write().unwrap();
+        let mut realized_pnl = 0.0;
+
+        let idx = positions.iter().position(|p| p.symbol == fill.order.symbol && p.position_side == fill.position_side);
+        let reducible = idx.is_some_and(|idx| is_buy_side(&positions[idx].side) != is_buy_side(&fill.order.side));
+        if fill.reduce_only && !reducible {
+            // A reduce-only fill never opens or increases a position.
+            let unchanged = match idx {
+                Some(idx) => positions[idx].clone(),
+                None => Position {
+                    symbol: fill.order.symbol.clone(),
+                    position_side: fill.position_side.clone(),
+                    side: fill.order.side.clone(),
+                    ..Default::default()
+                },
+            };
+            return (unchanged, 0.0);
+        }
+        let updated = match idx {
+            Some(idx) => {
+                let p = &mut positions[idx];
+                if is_buy_side(&p.side) != is_buy_side(&fill.order.side) {
+                    let closed = fill.quantity.min(p.amount);
+                    let direction = if is_buy_side(&p.side) { 1.0 } else { -1.0 };
+                    realized_pnl = (fill.price - p.cost) * closed * volume_multiple * direction;
+                    p.amount = p.amount - closed;
+                    let remain = fill.quantity - closed;
+                    if remain > 0.0 && !fill.reduce_only {
+                        p.side = fill.order.side.clone();
+                        p.amount = remain;
+                        p.cost = fill.price;
+                    }
+                } else {
+                    p.cost = (p.cost * p.amount + fill.price * fill.quantity) / (p.amount + fill.quantity);
+                    p.amount = p.amount + fill.quantity;
+                }
+                let updated = p.clone();
+                if updated.amount <= 0.0 {
+                    positions.remove(idx);
+                }
+                updated
+            },
+            None => {
+                let p = Position {
+                    symbol: fill.order.symbol.clone(),
+                    cost: fill.price,
+                    position_side: fill.position_side.clone(),
+                    side: fill.order.side.clone(),
+                    amount: fill.quantity,
+                    ..Default::default()
+                };
+                positions.push(p.clone());
+                p
+            },
+        };
+        (updated, realized_pnl)
+    }
+}
+
+const FUNDING_RELOAD_INTERVAL: u64 = 8 * 60 * 60 * 1000;
+
 pub struct SimTradeServer {
     pub config: SimTradeConfig,
+    pub order_status: SimOrderStatus,
     pub positions: Arc<RwLock<Vec<Position>>>,
     pub assets: Arc<RwLock<Vec<Wallet>>>,
+    pub account: SimAccount,
+    pub recorder: Arc<Mutex<BacktestRecorder>>,
+    pub engine: Arc<Mutex<MatchingEngine>>,
     pub subscription: Arc<Mutex<Subscription<TradeEvent>>>,
+    order_seq: AtomicU64,
+    market_time: Arc<AtomicU64>,
 }
 
 impl SimTradeServer {
-    pub fn new(config: SimTradeConfig) -> Self {
+    pub fn new(config: SimTradeConfig, order_status: SimOrderStatus, funding_loader: Option<Box<dyn FundingRateLoader>>) -> Self {
+        let matching_config = MatchingConfig {
+            fill_ratio: config.fill_ratio,
+            fee: config.fee.clone(),
+            slippage: config.slippage.clone(),
+        };
+        let positions = Arc::new(RwLock::new(Vec::new()));
+        let assets = Arc::new(RwLock::new(Vec::new()));
+        let account = SimAccount {
+            asset: config.asset.clone(),
+            leverage: config.leverage.unwrap_or(1),
+            positions: positions.clone(),
+            assets: assets.clone(),
+            margins: Arc::new(RwLock::new(HashMap::new())),
+            last_prices: Arc::new(RwLock::new(HashMap::new())),
+            funding_loader: funding_loader.map(|v| Arc::from(v)),
+            fundings: Arc::new(Mutex::new(HashMap::new())),
+            orders: Arc::new(RwLock::new(HashMap::new())),
+        };
         SimTradeServer {
+            recorder: Arc::new(Mutex::new(BacktestRecorder::new(config.balance as f64))),
             config,
-            positions: Arc::new(RwLock::new(Vec::new())),
-            assets: Arc::new(RwLock::new(Vec::new())),
+            order_status,
+            positions,
+            assets,
+            account,
+            engine: Arc::new(Mutex::new(MatchingEngine::new(matching_config))),
             subscription: Arc::new(Mutex::new(Subscription::top())),
+            order_seq: AtomicU64::new(0),
+            market_time: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    pub fn set_symbol_margin(&self, symbol: &str, margin: SimSymbolMargin) {
+        self.account.margins.write().unwrap().insert(symbol.to_string(), margin);
+    }
+
+    pub fn get_report(&self) -> BacktestReport {
+        self.recorder.lock().unwrap().report()
+    }
+
+    fn handle_fill(account: &SimAccount, engine: &Arc<Mutex<MatchingEngine>>, recorder: &Arc<Mutex<BacktestRecorder>>, order_status: &SimOrderStatus, sub: &Subscription<TradeEvent>, mut fill: Fill) {
+        let (mut position, realized_pnl) = account.apply_fill(&fill);
+        recorder.lock().unwrap().record_trade(&fill, realized_pnl, account.symbol_margin(&fill.order.symbol).volume_multiple);
+        account.mark_to_market();
+        if let Some(p) = account.positions.read().unwrap().iter().find(|p| p.symbol == position.symbol && p.position_side == position.position_side) {
+            position.unrealized_pnl = p.unrealized_pnl;
+        }
+        sub.send(&TradeEvent::PositionUpdate(position));
+
+        let open_orders = engine.lock().unwrap().get_all_orders();
+        if let Some(wallet) = account.refresh_wallet(&open_orders, realized_pnl - fill.fee) {
+            sub.send(&TradeEvent::AccountUpdate(wallet));
+        }
+
+        fill.order.status = if fill.order.traded >= fill.order.total {
+            order_status.filled.clone()
+        } else {
+            order_status.partially_filled.clone()
+        };
+        account.record_order(&fill.order);
+        sub.send(&TradeEvent::Fill(Execution {
+            symbol: fill.order.symbol.clone(),
+            order_id: fill.order.order_id.clone(),
+            client_order_id: fill.order.client_order_id.clone(),
+            trade_id: format!("{}-{}", fill.order.order_id, fill.order.traded),
+            side: fill.order.side.clone(),
+            offset: fill.order.offset.clone(),
+            price: fill.price,
+            quantity: fill.quantity,
+            fee: fill.fee,
+            fee_asset: account.asset.clone(),
+            is_maker: fill.is_maker,
+            timestamp: fill.timestamp,
+        }));
+        sub.send(&TradeEvent::OrderUpdate(fill.order));
+    }
+
+    fn settle(account: &SimAccount, engine: &Arc<Mutex<MatchingEngine>>, recorder: &Arc<Mutex<BacktestRecorder>>, order_status: &SimOrderStatus, sub: &Subscription<TradeEvent>, symbol: &str, timestamp: u64) {
+        account.mark_to_market();
+        if let Some(payment) = account.apply_funding(symbol, timestamp) {
+            let open_orders = engine.lock().unwrap().get_all_orders();
+            if let Some(wallet) = account.refresh_wallet(&open_orders, payment) {
+                sub.send(&TradeEvent::AccountUpdate(wallet));
+            }
+        }
+
+        if account.is_liquidatable() {
+            let canceled = engine.lock().unwrap().cancel_everything();
+            for mut o in canceled {
+                o.order.status = order_status.canceled.clone();
+                account.record_order(&o.order);
+                sub.send(&TradeEvent::OrderUpdate(o.order));
+            }
+            for (mut order, mark_price) in account.liquidation_orders(timestamp) {
+                let fill = engine.lock().unwrap().fill_at(&mut order, mark_price, timestamp);
+                Self::handle_fill(account, engine, recorder, order_status, sub, fill);
+            }
+        }
+
+        let balance = account.balance();
+        recorder.lock().unwrap().record_equity(timestamp, balance, balance + account.unrealized_pnl());
+    }
+
+    fn send_account_update(&self) {
+        let open_orders = self.engine.lock().unwrap().get_all_orders();
+        if let Some(wallet) = self.account.refresh_wallet(&open_orders, 0.0) {
+            self.subscription.lock().unwrap().send(&TradeEvent::AccountUpdate(wallet));
+        }
+    }
+
+    fn send_canceled(&self, mut canceled: Vec<RestingOrder>) {
+        let sub = self.subscription.lock().unwrap();
+        for o in canceled.iter_mut() {
+            o.order.status = self.order_status.canceled.clone();
+            self.account.record_order(&o.order);
+            sub.send(&TradeEvent::OrderUpdate(o.order.clone()));
         }
     }
 }
@@ -48,7 +498,7 @@ impl TradeServer for SimTradeServer {
     type SymbolConfig = String;
     type SymbolInfo = String;
     type Symbol = String;
-    
+
     fn init(&mut self) -> Result<(), AppError> {
         self.assets.write().unwrap().push(Wallet {
             asset: self.config.asset.clone(),
@@ -62,89 +512,194 @@ impl TradeServer for SimTradeServer {
     fn start(&mut self) -> Result<Subscription<TradeEvent>, AppError> {
         let mut top = Subscription::<TradeEvent>::top();
         let sub = top.subscribe();
-        self.subscription = Arc::new(Mutex::new(top));
+        *self.subscription.lock().unwrap() = top;
         Ok(sub)
     }
 
-    fn new_order(&mut self, _symbol: String, mut request : SimNewOrderRequest) -> Result<(), AppError> {
-        if request.order_type.to_string().to_uppercase() == "MARKET" {
-            return Err(AppError::new(-200, "Sim Trade Server does not support MARKET order type"));
-        }
-
-        let mut positions = self.positions.write().unwrap();
-        let mut found: Option<Position> = None;
-
-        for p in positions.iter_mut() {
-            if p.symbol == request.symbol && p.position_side == request.position_side {
-                let quantity: f64;
-                if let Some(v) = request.quantity {
-                    quantity = v.to_string().parse::<f64>().unwrap();
-                } else {
-                    quantity = p.amount;
+    fn bind_market(&mut self, mut subscription: Subscription<MarketData>) -> Result<(), AppError> {
+        let engine_ref = self.engine.clone();
+        let recorder = self.recorder.clone();
+        let account = self.account.clone();
+        let subscription_ref = self.subscription.clone();
+        let order_status = self.order_status.clone();
+        let market_time = self.market_time.clone();
+        let _ = subscription.stream(move |event| {
+            if let Some(data) = event {
+                match data {
+                    MarketData::Tick(t) => market_time.fetch_max(t.timestamp, Ordering::SeqCst),
+                    MarketData::Kline(k) => market_time.fetch_max(k.timestamp, Ordering::SeqCst),
+                    _ => 0,
+                };
+                let fills = engine_ref.lock().unwrap().on_market_data(data, BACKTEST_CLOCK.sequence());
+                account.update_price(data);
+                let sub = subscription_ref.lock().unwrap();
+                for fill in fills {
+                    Self::handle_fill(&account, &engine_ref, &recorder, &order_status, &sub, fill);
                 }
-                request.quantity = Decimal::from_f64(quantity);
-                if p.side != request.side.to_string() {
-                    p.amount = p.amount - quantity;
-                } else {
-                    p.amount = p.amount + quantity;
+                match data {
+                    MarketData::Tick(t) => Self::settle(&account, &engine_ref, &recorder, &order_status, &sub, &t.symbol, t.timestamp),
+                    MarketData::Kline(k) => Self::settle(&account, &engine_ref, &recorder, &order_status, &sub, &k.symbol, k.timestamp),
+                    MarketData::BacktestEnded => recorder.lock().unwrap().finish(),
+                    _ => {},
                 }
-                found = Some(p.clone());
-                break;
             }
+            Ok(true)
+        });
+        Ok(())
+    }
+
+    fn new_order(&mut self, _symbol: String, request : SimNewOrderRequest) -> Result<OrderAck, AppError> {
+        let quantity = match request.quantity {
+            Some(v) => v.to_f64().unwrap(),
+            None => {
+                let positions = self.positions.read().unwrap();
+                let position = positions.iter().find(|p| p.symbol == request.symbol && p.position_side == request.position_side);
+                match position {
+                    Some(p) => p.amount,
+                    None => return Err(AppError::new(-200, "The quantity is required when there is no position to close")),
+                }
+            },
+        };
+
+        let is_market = request.order_type.to_string().to_uppercase() == "MARKET";
+        let limit_price = if is_market { None } else { request.price.and_then(|v| v.to_f64()) };
+        if !is_market && limit_price.is_none() {
+            return Err(AppError::new(-200, "The price is required for a non-MARKET order"));
         }
 
-        if found.is_none() {
-            let quantity = request.quantity.unwrap().to_string().parse::<f64>().unwrap();
-            let p = Position {
-                symbol: request.symbol.clone(),
-                cost: request.price.unwrap().to_string().parse::<f64>().unwrap(),
-                position_side: request.position_side.clone(),
-                side: request.side.to_string(),
-                amount: quantity,
-                ..Default::default()
-            };
-            positions.push(p.clone());
-            found = Some(p);
+        let quantity = if request.reduce_only {
+            let closable = self.account.closable(&request.symbol, &request.position_side, &request.side);
+            if closable <= 0.0 {
+                return Err(AppError::new(-200, &format!("No {} position of {} to reduce", request.position_side, request.symbol)));
+            }
+            quantity.min(closable)
         } else {
-            let p = found.as_ref().unwrap();
-            if p.amount == 0.0 {
-                let idx = positions.iter().position(|x| x == p);
-                positions.remove(idx.unwrap());
+            let required = match self.account.order_margin(&request.symbol, limit_price, quantity) {
+                Some(v) => v,
+                None => return Err(AppError::new(-200, &format!("No market price of {} is available to calculate the margin", request.symbol))),
+            };
+            let available = self.account.available_balance();
+            if required > available {
+                return Err(AppError::new(-200, &format!("Insufficient margin, required {}, available {}", required, available)));
             }
-        }
-
-        let sub = self.subscription.lock().unwrap();
-        let p = found.take().unwrap();
-        sub.send(&TradeEvent::PositionUpdate(p));
+            quantity
+        };
 
-        let quantity = request.quantity.unwrap().to_string().parse::<f64>().unwrap();
-        let mut price = 0 as f64;
-        if let Some(v) = request.price {
-            price = v.to_string().parse::<f64>().unwrap();
-        }
+        let order_id = (self.order_seq.fetch_add(1, Ordering::SeqCst) + 1).to_string();
         let order_data = Order {
+            order_id: order_id.clone(),
             symbol: request.symbol.clone(),
-            client_order_id: request.new_client_order_id.unwrap().clone(),
+            client_order_id: request.new_client_order_id.clone().unwrap_or(order_id),
             side: request.side.to_string(),
             order_type: request.order_type.clone(),
-            price,
+            price: limit_price.unwrap_or(0.0),
             total: quantity,
-            traded: quantity,
-            status: self.config.order_completed_status.clone(),
+            traded: 0.0,
+            status: self.order_status.new.clone(),
+            offset: if request.reduce_only { "CLOSE".to_string() } else { "OPEN".to_string() },
+            timestamp: self.market_time.load(Ordering::SeqCst),
             ..Default::default()
         };
-        sub.send(&TradeEvent::OrderUpdate(order_data));
-        Ok(())
+        let ack = OrderAck {
+            order_id: order_data.order_id.clone(),
+            client_order_id: order_data.client_order_id.clone(),
+            status: order_data.status.clone(),
+            timestamp: order_data.timestamp,
+            ..Default::default()
+        };
+
+        self.engine.lock().unwrap().add(RestingOrder {
+            order: order_data.clone(),
+            position_side: request.position_side.clone(),
+            reduce_only: request.reduce_only,
+            limit_price,
+            volume_multiple: self.account.symbol_margin(&request.symbol).volume_multiple,
+            resting: false,
+            sequence: BACKTEST_CLOCK.sequence(),
+        });
+        self.account.record_order(&order_data);
+        self.subscription.lock().unwrap().send(&TradeEvent::OrderUpdate(order_data));
+        self.send_account_update();
+        Ok(ack)
     }
 
-    fn cancel_order(&mut self, _symbol: String, _request: String) -> Result<(), AppError> {
-        Ok(())
+    fn modify_order(&mut self, symbol: String, request: ModifyOrderRequest) -> Result<OrderAck, AppError> {
+        request.validate()?;
+        let id = request.client_order_id.as_str();
+        let resting = self.engine.lock().unwrap().get_orders(&symbol).into_iter().find(|o| o.order.client_order_id == id || o.order.order_id == id);
+        let resting = resting.ok_or(AppError::new(-200, &format!("The order {} is not found", id)))?;
+        if resting.limit_price.is_none() && request.price.is_some() {
+            return Err(invalid_field("price", "a MARKET order can not be repriced"));
+        }
+        if matches!(request.quantity, Some(v) if v <= resting.order.traded) {
+            return Err(invalid_field("quantity", &format!("must be greater than the traded quantity {}", resting.order.traded)));
+        }
+        let quantity = match request.quantity {
+            Some(quantity) if resting.reduce_only => {
+                let capped = quantity.min(resting.order.traded + self.account.closable(&symbol, &resting.position_side, &resting.order.side));
+                if capped <= resting.order.traded {
+                    return Err(AppError::new(-200, &format!("No {} position of {} to reduce", resting.position_side, symbol)));
+                }
+                Some(capped)
+            },
+            Some(quantity) if quantity > resting.order.total => {
+                let required = match self.account.order_margin(&symbol, request.price.or(resting.limit_price), quantity - resting.order.total) {
+                    Some(v) => v,
+                    None => return Err(AppError::new(-200, &format!("No market price of {} is available to calculate the margin", symbol))),
+                };
+                let available = self.account.available_balance();
+                if required > available {
+                    return Err(AppError::new(-200, &format!("Insufficient margin, required {}, available {}", required, available)));
+                }
+                Some(quantity)
+            },
+            quantity => quantity,
+        };
+
+        let modified = self.engine.lock().unwrap().modify(&symbol, id, request.price, quantity, BACKTEST_CLOCK.sequence());
+        let mut order_data = modified.ok_or(AppError::new(-200, &format!("The order {} is not found", id)))?.order;
+        order_data.timestamp = self.market_time.load(Ordering::SeqCst);
+        self.account.record_order(&order_data);
+        let ack = OrderAck {
+            order_id: order_data.order_id.clone(),
+            client_order_id: order_data.client_order_id.clone(),
+            status: order_data.status.clone(),
+            timestamp: order_data.timestamp,
+            ..Default::default()
+        };
+        self.subscription.lock().unwrap().send(&TradeEvent::OrderUpdate(order_data));
+        self.send_account_update();
+        Ok(ack)
+    }
+
+    fn cancel_order(&mut self, symbol: String, request: String) -> Result<(), AppError> {
+        let canceled = self.engine.lock().unwrap().cancel(&symbol, &request);
+        match canceled {
+            Some(o) => {
+                self.send_canceled(vec![o]);
+                self.send_account_update();
+                Ok(())
+            },
+            None => Err(AppError::new(-200, &format!("The order {} is not found", request))),
+        }
     }
 
-    fn cancel_orders(&mut self, _symbol: String) -> Result<(), AppError> {
+    fn cancel_orders(&mut self, symbol: String) -> Result<(), AppError> {
+        let canceled = self.engine.lock().unwrap().cancel_all(&symbol);
+        self.send_canceled(canceled);
+        self.send_account_update();
         Ok(())
     }
 
+    fn get_open_orders(&self, symbol: String) -> Result<Vec<Order>, AppError> {
+        let resting = self.engine.lock().unwrap().get_orders(&symbol);
+        Ok(resting.into_iter().map(|o| self.account.get_order(&symbol, &o.order.client_order_id).unwrap_or(o.order)).collect())
+    }
+
+    fn get_order(&self, symbol: String, client_order_id: &str) -> Result<Option<Order>, AppError> {
+        Ok(self.account.get_order(&symbol, client_order_id))
+    }
+
     fn get_positions(&self, symbol: String) -> Result<Vec<Position>, AppError> {
         let positions = self.positions.read().unwrap();
         let mut ret = vec![];
@@ -160,14 +715,14 @@ impl TradeServer for SimTradeServer {
         let assets = self.assets.read().unwrap();
         let mut ret = None;
         for asset in assets.iter() {
-            if account_id == asset.asset && asset.balance > 0.0 {
+            if account_id == asset.asset {
                 ret = Some(asset.clone());
                 break;
             }
         }
         Ok(ret)
     }
-    
+
     fn init_symbol(&self, _symbol: String, _config: Self::SymbolConfig) -> Result<Self::SymbolInfo, AppError> {
         Ok("".to_string())
     }
@@ -176,3 +731,273 @@ impl TradeServer for SimTradeServer {
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fill(side: &str, price: f64, quantity: f64) -> Fill {
+        Fill {
+            order: Order {
+                symbol: "BTCUSDT".to_string(),
+                side: side.to_string(),
+                ..Default::default()
+            },
+            position_side: "BOTH".to_string(),
+            reduce_only: false,
+            price,
+            quantity,
+            fee: 0.0,
+            is_maker: false,
+            timestamp: 0,
+        }
+    }
+
+    fn config(balance: u64) -> SimTradeConfig {
+        SimTradeConfig {
+            order_completed_status: "FILLED".to_string(),
+            asset: "USDT".to_string(),
+            balance,
+            leverage: Some(10),
+            fill_ratio: None,
+            fee: None,
+            slippage: None,
+        }
+    }
+
+    fn new_server(config: SimTradeConfig, funding_loader: Option<Box<dyn FundingRateLoader>>) -> SimTradeServer {
+        let mut server = SimTradeServer::new(config, SimOrderStatus {
+            new: "NEW".to_string(),
+            partially_filled: "PARTIALLY_FILLED".to_string(),
+            filled: "FILLED".to_string(),
+            canceled: "CANCELED".to_string(),
+        }, funding_loader);
+        server.init().unwrap();
+        server
+    }
+
+    #[test]
+    fn test_reducing_fill_flips_position() {
+        let account = new_server(config(1000), None).account;
+        account.apply_fill(&fill("BUY", 100.0, 1.0));
+        let (position, realized_pnl) = account.apply_fill(&fill("SELL", 90.0, 3.0));
+        assert_eq!(realized_pnl, -10.0);
+        assert_eq!((position.side.as_str(), position.amount, position.cost), ("SELL", 2.0, 90.0));
+        assert_eq!(account.positions.read().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_realized_pnl_and_margin() {
+        let server = new_server(config(1000), None);
+        let account = server.account.clone();
+
+        account.apply_fill(&fill("BUY", 100.0, 2.0));
+        let wallet = account.refresh_wallet(&vec![], 0.0).unwrap();
+        assert_eq!(wallet.available_balance, 980.0);
+
+        let (position, realized_pnl) = account.apply_fill(&fill("SELL", 110.0, 1.0));
+        assert_eq!(realized_pnl, 10.0);
+        assert_eq!(position.amount, 1.0);
+        let wallet = account.refresh_wallet(&vec![], realized_pnl).unwrap();
+        assert_eq!(wallet.balance, 1010.0);
+        assert_eq!(wallet.available_balance, 1000.0);
+
+        let wallet = account.refresh_wallet(&vec![], -1500.0).unwrap();
+        assert_eq!(wallet.balance, -490.0);
+        assert_eq!(server.get_account("USDT").unwrap().unwrap().balance, -490.0);
+    }
+
+    #[test]
+    fn test_reduce_only() {
+        let mut server = new_server(config(1000), None);
+        let account = server.account.clone();
+        let reduce = |side: &str, quantity: f64| Fill { reduce_only: true, ..fill(side, 100.0, quantity) };
+        account.apply_fill(&reduce("SELL", 1.0));
+        assert!(account.positions.read().unwrap().is_empty());
+        account.apply_fill(&fill("BUY", 100.0, 1.0));
+        let (position, _) = account.apply_fill(&reduce("BUY", 1.0));
+        assert_eq!(position.amount, 1.0);
+        let (position, _) = account.apply_fill(&reduce("SELL", 3.0));
+        assert_eq!(position.amount, 0.0);
+        assert!(account.positions.read().unwrap().is_empty());
+
+        let request = |side: &str| SimNewOrderRequest {
+            symbol: "BTCUSDT".to_string(),
+            side: side.to_string(),
+            position_side: "BOTH".to_string(),
+            order_type: "LIMIT".to_string(),
+            reduce_only: true,
+            quantity: Decimal::from_f64_retain(5.0),
+            price: Decimal::from_f64_retain(110.0),
+            new_client_order_id: None,
+        };
+        assert!(server.new_order("BTCUSDT".to_string(), request("SELL")).is_err());
+        account.apply_fill(&fill("BUY", 100.0, 2.0));
+        assert!(server.new_order("BTCUSDT".to_string(), request("BUY")).is_err());
+        let ack = server.new_order("BTCUSDT".to_string(), request("SELL")).unwrap();
+        assert_eq!(server.get_order("BTCUSDT".to_string(), &ack.client_order_id).unwrap().unwrap().total, 2.0);
+    }
+
+    #[test]
+    fn test_insufficient_margin() {
+        let mut server = new_server(config(1000), None);
+        server.account.last_prices.write().unwrap().insert("BTCUSDT".to_string(), 100.0);
+        let request = |quantity: f64| SimNewOrderRequest {
+            symbol: "BTCUSDT".to_string(),
+            side: "BUY".to_string(),
+            position_side: "BOTH".to_string(),
+            order_type: "MARKET".to_string(),
+            reduce_only: false,
+            quantity: Decimal::from_f64_retain(quantity),
+            price: None,
+            new_client_order_id: None,
+        };
+        assert!(server.new_order("BTCUSDT".to_string(), request(101.0)).is_err());
+        assert!(server.new_order("BTCUSDT".to_string(), request(100.0)).is_ok());
+        assert_eq!(server.account.available_balance(), 0.0);
+    }
+
+    #[test]
+    fn test_new_order_ack() {
+        let mut server = new_server(config(1000), None);
+        let request = |client_order_id: Option<&str>| SimNewOrderRequest {
+            symbol: "BTCUSDT".to_string(),
+            side: "BUY".to_string(),
+            position_side: "BOTH".to_string(),
+            order_type: "LIMIT".to_string(),
+            reduce_only: false,
+            quantity: Decimal::from_f64_retain(1.0),
+            price: Decimal::from_f64_retain(90.0),
+            new_client_order_id: client_order_id.map(|v| v.to_string()),
+        };
+        let ack = server.new_order("BTCUSDT".to_string(), request(Some("c1"))).unwrap();
+        assert_eq!((ack.order_id.as_str(), ack.client_order_id.as_str(), ack.status.as_str()), ("1", "c1", "NEW"));
+        assert!(ack.leg_order_ids.is_empty());
+        let ack = server.new_order("BTCUSDT".to_string(), request(None)).unwrap();
+        assert_eq!((ack.order_id.as_str(), ack.client_order_id.as_str()), ("2", "2"));
+    }
+
+    #[test]
+    fn test_open_orders_and_cancel() {
+        let mut server = new_server(config(1000), None);
+        for (id, price) in [("c1", 90.0), ("c2", 91.0)] {
+            server.new_order("BTCUSDT".to_string(), SimNewOrderRequest {
+                symbol: "BTCUSDT".to_string(),
+                side: "BUY".to_string(),
+                position_side: "BOTH".to_string(),
+                order_type: "LIMIT".to_string(),
+                reduce_only: false,
+                quantity: Decimal::from_f64_retain(1.0),
+                price: Decimal::from_f64_retain(price),
+                new_client_order_id: Some(id.to_string()),
+            }).unwrap();
+        }
+        assert_eq!(server.get_open_orders("BTCUSDT".to_string()).unwrap().len(), 2);
+        assert!(server.get_open_orders("ETHUSDT".to_string()).unwrap().is_empty());
+        let order = server.get_order("BTCUSDT".to_string(), "c1").unwrap().unwrap();
+        assert_eq!((order.status.as_str(), order.price), ("NEW", 90.0));
+        assert!(server.get_order("BTCUSDT".to_string(), "c3").unwrap().is_none());
+
+        server.cancel_order("BTCUSDT".to_string(), "c1".to_string()).unwrap();
+        let open_orders = server.get_open_orders("BTCUSDT".to_string()).unwrap();
+        assert_eq!(open_orders.iter().map(|o| o.client_order_id.as_str()).collect::<Vec<&str>>(), vec!["c2"]);
+        assert_eq!(server.get_order("BTCUSDT".to_string(), "c1").unwrap().unwrap().status, "CANCELED");
+        assert!(server.cancel_order("BTCUSDT".to_string(), "c1".to_string()).is_err());
+    }
+
+    #[test]
+    fn test_modify_order() {
+        let mut server = new_server(config(1000), None);
+        server.new_order("BTCUSDT".to_string(), SimNewOrderRequest {
+            symbol: "BTCUSDT".to_string(),
+            side: "BUY".to_string(),
+            position_side: "BOTH".to_string(),
+            order_type: "LIMIT".to_string(),
+            reduce_only: false,
+            quantity: Decimal::from_f64_retain(1.0),
+            price: Decimal::from_f64_retain(90.0),
+            new_client_order_id: Some("c1".to_string()),
+        }).unwrap();
+        let ack = server.modify_order("BTCUSDT".to_string(), ModifyOrderRequest { client_order_id: "c1".to_string(), quantity: Some(2.0), price: Some(95.0) }).unwrap();
+        assert_eq!((ack.order_id.as_str(), ack.client_order_id.as_str()), ("1", "c1"));
+        let order = server.get_order("BTCUSDT".to_string(), "c1").unwrap().unwrap();
+        assert_eq!((order.price, order.total), (95.0, 2.0));
+
+        let modify = |client_order_id: &str, quantity: Option<f64>, price: Option<f64>| ModifyOrderRequest { client_order_id: client_order_id.to_string(), quantity, price };
+        assert!(server.modify_order("BTCUSDT".to_string(), modify("c2", None, Some(95.0))).is_err());
+        assert!(server.modify_order("BTCUSDT".to_string(), modify("c1", None, None)).is_err());
+        assert!(server.modify_order("BTCUSDT".to_string(), modify("c1", Some(200.0), None)).is_err());
+        assert_eq!(server.get_order("BTCUSDT".to_string(), "c1").unwrap().unwrap().total, 2.0);
+    }
+
+    #[test]
+    fn test_batch_orders() {
+        let mut server = new_server(config(1000), None);
+        let requests = (0..3).map(|i| SimNewOrderRequest {
+            symbol: "BTCUSDT".to_string(),
+            side: "BUY".to_string(),
+            position_side: "BOTH".to_string(),
+            order_type: "LIMIT".to_string(),
+            reduce_only: false,
+            quantity: Decimal::from_f64_retain(if i == 1 { 200.0 } else { 1.0 }),
+            price: Decimal::from_f64_retain(80.0 + i as f64),
+            new_client_order_id: Some(format!("g{}", i)),
+        }).collect();
+        let results = server.new_orders("BTCUSDT".to_string(), requests).unwrap();
+        assert_eq!(results.iter().map(|r| r.index).collect::<Vec<usize>>(), vec![0, 1, 2]);
+        assert_eq!(results.iter().map(|r| r.error.is_none()).collect::<Vec<bool>>(), vec![true, false, true]);
+        assert_eq!(results[2].ack.as_ref().unwrap().client_order_id, "g2");
+        assert_eq!(server.get_open_orders("BTCUSDT".to_string()).unwrap().len(), 2);
+
+        let results = server.cancel_orders_by_id("BTCUSDT".to_string(), vec!["g0".to_string(), "g1".to_string(), "g2".to_string()]).unwrap();
+        assert_eq!(results.iter().map(|r| r.error.is_none()).collect::<Vec<bool>>(), vec![true, false, true]);
+        assert!(server.get_open_orders("BTCUSDT".to_string()).unwrap().is_empty());
+    }
+
+    struct FixedFundingRateLoader {}
+
+    impl FundingRateLoader for FixedFundingRateLoader {
+        fn load_funding_rate(&self, symbol: &str, start_time: u64, _limit: u32) -> Result<Vec<FundingRate>, AppError> {
+            Ok(vec![FundingRate {
+                symbol: symbol.to_string(),
+                funding_time: start_time + 10,
+                funding_rate: 0.01,
+            }])
+        }
+    }
+
+    #[test]
+    fn test_funding_payment() {
+        let server = new_server(config(100), Some(Box::new(FixedFundingRateLoader {})));
+        let account = server.account.clone();
+        account.apply_fill(&fill("BUY", 100.0, 5.0));
+
+        assert_eq!(account.apply_funding("BTCUSDT", 0), None);
+        assert_eq!(account.apply_funding("BTCUSDT", 10), None);
+        account.last_prices.write().unwrap().insert("BTCUSDT".to_string(), 100.0);
+        assert_eq!(account.apply_funding("BTCUSDT", 11), Some(-5.0));
+        assert_eq!(account.apply_funding("BTCUSDT", 12), None);
+    }
+
+    #[test]
+    fn test_liquidation() {
+        let server = new_server(SimTradeConfig {
+            fee: Some(FeeModel::Percentage { maker: 0.0, taker: 0.0005 }),
+            slippage: Some(SlippageModel::FixedTicks { ticks: 2.0, tick_size: 0.5 }),
+            ..config(100)
+        }, None);
+        let account = server.account.clone();
+        account.apply_fill(&fill("BUY", 100.0, 5.0));
+
+        account.last_prices.write().unwrap().insert("BTCUSDT".to_string(), 85.0);
+        account.mark_to_market();
+        assert!(!account.is_liquidatable());
+        account.last_prices.write().unwrap().insert("BTCUSDT".to_string(), 81.0);
+        account.mark_to_market();
+        assert!(account.is_liquidatable());
+
+        let sub = server.subscription.lock().unwrap();
+        SimTradeServer::settle(&account, &server.engine, &server.recorder, &server.order_status, &sub, "BTCUSDT", 20);
+        assert!(account.positions.read().unwrap().is_empty());
+        assert!((account.balance() - (100.0 + (80.0 - 100.0) * 5.0 - 80.0 * 5.0 * 0.0005)).abs() < 1e-9);
+    }
+}
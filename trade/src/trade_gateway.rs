@@ -1,49 +1,93 @@
-use std::{sync::{atomic::{AtomicUsize, Ordering}, Arc, Mutex}, thread::JoinHandle};
+use std::{collections::HashMap, sync::{atomic::{AtomicUsize, Ordering}, Arc, Mutex, MutexGuard}, thread::{self, JoinHandle}, time::Duration};
 
 use super::trade_server::*;
+use super::order::{ModifyOrderRequest, OrderRequest};
+use super::risk::{RiskConfig, RiskManager};
+use super::order_manager::{ManagedOrder, OrderManager};
+use super::kill_switch::{flatten_request, now, DeadMansSwitch, KillSwitchReport};
+use super::algo_order::{AlgoOrder, AlgoOrderEngine, AlgoOrderRequest, AlgoStatus};
+use super::conditional_order::{BracketOrderRequest, ConditionalOrder, ConditionalOrderEngine, ConditionalOrderRequest};
 use common::{error::AppError, msmc::{StreamError, Subscription}};
-use crossbeam::channel::{self, Receiver, Sender};
+use market::market_server::{KLine, MarketData};
+use log::*;
+
+const WATCHDOG_INTERVAL: u64 = 100;
 
 pub struct TradeGateway<S: TradeServer> {
-    server: Box<S>,
-    subscribers : Vec<(String, Sender<TradeEvent>)>,
+    server: Arc<Mutex<Box<S>>>,
+    subscribers : Vec<(String, Arc<Subscription<TradeEvent>>)>,
     pub handler: Option<JoinHandle<()>>,
     start_ticket: Arc<AtomicUsize>,
     subscription: Arc<Mutex<Subscription<TradeEvent>>>,
+    conditional: Arc<Mutex<ConditionalOrderEngine>>,
+    algo: Arc<Mutex<AlgoOrderEngine>>,
+    risk: Arc<Mutex<RiskManager>>,
+    orders: Arc<Mutex<OrderManager>>,
+    dead_mans_switch: Arc<Mutex<DeadMansSwitch>>,
+    watchdog: Option<JoinHandle<()>>,
+    symbols: Arc<Mutex<HashMap<String, S::Symbol>>>,
 }
 
 impl<S: TradeServer> TradeGateway<S> {
     pub fn new(server: Box<S>) -> Self {
         TradeGateway {
-            server,
+            server: Arc::new(Mutex::new(server)),
             subscribers: vec![],
             handler: None,
             start_ticket: Arc::new(AtomicUsize::new(0)),
             subscription: Arc::new(Mutex::new(Subscription::top())),
+            conditional: Arc::new(Mutex::new(ConditionalOrderEngine::new())),
+            algo: Arc::new(Mutex::new(AlgoOrderEngine::new())),
+            risk: Arc::new(Mutex::new(RiskManager::default())),
+            orders: Arc::new(Mutex::new(OrderManager::new())),
+            dead_mans_switch: Arc::new(Mutex::new(DeadMansSwitch::default())),
+            watchdog: None,
+            symbols: Arc::new(Mutex::new(HashMap::new())),
         }
     }
     
+    pub fn get_server(&self) -> MutexGuard<'_, Box<S>> {
+        self.server.lock().unwrap()
+    }
+
     pub fn init(&mut self) -> Result<(), AppError> {
-        self.server.init()
+        self.server.lock().unwrap().init()
     }
 
     pub fn start(&mut self) -> Result<(), AppError> {
         let start_ticket = self.start_ticket.fetch_add(1, Ordering::SeqCst);
         let start_ticket_ref = self.start_ticket.clone();
-        let subscription = self.server.start()?;
+        let subscription = self.server.lock().unwrap().start()?;
         self.subscription = Arc::new(Mutex::new(subscription));
 
         let subscribers = self.subscribers.clone();
+        let conditional = self.conditional.clone();
+        let algo = self.algo.clone();
+        let risk = self.risk.clone();
+        let orders = self.orders.clone();
         let handler = self.subscription.lock().unwrap().stream(move |event| {
             if start_ticket != start_ticket_ref.load(Ordering::SeqCst) - 1 {
                 return Err(StreamError::Exit);
             }
             match event {
                 Some(data) => {
-                    let symbol = data.get_symbol();
-                    for subscriber in subscribers.iter() {
-                        if subscriber.0 == symbol || symbol == "" {
-                            let _ = subscriber.1.send(data.clone());
+                    let fresh = match data {
+                        TradeEvent::OrderUpdate(order) => orders.lock().unwrap().on_order_update(order),
+                        _ => true,
+                    };
+                    if fresh {
+                        Self::publish(&subscribers, data);
+                        risk.lock().unwrap().on_trade_event(data);
+                        let parent = match data {
+                            TradeEvent::OrderUpdate(order) => {
+                                conditional.lock().unwrap().on_order_update(order);
+                                algo.lock().unwrap().on_order_update(order)
+                            },
+                            TradeEvent::Fill(fill) => algo.lock().unwrap().on_execution(fill),
+                            _ => None,
+                        };
+                        if let Some(parent) = parent {
+                            Self::publish(&subscribers, &TradeEvent::OrderUpdate(parent));
                         }
                     }
                 },
@@ -56,38 +100,351 @@ impl<S: TradeServer> TradeGateway<S> {
         Ok(())
     }
 
+    fn publish(subscribers: &[(String, Arc<Subscription<TradeEvent>>)], event: &TradeEvent) {
+        let symbol = event.get_symbol();
+        for subscriber in subscribers.iter() {
+            if subscriber.0 == symbol || symbol.is_empty() {
+                subscriber.1.send(event);
+            }
+        }
+    }
+
     pub fn close(&self) {
         self.start_ticket.fetch_add(1, Ordering::SeqCst);
-        self.server.close();
+        self.server.lock().unwrap().close();
     }
 
-    pub fn register_symbol(&mut self, symbol: S::Symbol) -> Result<Receiver<TradeEvent>, AppError> {
-        let (tx, rx) = channel::unbounded::<TradeEvent>();
-        self.subscribers.push((symbol.to_string(), tx.clone()));
-        Ok(rx)
+    pub fn register_symbol(&mut self, symbol: S::Symbol) -> Result<Subscription<TradeEvent>, AppError> {
+        let mut top = Subscription::<TradeEvent>::top();
+        let sub = top.subscribe();
+        self.subscribers.push((symbol.to_string(), Arc::new(top)));
+        self.symbols.lock().unwrap().insert(symbol.to_string(), symbol);
+        Ok(sub)
     }
 
     pub fn init_symbol(&mut self, symbol: S::Symbol, config: S::SymbolConfig) -> Result<S::SymbolInfo, AppError> {
-        self.server.init_symbol(symbol, config)
-    }
-
-    pub fn new_order(&mut self, symbol: S::Symbol, request : S::OrderRequest) -> Result<(), AppError> {
-        self.server.new_order(symbol, request)
+        self.symbols.lock().unwrap().insert(symbol.to_string(), symbol.clone());
+        self.server.lock().unwrap().init_symbol(symbol, config)
     }
 
     pub fn cancel_order(&mut self,  symbol: S::Symbol, request: S::CancelOrderRequest) -> Result<(), AppError> {
-        self.server.cancel_order(symbol, request)
+        self.server.lock().unwrap().cancel_order(symbol, request)
     }
 
     pub fn cancel_orders(&mut self, symbol: S::Symbol) -> Result<(), AppError> {
-        self.server.cancel_orders(symbol)
+        self.server.lock().unwrap().cancel_orders(symbol)
+    }
+
+    pub fn cancel_orders_by_id(&mut self, symbol: S::Symbol, requests: Vec<S::CancelOrderRequest>) -> Result<Vec<BatchOrderResult>, AppError> {
+        self.server.lock().unwrap().cancel_orders_by_id(symbol, requests)
+    }
+
+    pub fn get_open_orders(&mut self, symbol: S::Symbol) -> Result<Vec<Order>, AppError> {
+        self.server.lock().unwrap().get_open_orders(symbol)
+    }
+
+    pub fn get_order(&mut self, symbol: S::Symbol, client_order_id: &str) -> Result<Option<Order>, AppError> {
+        self.server.lock().unwrap().get_order(symbol, client_order_id)
     }
 
     pub fn get_positions(&mut self, symbol:S::Symbol) -> Result<Vec<Position>, AppError> {
-        self.server.get_positions(symbol)
+        self.server.lock().unwrap().get_positions(symbol)
+    }
+
+    pub fn get_session_orders(&self, symbol: &str) -> Vec<ManagedOrder> {
+        self.orders.lock().unwrap().get_orders(symbol)
+    }
+
+    pub fn get_session_order(&self, symbol: &str, client_order_id: &str) -> Option<ManagedOrder> {
+        self.orders.lock().unwrap().get_order(symbol, client_order_id)
+    }
+
+    pub fn set_risk_config(&mut self, config: RiskConfig) {
+        self.risk.lock().unwrap().set_config(config);
+    }
+
+    pub fn get_risk_config(&self) -> RiskConfig {
+        self.risk.lock().unwrap().get_config()
     }
 
     pub fn get_account(&mut self, account_id: &str) -> Result<Option<Wallet>, AppError> {
-        self.server.get_account(account_id)
+        self.server.lock().unwrap().get_account(account_id)
+    }
+}
+
+impl<S> TradeGateway<S>
+    where S: TradeServer + Send + 'static, S::OrderRequest: From<OrderRequest>, S::CancelOrderRequest: From<String>, S::Symbol: Send + 'static {
+    fn submit(server: &Arc<Mutex<Box<S>>>, risk: &Arc<Mutex<RiskManager>>, symbol: S::Symbol, request: OrderRequest) -> Result<OrderAck, AppError> {
+        let name = symbol.to_string();
+        let positions = Self::risk_positions(server, risk, &symbol)?;
+        risk.lock().unwrap().check(&name, &request, &positions)?;
+        let ack = server.lock().unwrap().new_order(symbol, request.into())?;
+        risk.lock().unwrap().on_accepted(&name, &ack.client_order_id);
+        Ok(ack)
+    }
+
+    fn risk_positions(server: &Arc<Mutex<Box<S>>>, risk: &Arc<Mutex<RiskManager>>, symbol: &S::Symbol) -> Result<Vec<Position>, AppError> {
+        if risk.lock().unwrap().get_config().max_position.is_none() {
+            return Ok(vec![]);
+        }
+        server.lock().unwrap().get_positions(symbol.clone())
+    }
+
+    pub fn new_order(&mut self, symbol: S::Symbol, request: OrderRequest) -> Result<OrderAck, AppError> {
+        self.symbols.lock().unwrap().insert(symbol.to_string(), symbol.clone());
+        Self::submit(&self.server, &self.risk, symbol, request)
+    }
+
+    pub fn new_orders(&mut self, symbol: S::Symbol, requests: Vec<OrderRequest>) -> Result<Vec<BatchOrderResult>, AppError> {
+        let name = symbol.to_string();
+        self.symbols.lock().unwrap().insert(name.clone(), symbol.clone());
+        let positions = Self::risk_positions(&self.server, &self.risk, &symbol)?;
+        let mut results = vec![];
+        let mut indexes = vec![];
+        let mut passed = vec![];
+        for (index, request) in requests.into_iter().enumerate() {
+            match self.risk.lock().unwrap().check(&name, &request, &positions) {
+                Ok(_) => {
+                    indexes.push(index);
+                    passed.push(request.into());
+                },
+                Err(e) => results.push(BatchOrderResult::from_result(index, Err(e))),
+            }
+        }
+        if !passed.is_empty() {
+            for mut result in self.server.lock().unwrap().new_orders(symbol, passed)? {
+                result.index = indexes[result.index];
+                if let Some(ack) = &result.ack {
+                    self.risk.lock().unwrap().on_accepted(&name, &ack.client_order_id);
+                }
+                results.push(result);
+            }
+        }
+        results.sort_by_key(|r| r.index);
+        Ok(results)
+    }
+
+    pub fn modify_order(&mut self, symbol: S::Symbol, request: ModifyOrderRequest) -> Result<OrderAck, AppError> {
+        self.risk.lock().unwrap().check_modify(&symbol.to_string(), &request)?;
+        self.server.lock().unwrap().modify_order(symbol, request)
+    }
+
+    pub fn bind_market(&mut self, mut subscription: Subscription<MarketData>) -> Result<(), AppError> {
+        self.server.lock().unwrap().bind_market(subscription.subscribe())?;
+        let server = self.server.clone();
+        let conditional = self.conditional.clone();
+        let algo = self.algo.clone();
+        let risk = self.risk.clone();
+        let symbols = self.symbols.clone();
+        let subscribers = self.subscribers.clone();
+        let _ = subscription.stream(move |event| {
+            let (symbol, price, timestamp) = match event {
+                Some(MarketData::Tick(t)) => (&t.symbol, t.close, t.timestamp),
+                Some(MarketData::Kline(k)) => (&k.symbol, k.close, k.timestamp),
+                Some(MarketData::TradeDayEnded(trading_day)) => {
+                    risk.lock().unwrap().on_trade_day_ended(trading_day);
+                    return Ok(true);
+                },
+                _ => return Ok(true),
+            };
+            risk.lock().unwrap().on_price(symbol, price, timestamp);
+            let place = |request: OrderRequest| {
+                let registered = symbols.lock().unwrap().get(symbol).cloned();
+                match registered {
+                    Some(s) => Self::submit(&server, &risk, s, request).map(|ack| ack.client_order_id),
+                    None => Err(AppError::new(-200, &format!("The symbol {} is not registered", symbol))),
+                }
+            };
+            let fired = conditional.lock().unwrap().on_price(symbol, price);
+            for order in fired {
+                let ret = place(order.order.clone());
+                conditional.lock().unwrap().on_fired(&order.id, ret);
+            }
+            let children = algo.lock().unwrap().on_time(symbol, timestamp);
+            for (algo_id, request) in children {
+                let quantity = request.quantity;
+                let ret = place(request);
+                let parent = algo.lock().unwrap().on_placed(&algo_id, quantity, ret);
+                if let Some(parent) = parent {
+                    Self::publish(&subscribers, &TradeEvent::OrderUpdate(parent));
+                }
+            }
+            Ok(true)
+        });
+        Ok(())
+    }
+
+    pub fn new_conditional_order(&mut self, symbol: S::Symbol, request: ConditionalOrderRequest) -> Result<ConditionalOrder, AppError> {
+        let order = self.conditional.lock().unwrap().add(&symbol.to_string(), request)?;
+        self.symbols.lock().unwrap().insert(symbol.to_string(), symbol);
+        Ok(order)
+    }
+
+    pub fn new_oco_order(&mut self, symbol: S::Symbol, requests: Vec<ConditionalOrderRequest>) -> Result<Vec<ConditionalOrder>, AppError> {
+        let orders = self.conditional.lock().unwrap().add_oco(&symbol.to_string(), requests)?;
+        self.symbols.lock().unwrap().insert(symbol.to_string(), symbol);
+        Ok(orders)
+    }
+
+    pub fn new_bracket_order(&mut self, symbol: S::Symbol, request: BracketOrderRequest) -> Result<Vec<ConditionalOrder>, AppError> {
+        request.validate()?;
+        let ack = Self::submit(&self.server, &self.risk, symbol.clone(), request.entry.clone())?;
+        let orders = self.conditional.lock().unwrap().add_bracket_exits(&symbol.to_string(), &ack.client_order_id, request.exits())?;
+        self.symbols.lock().unwrap().insert(symbol.to_string(), symbol.clone());
+        if let Some(entry) = self.server.lock().unwrap().get_order(symbol, &ack.client_order_id)? {
+            self.conditional.lock().unwrap().on_order_update(&entry);
+        }
+        Ok(orders)
+    }
+
+    pub fn cancel_conditional_order(&mut self, id: &str) -> Result<ConditionalOrder, AppError> {
+        self.conditional.lock().unwrap().cancel(id)
+    }
+
+    pub fn get_conditional_orders(&self, symbol: S::Symbol) -> Result<Vec<ConditionalOrder>, AppError> {
+        Ok(self.conditional.lock().unwrap().get_orders(&symbol.to_string()))
+    }
+
+    pub fn new_algo_order(&mut self, symbol: S::Symbol, request: AlgoOrderRequest, history: Vec<KLine>) -> Result<AlgoOrder, AppError> {
+        let order = self.algo.lock().unwrap().add(&symbol.to_string(), request, history)?;
+        self.symbols.lock().unwrap().insert(symbol.to_string(), symbol);
+        Self::publish(&self.subscribers, &TradeEvent::OrderUpdate(order.to_order()));
+        Ok(order)
+    }
+
+    pub fn cancel_algo_order(&mut self, id: &str) -> Result<AlgoOrder, AppError> {
+        let (order, working) = self.algo.lock().unwrap().cancel(id)?;
+        let symbol = self.symbols.lock().unwrap().get(&order.symbol).cloned();
+        if let Some(symbol) = symbol {
+            for client_order_id in working {
+                self.server.lock().unwrap().cancel_order(symbol.clone(), client_order_id.into())?;
+            }
+        }
+        Self::publish(&self.subscribers, &TradeEvent::OrderUpdate(order.to_order()));
+        Ok(order)
+    }
+
+    pub fn get_algo_orders(&self, symbol: S::Symbol) -> Result<Vec<AlgoOrder>, AppError> {
+        Ok(self.algo.lock().unwrap().get_orders(&symbol.to_string()))
+    }
+
+    fn kill(server: &Arc<Mutex<Box<S>>>, symbols: &Arc<Mutex<HashMap<String, S::Symbol>>>, conditional: &Arc<Mutex<ConditionalOrderEngine>>,
+            algo: &Arc<Mutex<AlgoOrderEngine>>, subscribers: &[(String, Arc<Subscription<TradeEvent>>)], flatten: bool) -> KillSwitchReport {
+        let mut report = KillSwitchReport::default();
+        let registered: Vec<S::Symbol> = symbols.lock().unwrap().values().cloned().collect();
+        for symbol in registered {
+            let name = symbol.to_string();
+            {
+                let mut conditional = conditional.lock().unwrap();
+                for order in conditional.get_orders(&name).iter().filter(|o| o.is_live()) {
+                    if conditional.cancel(&order.id).is_ok() {
+                        report.conditional_orders += 1;
+                    }
+                }
+            }
+            let running: Vec<String> = algo.lock().unwrap().get_orders(&name).into_iter().filter(|o| o.status == AlgoStatus::Running).map(|o| o.id).collect();
+            for id in running {
+                let canceled = algo.lock().unwrap().cancel(&id);
+                if let Ok((order, _)) = canceled {
+                    report.algo_orders += 1;
+                    Self::publish(subscribers, &TradeEvent::OrderUpdate(order.to_order()));
+                }
+            }
+            if let Err(e) = server.lock().unwrap().cancel_orders(symbol.clone()) {
+                report.errors.push(format!("{}: {}", name, e.message));
+            }
+            if flatten {
+                let positions = server.lock().unwrap().get_positions(symbol.clone());
+                match positions {
+                    Ok(positions) => {
+                        for request in positions.iter().filter(|p| p.symbol == name).filter_map(flatten_request) {
+                            match server.lock().unwrap().new_order(symbol.clone(), request.into()) {
+                                Ok(ack) => report.flatten_orders.push(ack),
+                                Err(e) => report.errors.push(format!("{}: {}", name, e.message)),
+                            }
+                        }
+                    },
+                    Err(e) => report.errors.push(format!("{}: {}", name, e.message)),
+                }
+            }
+            report.symbols.push(name);
+        }
+        report
+    }
+
+    pub fn kill_switch(&mut self, flatten: bool) -> Result<KillSwitchReport, AppError> {
+        let report = Self::kill(&self.server, &self.symbols, &self.conditional, &self.algo, &self.subscribers, flatten);
+        warn!("The kill switch canceled the orders of {:?}, errors: {:?}", report.symbols, report.errors);
+        Ok(report)
     }
-}
\ No newline at end of file
+
+    pub fn arm_dead_mans_switch(&mut self, timeout: u64, flatten: bool) -> Result<DeadMansSwitch, AppError> {
+        if timeout == 0 {
+            return Err(AppError::new(-200, "The dead man's switch timeout must be positive"));
+        }
+        self.dead_mans_switch.lock().unwrap().arm(timeout, flatten, now());
+        let state = self.heartbeat()?;
+        if self.watchdog.as_ref().is_none_or(|h| h.is_finished()) {
+            let server = self.server.clone();
+            let symbols = self.symbols.clone();
+            let conditional = self.conditional.clone();
+            let algo = self.algo.clone();
+            let subscribers = self.subscribers.clone();
+            let dead_mans_switch = self.dead_mans_switch.clone();
+            self.watchdog = Some(thread::spawn(move || loop {
+                thread::sleep(Duration::from_millis(WATCHDOG_INTERVAL));
+                let flatten = {
+                    let mut state = dead_mans_switch.lock().unwrap();
+                    if !state.armed {
+                        break;
+                    }
+                    if !state.is_expired(now()) {
+                        continue;
+                    }
+                    state.disarm();
+                    state.flatten
+                };
+                error!("No heartbeat within the dead man's switch timeout, canceling all orders");
+                let report = Self::kill(&server, &symbols, &conditional, &algo, &subscribers, flatten);
+                warn!("The dead man's switch canceled the orders of {:?}, errors: {:?}", report.symbols, report.errors);
+                break;
+            }));
+        }
+        Ok(state)
+    }
+
+    pub fn heartbeat(&mut self) -> Result<DeadMansSwitch, AppError> {
+        let timeout = {
+            let mut state = self.dead_mans_switch.lock().unwrap();
+            if !state.armed {
+                return Err(AppError::new(-200, "The dead man's switch is not armed"));
+            }
+            state.heartbeat(now());
+            state.timeout
+        };
+        let registered: Vec<S::Symbol> = self.symbols.lock().unwrap().values().cloned().collect();
+        let mut native = false;
+        for symbol in registered {
+            native |= self.server.lock().unwrap().set_countdown_cancel(symbol, timeout)?;
+        }
+        let mut state = self.dead_mans_switch.lock().unwrap();
+        state.native = native;
+        Ok(state.clone())
+    }
+
+    pub fn disarm_dead_mans_switch(&mut self) -> Result<DeadMansSwitch, AppError> {
+        let native = {
+            let mut state = self.dead_mans_switch.lock().unwrap();
+            let native = state.native;
+            state.disarm();
+            native
+        };
+        if native {
+            let registered: Vec<S::Symbol> = self.symbols.lock().unwrap().values().cloned().collect();
+            for symbol in registered {
+                self.server.lock().unwrap().set_countdown_cancel(symbol, 0)?;
+            }
+        }
+        Ok(self.dead_mans_switch.lock().unwrap().clone())
+    }
+}
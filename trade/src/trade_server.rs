@@ -1,13 +1,16 @@
 
 use std::fmt::Debug;
 use common::{error::AppError, msmc::Subscription};
+use market::market_server::MarketData;
 use serde::{Serialize, Deserialize};
+use crate::order::ModifyOrderRequest;
 
 #[derive(Clone, Debug)]
 pub enum TradeEvent {
     PositionUpdate(Position),
     AccountUpdate(Wallet),
     OrderUpdate(Order),
+    Fill(Execution),
 }
 
 impl SymbolRoute for TradeEvent {
@@ -19,6 +22,9 @@ impl SymbolRoute for TradeEvent {
             TradeEvent::OrderUpdate(o) => {
                 o.symbol.clone()
             }
+            TradeEvent::Fill(f) => {
+                f.symbol.clone()
+            }
             _ => {
                 "".to_string()
             }
@@ -35,6 +41,7 @@ pub struct Position {
     pub amount: f64,
     pub cost: f64,
     pub today_amount: f64,
+    pub unrealized_pnl: f64,
 }
 
 impl PartialEq for Position {
@@ -83,6 +90,57 @@ pub struct Order {
     pub side: String,
     pub message: String,
     pub timestamp: u64,
+    pub fee: f64,
+    pub slippage: f64,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Execution {
+    pub symbol: String,
+    pub order_id: String,
+    pub client_order_id: String,
+    pub trade_id: String,
+    pub side: String,
+    pub offset: String,
+    pub price: f64,
+    pub quantity: f64,
+    pub fee: f64,
+    pub fee_asset: String,
+    pub is_maker: bool,
+    pub timestamp: u64,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OrderAck {
+    pub order_id: String,
+    pub client_order_id: String,
+    pub status: String,
+    pub timestamp: u64,
+    /// The order ids of every leg when the request is split into several exchange orders, empty otherwise.
+    #[serde(default)]
+    pub leg_order_ids: Vec<String>,
+    /// The error of a leg that failed after the earlier legs were sent, those legs are working and listed above.
+    #[serde(default)]
+    pub leg_error: Option<String>,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BatchOrderResult {
+    pub index: usize,
+    pub ack: Option<OrderAck>,
+    pub error: Option<String>,
+}
+
+impl BatchOrderResult {
+    pub fn from_result(index: usize, result: Result<Option<OrderAck>, AppError>) -> Self {
+        match result {
+            Ok(ack) => BatchOrderResult { index, ack, error: None },
+            Err(e) => BatchOrderResult { index, ack: None, error: Some(e.message) },
+        }
+    }
 }
 
 pub trait SymbolRoute {
@@ -94,13 +152,34 @@ pub trait TradeServer {
     type CancelOrderRequest;
     type SymbolConfig;
     type SymbolInfo;
-    type Symbol: ToString;
+    type Symbol: ToString + Clone;
 
     fn init(&mut self) -> Result<(), AppError>;
     fn start(&mut self) -> Result<Subscription<TradeEvent>, AppError>;
-    fn new_order(&mut self, symbol: Self::Symbol, request: Self::OrderRequest) -> Result<(), AppError>;
+    fn bind_market(&mut self, subscription: Subscription<MarketData>) -> Result<(), AppError>;
+    fn new_order(&mut self, symbol: Self::Symbol, request: Self::OrderRequest) -> Result<OrderAck, AppError>;
+    fn modify_order(&mut self, symbol: Self::Symbol, request: ModifyOrderRequest) -> Result<OrderAck, AppError>;
     fn cancel_order(&mut self, symbol: Self::Symbol, request: Self::CancelOrderRequest) -> Result<(), AppError>;
     fn cancel_orders(&mut self, symbol: Self::Symbol) -> Result<(), AppError>;
+    fn new_orders(&mut self, symbol: Self::Symbol, requests: Vec<Self::OrderRequest>) -> Result<Vec<BatchOrderResult>, AppError> {
+        let mut results = vec![];
+        for (index, request) in requests.into_iter().enumerate() {
+            results.push(BatchOrderResult::from_result(index, self.new_order(symbol.clone(), request).map(Some)));
+        }
+        Ok(results)
+    }
+    fn cancel_orders_by_id(&mut self, symbol: Self::Symbol, requests: Vec<Self::CancelOrderRequest>) -> Result<Vec<BatchOrderResult>, AppError> {
+        let mut results = vec![];
+        for (index, request) in requests.into_iter().enumerate() {
+            results.push(BatchOrderResult::from_result(index, self.cancel_order(symbol.clone(), request).map(|_| None)));
+        }
+        Ok(results)
+    }
+    fn set_countdown_cancel(&mut self, _symbol: Self::Symbol, _timeout: u64) -> Result<bool, AppError> {
+        Ok(false)
+    }
+    fn get_open_orders(&self, symbol: Self::Symbol) -> Result<Vec<Order>, AppError>;
+    fn get_order(&self, symbol: Self::Symbol, client_order_id: &str) -> Result<Option<Order>, AppError>;
     fn init_symbol(&self, symbol: Self::Symbol, config: Self::SymbolConfig)-> Result<Self::SymbolInfo, AppError>;
     fn get_positions(&self, symbol: Self::Symbol) -> Result<Vec<Position>, AppError>;
     fn get_account(&self, account_id: &str) -> Result<Option<Wallet>, AppError>;